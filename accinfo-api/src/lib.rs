@@ -0,0 +1,125 @@
+//! accinfo服务端与客户端共用的接口类型, 字段与接口的json格式一一对应(camelCase)
+//!
+//! 服务端直接使用这里的类型解析请求, 客户端使用同样的类型序列化请求, 两端的字段不会不一致.
+//! 记录列表、记录详情等回复由服务端借用数据库中的数据序列化, 不在此定义
+use serde::{Deserialize, Serialize};
+
+/// 登录请求
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginRequest {
+    pub user: String,
+    pub pass: String,
+    /// 数据库名称, 为空时使用与用户名同名的数据库
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub database: Option<String>,
+}
+
+/// 记录列表查询请求
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ListRequest {
+    /// 标题、用户名、网址、非保护字段或备注包含的关键字, 为空时返回所有记录
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub q: Option<String>,
+}
+
+/// 记录的自定义字段(对应keepass条目中标准字段以外的字符串字段)
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordField {
+    pub name: String,
+    pub value: String,
+    /// 受保护的字段, 与口令一样脱敏显示, 不参与搜索
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub protected: bool,
+}
+
+/// 新建记录请求, 未填写的项继承所属分组的缺省设置
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct NewRecord {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub group: String,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub user: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub pass: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub url: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub notes: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub expire: u64,
+    #[serde(default)]
+    pub sensitive: bool,
+    /// otpauth uri或者base32密钥
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub otp: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<RecordField>,
+}
+
+/// 修改记录请求, 只修改设置了值的项
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordUpdate {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pass: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expire: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensitive: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub otp: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fields: Option<Vec<RecordField>>,
+}
+
+/// 后台任务状态
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct JobStatus {
+    pub id: u64,
+    /// 任务类型, 例如`export`
+    pub kind: String,
+    /// 状态: running/done/failed/cancelled/downloaded
+    pub status: String,
+    pub progress: u8,
+    pub created: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// 结果是否为文件, 文件结果需要通过结果接口下载
+    pub has_file: bool,
+}
+
+impl JobStatus {
+    /// 任务是否已结束
+    pub fn is_finished(&self) -> bool {
+        self.status != "running"
+    }
+}
+
+/// 创建后台任务的回复
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct JobCreated {
+    pub job_id: u64,
+}
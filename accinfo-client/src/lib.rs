@@ -0,0 +1,286 @@
+//! accinfo接口的异步客户端, 封装登录、记录查询及增删改、后台任务等接口, 自动管理会话令牌
+//!
+//! ```rust,no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! use accinfo_client::{Client, NewRecord};
+//!
+//! let client = Client::new("https://vault.example.com/api")?;
+//! client.login("simple", "12345678").await?;
+//! let list = client.list(Some("github")).await?;
+//! let rec = client.record_create(&NewRecord { title: "gitee".to_owned(), ..Default::default() }).await?;
+//! client.record_delete(&rec.id).await?;
+//! client.logout().await?;
+//! # Ok(())
+//! # }
+//! ```
+mod types;
+
+use std::fmt::Display;
+
+use anyhow::{anyhow, Result};
+use parking_lot::Mutex;
+use reqwest::{header::HeaderName, RequestBuilder, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+pub use types::*;
+
+/// 缺省的携带令牌的请求头
+const DEFAULT_TOKEN_HEADER: &str = "Authorization";
+/// 缺省的令牌认证方式
+const DEFAULT_TOKEN_SCHEME: &str = "session";
+
+/// 接口返回的错误, 可以通过`anyhow::Error::downcast_ref`获取
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    /// http状态码
+    pub status: u16,
+    /// 回复中的错误码
+    pub code: u32,
+    /// 错误信息
+    pub message: String,
+}
+
+/// 当前会话的令牌及其传递方式
+#[derive(Clone, Debug)]
+struct Session {
+    token: String,
+    header: HeaderName,
+    scheme: String,
+}
+
+/// 接口回复的统一格式
+#[derive(Deserialize)]
+struct Reply<T> {
+    code: u32,
+    #[serde(default)]
+    message: Option<String>,
+    data: Option<T>,
+}
+
+/// 接口客户端, 登录后保存会话令牌, 后续请求自动携带, 刷新令牌或保活后自动更新
+pub struct Client {
+    http: reqwest::Client,
+    base: String,
+    session: Mutex<Option<Session>>,
+}
+
+impl Client {
+    /// 创建客户端
+    ///
+    /// Arguments:
+    ///
+    /// * `base`: 接口地址前缀, 例如`http://127.0.0.1:8080/api`
+    pub fn new(base: &str) -> Result<Self> {
+        Ok(Self::with_client(reqwest::Client::builder().build()?, base))
+    }
+
+    /// 使用自定义的http客户端(代理、超时、证书等)创建客户端
+    pub fn with_client(http: reqwest::Client, base: &str) -> Self {
+        Client { http, base: base.trim_end_matches('/').to_owned(), session: Mutex::new(None) }
+    }
+
+    /// 当前的会话令牌, 未登录时返回None
+    pub fn token(&self) -> Option<String> {
+        self.session.lock().as_ref().map(|s| s.token.clone())
+    }
+
+    /// 使用已有的会话令牌(例如上次保存的无状态令牌), 使用缺省的`Authorization: session <令牌>`传递
+    pub fn set_token(&self, token: &str) {
+        self.session.lock().replace(Session {
+            token: token.to_owned(),
+            header: HeaderName::from_static("authorization"),
+            scheme: DEFAULT_TOKEN_SCHEME.to_owned(),
+        });
+    }
+
+    /// 登录到与用户名同名的数据库
+    pub async fn login(&self, user: &str, pass: &str) -> Result<LoginInfo> {
+        self.login_with(&LoginRequest { user: user.to_owned(), pass: pass.to_owned(), database: None }).await
+    }
+
+    /// 登录, 成功后保存会话令牌及服务端要求的令牌传递方式
+    pub async fn login_with(&self, req: &LoginRequest) -> Result<LoginInfo> {
+        let info: LoginInfo = self.call("login", req).await?;
+        let header = if info.token_header.is_empty() { DEFAULT_TOKEN_HEADER } else { &info.token_header };
+        let header = HeaderName::from_bytes(header.as_bytes())
+            .map_err(|_| anyhow!("invalid token header: {header}"))?;
+        self.session.lock().replace(Session {
+            token: info.token.clone(),
+            header,
+            scheme: info.token_scheme.clone(),
+        });
+        Ok(info)
+    }
+
+    /// 退出登录并清除保存的令牌
+    pub async fn logout(&self) -> Result<()> {
+        let res = self.call::<_, Value>("logout", &empty()).await;
+        self.session.lock().take();
+        res.map(|_| ())
+    }
+
+    /// 刷新令牌, 旧令牌立即失效, 会话的最长有效时间不会因刷新而延长
+    pub async fn refresh(&self) -> Result<SessionInfo> {
+        let info: SessionInfo = self.call("refresh", &empty()).await?;
+        self.update_token(&info.token);
+        Ok(info)
+    }
+
+    /// 延长会话的空闲过期时间, 服务端禁用请求自动延长会话时需定期调用
+    pub async fn keepalive(&self) -> Result<SessionInfo> {
+        let info: SessionInfo = self.call("keepalive", &empty()).await?;
+        self.update_token(&info.token);
+        Ok(info)
+    }
+
+    /// 查询记录列表
+    ///
+    /// * `q`: 标题、用户名、网址、非保护字段或备注包含的关键字, None时返回所有记录
+    pub async fn list(&self, q: Option<&str>) -> Result<RecordList> {
+        self.call("list", &ListRequest { q: q.map(str::to_owned) }).await
+    }
+
+    /// 获取记录详情, 口令已脱敏
+    pub async fn record_get(&self, id: &str) -> Result<Record> {
+        self.call("record/get", &IdRequest { id }).await
+    }
+
+    /// 查看记录的口令及受保护的自定义字段
+    pub async fn record_reveal(&self, id: &str) -> Result<RevealedPass> {
+        self.call("record/reveal", &IdRequest { id }).await
+    }
+
+    /// 新建记录, 返回新建的记录
+    pub async fn record_create(&self, rec: &NewRecord) -> Result<Record> {
+        self.call("record/create", rec).await
+    }
+
+    /// 修改记录, 返回修改后的记录
+    pub async fn record_update(&self, rec: &RecordUpdate) -> Result<Record> {
+        self.call("record/update", rec).await
+    }
+
+    /// 删除记录
+    pub async fn record_delete(&self, id: &str) -> Result<()> {
+        self.call::<_, Value>("record/delete", &IdRequest { id }).await.map(|_| ())
+    }
+
+    /// 创建后台导出任务, 返回任务id
+    ///
+    /// * `format`: 导出格式, `xml`或者`csv`
+    pub async fn export(&self, format: &str) -> Result<u64> {
+        #[derive(Serialize)]
+        struct ReqParam<'a> {
+            format: &'a str,
+            background: bool,
+        }
+
+        let res: JobCreated = self.call("export", &ReqParam { format, background: true }).await?;
+        Ok(res.job_id)
+    }
+
+    /// 所有后台任务的状态
+    pub async fn jobs(&self) -> Result<Vec<JobStatus>> {
+        self.call("jobs", &empty()).await
+    }
+
+    /// 后台任务的状态
+    pub async fn job(&self, id: u64) -> Result<JobStatus> {
+        self.call(&format!("jobs/{id}"), &empty()).await
+    }
+
+    /// 取消后台任务, 已结束的任务同时删除其结果
+    pub async fn job_cancel(&self, id: u64) -> Result<()> {
+        self.call::<_, Value>(&format!("jobs/{id}/cancel"), &empty()).await.map(|_| ())
+    }
+
+    /// 获取json结果的后台任务的结果
+    pub async fn job_result<T: DeserializeOwned>(&self, id: u64) -> Result<T> {
+        self.call(&format!("jobs/{id}/result"), &empty()).await
+    }
+
+    /// 下载文件结果的后台任务的结果(例如导出的文件)
+    pub async fn job_file(&self, id: u64) -> Result<Vec<u8>> {
+        let res = self.request(&format!("jobs/{id}/result"), &empty()).send().await?;
+        let is_json = res.headers().get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("application/json"));
+        if is_json || !res.status().is_success() {
+            let status = res.status();
+            return Err(Self::parse_reply::<Value>(status, &res.bytes().await?)
+                .err().unwrap_or_else(|| anyhow!("job {id} result is not a file")));
+        }
+        Ok(res.bytes().await?.to_vec())
+    }
+
+    /// 调用接口并解析回复中的数据, 调用失败或者回复的错误码不是200时返回`ApiError`
+    ///
+    /// Arguments:
+    ///
+    /// * `path`: 接口路径, 例如`record/get`
+    /// * `body`: 请求参数, 序列化为json
+    pub async fn call<B: Serialize + ?Sized, T: DeserializeOwned>(&self, path: &str, body: &B) -> Result<T> {
+        let res = self.request(path, body).send().await?;
+        let status = res.status();
+        Self::parse_reply(status, &res.bytes().await?)
+    }
+
+    fn request<B: Serialize + ?Sized>(&self, path: &str, body: &B) -> RequestBuilder {
+        let mut req = self.http.post(format!("{}/{}", self.base, path.trim_start_matches('/'))).json(body);
+        if let Some(s) = self.session.lock().as_ref() {
+            let value = if s.scheme.is_empty() { s.token.clone() } else { format!("{} {}", s.scheme, s.token) };
+            req = req.header(s.header.clone(), value);
+        }
+        req
+    }
+
+    fn update_token(&self, token: &str) {
+        if let Some(s) = self.session.lock().as_mut() {
+            token.clone_into(&mut s.token);
+        }
+    }
+
+    fn parse_reply<T: DeserializeOwned>(status: StatusCode, body: &[u8]) -> Result<T> {
+        let reply = match serde_json::from_slice::<Reply<T>>(body) {
+            Ok(reply) => reply,
+            Err(_) if !status.is_success() => return Err(ApiError {
+                status: status.as_u16(),
+                code: status.as_u16() as u32,
+                message: String::from_utf8_lossy(body).into_owned(),
+            }.into()),
+            Err(e) => return Err(e.into()),
+        };
+        if reply.code != 200 {
+            return Err(ApiError {
+                status: status.as_u16(),
+                code: reply.code,
+                message: reply.message.unwrap_or_default(),
+            }.into());
+        }
+        // 没有数据的回复(例如删除记录), 按null解析
+        match reply.data {
+            Some(data) => Ok(data),
+            None => Ok(serde_json::from_value(Value::Null)?),
+        }
+    }
+}
+
+/// 没有参数的请求使用空的json对象
+fn empty() -> Value {
+    Value::Object(serde_json::Map::new())
+}
+
+/// 只需要记录id的请求
+#[derive(Serialize)]
+struct IdRequest<'a> {
+    id: &'a str,
+}
+
+impl std::error::Error for ApiError {}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "api error {}: {}", self.code, self.message)
+    }
+}
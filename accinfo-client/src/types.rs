@@ -0,0 +1,125 @@
+//! 接口的回复类型, 字段与服务端的json格式一一对应(camelCase), 请求类型与服务端共用, 见`accinfo_api`
+use serde::Deserialize;
+
+pub use accinfo_api::{JobStatus, ListRequest, LoginRequest, NewRecord, RecordField, RecordUpdate};
+pub(crate) use accinfo_api::JobCreated;
+
+/// 登录回复
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LoginInfo {
+    /// 登录的数据库名称
+    pub database: String,
+    pub token: String,
+    /// 空闲过期时间
+    pub expire: String,
+    /// 建议的令牌刷新时间
+    pub refresh_time: String,
+    /// 绝对过期时间, 刷新令牌也不会延长
+    pub max_expire: String,
+    /// 上次登录时间
+    pub last_login: Option<String>,
+    /// 上次登录的客户端地址
+    pub last_login_ip: String,
+    /// 上次登录后的登录失败次数
+    pub failed_attempts: u32,
+    /// 携带令牌的请求头名称
+    pub token_header: String,
+    /// 令牌前的认证方式, 为空时整个请求头的值即令牌
+    pub token_scheme: String,
+}
+
+/// 刷新令牌及会话保活的回复
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SessionInfo {
+    pub token: String,
+    pub expire: String,
+    pub refresh_time: String,
+    pub max_expire: String,
+}
+
+/// 记录列表
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct RecordList {
+    pub total: usize,
+    pub records: Vec<RecordSummary>,
+}
+
+/// 记录摘要, 口令已脱敏
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RecordSummary {
+    pub id: String,
+    pub title: String,
+    pub user: String,
+    pub pass: String,
+    pub url: String,
+    pub icon: u32,
+    pub custom_icon: String,
+    pub group: String,
+    pub tags: Vec<String>,
+    pub expire: u64,
+    pub modified: u64,
+    pub has_notes: bool,
+    pub sensitive: bool,
+    pub has_otp: bool,
+    pub attachments: Vec<FileRef>,
+    pub fields: Vec<RecordField>,
+}
+
+/// 记录详情, 口令及受保护的自定义字段已脱敏, 需通过查看口令接口获取
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Record {
+    pub id: String,
+    pub title: String,
+    pub user: String,
+    pub pass: String,
+    pub url: String,
+    pub notes: String,
+    pub icon: u32,
+    pub custom_icon: String,
+    pub group: String,
+    pub tags: Vec<String>,
+    /// 过期时间(unix时间戳, 单位: 秒), 0表示永不过期
+    pub expire: u64,
+    /// 最后修改时间(unix时间戳, 单位: 秒)
+    pub modified: u64,
+    /// 创建时间(unix时间戳, 单位: 秒), 0表示未知
+    pub created: u64,
+    pub sensitive: bool,
+    pub attachments: Vec<FileRef>,
+    pub fields: Vec<RecordField>,
+    pub comments: Vec<RecordComment>,
+}
+
+/// 记录的附件
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct FileRef {
+    pub id: String,
+    pub name: String,
+    /// 文件大小(单位: 字节)
+    pub size: usize,
+}
+
+/// 记录的评论
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct RecordComment {
+    /// 添加时间(unix时间戳, 单位: 秒)
+    pub time: u64,
+    pub author: String,
+    pub text: String,
+}
+
+/// 查看口令的回复
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct RevealedPass {
+    pub pass: String,
+    /// 受保护的自定义字段
+    pub fields: Vec<RecordField>,
+}
@@ -0,0 +1,121 @@
+//! 子路径部署, 反向代理将`/vault/...`转发给服务时, 先去掉路径前缀再进行路由,
+//! 回复中以`/`开头的跳转地址及cookie路径加上前缀
+use compact_str::CompactString;
+use http_body_util::Full;
+use hyper::{
+    header::{HeaderValue, LOCATION, SET_COOKIE},
+    http::uri::PathAndQuery,
+    StatusCode, Uri,
+};
+
+use crate::{Bytes, Response};
+
+/// 规范化路径前缀, 返回以`/`开头且不以`/`结尾的前缀, 根路径返回空字符串
+pub(crate) fn normalize(base: &str) -> CompactString {
+    let base = base.trim().trim_matches('/');
+    if base.is_empty() {
+        return CompactString::with_capacity(0);
+    }
+    let mut p = CompactString::with_capacity(base.len() + 1);
+    p.push('/');
+    p.push_str(base);
+    p
+}
+
+/// 去掉请求地址中的路径前缀
+///
+/// Returns:
+///
+/// Ok(uri): 去掉前缀后的地址, Err(res): 不在前缀下的请求直接回复(访问前缀本身时跳转到`前缀/`, 其它返回404)
+pub(crate) fn strip(base: &str, uri: &Uri) -> Result<Uri, Box<Response>> {
+    let path = uri.path();
+    let rest = match path.strip_prefix(base) {
+        Some("") => return Err(Box::new(redirect(base, uri.query()))),
+        Some(rest) if rest.starts_with('/') => rest,
+        _ => return Err(Box::new(not_found())),
+    };
+
+    let pq = match uri.query() {
+        Some(q) => format!("{rest}?{q}"),
+        None => rest.to_owned(),
+    };
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = match PathAndQuery::try_from(pq) {
+        Ok(pq) => Some(pq),
+        Err(_) => return Err(Box::new(not_found())),
+    };
+    Uri::from_parts(parts).map_err(|_| Box::new(not_found()))
+}
+
+/// 回复中以`/`开头的跳转地址及cookie路径加上前缀
+pub(crate) fn rewrite(base: &str, res: &mut Response) {
+    let headers = res.headers_mut();
+    if let Some(location) = headers.get(LOCATION).and_then(|v| v.to_str().ok()) {
+        if location.starts_with('/') && !location.starts_with("//") {
+            if let Ok(v) = HeaderValue::from_str(&format!("{base}{location}")) {
+                headers.insert(LOCATION, v);
+            }
+        }
+    }
+
+    let cookies: Vec<HeaderValue> = headers.get_all(SET_COOKIE).iter()
+        .map(|v| match v.to_str() {
+            Ok(s) => HeaderValue::from_str(&rewrite_cookie(base, s)).unwrap_or_else(|_| v.clone()),
+            Err(_) => v.clone(),
+        })
+        .collect();
+    if !cookies.is_empty() {
+        headers.remove(SET_COOKIE);
+        for cookie in cookies {
+            headers.append(SET_COOKIE, cookie);
+        }
+    }
+}
+
+/// cookie的`Path`属性加上前缀, 没有`Path`属性时限定在前缀下
+fn rewrite_cookie(base: &str, cookie: &str) -> String {
+    let mut out = String::with_capacity(cookie.len() + base.len() + 8);
+    let mut has_path = false;
+    for (i, attr) in cookie.split(';').enumerate() {
+        if i > 0 {
+            out.push(';');
+        }
+        let trimmed = attr.trim_start();
+        match trimmed.get(..5) {
+            Some(name) if i > 0 && name.eq_ignore_ascii_case("path=") => {
+                has_path = true;
+                let path = &trimmed[5..];
+                out.push_str(" Path=");
+                out.push_str(base);
+                if path != "/" {
+                    out.push_str(path);
+                }
+            }
+            _ => out.push_str(attr),
+        }
+    }
+    if !has_path {
+        out.push_str("; Path=");
+        out.push_str(base);
+    }
+    out
+}
+
+fn redirect(base: &str, query: Option<&str>) -> Response {
+    let location = match query {
+        Some(q) => format!("{base}/?{q}"),
+        None => format!("{base}/"),
+    };
+    let mut res = Response::new(Full::new(Bytes::new()));
+    *res.status_mut() = StatusCode::MOVED_PERMANENTLY;
+    if let Ok(v) = HeaderValue::from_str(&location) {
+        res.headers_mut().insert(LOCATION, v);
+    }
+    res
+}
+
+fn not_found() -> Response {
+    let mut res = Response::new(Full::new(Bytes::from_static(b"Not Found")));
+    *res.status_mut() = StatusCode::NOT_FOUND;
+    res
+}
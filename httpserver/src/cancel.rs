@@ -108,3 +108,33 @@ impl Clone for CancelReceiver {
         Self::new(self.receiver.clone(), self.count.clone())
     }
 }
+
+/// 等待进程退出信号(ctrl-c, unix下还包括SIGTERM)
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            log::error!("listen ctrl-c signal error: {e:?}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut s) => { s.recv().await; }
+            Err(e) => {
+                log::error!("listen SIGTERM signal error: {e:?}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
@@ -0,0 +1,126 @@
+//! 可组合的缺省处理链, 未匹配到接口的请求依次交给链中的处理器, 全部未处理时返回404
+use http_body_util::Full;
+use hyper::header::ACCEPT;
+
+use crate::{HttpContext, HttpHandler, HttpResponse, Resp, CONTENT_TYPE};
+
+/// 处理链中单个处理器的处理结果
+pub enum ChainResult {
+    /// 已处理, 返回该结果
+    Done(HttpResponse),
+    /// 未处理, 交给链中的下一个处理器
+    Pass(Box<HttpContext>),
+}
+
+impl ChainResult {
+    /// 未处理, 交给链中的下一个处理器
+    pub fn pass(ctx: HttpContext) -> Self {
+        ChainResult::Pass(Box::new(ctx))
+    }
+}
+
+/// 处理链中的处理器
+#[async_trait::async_trait]
+pub trait ChainHandler: Send + Sync + 'static {
+    async fn handle(&self, ctx: HttpContext) -> ChainResult;
+}
+
+/// 404回复的格式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NotFoundFormat {
+    /// 根据Accept请求头选择, 浏览器请求页面时返回html, 否则返回json
+    #[default]
+    Auto,
+    Json,
+    Html,
+}
+
+/// 缺省处理链, 作为`HttpServer::set_default_handler`的参数使用
+///
+///  ## Example
+/// ```rust
+/// use httpserver::{HandlerChain, NotFoundFormat};
+///
+/// let chain = HandlerChain::new().not_found(NotFoundFormat::Json);
+/// ```
+#[derive(Default)]
+pub struct HandlerChain {
+    handlers: Vec<Box<dyn ChainHandler>>,
+    not_found: NotFoundFormat,
+}
+
+const NOT_FOUND_HTML: &str = "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>404 Not Found</title></head>\
+    <body><h1>404 Not Found</h1></body></html>";
+
+impl NotFoundFormat {
+    /// 解析配置值: auto/json/html
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "auto" => Some(NotFoundFormat::Auto),
+            "json" => Some(NotFoundFormat::Json),
+            "html" => Some(NotFoundFormat::Html),
+            _ => None,
+        }
+    }
+}
+
+impl HandlerChain {
+    /// 创建空的处理链, 所有请求都返回404
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 在链的末尾追加处理器
+    pub fn then<T: ChainHandler>(mut self, handler: T) -> Self {
+        self.handlers.push(Box::new(handler));
+        self
+    }
+
+    /// 设置404回复的格式
+    pub fn not_found(mut self, format: NotFoundFormat) -> Self {
+        self.not_found = format;
+        self
+    }
+
+    /// 链中处理器的数量
+    pub fn len(&self) -> usize {
+        self.handlers.len()
+    }
+
+    /// 链中是否没有处理器
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+
+    fn resp_not_found(&self, ctx: &HttpContext) -> HttpResponse {
+        let html = match self.not_found {
+            NotFoundFormat::Json => false,
+            NotFoundFormat::Html => true,
+            NotFoundFormat::Auto => ctx.req.headers().get(ACCEPT)
+                .map(|v| v.as_bytes().windows(9).any(|w| w == b"text/html"))
+                .unwrap_or(false),
+        };
+
+        if html {
+            Ok(hyper::Response::builder()
+                .status(hyper::StatusCode::NOT_FOUND)
+                .header(CONTENT_TYPE, "text/html; charset=utf-8")
+                .body(Full::from(NOT_FOUND_HTML))?)
+        } else {
+            Resp::fail_with_status(hyper::StatusCode::NOT_FOUND, 404, "Not Found")
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpHandler for HandlerChain {
+    async fn handle(&self, mut ctx: HttpContext) -> HttpResponse {
+        for handler in self.handlers.iter() {
+            ctx = match handler.handle(ctx).await {
+                ChainResult::Done(res) => return res,
+                ChainResult::Pass(ctx) => *ctx,
+            };
+        }
+        self.resp_not_found(&ctx)
+    }
+}
@@ -0,0 +1,102 @@
+//! 终端颜色输出
+use std::{fmt::Display, io::IsTerminal, sync::atomic::{AtomicBool, Ordering}};
+
+/// 是否输出ansi颜色
+static COLOR_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// ansi前景色
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Color {
+    Red = 31,
+    Green = 32,
+    Yellow = 33,
+    Blue = 34,
+}
+
+/// 带颜色输出的值, 禁用颜色时原样输出
+pub struct Colored<T> {
+    color: Color,
+    val: T,
+}
+
+/// 初始化终端颜色输出
+///
+/// `enable`为false、设置了`NO_COLOR`环境变量、`TERM=dumb`或者标准输出不是终端时禁用颜色,
+/// windows下自动开启控制台的虚拟终端支持, 开启失败时禁用颜色
+///
+/// Returns:
+///
+/// 是否启用颜色输出
+pub fn init_color(enable: bool) -> bool {
+    let enable = enable
+        && std::env::var_os("NO_COLOR").map(|v| v.is_empty()).unwrap_or(true)
+        && std::env::var("TERM").map(|v| v != "dumb").unwrap_or(true)
+        && std::io::stdout().is_terminal()
+        && enable_virtual_terminal();
+    set_color(enable);
+    enable
+}
+
+/// 设置是否输出ansi颜色
+pub fn set_color(enable: bool) {
+    COLOR_ENABLED.store(enable, Ordering::Release);
+}
+
+/// 是否输出ansi颜色
+pub fn is_color() -> bool {
+    COLOR_ENABLED.load(Ordering::Acquire)
+}
+
+/// 使用指定颜色输出
+///
+///  ## Example
+/// ```rust
+/// use httpserver::{colored, Color};
+///
+/// log::info!("listen on {}", colored("0.0.0.0:8080", Color::Blue));
+/// ```
+pub fn colored<T: Display>(val: T, color: Color) -> Colored<T> {
+    Colored { color, val }
+}
+
+impl<T: Display> Display for Colored<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if is_color() {
+            write!(f, "\x1b[{}m{}\x1b[0m", self.color as u8, self.val)
+        } else {
+            Display::fmt(&self.val, f)
+        }
+    }
+}
+
+#[cfg(not(windows))]
+fn enable_virtual_terminal() -> bool {
+    true
+}
+
+/// 开启windows控制台的虚拟终端支持(旧版本的windows不支持, 返回false)
+#[cfg(windows)]
+fn enable_virtual_terminal() -> bool {
+    type Handle = *mut std::ffi::c_void;
+    const STD_OUTPUT_HANDLE: u32 = -11_i32 as u32;
+    const STD_ERROR_HANDLE: u32 = -12_i32 as u32;
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetStdHandle(std_handle: u32) -> Handle;
+        fn GetConsoleMode(console: Handle, mode: *mut u32) -> i32;
+        fn SetConsoleMode(console: Handle, mode: u32) -> i32;
+    }
+
+    [STD_OUTPUT_HANDLE, STD_ERROR_HANDLE].iter().all(|&h| unsafe {
+        let handle = GetStdHandle(h);
+        let mut mode = 0;
+        if handle.is_null() || GetConsoleMode(handle, &mut mode) == 0 {
+            return false;
+        }
+        mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0
+            || SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0
+    })
+}
@@ -0,0 +1,167 @@
+//! 响应内容压缩中间件, 根据Accept-Encoding选择br/gzip/deflate压缩算法
+use std::io::Write;
+
+use anyhow::Result;
+use http_body_util::{BodyExt, Full};
+use hyper::{
+    body::Bytes,
+    header::{HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, VARY},
+};
+
+use crate::{HttpContext, HttpMiddleware, HttpResponse, Next, Response};
+
+/// 缺省的最小压缩长度, 内容太短时压缩的收益不足以抵消开销
+const DEFAULT_MIN_SIZE: usize = 1024;
+/// brotli压缩等级(0-11), 动态内容使用中等等级兼顾速度
+const BROTLI_QUALITY: u32 = 5;
+/// brotli窗口大小(以2为底的对数)
+const BROTLI_LGWIN: u32 = 22;
+
+/// 已经是压缩格式的内容类型, 再次压缩没有意义
+const COMPRESSED_TYPES: [&str; 10] = [
+    "image/", "video/", "audio/", "font/woff", "application/zip", "application/gzip",
+    "application/x-gzip", "application/x-7z", "application/x-rar", "application/pdf",
+];
+
+/// 压缩算法
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+    Deflate,
+}
+
+/// 响应内容压缩中间件
+///
+/// 只压缩长度不小于`min_size`的响应, 跳过已设置Content-Encoding及已经是压缩格式的内容,
+/// 压缩后长度没有减少时返回原内容
+pub struct Compression {
+    min_size: usize,
+}
+
+impl Encoding {
+    fn name(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    /// 根据Accept-Encoding请求头选择压缩算法, q值相同时按br、gzip、deflate的顺序优先
+    pub fn negotiate(accept: &str) -> Option<Encoding> {
+        let mut best: Option<(Encoding, f32)> = None;
+        for item in accept.split(',') {
+            let mut parts = item.split(';');
+            let name = parts.next().unwrap_or("").trim();
+            let q = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .and_then(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let enc = match name.to_ascii_lowercase().as_str() {
+                "br" => Encoding::Brotli,
+                "gzip" | "x-gzip" => Encoding::Gzip,
+                "deflate" => Encoding::Deflate,
+                _ => continue,
+            };
+            if q <= 0.0 {
+                continue;
+            }
+            match best {
+                Some((b, bq)) if bq > q || (bq == q && (b as u8) < (enc as u8)) => {}
+                _ => best = Some((enc, q)),
+            }
+        }
+        best.map(|(enc, _)| enc)
+    }
+
+    /// 压缩数据
+    pub fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let out = Vec::with_capacity(data.len() / 2);
+        Ok(match self {
+            Encoding::Brotli => {
+                let mut w = brotli::CompressorWriter::new(out, 4096, BROTLI_QUALITY, BROTLI_LGWIN);
+                w.write_all(data)?;
+                w.into_inner()
+            }
+            Encoding::Gzip => {
+                let mut w = flate2::write::GzEncoder::new(out, flate2::Compression::default());
+                w.write_all(data)?;
+                w.finish()?
+            }
+            Encoding::Deflate => {
+                let mut w = flate2::write::ZlibEncoder::new(out, flate2::Compression::default());
+                w.write_all(data)?;
+                w.finish()?
+            }
+        })
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::new(DEFAULT_MIN_SIZE)
+    }
+}
+
+impl Compression {
+    /// 创建中间件
+    ///
+    /// Arguments:
+    ///
+    /// * `min_size`: 最小压缩长度(单位: 字节)
+    pub fn new(min_size: usize) -> Self {
+        Compression { min_size }
+    }
+
+    /// 响应是否需要压缩
+    fn should_compress(&self, res: &Response) -> bool {
+        let status = res.status();
+        if status == hyper::StatusCode::NO_CONTENT || status == hyper::StatusCode::NOT_MODIFIED
+                || status == hyper::StatusCode::PARTIAL_CONTENT
+                || status == hyper::StatusCode::SWITCHING_PROTOCOLS {
+            return false;
+        }
+        // 事件流回复的数据在处理完成后才开始发送, 无法整体压缩
+        if crate::Sse::is_stream(res) {
+            return false;
+        }
+        let headers = res.headers();
+        if headers.contains_key(CONTENT_ENCODING) {
+            return false;
+        }
+        match headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok()) {
+            Some(ct) => !COMPRESSED_TYPES.iter().any(|t| ct.starts_with(t)) || ct.starts_with("image/svg"),
+            None => true,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpMiddleware for Compression {
+    async fn handle<'a>(&'a self, ctx: HttpContext, next: Next<'a>) -> HttpResponse {
+        let encoding = ctx.header_str(ACCEPT_ENCODING).and_then(Encoding::negotiate);
+
+        let res = next.run(ctx).await?;
+        let encoding = match encoding {
+            Some(enc) if self.should_compress(&res) => enc,
+            _ => return Ok(res),
+        };
+
+        let (mut parts, body) = res.into_parts();
+        let body: Bytes = body.collect().await?.to_bytes();
+        if body.len() < self.min_size {
+            return Ok(Response::from_parts(parts, Full::new(body)));
+        }
+
+        let data = encoding.encode(&body)?;
+        if data.len() >= body.len() {
+            return Ok(Response::from_parts(parts, Full::new(body)));
+        }
+
+        parts.headers.remove(CONTENT_LENGTH);
+        parts.headers.insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.name()));
+        parts.headers.append(VARY, HeaderValue::from_static("Accept-Encoding"));
+        Ok(Response::from_parts(parts, Full::from(data)))
+    }
+}
@@ -0,0 +1,161 @@
+//! 类型化的接口函数, 接口参数由提取器从请求上下文中获取, 返回值自动包装为200回复
+//!
+//! ```rust,ignore
+//! use httpserver::{ApiReply, Json, RemoteIp};
+//!
+//! async fn login(Json(req): Json<ReqParam>, RemoteIp(ip): RemoteIp) -> ApiReply<ResData> {
+//!     ...
+//! }
+//!
+//! register_apis!(srv, "/api",
+//!     POST "/login": httpserver::typed(login),
+//! );
+//! ```
+use std::{future::Future, marker::PhantomData, net::Ipv4Addr};
+
+use anyhow::{Error, Result};
+use compact_str::CompactString;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{log_debug, HttpContext, HttpError, HttpHandler, HttpResponse, PathParams, Resp};
+
+/// 请求参数错误时回复的错误码
+const BAD_REQUEST: u32 = 400;
+
+/// 类型化接口函数的返回值, Ok时将返回值序列化为`data`回复200, Err时与普通接口函数的错误处理相同
+pub type ApiReply<T> = Result<T>;
+
+/// 从请求上下文中提取接口参数
+///
+/// 提取失败时返回错误码为400的`HttpError`回复http状态400, 其它错误按普通接口函数的错误处理
+pub trait FromContext: Sized {
+    fn from_context(ctx: &HttpContext) -> Result<Self>;
+}
+
+/// json格式的请求体, 请求体为空时回复400, 允许为空时使用`Option<Json<T>>`
+pub struct Json<T>(pub T);
+
+/// 客户端地址, 与`HttpContext::remote_ip`相同
+pub struct RemoteIp(pub Ipv4Addr);
+
+/// 路由捕获的路径参数
+pub struct Params(pub PathParams);
+
+/// 登录校验中间件设置的用户ID
+pub struct Uid(pub CompactString);
+
+/// 通过`HttpServer::set_state`注册的全局状态
+pub struct State<T>(pub T);
+
+/// 类型化接口函数的包装, 通过`typed`创建, `A`为接口函数的参数类型
+pub struct Typed<F, A> {
+    f: F,
+    _args: PhantomData<fn() -> A>,
+}
+
+/// 将参数实现了`FromContext`、返回值为`ApiReply<T>`的异步函数包装为接口函数
+///
+/// Arguments:
+///
+/// * `f`: 异步函数, 最多支持5个参数
+pub fn typed<F, A>(f: F) -> Typed<F, A>
+where
+    Typed<F, A>: HttpHandler,
+{
+    Typed { f, _args: PhantomData }
+}
+
+/// 将错误转换为错误码为400的`HttpError`, 保留原有的错误信息
+pub fn bad_request(e: Error) -> Error {
+    match e.downcast::<HttpError>() {
+        Ok(mut e) => {
+            e.code = BAD_REQUEST;
+            Error::new(e)
+        }
+        Err(e) => HttpError::create_with_code(BAD_REQUEST, e.to_string()),
+    }
+}
+
+/// 提取参数失败时的回复, 参数错误回复http状态400
+fn reject(id: u32, e: Error) -> HttpResponse {
+    match e.downcast_ref::<HttpError>() {
+        Some(he) if he.code == BAD_REQUEST => {
+            #[cfg(not(feature = "english"))]
+            log_debug!(id, "请求参数错误: {e:?}");
+            #[cfg(feature = "english")]
+            log_debug!(id, "bad request: {e:?}");
+            Resp::fail_with_status(hyper::StatusCode::BAD_REQUEST, BAD_REQUEST, &he.message)
+        }
+        _ => Err(e),
+    }
+}
+
+impl<T: DeserializeOwned> FromContext for Json<T> {
+    fn from_context(ctx: &HttpContext) -> Result<Self> {
+        ctx.parse_json().map(Json).map_err(bad_request)
+    }
+}
+
+impl<T: DeserializeOwned> FromContext for Option<Json<T>> {
+    fn from_context(ctx: &HttpContext) -> Result<Self> {
+        ctx.parse_json_opt().map(|v| v.map(Json)).map_err(bad_request)
+    }
+}
+
+impl FromContext for RemoteIp {
+    fn from_context(ctx: &HttpContext) -> Result<Self> {
+        Ok(RemoteIp(ctx.remote_ip()))
+    }
+}
+
+impl FromContext for Params {
+    fn from_context(ctx: &HttpContext) -> Result<Self> {
+        Ok(Params(ctx.params.clone()))
+    }
+}
+
+impl FromContext for Uid {
+    fn from_context(ctx: &HttpContext) -> Result<Self> {
+        Ok(Uid(ctx.uid.clone()))
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> FromContext for State<T> {
+    fn from_context(ctx: &HttpContext) -> Result<Self> {
+        match ctx.state::<T>() {
+            Some(state) => Ok(State(state.clone())),
+            None => Err(anyhow::anyhow!("state {} not registered", std::any::type_name::<T>())),
+        }
+    }
+}
+
+macro_rules! impl_typed {
+    ($($arg:ident $var:ident),*) => {
+        #[async_trait::async_trait]
+        impl<F, Fut, T, $($arg,)*> HttpHandler for Typed<F, ($($arg,)*)>
+        where
+            F: Fn($($arg),*) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output = ApiReply<T>> + Send + 'static,
+            T: Serialize + Send,
+            $($arg: FromContext + Send + 'static,)*
+        {
+            async fn handle(&self, ctx: HttpContext) -> HttpResponse {
+                $(
+                    let $var = match <$arg as FromContext>::from_context(&ctx) {
+                        Ok(v) => v,
+                        Err(e) => return reject(ctx.id, e),
+                    };
+                )*
+                drop(ctx);
+                Resp::ok(&(self.f)($($var),*).await?)
+            }
+        }
+    };
+}
+
+impl_typed!();
+impl_typed!(A1 a1);
+impl_typed!(A1 a1, A2 a2);
+impl_typed!(A1 a1, A2 a2, A3 a3);
+impl_typed!(A1 a1, A2 a2, A3 a3, A4 a4);
+impl_typed!(A1 a1, A2 a2, A3 a3, A4 a4, A5 a5);
@@ -0,0 +1,82 @@
+//! 响应字段选择(sparse fieldsets), 客户端通过url参数`fields=title,url,id`只获取需要的字段,
+//! 适用于带宽受限的客户端, 例如通过ssh隧道访问的命令行工具
+use compact_str::CompactString;
+use serde::{ser::Error, Serialize, Serializer};
+use serde_json::Value;
+
+use crate::HttpContext;
+
+/// url参数名
+pub const FIELDS_PARAM: &str = "fields";
+
+/// 需要返回的字段名称列表
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Fields {
+    names: Vec<CompactString>,
+}
+
+/// 按字段选择序列化数据的包装器, 对象只保留选择的字段, 数组对每个元素分别处理
+///
+/// 未指定字段选择时按原样序列化, 没有额外开销
+pub struct Sparse<'a, T: ?Sized> {
+    data: &'a T,
+    fields: Option<&'a Fields>,
+}
+
+impl Fields {
+    /// 解析逗号分隔的字段名称, 忽略空白及空的名称
+    pub fn parse(text: &str) -> Self {
+        let mut names: Vec<CompactString> = text.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(CompactString::from)
+            .collect();
+        names.dedup();
+        Fields { names }
+    }
+
+    /// 从请求的url参数`fields`中读取字段选择, 未指定或者为空时返回None
+    pub fn from_ctx(ctx: &HttpContext) -> Option<Self> {
+        let fields = Self::parse(&ctx.get_url_param_str(FIELDS_PARAM)?);
+        if fields.names.is_empty() { None } else { Some(fields) }
+    }
+
+    /// 是否选择了字段`name`
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.iter().any(|n| *n == name)
+    }
+
+    /// 按字段选择过滤json值
+    fn filter(&self, value: &mut Value) {
+        match value {
+            Value::Object(map) => map.retain(|k, _| self.contains(k)),
+            Value::Array(items) => items.iter_mut().for_each(|v| self.filter(v)),
+            _ => {}
+        }
+    }
+}
+
+impl<'a, T: ?Sized + Serialize> Sparse<'a, T> {
+    /// 创建包装器
+    ///
+    /// Arguments:
+    ///
+    /// * `data`: 需要序列化的数据
+    /// * `fields`: 字段选择, None表示返回全部字段
+    pub fn new(data: &'a T, fields: Option<&'a Fields>) -> Self {
+        Sparse { data, fields }
+    }
+}
+
+impl<T: ?Sized + Serialize> Serialize for Sparse<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let fields = match self.fields {
+            Some(fields) => fields,
+            None => return self.data.serialize(serializer),
+        };
+        let mut value = serde_json::to_value(self.data).map_err(S::Error::custom)?;
+        fields.filter(&mut value);
+        value.serialize(serializer)
+    }
+}
+
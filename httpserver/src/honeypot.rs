@@ -0,0 +1,76 @@
+//! 蜜罐中间件, 访问诱饵路径(例如`/wp-login.php`、`/.env`)的客户端被加入临时拒绝名单
+use std::{net::IpAddr, time::Duration};
+
+use compact_str::CompactString;
+
+use crate::{Blocklist, HttpContext, HttpMiddleware, HttpResponse, Next, Resp};
+
+/// 蜜罐中间件
+///
+/// 正常用户不会访问诱饵路径, 访问者基本都是自动扫描工具, 命中后在拒绝时长内
+/// 该地址的新连接在接受时直接丢弃, 已建立连接上的后续请求返回403
+pub struct Honeypot {
+    /// 诱饵路径(小写), 以`*`结尾时按前缀匹配
+    paths: Vec<CompactString>,
+    /// 拒绝时长
+    duration: Duration,
+    /// 拒绝名单, 与http服务共享
+    blocklist: Blocklist,
+}
+
+impl Honeypot {
+    /// 创建中间件
+    ///
+    /// Arguments:
+    ///
+    /// * `paths`: 诱饵路径, 不区分大小写, 以`*`结尾时按前缀匹配, 例如`/.git/*`
+    /// * `duration`: 命中后的拒绝时长
+    /// * `blocklist`: 拒绝名单, 需同时通过`HttpServer::set_blocklist`设置给http服务
+    pub fn new<S: AsRef<str>>(paths: &[S], duration: Duration, blocklist: Blocklist) -> Self {
+        Honeypot {
+            paths: paths.iter()
+                .map(|p| CompactString::new(p.as_ref().trim().to_ascii_lowercase()))
+                .filter(|p| !p.is_empty())
+                .collect(),
+            duration,
+            blocklist,
+        }
+    }
+
+    /// 请求路径是否为诱饵路径
+    pub fn is_decoy(&self, path: &str) -> bool {
+        let path = path.to_ascii_lowercase();
+        self.paths.iter().any(|p| match p.strip_suffix('*') {
+            Some(prefix) => path.starts_with(prefix),
+            None => *p == path,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpMiddleware for Honeypot {
+    async fn handle<'a>(&'a self, ctx: HttpContext, next: Next<'a>) -> HttpResponse {
+        const FORBIDDEN: hyper::StatusCode = hyper::StatusCode::FORBIDDEN;
+        const NOT_FOUND: hyper::StatusCode = hyper::StatusCode::NOT_FOUND;
+
+        // 经由反向代理访问时, 接受连接时无法得知真实地址, 需要在请求中再次检查
+        let ip = IpAddr::V4(ctx.remote_ip());
+        if self.blocklist.is_blocked(ip) {
+            return Resp::fail_with_status(FORBIDDEN, FORBIDDEN.as_u16() as u32, FORBIDDEN.as_str());
+        }
+
+        let path = ctx.req.uri().path();
+        if !self.is_decoy(path) {
+            return next.run(ctx).await;
+        }
+
+        self.blocklist.block(ip, self.duration);
+        #[cfg(not(feature = "english"))]
+        log::warn!("蜜罐路径 {path} 被 {ip} 访问, 拒绝该地址 {} 秒", self.duration.as_secs());
+        #[cfg(feature = "english")]
+        log::warn!("honeypot {path} hit by {ip}, blocked for {} seconds", self.duration.as_secs());
+
+        // 与不存在的路径返回相同的结果, 不暴露蜜罐
+        Resp::fail_with_status(NOT_FOUND, NOT_FOUND.as_u16() as u32, NOT_FOUND.as_str())
+    }
+}
@@ -0,0 +1,91 @@
+//! HSTS响应头中间件及http到https的跳转处理
+use hyper::header::{HeaderValue, HOST, LOCATION, STRICT_TRANSPORT_SECURITY};
+use http_body_util::Full;
+
+use crate::{HttpContext, HttpHandler, HttpMiddleware, HttpResponse, Next};
+
+/// 加入浏览器预加载列表要求的最小有效期(单位: 秒)
+const PRELOAD_MIN_AGE: u64 = 31536000;
+
+/// HSTS中间件, 只应在https服务上使用
+pub struct Hsts {
+    value: HeaderValue,
+}
+
+/// http到https的跳转处理函数, 用作http服务的缺省处理函数
+#[derive(Clone)]
+pub struct HttpsRedirect {
+    /// https端口, 443时跳转地址中省略端口
+    port: u16,
+}
+
+impl Hsts {
+    /// 创建中间件
+    ///
+    /// Arguments:
+    ///
+    /// * `max_age`: 有效期(单位: 秒)
+    /// * `preload`: 是否申请加入浏览器预加载列表, 同时包含所有子域名, 有效期不能少于1年
+    pub fn new(max_age: u64, preload: bool) -> Self {
+        let value = if preload {
+            format!("max-age={}; includeSubDomains; preload", max_age.max(PRELOAD_MIN_AGE))
+        } else {
+            format!("max-age={max_age}")
+        };
+        Hsts { value: HeaderValue::from_str(&value).unwrap() }
+    }
+}
+
+impl HttpsRedirect {
+    /// 创建跳转处理函数
+    ///
+    /// Arguments:
+    ///
+    /// * `port`: https服务端口
+    pub fn new(port: u16) -> Self {
+        HttpsRedirect { port }
+    }
+
+    /// 生成请求对应的https地址, 请求中没有Host头时返回None
+    pub fn location(&self, ctx: &HttpContext) -> Option<String> {
+        let host = ctx.header_str(HOST)?;
+        // 去掉http端口, ipv6地址形如[::1]:80
+        let host = match host.rfind(':') {
+            Some(pos) if !host[pos..].contains(']') => &host[..pos],
+            _ => host,
+        };
+        if host.is_empty() {
+            return None;
+        }
+        let path = ctx.req.uri().path_and_query().map(|p| p.as_str()).unwrap_or("/");
+        Some(if self.port == 443 {
+            format!("https://{host}{path}")
+        } else {
+            format!("https://{host}:{}{path}", self.port)
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpMiddleware for Hsts {
+    async fn handle<'a>(&'a self, ctx: HttpContext, next: Next<'a>) -> HttpResponse {
+        let mut res = next.run(ctx).await?;
+        res.headers_mut().insert(STRICT_TRANSPORT_SECURITY, self.value.clone());
+        Ok(res)
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpHandler for HttpsRedirect {
+    async fn handle(&self, ctx: HttpContext) -> HttpResponse {
+        match self.location(&ctx) {
+            Some(location) => Ok(
+                hyper::Response::builder()
+                    .status(hyper::StatusCode::MOVED_PERMANENTLY)
+                    .header(LOCATION, location)
+                    .body(Full::default())?
+            ),
+            None => crate::Resp::fail_with_status(hyper::StatusCode::BAD_REQUEST, 400, "Missing Host header"),
+        }
+    }
+}
@@ -1,13 +1,15 @@
-use std::{borrow::Cow, collections::HashMap, net::{Ipv4Addr, SocketAddr}, str::FromStr};
+use std::{borrow::Cow, collections::HashMap, net::{IpAddr, Ipv4Addr, SocketAddr}, str::FromStr};
 
 use anyhow::Result;
 use compact_str::CompactString;
 use fnv::FnvHashMap;
-use hyper::{body::Bytes, header::{AsHeaderName, HeaderValue}};
-use serde::de::DeserializeOwned;
+use http_body_util::Full;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hyper::{body::Bytes, header::{AsHeaderName, HeaderValue, AUTHORIZATION, CONTENT_LENGTH}, http::request::Builder};
+use serde::{de::DeserializeOwned, Serialize};
 use serde_json::Value;
 
-use crate::{http_bail, log_error, HttpCtxAttrs, HttpError, Request, CONTENT_TYPE};
+use crate::{http_bail, log_error, HttpCtxAttrs, HttpError, PathParams, Request, CONTENT_TYPE};
 
 
 /// api function param
@@ -18,17 +20,77 @@ pub struct HttpContext {
     pub body: Bytes,
     /// match path length
     pub path_len: u32,
+    /// path parameters captured by the router, e.g. `record/:id`, `files/*path`
+    pub params: PathParams,
     /// http request client ip address
     pub addr: SocketAddr,
     /// http request ID (each request ID is unique)
     pub id: u32,
+    /// globally unique request ID set by the `RequestId` middleware, also returned to the client
+    pub request_id: Option<CompactString>,
     /// current login user ID (parsed from token, not logged in is empty)
     pub uid: CompactString,
     /// additional attributes (user-defined)
     pub attrs: HttpCtxAttrs,
 }
 
+/// 经可信代理解析后的客户端地址, 由服务端在处理请求前写入请求的extensions
+#[derive(Clone, Copy)]
+pub(crate) struct ClientIp(pub IpAddr);
+
+/// HttpContext builder, used to construct the context for api unit tests without binding a socket
+pub struct HttpContextBuilder {
+    builder: Builder,
+    body: Bytes,
+    path_len: u32,
+    params: PathParams,
+    addr: SocketAddr,
+    id: u32,
+    uid: CompactString,
+}
+
 impl HttpContext {
+    /// create a HttpContext builder for unit testing api functions
+    ///
+    ///  ## Example
+    /// ```rust
+    /// use httpserver::HttpContext;
+    ///
+    /// let ctx = HttpContext::test_builder()
+    ///     .method("POST")
+    ///     .path("/api/ping")
+    ///     .json(&serde_json::json!({"reply": "hello"}))
+    ///     .build();
+    /// ```
+    pub fn test_builder() -> HttpContextBuilder {
+        HttpContextBuilder {
+            builder: Builder::new().method("POST").uri("/"),
+            body: Bytes::new(),
+            path_len: 0,
+            params: PathParams::default(),
+            addr: SocketAddr::from(([127, 0, 0, 1], 0)),
+            id: 1,
+            uid: CompactString::with_capacity(0),
+        }
+    }
+
+    /// get the shared state registered by `HttpServer::set_state`
+    ///
+    ///  ## Example
+    /// ```rust
+    /// use httpserver::{HttpContext, HttpResponse, Resp};
+    ///
+    /// struct AppState { name: String }
+    ///
+    /// async fn ping(ctx: HttpContext) -> HttpResponse {
+    ///     let state = ctx.state::<std::sync::Arc<AppState>>().unwrap();
+    ///     Resp::ok(&state.name)
+    /// }
+    /// ```
+    pub fn state<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.req.extensions().get::<T>()
+    }
+
     /// check request content type is application/json
     pub fn is_json(&self) -> bool {
         if let Some(s) = self.req.headers().get(CONTENT_TYPE) {
@@ -222,35 +284,106 @@ impl HttpContext {
         }
     }
 
-    /// 获取客户端的真实ip, 获取优先级为X-Real-IP > X-Forwarded-For > socketaddr
+    /// 获取客户端的真实ip, 对端为可信代理时使用X-Forwarded-For/X-Real-IP中的地址, 否则为socketaddr
+    ///
+    /// 可信代理通过`HttpServer::set_trusted_proxies`设置, 不是ipv4地址时返回0.0.0.0
     pub fn remote_ip(&self) -> Ipv4Addr {
-        if let Some(ip) = self.req.headers().get("X-Real-IP") {
-            if let Ok(ip) = ip.to_str() {
-                if let Ok(ip) = ip.parse() {
-                    return ip;
-                }
-            }
+        let ip = match self.req.extensions().get::<ClientIp>() {
+            Some(ip) => ip.0,
+            None => crate::ipfilter::normalize(self.addr.ip()),
+        };
+        match ip {
+            IpAddr::V4(ip) => ip,
+            _ => Ipv4Addr::new(0, 0, 0, 0),
         }
+    }
 
-        if let Some(ip) = self.req.headers().get("X-Forwarded-For") {
-            if let Ok(ip) = ip.to_str() {
-                if let Some(ip) = ip.split(',').next() {
-                    if let Ok(ip) = ip.parse() {
-                        return ip;
-                    }
-                }
+    /// 获取http头部
+    pub fn header_value<K: AsHeaderName>(&self, key: K) -> Option<&HeaderValue> {
+        self.req.headers().get(key)
+    }
+
+    /// 获取http头部
+    #[deprecated(note = "renamed to header_value")]
+    pub fn header<K: AsHeaderName>(&self, key: K) -> Option<&HeaderValue> {
+        self.header_value(key)
+    }
+
+    /// 获取http头部的字符串值, 不存在或者不是合法的可见字符时返回None
+    pub fn header_str<K: AsHeaderName>(&self, key: K) -> Option<&str> {
+        self.req.headers().get(key).and_then(|v| v.to_str().ok())
+    }
+
+    /// 获取http头部并转换为指定类型, 不存在时返回None, 格式错误时返回错误
+    ///
+    /// # Examples
+    /// ```
+    /// use httpserver::HttpContext;
+    ///
+    /// fn handle(ctx: HttpContext) -> anyhow::Result<()> {
+    ///     let len: Option<u64> = ctx.parse_header("X-Upload-Length")?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn parse_header<T: FromStr, K: AsRef<str>>(&self, key: K) -> Result<Option<T>> {
+        let val = match self.req.headers().get(key.as_ref()) {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        match val.to_str().ok().and_then(|v| v.trim().parse().ok()) {
+            Some(v) => Ok(Some(v)),
+            None => {
+                #[cfg(not(feature = "english"))]
+                http_bail!("请求头 {} 格式错误", key.as_ref());
+                #[cfg(feature = "english")]
+                http_bail!("header {} format error", key.as_ref());
             }
         }
+    }
 
-        match self.addr.ip() {
-            std::net::IpAddr::V4(ip) => ip,
-            _ => std::net::Ipv4Addr::new(0, 0, 0, 0),
-        }
+    /// 获取Authorization头中指定认证方式的凭据, 认证方式不区分大小写
+    ///
+    /// Arguments:
+    ///
+    /// * `scheme`: 认证方式, 例如`Bearer`、`Basic`
+    pub fn auth_token(&self, scheme: &str) -> Option<&str> {
+        self.header_token(AUTHORIZATION, scheme)
     }
 
-    /// 获取http头部
-    pub fn header<K: AsHeaderName>(&self, key: K) -> Option<&HeaderValue> {
-        self.req.headers().get(key)
+    /// 获取指定请求头中的令牌, 格式为`认证方式 令牌`, `scheme`为空时整个请求头的值即令牌
+    pub fn header_token<K: AsHeaderName>(&self, key: K, scheme: &str) -> Option<&str> {
+        let value = self.header_str(key)?.trim();
+        let token = if scheme.is_empty() {
+            value
+        } else {
+            let (s, token) = value.split_once(' ')?;
+            if !s.eq_ignore_ascii_case(scheme) {
+                return None;
+            }
+            token.trim()
+        };
+        (!token.is_empty()).then_some(token)
+    }
+
+    /// 获取Authorization头中的Bearer令牌
+    pub fn bearer_token(&self) -> Option<&str> {
+        self.auth_token("Bearer")
+    }
+
+    /// 获取Authorization头中的Basic认证用户名及密码
+    pub fn basic_auth(&self) -> Option<(CompactString, CompactString)> {
+        let data = STANDARD.decode(self.auth_token("Basic")?).ok()?;
+        let text = std::str::from_utf8(&data).ok()?;
+        let (user, pass) = text.split_once(':')?;
+        Some((CompactString::new(user), CompactString::new(pass)))
+    }
+
+    /// 请求内容长度, 优先使用Content-Length头, 格式错误或者不存在时使用实际读取的长度
+    pub fn content_length(&self) -> u64 {
+        match self.parse_header(CONTENT_LENGTH) {
+            Ok(Some(n)) => n,
+            _ => self.body.len() as u64,
+        }
     }
 
     /// 获取自定义参数
@@ -320,3 +453,97 @@ impl HttpContext {
     }
 
 }
+
+impl HttpContextBuilder {
+    /// set http request method
+    pub fn method(mut self, method: &str) -> Self {
+        self.builder = self.builder.method(method);
+        self
+    }
+
+    /// set http request path (can include query string)
+    pub fn path(mut self, path: &str) -> Self {
+        self.builder = self.builder.uri(path);
+        self
+    }
+
+    /// set the matched route path length
+    pub fn path_len(mut self, path_len: u32) -> Self {
+        self.path_len = path_len;
+        self
+    }
+
+    /// add http request header
+    pub fn header(mut self, key: &str, value: &str) -> Self {
+        self.builder = self.builder.header(key, value);
+        self
+    }
+
+    /// set http request body
+    pub fn body<T: Into<Bytes>>(mut self, body: T) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// set http request body as json, and set header `Content-Type`
+    pub fn json<T: ?Sized + Serialize>(mut self, value: &T) -> Self {
+        self.body = Bytes::from(serde_json::to_vec(value).expect("json serialization failed"));
+        self.builder = self.builder.header(CONTENT_TYPE, "application/json");
+        self
+    }
+
+    /// add path parameter
+    pub fn param(mut self, name: &str, value: &str) -> Self {
+        self.params.insert(name, value);
+        self
+    }
+
+    /// add the remaining path captured by a trailing wildcard, must be the last parameter
+    pub fn wildcard(mut self, name: &str, value: &str) -> Self {
+        self.params.insert_wildcard(name, value);
+        self
+    }
+
+    /// set client address
+    pub fn addr(mut self, addr: SocketAddr) -> Self {
+        self.addr = addr;
+        self
+    }
+
+    /// set request id
+    pub fn id(mut self, id: u32) -> Self {
+        self.id = id;
+        self
+    }
+
+    /// set login user id
+    pub fn uid(mut self, uid: &str) -> Self {
+        self.uid = CompactString::new(uid);
+        self
+    }
+
+    /// inject shared state, the same as `HttpServer::set_state`
+    pub fn state<T: Clone + Send + Sync + 'static>(mut self, state: T) -> Self {
+        self.builder = self.builder.extension(state);
+        self
+    }
+
+    /// build HttpContext, panic when the method, path or header is invalid
+    pub fn build(self) -> HttpContext {
+        let req = self.builder
+            .body(Full::new(self.body.clone()))
+            .expect("build http request failed");
+
+        HttpContext {
+            req,
+            body: self.body,
+            path_len: self.path_len,
+            params: self.params,
+            addr: self.addr,
+            id: self.id,
+            request_id: None,
+            uid: self.uid,
+            attrs: None,
+        }
+    }
+}
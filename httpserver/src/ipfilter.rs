@@ -0,0 +1,319 @@
+//! 基于CIDR的客户端地址过滤及临时拒绝名单, 在接受连接时直接丢弃被拒绝的连接, 不做任何回复
+use std::{
+    collections::HashMap, fmt::Display, net::IpAddr, str::FromStr,
+    sync::{Arc, Mutex}, time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use hyper::HeaderMap;
+
+/// CIDR格式的地址段, 例如`10.0.0.0/8`、`2001:db8::/32`, 不带前缀长度时表示单个地址
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+/// 客户端地址过滤器, 拒绝列表优先, 允许列表为空时允许所有未被拒绝的地址
+#[derive(Clone, Debug, Default)]
+pub struct IpFilter {
+    allow: Vec<Cidr>,
+    deny: Vec<Cidr>,
+}
+
+/// 可信的反向代理地址段, 只有直接连接的对端是可信代理时才使用转发头中的客户端地址,
+/// 否则客户端可以伪造`X-Real-IP`绕过限流及登录锁定
+///
+/// 可信代理必须覆盖(而不是透传)客户端发送的`X-Real-IP`, `X-Forwarded-For`则应追加对端地址
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies {
+    list: Vec<Cidr>,
+}
+
+impl Cidr {
+    /// 地址是否在该地址段内, ipv4映射的ipv6地址按ipv4处理
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, normalize(ip)) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr = normalize(addr.parse().map_err(|_| anyhow!("invalid cidr address: {s}"))?);
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => match p.parse::<u8>() {
+                Ok(p) if p <= max => p,
+                _ => return Err(anyhow!("invalid cidr prefix length: {s}")),
+            },
+            None => max,
+        };
+
+        Ok(Cidr { addr, prefix })
+    }
+}
+
+impl Display for Cidr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+impl IpFilter {
+    /// 创建过滤器
+    ///
+    /// Arguments:
+    ///
+    /// * `allow`: 允许的地址段, 为空表示允许所有地址
+    /// * `deny`: 拒绝的地址段, 优先于允许列表
+    pub fn new<S: AsRef<str>>(allow: &[S], deny: &[S]) -> Result<Self> {
+        let parse = |list: &[S]| list.iter()
+            .map(|s| s.as_ref().trim())
+            .filter(|s| !s.is_empty())
+            .map(Cidr::from_str)
+            .collect::<Result<Vec<_>>>();
+
+        Ok(IpFilter { allow: parse(allow)?, deny: parse(deny)? })
+    }
+
+    /// 是否未设置任何规则
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    /// 地址是否被允许
+    pub fn is_allowed(&self, ip: IpAddr) -> bool {
+        if self.deny.iter().any(|c| c.contains(ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|c| c.contains(ip))
+    }
+}
+
+impl TrustedProxies {
+    /// 创建可信代理列表
+    ///
+    /// Arguments:
+    ///
+    /// * `list`: 可信代理的地址段, 为空表示不信任任何转发头
+    pub fn new<S: AsRef<str>>(list: &[S]) -> Result<Self> {
+        let list = list.iter()
+            .map(|s| s.as_ref().trim())
+            .filter(|s| !s.is_empty())
+            .map(Cidr::from_str)
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(TrustedProxies { list })
+    }
+
+    /// 是否未设置任何可信代理
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// 地址是否为可信代理
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.list.iter().any(|c| c.contains(ip))
+    }
+
+    /// 解析客户端的真实地址, 对端不是可信代理时直接使用对端地址
+    ///
+    /// `X-Forwarded-For`从右向左依次是离服务越来越远的代理, 跳过其中的可信代理,
+    /// 第一个不可信的地址即客户端地址; 没有`X-Forwarded-For`时使用`X-Real-IP`
+    ///
+    /// Arguments:
+    ///
+    /// * `peer`: 直接连接的对端地址
+    /// * `headers`: 请求头
+    pub fn resolve(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let peer = normalize(peer);
+        if !self.is_trusted(peer) {
+            return peer;
+        }
+
+        let mut client = peer;
+        let mut forwarded = false;
+        // 多个X-Forwarded-For头按出现顺序拼接, 同样从最后一个开始处理
+        for value in headers.get_all(X_FORWARDED_FOR).iter().rev() {
+            let value = match value.to_str() {
+                Ok(v) => v,
+                Err(_) => return client,
+            };
+            for hop in value.rsplit(',') {
+                match hop.trim().parse::<IpAddr>() {
+                    Ok(ip) => {
+                        forwarded = true;
+                        client = normalize(ip);
+                        if !self.is_trusted(client) {
+                            return client;
+                        }
+                    }
+                    // 无法解析的地址之后的内容不可信, 使用最后一个可信代理记录的地址
+                    Err(_) => return client,
+                }
+            }
+        }
+        if forwarded {
+            return client;
+        }
+
+        match headers.get(X_REAL_IP).and_then(|v| v.to_str().ok()).map(|v| v.trim().parse::<IpAddr>()) {
+            Some(Ok(ip)) => normalize(ip),
+            _ => peer,
+        }
+    }
+}
+
+const X_FORWARDED_FOR: &str = "X-Forwarded-For";
+const X_REAL_IP: &str = "X-Real-IP";
+
+/// 将ipv4映射的ipv6地址(::ffff:a.b.c.d)转换为ipv4地址
+pub(crate) fn normalize(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => ip,
+        },
+        ip => ip,
+    }
+}
+
+/// 临时拒绝名单, 名单中的地址在到期前被拒绝, 由蜜罐等检测到恶意行为时加入
+///
+/// 克隆后共享同一名单, 可以同时交给http服务及中间件使用
+#[derive(Clone, Default)]
+pub struct Blocklist {
+    /// key: 地址, value: 解除拒绝的时间
+    items: Arc<Mutex<HashMap<IpAddr, Instant>>>,
+}
+
+impl Blocklist {
+    /// 将地址加入拒绝名单, 已在名单中时延长拒绝时间
+    ///
+    /// Arguments:
+    ///
+    /// * `ip`: 客户端地址
+    /// * `duration`: 拒绝时长
+    pub fn block(&self, ip: IpAddr, duration: Duration) {
+        let until = Instant::now() + duration;
+        let mut items = self.items.lock().unwrap();
+        let v = items.entry(normalize(ip)).or_insert(until);
+        *v = (*v).max(until);
+    }
+
+    /// 地址是否在拒绝名单中且未到期
+    pub fn is_blocked(&self, ip: IpAddr) -> bool {
+        let items = self.items.lock().unwrap();
+        !items.is_empty() && items.get(&normalize(ip)).is_some_and(|t| *t > Instant::now())
+    }
+
+    /// 删除已到期的地址, 由定时任务调用
+    pub fn purge(&self) {
+        let now = Instant::now();
+        self.items.lock().unwrap().retain(|_, t| *t > now);
+    }
+
+    /// 名单中的地址数量(包括已到期但未清理的地址)
+    pub fn len(&self) -> usize {
+        self.items.lock().unwrap().len()
+    }
+
+    /// 名单是否为空
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::HeaderValue;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn headers(list: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in list {
+            headers.append(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn untrusted_peer() {
+        let proxies = TrustedProxies::new(&["10.0.0.0/8"]).unwrap();
+        // 不可信的对端伪造的转发头被忽略
+        let h = headers(&[(X_FORWARDED_FOR, "1.2.3.4"), (X_REAL_IP, "5.6.7.8")]);
+        assert_eq!(proxies.resolve(ip("203.0.113.9"), &h), ip("203.0.113.9"));
+        // 未配置可信代理时不信任任何转发头
+        let none = TrustedProxies::new::<&str>(&[]).unwrap();
+        assert_eq!(none.resolve(ip("10.0.0.1"), &h), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn proxy_chain() {
+        let proxies = TrustedProxies::new(&["10.0.0.0/8", "192.168.1.1"]).unwrap();
+        let peer = ip("10.0.0.2");
+        // 客户端伪造的最左侧地址被跳过, 取从右向左第一个不可信的地址
+        let h = headers(&[(X_FORWARDED_FOR, "6.6.6.6, 198.51.100.7, 192.168.1.1, 10.0.0.3")]);
+        assert_eq!(proxies.resolve(peer, &h), ip("198.51.100.7"));
+        // 多个请求头按顺序拼接
+        let h = headers(&[(X_FORWARDED_FOR, "6.6.6.6, 198.51.100.7"), (X_FORWARDED_FOR, "10.0.0.3")]);
+        assert_eq!(proxies.resolve(peer, &h), ip("198.51.100.7"));
+        // 全部为可信代理时使用最左侧的地址
+        let h = headers(&[(X_FORWARDED_FOR, "10.0.0.5, 10.0.0.3")]);
+        assert_eq!(proxies.resolve(peer, &h), ip("10.0.0.5"));
+        // 无法解析的地址之后的内容不可信
+        let h = headers(&[(X_FORWARDED_FOR, "198.51.100.7, unknown, 10.0.0.3")]);
+        assert_eq!(proxies.resolve(peer, &h), ip("10.0.0.3"));
+        // 转发头优先于X-Real-IP, 没有转发头时使用X-Real-IP
+        let h = headers(&[(X_FORWARDED_FOR, "198.51.100.7"), (X_REAL_IP, "6.6.6.6")]);
+        assert_eq!(proxies.resolve(peer, &h), ip("198.51.100.7"));
+        let h = headers(&[(X_REAL_IP, " 198.51.100.8 ")]);
+        assert_eq!(proxies.resolve(peer, &h), ip("198.51.100.8"));
+        let h = headers(&[(X_REAL_IP, "bad")]);
+        assert_eq!(proxies.resolve(peer, &h), peer);
+    }
+
+    #[test]
+    fn ipv6() {
+        let proxies = TrustedProxies::new(&["::1", "fd00::/8", "127.0.0.1"]).unwrap();
+        let h = headers(&[(X_FORWARDED_FOR, "2001:db8::1, fd00::2")]);
+        assert_eq!(proxies.resolve(ip("::1"), &h), ip("2001:db8::1"));
+        // ipv4映射的ipv6地址按ipv4处理
+        let h = headers(&[(X_FORWARDED_FOR, "::ffff:198.51.100.7")]);
+        assert_eq!(proxies.resolve(ip("::ffff:127.0.0.1"), &h), ip("198.51.100.7"));
+        assert_eq!(proxies.resolve(ip("2001:db8::9"), &h), ip("2001:db8::9"));
+    }
+
+    #[test]
+    fn parse_cidr() {
+        assert!(TrustedProxies::new(&["10.0.0.0/8", " ", "::1"]).is_ok());
+        assert!(TrustedProxies::new(&["10.0.0.0/33"]).is_err());
+        assert!(TrustedProxies::new(&["fd00::/129"]).is_err());
+        assert!(TrustedProxies::new(&["localhost"]).is_err());
+        let c: Cidr = "192.168.0.0/16".parse().unwrap();
+        assert!(c.contains(ip("192.168.255.1")) && !c.contains(ip("192.169.0.1")));
+        let all: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(all.contains(ip("8.8.8.8")) && !all.contains(ip("2001:db8::1")));
+    }
+}
@@ -1,16 +1,38 @@
 //! http server
+mod basepath;
 mod cancel;
+mod chain;
+mod color;
+mod compression;
+mod extract;
+mod fields;
+mod honeypot;
+mod hsts;
 mod httpcontext;
 mod httperror;
+mod ipfilter;
+mod logtime;
 mod macros;
 mod middleware;
+mod options;
+mod proxy_protocol;
+mod ratelimit;
+mod requestid;
 mod resp;
+mod router;
+mod shadow;
+mod sse;
+mod stream;
+mod tls;
+mod validate;
+mod version;
+mod websocket;
 
 use anyhow::{Error, Result};
 use compact_str::CompactString;
 use fnv::FnvHashMap;
 use http_body_util::{BodyExt, Full};
-use hyper::{body::Incoming, server::conn::http1, service};
+use hyper::{body::Incoming, service};
 use hyper_util::rt::TokioIo;
 use serde_json::Value;
 use std::{
@@ -22,13 +44,39 @@ use std::{
 };
 use tokio::net::{TcpListener, TcpStream};
 
-pub use cancel::{CancelManager, CancelSender, new_cancel};
+use httpcontext::ClientIp;
+use router::{Endpoint, ParamRouter};
+use sse::ServeBody;
+
+pub use cancel::{CancelManager, CancelSender, new_cancel, shutdown_signal};
+pub use chain::{ChainHandler, ChainResult, HandlerChain, NotFoundFormat};
+pub use color::{colored, init_color, is_color, set_color, Color, Colored};
+pub use compression::{Compression, Encoding};
+pub use extract::{bad_request, typed, ApiReply, FromContext, Json, Params, RemoteIp, State, Typed, Uid};
+pub use fields::{Fields, Sparse, FIELDS_PARAM};
 pub use compact_str;
+pub use regex;
 pub use hyper::body::Bytes;
-pub use middleware::{AccessLog, CorsMiddleware, HttpMiddleware};
-pub use resp::{ApiResult, Resp};
-pub use httpcontext::HttpContext;
+pub use hyper::Method;
+pub use middleware::{AccessLog, AllowedHosts, CorsMiddleware, HttpMiddleware, LogFormat};
+pub use options::HttpOptions;
+pub use ratelimit::{KeyExtractor, RateLimit};
+pub use requestid::{RequestId, X_REQUEST_ID};
+pub use resp::{ApiResult, Envelope, Resp};
+pub use router::PathParams;
+pub use shadow::{Shadow, SHADOW_ATTR};
+pub use sse::{Sse, SseSender, TEXT_EVENT_STREAM};
+pub use stream::{StreamBody, StreamSender, StreamWriter};
+pub use tls::TlsConfig;
+pub use validate::{FieldError, Measure, Present, Text, ValidationError, Validator, VALIDATION_CODE};
+pub use version::{split_api_version, ApiVersion};
+pub use websocket::{Message, WebSocket, CLOSE_NORMAL, CLOSE_PROTOCOL_ERROR, CLOSE_TOO_BIG};
+pub use honeypot::Honeypot;
+pub use hsts::{Hsts, HttpsRedirect};
+pub use httpcontext::{HttpContext, HttpContextBuilder};
 pub use httperror::HttpError;
+pub use ipfilter::{Blocklist, Cidr, IpFilter, TrustedProxies};
+pub use logtime::{has_log_time_format, init_log_time, log_time, ISO8601};
 
 /// http header "Content-Type"
 pub const CONTENT_TYPE: &str = "Content-Type";
@@ -42,7 +90,8 @@ pub type HttpResponse = Result<Response>;
 pub type BoxHttpHandler = Box<dyn HttpHandler>;
 
 type HttpCtxAttrs = Option<HashMap<CompactString, Value>>;
-type Router = FnvHashMap<CompactString, BoxHttpHandler>;
+type StateInjector = Box<dyn Fn(&mut hyper::http::Extensions) + Send + Sync>;
+type Router = FnvHashMap<CompactString, Endpoint>;
 
 // use for HttpServer.run_with_callback
 #[async_trait::async_trait]
@@ -72,17 +121,69 @@ pub enum FuzzyFind {
     Many,
 }
 
+/// 路由查找结果
+struct RouteMatch<'a> {
+    endpoint: &'a Endpoint,
+    /// 模糊匹配时匹配的路径长度, 非模糊匹配为0
+    path_len: u32,
+    /// 路径参数
+    params: PathParams,
+}
+
+impl<'a> RouteMatch<'a> {
+    fn new(endpoint: &'a Endpoint, path_len: usize) -> Self {
+        RouteMatch { endpoint, path_len: path_len as u32, params: PathParams::default() }
+    }
+
+    /// 模糊匹配的路径长度加上前缀的长度
+    fn offset(mut self, prefix_len: usize) -> Self {
+        if self.path_len > 0 {
+            self.path_len += prefix_len as u32;
+        }
+        self
+    }
+}
+
+/// 路径匹配但请求方法不匹配时的处理函数, 回复405及允许的请求方法
+struct MethodNotAllowed(String);
+
+#[async_trait::async_trait]
+impl HttpHandler for MethodNotAllowed {
+    async fn handle(&self, _: HttpContext) -> HttpResponse {
+        let mut resp = Resp::fail_with_status(
+            hyper::StatusCode::METHOD_NOT_ALLOWED,
+            405,
+            "Method Not Allowed",
+        )?;
+        if let Ok(allow) = hyper::header::HeaderValue::from_str(&self.0) {
+            resp.headers_mut().insert(hyper::header::ALLOW, allow);
+        }
+        Ok(resp)
+    }
+}
+
 /// http server
 pub struct HttpServer {
     id:                 AtomicU32,                      // 自增的请求id
     count:              AtomicU32,                      // 当前连接总数
+    base_path:          CompactString,                  // 子路径部署时的路径前缀
     content_path:       CompactString,                  // 上下文路径
     router:             Router,                         // 路由表
+    param_router:       ParamRouter,                    // 带路径参数的路由表
     middlewares:        Vec<Box<dyn HttpMiddleware>>,   // 中间件
     default_handler:    BoxHttpHandler,                 // 缺省处理函数
     error_handler:      fn(u32, Error) -> Response,     // 错误处理函数
     fuzzy_find:         FuzzyFind,                      // 路径匹配模式
     cancel_manager:     Option<CancelManager>,          // 进程退出标志
+    states:             Vec<StateInjector>,             // 注入到请求扩展中的共享状态
+    versions:           Vec<ApiVersion>,                // api版本
+    proxy_protocol:     bool,                           // 连接是否带有PROXY协议头
+    ip_filter:          Option<IpFilter>,               // 客户端地址过滤
+    blocklist:          Option<Blocklist>,              // 临时拒绝名单
+    trusted_proxies:    TrustedProxies,                 // 可信的反向代理
+    tls:                Option<Arc<TlsConfig>>,         // https配置
+    options:            HttpOptions,                    // 连接参数
+    default_version:    Option<usize>,                  // 未指定版本时使用的api版本
 }
 
 #[async_trait::async_trait]
@@ -125,16 +226,40 @@ impl HttpServer {
         HttpServer {
             id:                 AtomicU32::new(1),
             count:              AtomicU32::new(0),
+            base_path:          CompactString::with_capacity(0),
             content_path:       CompactString::with_capacity(0),
             router:             FnvHashMap::default(),
+            param_router:       ParamRouter::default(),
             middlewares:        Vec::<Box<dyn HttpMiddleware>>::new(),
             default_handler:    Box::new(Self::handle_not_found),
             error_handler:      Self::handle_error,
             fuzzy_find:         FuzzyFind::None,
             cancel_manager:             None,
+            states:             Vec::new(),
+            versions:           Vec::new(),
+            proxy_protocol:     false,
+            ip_filter:          None,
+            blocklist:          None,
+            trusted_proxies:    TrustedProxies::default(),
+            tls:                None,
+            options:            HttpOptions::default(),
+            default_version:    None,
         }
     }
 
+    /// set base path for deployment under a sub path behind a reverse proxy,
+    /// the prefix is stripped before routing, so middlewares and handlers see the path
+    /// as if deployed at the root, and `Location` headers and cookie paths of responses
+    /// starting with `/` are prefixed with it
+    ///
+    /// Arguments:
+    ///
+    /// * `base`: base path, e.g. `/vault`
+    ///
+    pub fn set_base_path(&mut self, base: &str) {
+        self.base_path = basepath::normalize(base);
+    }
+
     /// set api content path
     ///
     /// Arguments:
@@ -192,14 +317,36 @@ impl HttpServer {
         self.error_handler = handler;
     }
 
-    /// register api function for path
+    /// register api function for path and http method
+    ///
+    /// path supports named parameters (`record/:id`), a trailing wildcard (`files/*rest`)
+    /// and in-segment globs (`img/*.png`), the captured values can be obtained through
+    /// `HttpContext.params`, the wildcard remainder also through `params.wildcard()`.
+    /// A request whose path matches but whose method is not registered gets a 405 reply
+    /// with an `Allow` header, a `GET` handler also serves `HEAD` requests
+    ///
+    /// Arguments:
+    ///
+    /// * `method`: http method
+    /// * `path`: api path
+    /// * `handler`: handle of api function
+    #[inline]
+    pub fn register(&mut self, method: Method, path: &str, handler: impl HttpHandler) {
+        self.register_route(Some(method), path, Box::new(handler));
+    }
+
+    /// register api function for path, accepting any http method
     ///
     /// Arguments:
     ///
     /// * `path`: api path
     /// * `handler`: handle of api function
     #[inline]
-    pub fn register(&mut self, mut path: &str, handler: impl HttpHandler) {
+    pub fn register_any(&mut self, path: &str, handler: impl HttpHandler) {
+        self.register_route(None, path, Box::new(handler));
+    }
+
+    fn register_route(&mut self, method: Option<Method>, mut path: &str, handler: BoxHttpHandler) {
         debug_assert!(!path.is_empty());
         let pbs = path.as_bytes();
         let mut real_path = CompactString::with_capacity(0);
@@ -215,7 +362,40 @@ impl HttpServer {
 
         real_path.push_str(path);
 
-        self.router.insert(real_path, Box::new(handler));
+        if ParamRouter::is_param_path(&real_path) {
+            self.param_router.insert(method, &real_path, handler);
+        } else {
+            self.router.entry(real_path).or_default().insert(method, handler);
+        }
+    }
+
+    /// add api version, the request path `{content_path}/{version}/{api}` will first match
+    /// the api registered as `{version}/{api}`, and then the api registered as `{api}`
+    ///
+    /// Arguments:
+    ///
+    /// * `version`: api version
+    pub fn add_api_version(&mut self, version: ApiVersion) {
+        match self.versions.iter().position(|v| v.name == version.name) {
+            Some(idx) => self.versions[idx] = version,
+            None => self.versions.push(version),
+        }
+    }
+
+    /// set the api version used when the request path does not specify a version
+    ///
+    /// Arguments:
+    ///
+    /// * `name`: api version name, must be added by `add_api_version`
+    pub fn set_default_api_version(&mut self, name: &str) {
+        self.default_version = self.versions.iter().position(|v| v.name == name);
+        debug_assert!(self.default_version.is_some());
+    }
+
+    /// enable HAProxy PROXY protocol (v1/v2), each connection must begin with a PROXY header,
+    /// the client address in the header is used as `HttpContext.addr`
+    pub fn set_proxy_protocol(&mut self, enable: bool) {
+        self.proxy_protocol = enable;
     }
 
     /// register middleware
@@ -223,6 +403,72 @@ impl HttpServer {
         self.middlewares.push(Box::new(middleware));
     }
 
+    /// register shared state, which is injected into each request's extensions
+    /// and can be obtained through `HttpContext::state`
+    ///
+    /// Arguments:
+    ///
+    /// * `state`: shared state, usually `Arc<T>`
+    pub fn set_state<T: Clone + Send + Sync + 'static>(&mut self, state: T) {
+        self.states.push(Box::new(move |ext| {
+            ext.insert(state.clone());
+        }));
+    }
+
+    /// set client address filter, connections from denied addresses are dropped
+    /// at accept time without any response
+    ///
+    /// Arguments:
+    ///
+    /// * `filter`: client address filter
+    pub fn set_ip_filter(&mut self, filter: IpFilter) {
+        self.ip_filter = if filter.is_empty() { None } else { Some(filter) };
+    }
+
+    /// set temporary blocklist shared with middlewares such as `Honeypot`,
+    /// connections from blocked addresses are dropped at accept time until they expire
+    ///
+    /// Arguments:
+    ///
+    /// * `blocklist`: temporary blocklist
+    pub fn set_blocklist(&mut self, blocklist: Blocklist) {
+        self.blocklist = Some(blocklist);
+    }
+
+    /// set trusted reverse proxies, the client address in `X-Forwarded-For` / `X-Real-IP`
+    /// is only used by `HttpContext::remote_ip` when the peer is a trusted proxy,
+    /// by default no proxy is trusted and forwarding headers are ignored
+    ///
+    /// Arguments:
+    ///
+    /// * `proxies`: trusted proxy CIDRs
+    pub fn set_trusted_proxies(&mut self, proxies: TrustedProxies) {
+        self.trusted_proxies = proxies;
+    }
+
+    /// enable https, the certificate can be replaced at runtime through `TlsConfig::reload`
+    ///
+    /// Arguments:
+    ///
+    /// * `tls`: tls config
+    pub fn set_tls(&mut self, tls: Arc<TlsConfig>) {
+        tls.set_http2(self.options.is_http2());
+        self.tls = Some(tls);
+    }
+
+    /// set connection options such as http2, keep-alive and header size limits,
+    /// hyper defaults are used for options that are not set
+    ///
+    /// Arguments:
+    ///
+    /// * `options`: connection options
+    pub fn set_http_options(&mut self, options: HttpOptions) {
+        if let Some(tls) = &self.tls {
+            tls.set_http2(options.is_http2());
+        }
+        self.options = options;
+    }
+
     /// set process exit cancel token
     pub fn set_cancel_manager(&mut self, cancel: CancelManager) {
         self.cancel_manager = Some(cancel);
@@ -256,8 +502,58 @@ impl HttpServer {
 
         loop {
             let (tcp, addr) = listener.accept().await?;
-            let io = TokioIo::new(tcp);
-            tokio::spawn(Self::on_accept(srv.clone(), addr, io));
+            if srv.is_denied(addr, false) {
+                continue;
+            }
+            tokio::spawn(Self::on_accept(srv.clone(), addr, tcp));
+        }
+    }
+
+    /// run http service until `signal` completes, then stop accepting new connections,
+    /// gracefully shut down the existing connections and wait for in-flight requests
+    ///
+    /// Arguments:
+    ///
+    /// * `addr`: listen addr
+    /// * `signal`: shutdown signal, usually `httpserver::shutdown_signal()`
+    /// * `timeout`: maximum time to wait for in-flight requests
+    pub async fn run_with_shutdown<S: Future<Output = ()>>(
+        mut self,
+        addr: std::net::SocketAddr,
+        signal: S,
+        timeout: std::time::Duration,
+    ) -> Result<()> {
+        let (sender, manager) = new_cancel();
+        self.set_cancel_manager(manager);
+        let listener = self.listen(addr).await?;
+
+        let serve = self.serve(listener);
+        tokio::pin!(serve);
+        tokio::select! {
+            res = &mut serve => return res,
+            _ = signal => {}
+        }
+
+        #[cfg(not(feature = "english"))]
+        log::info!("收到退出信号, 停止接收新连接, 等待{}个任务结束...", sender.count());
+        #[cfg(feature = "english")]
+        log::info!("shutdown signal received, stop accepting, waiting for {} tasks...", sender.count());
+        let _ = sender.cancel();
+
+        let drain = async {
+            let res = serve.await;
+            sender.wait(std::time::Duration::from_millis(50)).await;
+            res
+        };
+        match tokio::time::timeout(timeout, drain).await {
+            Ok(res) => res,
+            Err(_) => {
+                #[cfg(not(feature = "english"))]
+                log::warn!("等待任务结束超时, 强制退出, 剩余任务数: {}", sender.count());
+                #[cfg(feature = "english")]
+                log::warn!("graceful shutdown timeout, remaining tasks: {}", sender.count());
+                Ok(())
+            }
         }
     }
 
@@ -276,8 +572,10 @@ impl HttpServer {
                 tokio::select! {
                     res = listener.accept() => {
                         let (tcp, addr) = res?;
-                        let io = TokioIo::new(tcp);
-                        tokio::spawn(Self::on_accept(srv.clone(), addr, io));
+                        if srv.is_denied(addr, false) {
+                            continue;
+                        }
+                        tokio::spawn(Self::on_accept(srv.clone(), addr, tcp));
                     }
                     _ = cancel.cancelled() => {
                         cancel.finish();
@@ -292,24 +590,113 @@ impl HttpServer {
         } else {
             loop {
                 let (tcp, addr) = listener.accept().await?;
-                let io = TokioIo::new(tcp);
-                tokio::spawn(Self::on_accept(srv.clone(), addr, io));
+                if srv.is_denied(addr, false) {
+                    continue;
+                }
+                tokio::spawn(Self::on_accept(srv.clone(), addr, tcp));
             }
         }
     }
 
-    async fn on_accept(srv: Arc<HttpServer>, addr: SocketAddr, io: TokioIo<TcpStream>) {
+    /// 客户端地址是否被拒绝, 被拒绝的连接直接丢弃
+    ///
+    /// 启用PROXY协议时, 接受连接时的地址是代理的地址, 需要在解析协议头后再校验
+    fn is_denied(&self, addr: SocketAddr, real_addr: bool) -> bool {
+        if !real_addr && self.proxy_protocol {
+            return false;
+        }
+        let denied = self.ip_filter.as_ref().is_some_and(|f| !f.is_allowed(addr.ip()))
+            || self.blocklist.as_ref().is_some_and(|b| b.is_blocked(addr.ip()));
+        if !denied {
+            return false;
+        }
+        #[cfg(not(feature = "english"))]
+        log::trace!("拒绝客户端连接 {addr}");
+        #[cfg(feature = "english")]
+        log::trace!("deny client connection {addr}");
+        true
+    }
+
+    async fn on_accept(srv: Arc<HttpServer>, addr: SocketAddr, mut tcp: TcpStream) {
+        let addr = if srv.proxy_protocol {
+            const HEADER_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+            match tokio::time::timeout(HEADER_TIMEOUT, proxy_protocol::read_header(&mut tcp)).await {
+                Ok(Ok(Some(real_addr))) => real_addr,
+                Ok(Ok(None)) => addr,
+                Ok(Err(e)) => {
+                    #[cfg(not(feature = "english"))]
+                    log::warn!("PROXY协议头解析失败, 关闭连接 {addr}: {e:?}");
+                    #[cfg(feature = "english")]
+                    log::warn!("parse PROXY protocol header failed, close connection {addr}: {e:?}");
+                    return;
+                }
+                Err(_) => {
+                    #[cfg(not(feature = "english"))]
+                    log::warn!("读取PROXY协议头超时, 关闭连接 {addr}");
+                    #[cfg(feature = "english")]
+                    log::warn!("read PROXY protocol header timeout, close connection {addr}");
+                    return;
+                }
+            }
+        } else {
+            addr
+        };
+        if srv.proxy_protocol && srv.is_denied(addr, true) {
+            return;
+        }
+
+        match &srv.tls {
+            Some(tls) => {
+                const HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, tls.acceptor().accept(tcp)).await {
+                    Ok(Ok(stream)) => Self::serve_connection(srv, addr, TokioIo::new(stream)).await,
+                    Ok(Err(e)) => {
+                        #[cfg(not(feature = "english"))]
+                        log::debug!("tls握手失败, 关闭连接 {addr}: {e:?}");
+                        #[cfg(feature = "english")]
+                        log::debug!("tls handshake failed, close connection {addr}: {e:?}");
+                    }
+                    Err(_) => {
+                        #[cfg(not(feature = "english"))]
+                        log::debug!("tls握手超时, 关闭连接 {addr}");
+                        #[cfg(feature = "english")]
+                        log::debug!("tls handshake timeout, close connection {addr}");
+                    }
+                }
+            }
+            None => Self::serve_connection(srv, addr, TokioIo::new(tcp)).await,
+        }
+    }
+
+    async fn serve_connection<I>(srv: Arc<HttpServer>, addr: SocketAddr, io: I)
+    where
+        I: hyper::rt::Read + hyper::rt::Write + Unpin + Send + 'static,
+    {
         srv.count.fetch_add(1, std::sync::atomic::Ordering::Release);
         let id = Self::step_id(&srv.id);
 
-        let srv_fn = |req: hyper::Request<Incoming>| {
+        let srv_fn = |mut req: hyper::Request<Incoming>| {
             let srv = srv.clone();
             async move {
+                if !srv.base_path.is_empty() {
+                    match basepath::strip(&srv.base_path, req.uri()) {
+                        Ok(uri) => *req.uri_mut() = uri,
+                        Err(resp) => return Ok::<_, Infallible>(ServeBody::from_response(*resp)),
+                    }
+                }
                 let path = req.uri().path();
-                let (endpoint, path_len) = srv.find_http_handler(path);
-                let endpoint = match endpoint {
-                    Some(v) => v,
-                    None => srv.default_handler.as_ref(),
+                let (route, version) = srv.find_http_handler(path);
+                let not_allowed;
+                let (endpoint, path_len, params) = match route {
+                    Some(m) => match m.endpoint.get(req.method()) {
+                        Some(handler) => (handler, m.path_len, m.params),
+                        None => {
+                            // 路径匹配但请求方法不匹配
+                            not_allowed = MethodNotAllowed(m.endpoint.allow());
+                            (&not_allowed as &dyn HttpHandler, m.path_len, m.params)
+                        }
+                    },
+                    None => (srv.default_handler.as_ref(), 0, PathParams::default()),
                 };
                 let next = Next {
                     endpoint,
@@ -325,32 +712,46 @@ impl HttpServer {
                         #[cfg(feature = "english")]
                         let e = Error::new(e).context("read from request body fail");
                         let resp = (srv.error_handler)(id, e);
-                        return Ok::<_, Infallible>(resp);
+                        return Ok::<_, Infallible>(ServeBody::from_response(resp));
                     }
                 };
-                let req = Request::from_parts(parts, Full::new(body.clone()));
+                let mut req = Request::from_parts(parts, Full::new(body.clone()));
+                for inject in srv.states.iter() {
+                    inject(req.extensions_mut());
+                }
+                let client_ip = srv.trusted_proxies.resolve(addr.ip(), req.headers());
+                req.extensions_mut().insert(ClientIp(client_ip));
 
                 let ctx = HttpContext {
                     req,
                     body,
                     path_len,
+                    params,
                     addr,
                     id,
+                    request_id: None,
                     uid: CompactString::with_capacity(0),
                     attrs: None,
                 };
 
-                let resp = match next.run(ctx).await {
+                let mut resp = match next.run(ctx).await {
                     Ok(resp) => resp,
                     Err(e) => (srv.error_handler)(id, e),
                 };
 
-                Ok::<_, Infallible>(resp)
+                if let Some(version) = version.filter(|v| v.is_deprecated()) {
+                    Self::set_deprecation_headers(&mut resp, version);
+                }
+                if !srv.base_path.is_empty() {
+                    basepath::rewrite(&srv.base_path, &mut resp);
+                }
+
+                Ok::<_, Infallible>(ServeBody::from_response(resp))
             }
         };
 
-        let conn = http1::Builder::new()
-            .serve_connection(io, service::service_fn(srv_fn));
+        let builder = srv.options.builder();
+        let conn = builder.serve_connection_with_upgrades(io, service::service_fn(srv_fn));
         tokio::pin!(conn);
 
         if let Some(cancel) = &srv.cancel_manager {
@@ -405,14 +806,15 @@ impl HttpServer {
         }
     }
 
-    /// 路由查找，返回路由处理函数及路径匹配的长度
-    fn find_http_handler<'a>(&'a self, path: &str) -> (Option<&'a dyn HttpHandler>, u32) {
+    /// 路由查找，返回路由匹配结果及匹配的api版本
+    fn find_http_handler<'a>(&'a self, path: &str)
+            -> (Option<RouteMatch<'a>>, Option<&'a ApiVersion>) {
         let prefix = self.content_path.as_str();
 
         let pl = if !prefix.is_empty() {
             // 前缀不匹配
             if !path.starts_with(prefix) {
-                return (None, 0);
+                return (None, None);
             }
             prefix.len() - 1
         } else {
@@ -424,9 +826,50 @@ impl HttpServer {
             path = &path[0..path.len() - 1];
         }
 
+        if self.versions.is_empty() {
+            return (self.find_route(path).map(|m| m.offset(pl)), None);
+        }
+
+        // 拆分路径中的版本前缀, 未指定版本时使用缺省版本
+        let (version, path, vpl) = match split_api_version(path) {
+            Some((name, rest)) => match self.versions.iter().find(|v| v.name == name) {
+                Some(v) => (Some(v), rest, name.len() + 1),
+                None => (None, path, 0),
+            },
+            None => (self.default_version.map(|i| &self.versions[i]), path, 0),
+        };
+
+        // 优先查找指定版本的接口, 找不到时使用未指定版本的接口
+        if let Some(v) = version {
+            let mut vpath = CompactString::with_capacity(v.name.len() + path.len() + 1);
+            vpath.push('/');
+            vpath.push_str(&v.name);
+            vpath.push_str(path);
+            if let Some(m) = self.find_route(&vpath) {
+                // 匹配长度需要换算回原始路径
+                let m = m.offset(pl + vpl);
+                let m = RouteMatch {
+                    path_len: if m.path_len > 0 { m.path_len - (v.name.len() + 1) as u32 } else { 0 },
+                    ..m
+                };
+                return (Some(m), version);
+            }
+        }
+        (self.find_route(path).map(|m| m.offset(pl + vpl)), version)
+    }
+
+    /// 在路由表中查找路径, 依次为精确匹配、路径参数匹配、模糊匹配
+    fn find_route<'a>(&'a self, mut path: &str) -> Option<RouteMatch<'a>> {
         // 找到直接匹配的路径
-        if let Some(handler) = self.router.get(path) {
-            return (Some(handler.as_ref()), 0);
+        if let Some(endpoint) = self.router.get(path) {
+            return Some(RouteMatch::new(endpoint, 0));
+        }
+
+        // 带路径参数的接口
+        if !self.param_router.is_empty() {
+            if let Some((endpoint, params)) = self.param_router.find(path) {
+                return Some(RouteMatch { endpoint, path_len: 0, params });
+            }
         }
 
         match self.fuzzy_find {
@@ -434,26 +877,53 @@ impl HttpServer {
             FuzzyFind::One => {
                 // 查找上级路径带路径参数的接口
                 if let Some(pos) = path.rfind('/') {
-                    if let Some(handler) = self.router.get(&path[..pos + 1]) {
-                        return (Some(handler.as_ref()), (pl + pos + 1) as u32);
+                    if let Some(endpoint) = self.router.get(&path[..pos + 1]) {
+                        return Some(RouteMatch::new(endpoint, pos + 1));
                     }
                 }
             }
             FuzzyFind::Many => {
                 // 尝试递归上级路径查找带路径参数的接口
                 while let Some(pos) = path.rfind('/') {
-                    if let Some(handler) = self.router.get(&path[..pos + 1]) {
-                        return (Some(handler.as_ref()), (pl + pos + 1) as u32);
+                    if let Some(endpoint) = self.router.get(&path[..pos + 1]) {
+                        return Some(RouteMatch::new(endpoint, pos + 1));
                     }
                     path = &path[..pos];
                 }
             }
         }
 
-        (None, 0)
+        None
+    }
+
+    /// 设置弃用版本的响应头
+    fn set_deprecation_headers(resp: &mut Response, version: &ApiVersion) {
+        let headers = resp.headers_mut();
+        if let Some(val) = &version.deprecation {
+            if let Ok(val) = hyper::header::HeaderValue::from_str(val) {
+                headers.insert("Deprecation", val);
+            }
+        }
+        if let Some(val) = &version.sunset {
+            if let Ok(val) = hyper::header::HeaderValue::from_str(val) {
+                headers.insert("Sunset", val);
+            }
+        }
     }
 
-    fn handle_error(id: u32, err: Error) -> Response {
+    pub(crate) fn handle_error(id: u32, err: Error) -> Response {
+        // 请求参数校验错误回复400及各字段的错误信息
+        let err = match err.downcast::<ValidationError>() {
+            Ok(e) => {
+                log_debug!(id, "{e}");
+                let status = hyper::StatusCode::BAD_REQUEST;
+                match Resp::fail_with_data(status, VALIDATION_CODE, &e.message(), &e.errors) {
+                    Ok(resp) => return resp,
+                    Err(e) => e,
+                }
+            }
+            Err(e) => e,
+        };
         let (code, msg) = match err.downcast::<HttpError>() {
             Ok(e) => {
                 if e.source.is_some() {
@@ -499,7 +969,7 @@ impl HttpServer {
     fn log_api_info(&self, addr: SocketAddr) {
         if log::log_enabled!(log::Level::Trace) {
             let mut buf = String::with_capacity(1024);
-            if self.router.is_empty() {
+            if self.router.is_empty() && self.param_router.is_empty() {
                 #[cfg(not(feature = "english"))]
                 buf.push_str("已注册接口: <无>");
                 #[cfg(feature = "english")]
@@ -527,13 +997,19 @@ impl HttpServer {
                 }
                 buf
             });
+            let buf = self.param_router.paths().iter().fold(buf, |mut buf, v| {
+                buf.push('\n');
+                buf.push('\t');
+                buf.push_str(v.as_str());
+                buf
+            });
             log::trace!("{}", buf);
         }
 
         #[cfg(not(feature = "english"))]
-        log::info!("启动http服务: \x1b[34m{addr}\x1b[0m");
+        log::info!("启动http服务: {}", colored(addr, Color::Blue));
         #[cfg(feature = "english")]
-        log::info!("Startup http server on \x1b[34m{addr}\x1b[0m");
+        log::info!("Startup http server on {}", colored(addr, Color::Blue));
     }
 
 }
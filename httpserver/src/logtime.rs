@@ -0,0 +1,61 @@
+//! 日志时间戳格式化
+use std::sync::OnceLock;
+
+use chrono::{format::{Item, StrftimeItems}, Local, SecondsFormat, Utc};
+
+/// ISO8601格式的名称
+pub const ISO8601: &str = "iso8601";
+/// 文本日志缺省的时间格式
+const DEFAULT_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+struct LogTimeConfig {
+    /// 是否使用utc时间
+    utc: bool,
+    /// 时间格式, 为空时使用缺省格式
+    format: String,
+}
+
+static LOG_TIME: OnceLock<LogTimeConfig> = OnceLock::new();
+
+/// 设置日志时间戳的时区和格式, 只能设置一次
+///
+/// * `utc`: true使用utc时间, false使用本地时间
+/// * `format`: strftime格式, 为空时使用缺省格式, "iso8601"表示ISO8601格式
+///
+/// Returns:
+///
+/// 时间格式是否合法
+pub fn init_log_time(utc: bool, format: &str) -> bool {
+    if format != ISO8601 && StrftimeItems::new(format).any(|i| matches!(i, Item::Error)) {
+        return false;
+    }
+    let _ = LOG_TIME.set(LogTimeConfig { utc, format: format.to_owned() });
+    true
+}
+
+/// 是否设置了日志时间格式
+pub fn has_log_time_format() -> bool {
+    LOG_TIME.get().map(|c| !c.format.is_empty()).unwrap_or(false)
+}
+
+/// 按配置的时区和格式格式化当前时间
+///
+/// * `iso_default`: 未设置时间格式时, true使用ISO8601格式(用于json日志), false使用文本日志的缺省格式
+pub fn log_time(iso_default: bool) -> String {
+    let (utc, format) = match LOG_TIME.get() {
+        Some(c) => (c.utc, c.format.as_str()),
+        None => (false, ""),
+    };
+    let format = match format {
+        "" if iso_default => ISO8601,
+        "" => DEFAULT_FORMAT,
+        f => f,
+    };
+
+    match (format == ISO8601, utc) {
+        (true, true) => Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        (true, false) => Local::now().to_rfc3339_opts(SecondsFormat::Millis, false),
+        (false, true) => Utc::now().format(format).to_string(),
+        (false, false) => Local::now().format(format).to_string(),
+    }
+}
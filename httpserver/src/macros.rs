@@ -30,6 +30,9 @@ macro_rules! http_error {
 
 /// Batch registration API interface
 ///
+/// Each path may be prefixed with the allowed http methods separated by `|`,
+/// a path without methods accepts any method
+///
 /// ## Example
 /// ```rust
 /// use anyhow::Result;
@@ -37,23 +40,64 @@ macro_rules! http_error {
 ///
 /// async fn ping(ctx: HttpContext) -> Result<Response> { todo!() }
 /// async fn login(ctx: HttpContext) -> Result<Response> { todo!() }
+/// async fn echo(ctx: HttpContext) -> Result<Response> { todo!() }
 ///
 /// let mut srv = HttpServer::new(true);
 /// register_apis!(srv, "/api",
-///     "/ping": apis::ping,
-///     "/login": apis::login,
+///     GET | POST "/ping": apis::ping,
+///     POST "/login": apis::login,
+///     "/echo": apis::echo,
 /// );
 /// ```
 #[macro_export]
 macro_rules! register_apis {
-    ($server:expr, $base:expr, $($path:literal : $handler:expr,)+) => {
+    (@route $server:expr, $base:expr, [], $path:literal, $handler:expr) => {
+        $server.register_any(&$crate::compact_str::format_compact!("{}{}",
+            $base, $path), $handler);
+    };
+    (@route $server:expr, $base:expr, [$($method:ident)|+], $path:literal, $handler:expr) => {
         $(
-            $server.register(&$crate::compact_str::format_compact!("{}{}",
+            $server.register($crate::Method::$method, &$crate::compact_str::format_compact!("{}{}",
                 $base, $path), $handler);
+        )+
+    };
+    ($server:expr, $base:expr, $($($method:ident)|* $path:literal : $handler:expr,)+) => {
+        $(
+            $crate::register_apis!(@route $server, $base, [$($method)|*], $path, $handler);
         )*
     };
 }
 
+/// Validate the fields of a request parameter, all field errors are collected and returned
+/// together as a 400 reply, the rules are the methods of `Validator`:
+/// `required()`, `length(range)`, `range(range)`, `pattern(&regex)` and `custom(fn)`
+///
+/// ## Example
+/// ```rust,ignore
+/// httpserver::validate!(req_param,
+///     user: required(),
+///     user: length(1..=64),
+///     expire: range(1..=1440),
+///     url: pattern(&URL_RE),
+/// );
+/// ```
+#[macro_export]
+macro_rules! validate {
+    (@rule $v:ident, $target:expr, $field:ident, range, $($arg:expr),*) => {
+        $v.range(stringify!($field), $target.$field.clone(), $($arg),*);
+    };
+    (@rule $v:ident, $target:expr, $field:ident, $rule:ident, $($arg:expr),*) => {
+        $v.$rule(stringify!($field), &$target.$field, $($arg),*);
+    };
+    ($target:expr, $($field:ident : $rule:ident ( $($arg:expr),* ) ),+ $(,)?) => {{
+        let mut v = $crate::Validator::new();
+        $(
+            $crate::validate!(@rule v, $target, $field, $rule, $($arg),*);
+        )+
+        v.finish()?;
+    }};
+}
+
 /// Error message response returned when struct fields is Option::None
 ///
 /// ## Example
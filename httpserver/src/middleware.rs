@@ -3,8 +3,8 @@ use http_body_util::{BodyExt, Full};
 use hyper::{body::Bytes, header::HeaderValue};
 
 use crate::{
-    log_debug, log_error, log_info, log_trace, if_else, HttpContext, HttpResponse,
-    Next, Response, CONTENT_TYPE
+    colored, has_log_time_format, log_debug, log_error, log_info, log_time, log_trace, if_else,
+    Color, HttpContext, HttpResponse, Next, Resp, Response, CONTENT_TYPE
 };
 
 /// middleware interface
@@ -14,9 +14,122 @@ pub trait HttpMiddleware: Send + Sync + 'static {
 }
 
 /// Log middleware，访问日志中间件
-pub struct AccessLog;
+#[derive(Default)]
+pub struct AccessLog {
+    format: LogFormat,
+}
+/// 访问日志格式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// 彩色文本，便于人工阅读
+    #[default]
+    Text,
+    /// 每个请求输出一行json对象，便于日志系统采集
+    Json,
+}
 /// Cors middleware，跨域访问中间件
 pub struct CorsMiddleware;
+/// Allowed hosts middleware，校验请求的Host头，防御DNS重绑定攻击
+pub struct AllowedHosts {
+    /// 允许的主机名(小写，不含端口)，支持`*.example.com`形式的通配符
+    hosts: Vec<CompactString>,
+    /// 不做校验的路径，例如健康检查接口
+    exempt_paths: Vec<CompactString>,
+}
+
+/// 访问日志需要的请求信息, 请求上下文交给后续处理后仍需使用
+struct AccessInfo<'a> {
+    id: u32,
+    rid: Option<&'a str>,
+    method: &'a hyper::Method,
+    path: &'a str,
+    ip: std::net::Ipv4Addr,
+}
+
+impl LogFormat {
+    /// 解析日志格式名称(text/json)
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" | "" => Some(LogFormat::Text),
+            "json" => Some(LogFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+impl AccessLog {
+    /// 创建指定格式的访问日志中间件
+    pub fn new(format: LogFormat) -> Self {
+        AccessLog { format }
+    }
+
+    /// 输出json格式的访问日志
+    fn log_json(&self, ctx: &AccessInfo<'_>, ms: u128, res: &HttpResponse) {
+        let mut value = serde_json::json!({
+            "ts": log_time(true),
+            "id": ctx.id,
+            "request_id": ctx.rid,
+            "method": ctx.method.as_str(),
+            "path": ctx.path,
+            "status": 500,
+            "latency_ms": ms as u64,
+            "ip": ctx.ip.to_string(),
+        });
+        match res {
+            Ok(res) => {
+                value["status"] = res.status().as_u16().into();
+                log::info!("{value}");
+            }
+            Err(e) => {
+                value["error"] = e.to_string().into();
+                log::error!("{value}");
+            }
+        }
+    }
+}
+
+impl AllowedHosts {
+    /// 创建中间件
+    ///
+    /// Arguments:
+    ///
+    /// * `hosts`: 允许的主机名，支持`*.example.com`形式的通配符
+    /// * `exempt_paths`: 不做校验的请求路径
+    pub fn new<S: AsRef<str>>(hosts: &[S], exempt_paths: &[S]) -> Self {
+        AllowedHosts {
+            hosts: hosts.iter()
+                .map(|h| CompactString::new(h.as_ref().trim().to_ascii_lowercase()))
+                .filter(|h| !h.is_empty())
+                .collect(),
+            exempt_paths: exempt_paths.iter().map(|p| CompactString::new(p.as_ref())).collect(),
+        }
+    }
+
+    /// 主机名是否被允许
+    pub fn is_allowed(&self, host: &str) -> bool {
+        let host = strip_port(host).to_ascii_lowercase();
+        self.hosts.iter().any(|h| match h.strip_prefix("*.") {
+            Some(domain) => host.len() > domain.len() + 1
+                && host.ends_with(domain)
+                && host.as_bytes()[host.len() - domain.len() - 1] == b'.',
+            None => *h == host,
+        })
+    }
+}
+
+/// 去掉主机名中的端口部分，支持ipv6地址格式`[::1]:8080`
+fn strip_port(host: &str) -> &str {
+    if let Some(rest) = host.strip_prefix('[') {
+        return match rest.find(']') {
+            Some(pos) => &rest[..pos],
+            None => host,
+        };
+    }
+    match host.rfind(':') {
+        Some(pos) => &host[..pos],
+        None => host,
+    }
+}
 
 #[async_trait::async_trait]
 impl HttpMiddleware for AccessLog {
@@ -24,9 +137,12 @@ impl HttpMiddleware for AccessLog {
         let start = std::time::Instant::now();
         let ip = ctx.remote_ip();
         let id = ctx.id;
+        let rid = ctx.request_id.clone();
         let method = ctx.req.method().clone();
         let path = CompactString::new(ctx.req.uri().path());
-        log_debug!(id, "{method} \x1b[33m{path}\x1b[0m");
+        if self.format == LogFormat::Text {
+            log_debug!(id, "{method} {}", colored(&path, Color::Yellow));
+        }
 
         // 记录请求参数日志
         if log::log_enabled!(log::Level::Trace) {
@@ -57,20 +173,41 @@ impl HttpMiddleware for AccessLog {
         let mut res = next.run(ctx).await;
         // 输出接口调用耗时
         let ms = start.elapsed().as_millis();
-        match &res {
-            Ok(res) => {
-                let c = if_else!(res.status() == hyper::StatusCode::OK, 2, 1);
-                log_info!(
+        if self.format == LogFormat::Json {
+            let info = AccessInfo { id, rid: rid.as_deref(), method: &method, path: &path, ip };
+            self.log_json(&info, ms, &res);
+        } else {
+            // 设置了日志时间格式时, 在访问日志中输出按配置格式化的请求完成时间
+            let ts = if has_log_time_format() {
+                let mut ts = log_time(false);
+                ts.push(' ');
+                ts
+            } else {
+                String::new()
+            };
+            // 设置了请求id时一并输出, 便于与客户端反馈的请求id对应
+            let rid = match &rid {
+                Some(rid) => format!(", request: {rid}"),
+                None => String::new(),
+            };
+            match &res {
+                Ok(res) => {
+                    let c = if_else!(res.status() == hyper::StatusCode::OK, Color::Green, Color::Red);
+                    log_info!(
+                        id,
+                        "{ts}{method} {} {} {ms}ms, client: {ip}{rid}",
+                        colored(&path, Color::Blue),
+                        colored(res.status().as_u16(), c)
+                    );
+                }
+                Err(e) => log_error!(
                     id,
-                    "{method} \x1b[34m{path} \x1b[3{c}m{}\x1b[0m {ms}ms, client: {ip}",
-                    res.status().as_u16()
-                );
-            }
-            Err(e) => log_error!(
-                id,
-                "{method} \x1b[34m{path}\x1b[0m \x1b[31m500\x1b[0m {ms}ms, error: {e:?}"
-            ),
-        };
+                    "{ts}{method} {} {} {ms}ms{rid}, error: {e:?}",
+                    colored(&path, Color::Blue),
+                    colored(500, Color::Red)
+                ),
+            };
+        }
 
         // 记录回复结果日志
         if log::log_enabled!(log::Level::Trace) {
@@ -115,3 +252,33 @@ impl HttpMiddleware for CorsMiddleware {
         }
     }
 }
+
+#[async_trait::async_trait]
+impl HttpMiddleware for AllowedHosts {
+    async fn handle<'a>(&'a self, ctx: HttpContext, next: Next<'a>) -> HttpResponse {
+        if self.hosts.is_empty() {
+            return next.run(ctx).await;
+        }
+
+        let path = ctx.req.uri().path();
+        if self.exempt_paths.iter().any(|p| *p == path) {
+            return next.run(ctx).await;
+        }
+
+        let host = match ctx.header_value(hyper::header::HOST) {
+            Some(h) => h.to_str().ok(),
+            None => ctx.req.uri().host(),
+        };
+
+        match host {
+            Some(host) if self.is_allowed(host) => next.run(ctx).await,
+            _ => {
+                #[cfg(not(feature = "english"))]
+                log_info!(ctx.id, "拒绝非法的Host请求头: {host:?}");
+                #[cfg(feature = "english")]
+                log_info!(ctx.id, "reject invalid Host header: {host:?}");
+                Resp::fail_with_status(hyper::StatusCode::FORBIDDEN, 403, "Invalid Host header")
+            }
+        }
+    }
+}
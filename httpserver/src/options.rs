@@ -0,0 +1,112 @@
+//! http连接参数, 控制协议版本、keep-alive、并发流数量及请求头大小, 未设置的参数使用hyper的缺省值
+use std::time::Duration;
+
+use hyper_util::{rt::{TokioExecutor, TokioTimer}, server::conn::auto};
+
+/// http1请求头缓冲区的最小值, 小于该值时hyper会panic
+const MIN_HEADER_SIZE: usize = 8192;
+
+/// http连接参数
+#[derive(Clone, Debug, Default)]
+pub struct HttpOptions {
+    /// 是否支持http2, 明文连接使用h2c(prior knowledge), https连接通过ALPN协商
+    http2: bool,
+    /// http1是否支持keep-alive
+    keep_alive: Option<bool>,
+    /// http1读取请求头的超时时间
+    header_read_timeout: Option<Duration>,
+    /// http2发送ping的间隔
+    keep_alive_interval: Option<Duration>,
+    /// http2等待ping应答的超时时间
+    keep_alive_timeout: Option<Duration>,
+    /// http2每个连接的最大并发流数量
+    max_concurrent_streams: Option<u32>,
+    /// 请求头的最大字节数
+    max_header_size: Option<usize>,
+}
+
+impl HttpOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 是否支持http2
+    pub fn http2(mut self, enable: bool) -> Self {
+        self.http2 = enable;
+        self
+    }
+
+    /// http1是否支持keep-alive, 关闭后每个连接只处理一个请求
+    pub fn keep_alive(mut self, enable: bool) -> Self {
+        self.keep_alive = Some(enable);
+        self
+    }
+
+    /// http1读取请求头的超时时间, 超时后关闭连接
+    pub fn header_read_timeout(mut self, timeout: Duration) -> Self {
+        self.header_read_timeout = Some(timeout);
+        self
+    }
+
+    /// http2发送ping检测连接是否存活的间隔及等待应答的超时时间
+    pub fn keep_alive_ping(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.keep_alive_interval = Some(interval);
+        self.keep_alive_timeout = Some(timeout);
+        self
+    }
+
+    /// http2每个连接的最大并发流数量
+    pub fn max_concurrent_streams(mut self, max: u32) -> Self {
+        self.max_concurrent_streams = Some(max);
+        self
+    }
+
+    /// 请求头的最大字节数, http1最小为8k
+    pub fn max_header_size(mut self, size: usize) -> Self {
+        self.max_header_size = Some(size);
+        self
+    }
+
+    /// 是否支持http2
+    pub fn is_http2(&self) -> bool {
+        self.http2
+    }
+
+    /// 按参数创建连接处理器
+    pub(crate) fn builder(&self) -> auto::Builder<TokioExecutor> {
+        let mut builder = auto::Builder::new(TokioExecutor::new());
+
+        let mut http1 = builder.http1();
+        http1.timer(TokioTimer::new());
+        if let Some(enable) = self.keep_alive {
+            http1.keep_alive(enable);
+        }
+        if let Some(timeout) = self.header_read_timeout {
+            http1.header_read_timeout(timeout);
+        }
+        if let Some(size) = self.max_header_size {
+            http1.max_buf_size(size.max(MIN_HEADER_SIZE));
+        }
+
+        if !self.http2 {
+            return builder.http1_only();
+        }
+
+        let mut http2 = builder.http2();
+        http2.timer(TokioTimer::new());
+        if let Some(interval) = self.keep_alive_interval {
+            http2.keep_alive_interval(interval);
+        }
+        if let Some(timeout) = self.keep_alive_timeout {
+            http2.keep_alive_timeout(timeout);
+        }
+        if let Some(max) = self.max_concurrent_streams {
+            http2.max_concurrent_streams(max);
+        }
+        if let Some(size) = self.max_header_size {
+            http2.max_header_list_size(size as u32);
+        }
+
+        builder
+    }
+}
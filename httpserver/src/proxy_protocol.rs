@@ -0,0 +1,93 @@
+//! HAProxy PROXY协议(v1/v2)解析, 用于获取经过tcp代理转发的真实客户端地址
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use anyhow::{bail, Result};
+use tokio::{io::AsyncReadExt, net::TcpStream};
+
+/// v2协议签名
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+/// v1协议头最大长度
+const V1_MAX_LEN: usize = 107;
+
+/// 从连接中读取PROXY协议头, 只读取协议头部分, 不影响后续的http数据
+///
+/// Returns:
+///
+/// 真实的客户端地址, 协议头为LOCAL/UNKNOWN时返回None
+pub(crate) async fn read_header(tcp: &mut TcpStream) -> Result<Option<SocketAddr>> {
+    // v1最短的协议头"PROXY UNKNOWN\r\n"也超过12字节
+    let mut buf = [0u8; 12];
+    tcp.read_exact(&mut buf).await?;
+
+    if &buf == V2_SIGNATURE {
+        read_v2(tcp).await
+    } else if buf.starts_with(b"PROXY ") {
+        read_v1(tcp, &buf).await
+    } else {
+        bail!("invalid proxy protocol header")
+    }
+}
+
+async fn read_v1(tcp: &mut TcpStream, head: &[u8]) -> Result<Option<SocketAddr>> {
+    let mut line = Vec::with_capacity(V1_MAX_LEN);
+    line.extend_from_slice(head);
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LEN {
+            bail!("proxy protocol v1 header too long");
+        }
+        line.push(tcp.read_u8().await?);
+    }
+
+    let line = std::str::from_utf8(&line[..line.len() - 2])?;
+    let mut fields = line.split(' ').skip(1);
+    match fields.next() {
+        Some("TCP4") | Some("TCP6") => {}
+        Some("UNKNOWN") => return Ok(None),
+        _ => bail!("invalid proxy protocol v1 header: {line}"),
+    }
+
+    let src: IpAddr = match fields.next().map(str::parse) {
+        Some(Ok(ip)) => ip,
+        _ => bail!("invalid proxy protocol v1 source address: {line}"),
+    };
+    let port: u16 = match fields.nth(1).map(str::parse) {
+        Some(Ok(port)) => port,
+        _ => bail!("invalid proxy protocol v1 source port: {line}"),
+    };
+
+    Ok(Some(SocketAddr::new(src, port)))
+}
+
+async fn read_v2(tcp: &mut TcpStream) -> Result<Option<SocketAddr>> {
+    let ver_cmd = tcp.read_u8().await?;
+    let family = tcp.read_u8().await?;
+    let len = tcp.read_u16().await? as usize;
+    let mut data = vec![0u8; len];
+    tcp.read_exact(&mut data).await?;
+
+    if ver_cmd >> 4 != 2 {
+        bail!("unsupported proxy protocol version: {ver_cmd:#x}");
+    }
+    // LOCAL命令, 由代理自身发起的连接(如健康检查)
+    if ver_cmd & 0x0f == 0 {
+        return Ok(None);
+    }
+
+    let addr = match family >> 4 {
+        // AF_INET: src(4) + dst(4) + src_port(2) + dst_port(2)
+        1 if len >= 12 => {
+            let ip = Ipv4Addr::new(data[0], data[1], data[2], data[3]);
+            SocketAddr::new(IpAddr::V4(ip), u16::from_be_bytes([data[8], data[9]]))
+        }
+        // AF_INET6: src(16) + dst(16) + src_port(2) + dst_port(2)
+        2 if len >= 36 => {
+            let mut ip = [0u8; 16];
+            ip.copy_from_slice(&data[..16]);
+            SocketAddr::new(IpAddr::V6(Ipv6Addr::from(ip)), u16::from_be_bytes([data[32], data[33]]))
+        }
+        // AF_UNSPEC或者AF_UNIX, 无法获取ip地址
+        _ => return Ok(None),
+    };
+
+    Ok(Some(addr))
+}
@@ -0,0 +1,124 @@
+//! 基于令牌桶算法的请求限流中间件
+use std::{sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use compact_str::{format_compact, CompactString};
+use fnv::FnvHashMap;
+
+use crate::{log_info, HttpContext, HttpMiddleware, HttpResponse, Next, Resp};
+
+/// 限流键提取函数, 返回None表示该请求不限流
+pub type KeyExtractor = dyn Fn(&HttpContext) -> Option<CompactString> + Send + Sync;
+
+/// 令牌桶
+struct Bucket {
+    /// 剩余令牌数
+    tokens: f64,
+    /// 最后一次补充令牌的时间
+    last: Instant,
+}
+
+/// 请求限流中间件, 按键(默认为客户端ip)分别维护令牌桶
+///
+/// 每个令牌桶最多容纳`burst`个令牌, 每个`window`时间段补满一次,
+/// 每个请求消耗一个令牌, 令牌耗尽时返回429
+#[derive(Clone)]
+pub struct RateLimit {
+    /// 令牌桶容量, 即允许的突发请求数
+    burst: u32,
+    /// 令牌桶从空到满所需的时间
+    window: Duration,
+    /// 限流键提取函数
+    key: Arc<KeyExtractor>,
+    /// 各个键对应的令牌桶
+    buckets: Arc<Mutex<FnvHashMap<CompactString, Bucket>>>,
+}
+
+impl RateLimit {
+    /// 创建中间件, 默认按客户端ip限流
+    ///
+    /// Arguments:
+    ///
+    /// * `burst`: 令牌桶容量, 即允许的突发请求数
+    /// * `window`: 令牌桶从空到满所需的时间
+    pub fn new(burst: u32, window: Duration) -> Self {
+        RateLimit {
+            burst: burst.max(1),
+            window: window.max(Duration::from_millis(1)),
+            key: Arc::new(|ctx: &HttpContext| Some(format_compact!("{}", ctx.remote_ip()))),
+            buckets: Arc::new(Mutex::new(FnvHashMap::default())),
+        }
+    }
+
+    /// 设置限流键提取函数, 例如按接口路径或者用户限流, 返回None的请求不限流
+    pub fn with_key<F>(mut self, key: F) -> Self
+    where
+        F: Fn(&HttpContext) -> Option<CompactString> + Send + Sync + 'static,
+    {
+        self.key = Arc::new(key);
+        self
+    }
+
+    /// 消耗指定键的一个令牌, 令牌不足时返回false
+    pub fn check(&self, key: &str) -> bool {
+        let now = Instant::now();
+        let burst = self.burst as f64;
+        let mut buckets = self.buckets.lock().unwrap();
+
+        let bucket = match buckets.get_mut(key) {
+            Some(b) => b,
+            None => buckets.entry(CompactString::new(key))
+                .or_insert(Bucket { tokens: burst, last: now }),
+        };
+
+        let refill = now.duration_since(bucket.last).as_secs_f64() * burst / self.window.as_secs_f64();
+        bucket.tokens = (bucket.tokens + refill).min(burst);
+        bucket.last = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// 删除已经补满的令牌桶, 补满的令牌桶与新建的令牌桶等价, 由定时任务调用
+    pub fn purge(&self) {
+        let mut buckets = self.buckets.lock().unwrap();
+        let window = self.window;
+        buckets.retain(|_, b| b.last.elapsed() < window);
+    }
+
+    /// 当前令牌桶的数量
+    pub fn len(&self) -> usize {
+        self.buckets.lock().unwrap().len()
+    }
+
+    /// 是否没有令牌桶
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpMiddleware for RateLimit {
+    async fn handle<'a>(&'a self, ctx: HttpContext, next: Next<'a>) -> HttpResponse {
+        let key = match (self.key)(&ctx) {
+            Some(key) => key,
+            None => return next.run(ctx).await,
+        };
+
+        if self.check(&key) {
+            return next.run(ctx).await;
+        }
+
+        #[cfg(not(feature = "english"))]
+        log_info!(ctx.id, "请求过于频繁, 限流键: {key}");
+        #[cfg(feature = "english")]
+        log_info!(ctx.id, "too many requests, rate limit key: {key}");
+
+        const TOO_MANY_REQUESTS: hyper::StatusCode = hyper::StatusCode::TOO_MANY_REQUESTS;
+        Resp::fail_with_status(TOO_MANY_REQUESTS, TOO_MANY_REQUESTS.as_u16() as u32,
+            TOO_MANY_REQUESTS.canonical_reason().unwrap_or("Too Many Requests"))
+    }
+}
@@ -0,0 +1,77 @@
+//! 请求id中间件, 为每个请求生成全局唯一的id, 便于关联客户端、访问日志及错误日志
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use compact_str::{format_compact, CompactString};
+use hyper::header::HeaderValue;
+
+use crate::{HttpContext, HttpMiddleware, HttpResponse, HttpServer, Next};
+
+/// 请求id的请求头及响应头
+pub const X_REQUEST_ID: &str = "X-Request-Id";
+/// 客户端传入的请求id的最大长度, 超过时重新生成
+const MAX_ID_LEN: usize = 128;
+/// 序号占用的位数, 同一毫秒内最多生成2^20个不重复的id
+const SEQ_BITS: u32 = 20;
+
+/// 同一毫秒内的请求序号
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// 请求id中间件, 应放在访问日志中间件之前注册
+///
+/// 优先使用客户端或上游代理传入的X-Request-Id, 不存在或者格式不合法时生成
+/// 雪花算法形式的id(毫秒时间戳 + 序号, 16位十六进制), 保存到`HttpContext.request_id`,
+/// 并在响应头中返回. 后续处理返回错误时, 由缺省错误处理函数转换为回复, 以便错误回复同样带上请求id
+#[derive(Default)]
+pub struct RequestId {
+    /// 忽略客户端传入的请求id, 总是重新生成
+    ignore_incoming: bool,
+}
+
+impl RequestId {
+    /// 创建中间件
+    ///
+    /// Arguments:
+    ///
+    /// * `ignore_incoming`: 忽略客户端传入的请求id, 服务直接暴露在公网时可避免伪造的id污染日志
+    pub fn new(ignore_incoming: bool) -> Self {
+        RequestId { ignore_incoming }
+    }
+
+    /// 生成新的请求id
+    pub fn generate() -> CompactString {
+        let ms = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+        let seq = SEQUENCE.fetch_add(1, Ordering::Relaxed) & ((1 << SEQ_BITS) - 1);
+        format_compact!("{:016x}", (ms << SEQ_BITS) | seq)
+    }
+
+    /// 客户端传入的请求id是否合法, 只允许字母、数字及`-_.:`
+    fn is_valid(id: &str) -> bool {
+        !id.is_empty() && id.len() <= MAX_ID_LEN
+            && id.bytes().all(|b| b.is_ascii_alphanumeric() || b"-_.:".contains(&b))
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpMiddleware for RequestId {
+    async fn handle<'a>(&'a self, mut ctx: HttpContext, next: Next<'a>) -> HttpResponse {
+        let incoming = match self.ignore_incoming {
+            true => None,
+            false => ctx.header_str(X_REQUEST_ID).filter(|id| Self::is_valid(id)).map(CompactString::new),
+        };
+        let rid = incoming.unwrap_or_else(Self::generate);
+        ctx.request_id = Some(rid.clone());
+        let id = ctx.id;
+
+        let mut res = match next.run(ctx).await {
+            Ok(res) => res,
+            Err(e) => HttpServer::handle_error(id, e),
+        };
+        if let Ok(val) = HeaderValue::from_str(&rid) {
+            res.headers_mut().insert(X_REQUEST_ID, val);
+        }
+        Ok(res)
+    }
+}
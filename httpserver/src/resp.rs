@@ -1,8 +1,8 @@
 //! resp
 
-use std::fmt::Display;
+use std::{fmt::Display, str::FromStr, sync::OnceLock};
 
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use http_body_util::Full;
 use hyper::body::Bytes;
 use serde::{Deserialize, Serialize};
@@ -26,6 +26,110 @@ pub struct ApiResult<T> {
 /// Build http response object
 pub struct Resp;
 
+/// Global response envelope, the default `{code, message, data}` format is used when not set
+static ENVELOPE: OnceLock<Envelope> = OnceLock::new();
+
+/// Response envelope field mapping, lets clients that expect a different format
+/// (e.g. `{success, errorMsg, result}`) use the api without rewriting
+///
+/// Parsed from `key=name` pairs separated by comma, keys are `code`, `message`, `data` and `success`,
+/// an empty name omits the field, e.g. `success=success,code=,message=errorMsg,data=result`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Envelope {
+    // field names are stored as quoted json strings, empty means omitted
+    code: String,
+    message: String,
+    data: String,
+    success: String,
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Envelope {
+            code: quote("code"),
+            message: quote("message"),
+            data: quote("data"),
+            success: String::new(),
+        }
+    }
+}
+
+impl FromStr for Envelope {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut envelope = Envelope::default();
+        for item in s.split(',').map(str::trim).filter(|v| !v.is_empty()) {
+            let (key, name) = item.split_once('=').ok_or_else(|| anyhow!("invalid envelope item: {item}"))?;
+            let name = quote(name.trim());
+            match key.trim() {
+                "code" => envelope.code = name,
+                "message" => envelope.message = name,
+                "data" => envelope.data = name,
+                "success" => envelope.success = name,
+                key => return Err(anyhow!("unknown envelope field: {key}")),
+            }
+        }
+
+        if envelope.message.is_empty() || envelope.data.is_empty() {
+            return Err(anyhow!("envelope message and data field name can't be empty"));
+        }
+        if envelope.code.is_empty() && envelope.success.is_empty() {
+            return Err(anyhow!("envelope requires code or success field"));
+        }
+        let names = [&envelope.code, &envelope.message, &envelope.data, &envelope.success];
+        for (i, name) in names.iter().enumerate() {
+            if !name.is_empty() && names[..i].contains(name) {
+                return Err(anyhow!("duplicate envelope field name: {name}"));
+            }
+        }
+
+        Ok(envelope)
+    }
+}
+
+impl Envelope {
+    /// Build the response body, `data` is the serialized json of ApiResult.data
+    fn build(&self, code: u32, message: Option<&str>, data: Option<&[u8]>) -> anyhow::Result<Vec<u8>> {
+        let mut w = Vec::with_capacity(64 + data.map(|d| d.len()).unwrap_or(0));
+        w.push(b'{');
+        if !self.success.is_empty() {
+            w.extend_from_slice(self.success.as_bytes());
+            w.extend_from_slice(if code == 200 { b":true" } else { b":false" });
+        }
+        if !self.code.is_empty() {
+            if w.len() > 1 { w.push(b','); }
+            w.extend_from_slice(self.code.as_bytes());
+            w.push(b':');
+            w.extend_from_slice(itoa::Buffer::new().format(code).as_bytes());
+        }
+        if let Some(message) = message {
+            w.push(b',');
+            w.extend_from_slice(self.message.as_bytes());
+            w.push(b':');
+            serde_json::to_writer(&mut w, message)?;
+        }
+        if let Some(data) = data {
+            w.push(b',');
+            w.extend_from_slice(self.data.as_bytes());
+            w.push(b':');
+            w.extend_from_slice(data);
+        }
+        w.push(b'}');
+        Ok(w)
+    }
+}
+
+/// Field name as quoted json string, empty name keeps empty
+fn quote(name: &str) -> String {
+    if name.is_empty() {
+        String::new()
+    } else {
+        // serializing a str never fails
+        serde_json::to_string(name).unwrap()
+    }
+}
+
 impl<T> ApiResult<T> {
     /// Generate an ApiResult that represents success using the specified data
     #[inline]
@@ -121,6 +225,15 @@ impl<T> ApiResult<T> {
 }
 
 impl Resp {
+    /// Set the global response envelope, can only be set once
+    ///
+    /// Returns:
+    ///
+    /// false if the envelope has been set
+    pub fn set_envelope(envelope: Envelope) -> bool {
+        ENVELOPE.set(envelope).is_ok()
+    }
+
     /// Create a reply message with the specified status code and content
     ///
     /// Arguments:
@@ -210,6 +323,9 @@ impl Resp {
 
     /// Create a reply message with 200, response body is empty
     pub fn ok_with_empty() -> HttpResponse {
+        if let Some(envelope) = ENVELOPE.get() {
+            return Self::resp_ok(envelope.build(200, None, None)?);
+        }
         Self::resp_ok(hyper::body::Bytes::from(r#"{"code":200}"#))
     }
 
@@ -235,6 +351,13 @@ impl Resp {
     #[inline]
     pub fn ok<T: ?Sized + Serialize>(data: &T) -> HttpResponse {
         // Self::ok_opt(Some(data))
+        if let Some(envelope) = ENVELOPE.get() {
+            #[cfg(not(feature = "english"))]
+            let data = serde_json::to_vec(data).context("json序列化失败")?;
+            #[cfg(feature = "english")]
+            let data = serde_json::to_vec(data).context("json serialization failed")?;
+            return Self::resp_ok(envelope.build(200, None, Some(&data))?);
+        }
         let mut w = Vec::with_capacity(512);
         w.extend_from_slice(br#"{"code":200,"data":"#);
         #[cfg(not(feature = "english"))]
@@ -313,6 +436,9 @@ impl Resp {
     ///         10086, "required field `username`")?;
     /// ````
     pub fn fail_with_status(status: hyper::StatusCode, code: u32, message: &str) -> HttpResponse {
+        if let Some(envelope) = ENVELOPE.get() {
+            return Self::resp(status, envelope.build(code, Some(message), None)?);
+        }
         let mut buf = itoa::Buffer::new();
         let code = buf.format(code);
         let mut w = Vec::with_capacity(256);
@@ -327,6 +453,27 @@ impl Resp {
         Self::resp(status, w)
     }
 
+    /// Create a reply message with specified http status, error code and error details
+    ///
+    /// Arguments:
+    ///
+    /// * `status`: http reponse status
+    /// * `code`: http error code
+    /// * `message`: http error message
+    /// * `data`: error details, e.g. the field errors of request validation
+    pub fn fail_with_data<T: ?Sized + Serialize>(status: hyper::StatusCode, code: u32, message: &str,
+            data: &T) -> HttpResponse {
+        #[cfg(not(feature = "english"))]
+        let data = serde_json::to_vec(data).context("json序列化失败")?;
+        #[cfg(feature = "english")]
+        let data = serde_json::to_vec(data).context("json serialization failed")?;
+        let body = match ENVELOPE.get() {
+            Some(envelope) => envelope.build(code, Some(message), Some(&data))?,
+            None => Envelope::default().build(code, Some(message), Some(&data))?,
+        };
+        Self::resp(status, body)
+    }
+
     /// Create a reply message with specified http status and error code
     ///
     /// Arguments:
@@ -0,0 +1,269 @@
+//! 带路径参数的路由, 支持命名参数(`:id`)、通配符(`*rest`)及段内通配(`*.png`、`app-*.js`)
+use compact_str::CompactString;
+use fnv::FnvHashMap;
+use hyper::Method;
+
+use crate::{BoxHttpHandler, HttpHandler};
+
+/// 路径参数
+#[derive(Debug, Default, Clone)]
+pub struct PathParams {
+    items: Vec<(CompactString, CompactString)>,
+    /// 最后一个参数是否为通配符捕获的剩余路径
+    wildcard: bool,
+}
+
+impl PathParams {
+    /// 获取指定名称的路径参数
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.items.iter().find(|(k, _)| *k == name).map(|(_, v)| v.as_str())
+    }
+
+    /// 通配符(`files/*path`)捕获的剩余路径, 不含开头的`/`且未经url解码, 与通配符名称无关
+    pub fn wildcard(&self) -> Option<&str> {
+        match self.wildcard {
+            true => self.items.last().map(|(_, v)| v.as_str()),
+            false => None,
+        }
+    }
+
+    /// 是否没有路径参数
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// 路径参数数量
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// 遍历所有路径参数
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.items.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub(crate) fn insert(&mut self, name: &str, value: &str) {
+        self.items.push((CompactString::new(name), CompactString::new(value)));
+    }
+
+    pub(crate) fn insert_wildcard(&mut self, name: &str, value: &str) {
+        self.insert(name, value);
+        self.wildcard = true;
+    }
+
+    fn push(&mut self, name: &CompactString, value: &str) {
+        let value = match urlencoding::decode(value) {
+            Ok(v) => CompactString::new(v),
+            Err(_) => CompactString::new(value),
+        };
+        self.items.push((name.clone(), value));
+    }
+
+    /// 剩余路径不做url解码, 避免`%2F`解码后改变路径层级
+    fn push_wildcard(&mut self, name: &CompactString, value: &str) {
+        self.items.push((name.clone(), CompactString::new(value)));
+        self.wildcard = true;
+    }
+}
+
+/// 同一路径下按请求方法注册的处理函数
+#[derive(Default)]
+pub(crate) struct Endpoint {
+    /// 指定请求方法的处理函数
+    methods: Vec<(Method, BoxHttpHandler)>,
+    /// 不限请求方法的处理函数
+    any: Option<BoxHttpHandler>,
+}
+
+impl Endpoint {
+    /// 注册处理函数, `method`为None时不限请求方法, 重复注册时后注册的覆盖先注册的
+    pub fn insert(&mut self, method: Option<Method>, handler: BoxHttpHandler) {
+        match method {
+            Some(method) => match self.methods.iter_mut().find(|(m, _)| *m == method) {
+                Some(item) => item.1 = handler,
+                None => self.methods.push((method, handler)),
+            },
+            None => self.any = Some(handler),
+        }
+    }
+
+    /// 查找请求方法对应的处理函数, HEAD请求未单独注册时使用GET的处理函数
+    pub fn get(&self, method: &Method) -> Option<&dyn HttpHandler> {
+        let find = |method: &Method| self.methods.iter()
+            .find(|(m, _)| m == method)
+            .map(|(_, h)| h.as_ref());
+
+        find(method)
+            .or_else(|| if *method == Method::HEAD { find(&Method::GET) } else { None })
+            .or(self.any.as_deref())
+    }
+
+    /// 允许的请求方法, 用于405回复的Allow头
+    pub fn allow(&self) -> String {
+        let mut allow = String::new();
+        for (m, _) in self.methods.iter() {
+            if !allow.is_empty() {
+                allow.push_str(", ");
+            }
+            allow.push_str(m.as_str());
+        }
+        let has = |method: &Method| self.methods.iter().any(|(m, _)| m == method);
+        if has(&Method::GET) && !has(&Method::HEAD) {
+            allow.push_str(", HEAD");
+        }
+        allow
+    }
+}
+
+#[derive(Default)]
+struct Node {
+    statics: FnvHashMap<CompactString, Node>,
+    /// 段内通配, 按注册顺序匹配
+    globs: Vec<(CompactString, Node)>,
+    param: Option<(CompactString, Box<Node>)>,
+    wildcard: Option<(CompactString, Endpoint)>,
+    endpoint: Option<Endpoint>,
+}
+
+/// 按路径分段匹配的路由表, 匹配优先级: 静态段 > 段内通配 > 命名参数 > 通配符
+#[derive(Default)]
+pub(crate) struct ParamRouter {
+    root: Node,
+    /// 已注册的路由, 用于日志输出
+    paths: Vec<CompactString>,
+}
+
+impl ParamRouter {
+    /// 路径是否包含路径参数
+    pub fn is_param_path(path: &str) -> bool {
+        path.split('/').any(|s| s.len() > 1 && (s.starts_with(':') || s.contains('*')))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    pub fn paths(&self) -> &[CompactString] {
+        &self.paths
+    }
+
+    /// 注册路由, 通配符只能作为最后一段, `method`为None时不限请求方法
+    pub fn insert(&mut self, method: Option<Method>, path: &str, handler: BoxHttpHandler) {
+        if !self.paths.iter().any(|p| *p == path) {
+            self.paths.push(CompactString::new(path));
+        }
+
+        let mut node = &mut self.root;
+        let mut segs = path.split('/').filter(|s| !s.is_empty()).peekable();
+
+        while let Some(seg) = segs.next() {
+            if let Some(name) = seg.strip_prefix('*').filter(|s| is_wildcard_name(s)) {
+                assert!(segs.peek().is_none(), "wildcard must be the last segment: {path}");
+                let wildcard = node.wildcard.get_or_insert_with(|| (CompactString::new(name), Endpoint::default()));
+                assert!(wildcard.0 == name, "conflicting wildcard name: {path}");
+                wildcard.1.insert(method, handler);
+                return;
+            }
+
+            node = if let Some(name) = seg.strip_prefix(':') {
+                let param = node.param.get_or_insert_with(|| (CompactString::new(name), Box::default()));
+                assert!(param.0 == name, "conflicting path parameter name: {path}");
+                param.1.as_mut()
+            } else if seg.contains('*') {
+                match node.globs.iter().position(|(p, _)| *p == seg) {
+                    Some(pos) => &mut node.globs[pos].1,
+                    None => {
+                        node.globs.push((CompactString::new(seg), Node::default()));
+                        &mut node.globs.last_mut().unwrap().1
+                    }
+                }
+            } else {
+                node.statics.entry(CompactString::new(seg)).or_default()
+            };
+        }
+
+        node.endpoint.get_or_insert_with(Endpoint::default).insert(method, handler);
+    }
+
+    /// 查找路由, 返回路径对应的处理函数集合及捕获的路径参数
+    pub fn find<'a>(&'a self, path: &str) -> Option<(&'a Endpoint, PathParams)> {
+        let segs: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let mut params = PathParams::default();
+        let endpoint = Self::find_node(&self.root, path, &segs, &mut params)?;
+        Some((endpoint, params))
+    }
+
+    fn find_node<'a>(node: &'a Node, path: &str, segs: &[&str], params: &mut PathParams)
+            -> Option<&'a Endpoint> {
+        let (seg, rest) = match segs.split_first() {
+            Some(v) => v,
+            None => {
+                if node.endpoint.is_some() {
+                    return node.endpoint.as_ref();
+                }
+                // 通配符允许匹配空路径
+                let (name, endpoint) = node.wildcard.as_ref()?;
+                params.push_wildcard(name, "");
+                return Some(endpoint);
+            }
+        };
+
+        if let Some(child) = node.statics.get(*seg) {
+            if let Some(h) = Self::find_node(child, path, rest, params) {
+                return Some(h);
+            }
+        }
+
+        for (pattern, child) in node.globs.iter() {
+            if glob_match(pattern, seg) {
+                if let Some(h) = Self::find_node(child, path, rest, params) {
+                    return Some(h);
+                }
+            }
+        }
+
+        if let Some((name, child)) = &node.param {
+            let len = params.len();
+            params.push(name, seg);
+            if let Some(h) = Self::find_node(child, path, rest, params) {
+                return Some(h);
+            }
+            params.items.truncate(len);
+        }
+
+        if let Some((name, endpoint)) = &node.wildcard {
+            // 通配符捕获剩余的全部路径
+            let pos = seg.as_ptr() as usize - path.as_ptr() as usize;
+            params.push_wildcard(name, &path[pos..]);
+            return Some(endpoint);
+        }
+
+        None
+    }
+}
+
+/// `*`后面是否为通配符名称, 否则该段为段内通配, 例如`*.png`
+fn is_wildcard_name(s: &str) -> bool {
+    s.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+/// 段内通配匹配, `*`匹配任意数量的字符(不跨越`/`)
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    // 第一部分必须是前缀
+    let first = parts.next().unwrap_or("");
+    let mut text = match text.strip_prefix(first) {
+        Some(t) => t,
+        None => return false,
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    // 最后一部分必须是后缀
+    let last = parts.pop().unwrap_or("");
+    for part in parts.iter().filter(|p| !p.is_empty()) {
+        match text.find(part) {
+            Some(pos) => text = &text[pos + part.len()..],
+            None => return false,
+        }
+    }
+    text.ends_with(last)
+}
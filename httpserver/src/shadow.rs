@@ -0,0 +1,160 @@
+//! 影子路由中间件, 将指定接口的部分请求同时交给候选实现处理, 候选实现的结果只用于与正式实现比较,
+//! 差异记录到日志中, 用于在生产流量下验证接口重写的正确性
+use std::{sync::{atomic::{AtomicU64, Ordering}, Arc}, time::Instant};
+
+use compact_str::CompactString;
+use fnv::FnvHashMap;
+use http_body_util::{BodyExt, Full};
+use hyper::{body::Bytes, StatusCode};
+use sha1::{Digest, Sha1};
+
+use crate::{log_debug, log_warn, HttpContext, HttpHandler, HttpMiddleware, HttpResponse, Next, Request, Response};
+
+/// 影子请求的上下文属性, 候选实现可以据此跳过访问统计等副作用
+pub const SHADOW_ATTR: &str = "shadow";
+
+/// 影子路由中间件
+///
+/// 需放在登录校验等中间件之后, 候选实现收到的请求与正式实现相同(包括`uid`等上下文),
+/// 在正式实现回复后异步执行, 不影响正式请求的耗时. 候选实现不能修改数据
+#[derive(Default)]
+pub struct Shadow {
+    routes: FnvHashMap<CompactString, ShadowRoute>,
+}
+
+struct ShadowRoute {
+    /// 候选实现
+    handler: Arc<dyn HttpHandler>,
+    /// 镜像的请求百分比(1-100)
+    percent: u64,
+    /// 请求计数, 用于按比例均匀抽样
+    count: AtomicU64,
+}
+
+impl Shadow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 添加影子路由
+    ///
+    /// Arguments:
+    ///
+    /// * `path`: 完整的请求路径(包括接口前缀), 例如`/api/list`
+    /// * `percent`: 镜像的请求百分比, 超过100时按100处理, 为0时忽略
+    /// * `handler`: 候选实现
+    pub fn route(mut self, path: &str, percent: u32, handler: impl HttpHandler) -> Self {
+        if percent > 0 {
+            self.routes.insert(CompactString::new(path), ShadowRoute {
+                handler: Arc::new(handler),
+                percent: percent.min(100) as u64,
+                count: AtomicU64::new(0),
+            });
+        }
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// 是否为影子请求
+    pub fn is_shadow(ctx: &HttpContext) -> bool {
+        ctx.attr(SHADOW_ATTR).is_some()
+    }
+}
+
+impl ShadowRoute {
+    /// 每100个请求中抽取`percent`个, 抽中的请求均匀分布
+    fn sampled(&self) -> bool {
+        let n = self.count.fetch_add(1, Ordering::Relaxed);
+        n * self.percent / 100 != (n + 1) * self.percent / 100
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpMiddleware for Shadow {
+    async fn handle<'a>(&'a self, ctx: HttpContext, next: Next<'a>) -> HttpResponse {
+        let route = match self.routes.get(ctx.req.uri().path()) {
+            Some(route) if route.sampled() => route,
+            _ => return next.run(ctx).await,
+        };
+
+        let shadow_ctx = duplicate(&ctx);
+        let (parts, body) = next.run(ctx).await?.into_parts();
+        let body = body.collect().await?.to_bytes();
+        let handler = route.handler.clone();
+        tokio::spawn(compare(handler, shadow_ctx, parts.status, body.clone()));
+
+        Ok(Response::from_parts(parts, Full::new(body)))
+    }
+}
+
+/// 复制请求上下文, 并标记为影子请求
+fn duplicate(ctx: &HttpContext) -> HttpContext {
+    let src = &ctx.req;
+    let mut req = Request::new(Full::new(ctx.body.clone()));
+    *req.method_mut() = src.method().clone();
+    *req.uri_mut() = src.uri().clone();
+    *req.version_mut() = src.version();
+    *req.headers_mut() = src.headers().clone();
+    *req.extensions_mut() = src.extensions().clone();
+
+    let mut shadow = HttpContext {
+        req,
+        body: ctx.body.clone(),
+        path_len: ctx.path_len,
+        params: ctx.params.clone(),
+        addr: ctx.addr,
+        id: ctx.id,
+        request_id: ctx.request_id.clone(),
+        uid: ctx.uid.clone(),
+        attrs: ctx.attrs.clone(),
+    };
+    shadow.set_attr(CompactString::new(SHADOW_ATTR), true);
+    shadow
+}
+
+/// 执行候选实现并与正式实现的回复比较
+async fn compare(handler: Arc<dyn HttpHandler>, ctx: HttpContext, status: StatusCode, body: Bytes) {
+    let id = ctx.id;
+    let path = CompactString::new(ctx.req.uri().path());
+    let start = Instant::now();
+
+    let (shadow_status, shadow_body) = match handler.handle(ctx).await {
+        Ok(resp) => {
+            let (parts, body) = resp.into_parts();
+            match body.collect().await {
+                Ok(body) => (parts.status, body.to_bytes()),
+                Err(_) => (parts.status, Bytes::new()),
+            }
+        }
+        Err(e) => {
+            #[cfg(not(feature = "english"))]
+            log_warn!(id, "影子路由 {path} 候选实现执行失败: {e:?}");
+            #[cfg(feature = "english")]
+            log_warn!(id, "shadow {path} candidate failed: {e:?}");
+            return;
+        }
+    };
+    let elapsed = start.elapsed();
+
+    if status == shadow_status && body == shadow_body {
+        #[cfg(not(feature = "english"))]
+        log_debug!(id, "影子路由 {path} 结果一致, 候选实现耗时 {elapsed:?}");
+        #[cfg(feature = "english")]
+        log_debug!(id, "shadow {path} matched, candidate took {elapsed:?}");
+    } else {
+        #[cfg(not(feature = "english"))]
+        log_warn!(id, "影子路由 {path} 结果不一致, 候选实现耗时 {elapsed:?}, 状态码: {} / {}, 内容: {} / {}",
+            status.as_u16(), shadow_status.as_u16(), digest(&body), digest(&shadow_body));
+        #[cfg(feature = "english")]
+        log_warn!(id, "shadow {path} differs, candidate took {elapsed:?}, status: {} / {}, body: {} / {}",
+            status.as_u16(), shadow_status.as_u16(), digest(&body), digest(&shadow_body));
+    }
+}
+
+/// 回复内容的长度及sha1摘要用于日志输出, 回复中包含账号信息, 不能输出原文
+fn digest(body: &[u8]) -> String {
+    format!("len={} sha1={:x}", body.len(), Sha1::digest(body))
+}
@@ -0,0 +1,216 @@
+//! Server-Sent Events, 以流式回复体持续推送事件, 空闲时定时发送注释保持连接
+//!
+//! ```rust,ignore
+//! async fn events(ctx: HttpContext) -> HttpResponse {
+//!     Sse::response(|mut sse| async move {
+//!         while sse.send("tick", "hello").await {
+//!             tokio::time::sleep(Duration::from_secs(1)).await;
+//!         }
+//!     })
+//! }
+//! ```
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use http_body_util::Full;
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+use crate::{HttpResponse, Response};
+
+/// 事件流的Content-Type
+pub const TEXT_EVENT_STREAM: &str = "text/event-stream";
+/// 保持连接的注释发送间隔, 避免空闲连接被代理断开
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+/// 待发送数据的缓冲数量
+const CHANNEL_CAPACITY: usize = 16;
+
+/// 流式回复体的接收端, 通过回复的extensions传递给服务端连接
+#[derive(Clone)]
+pub(crate) struct BodyStream {
+    rx: Arc<Mutex<Option<mpsc::Receiver<Bytes>>>>,
+    /// 回复体的总长度, 未知时使用chunked编码
+    len: Option<u64>,
+    /// 发送端异常中止, 接收完后以错误结束回复体, 使客户端能发现回复不完整
+    aborted: Arc<AtomicBool>,
+}
+
+/// 服务端实际发送的回复体, 普通回复为完整数据, 事件流及流式回复为持续接收的数据
+pub(crate) enum ServeBody {
+    Full(Full<Bytes>),
+    Stream(mpsc::Receiver<Bytes>, Option<u64>, Arc<AtomicBool>),
+}
+
+impl BodyStream {
+    pub(crate) fn new(rx: mpsc::Receiver<Bytes>, len: Option<u64>) -> Self {
+        BodyStream { rx: Arc::new(Mutex::new(Some(rx))), len, aborted: Arc::new(AtomicBool::new(false)) }
+    }
+
+    /// 发送端的中止标志
+    pub(crate) fn aborted(&self) -> Arc<AtomicBool> {
+        self.aborted.clone()
+    }
+}
+
+/// 事件流发送端
+pub struct SseSender {
+    tx: mpsc::Sender<Bytes>,
+}
+
+/// Server-Sent Events回复
+pub struct Sse;
+
+impl Sse {
+    /// 生成事件流回复, `f`在后台任务中执行, 通过`SseSender`推送事件, `f`结束或者客户端断开时关闭事件流
+    pub fn response<F, Fut>(f: F) -> HttpResponse
+    where
+        F: FnOnce(SseSender) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+        let keep_alive = SseSender { tx: tx.clone() };
+
+        tokio::spawn(async move {
+            let keep_alive_task = async {
+                let mut interval = tokio::time::interval(KEEP_ALIVE);
+                interval.tick().await;
+                loop {
+                    interval.tick().await;
+                    if !keep_alive.comment("").await {
+                        break;
+                    }
+                }
+            };
+            tokio::select! {
+                _ = f(SseSender { tx }) => {}
+                _ = keep_alive_task => {}
+            }
+        });
+
+        let mut res = hyper::Response::builder()
+            .header(hyper::header::CONTENT_TYPE, TEXT_EVENT_STREAM)
+            .header(hyper::header::CACHE_CONTROL, "no-cache")
+            .header("X-Accel-Buffering", "no")
+            .body(Full::new(Bytes::new()))?;
+        res.extensions_mut().insert(BodyStream::new(rx, None));
+        Ok(res)
+    }
+
+    /// 回复是否为事件流或者流式回复
+    pub fn is_stream(res: &Response) -> bool {
+        res.extensions().get::<BodyStream>().is_some()
+    }
+}
+
+impl SseSender {
+    /// 发送事件, `event`为空时不发送事件名称, 多行数据拆分为多个`data:`行
+    ///
+    /// Returns:
+    ///
+    /// 客户端已断开时返回false
+    pub async fn send(&self, event: &str, data: &str) -> bool {
+        let mut buf = String::with_capacity(event.len() + data.len() + 16);
+        if !event.is_empty() {
+            buf.push_str("event: ");
+            buf.push_str(event);
+            buf.push('\n');
+        }
+        for line in data.split('\n') {
+            buf.push_str("data: ");
+            buf.push_str(line.trim_end_matches('\r'));
+            buf.push('\n');
+        }
+        buf.push('\n');
+        self.send_raw(buf).await
+    }
+
+    /// 发送json格式的事件, 序列化失败时返回false
+    pub async fn send_json<T: Serialize + ?Sized>(&self, event: &str, data: &T) -> bool {
+        match serde_json::to_string(data) {
+            Ok(text) => self.send(event, &text).await,
+            Err(_) => false,
+        }
+    }
+
+    /// 发送注释, 客户端会忽略注释, 通常用于保持连接
+    pub async fn comment(&self, text: &str) -> bool {
+        let mut buf = String::with_capacity(text.len() + 4);
+        for line in text.split('\n') {
+            buf.push(':');
+            buf.push_str(line.trim_end_matches('\r'));
+            buf.push('\n');
+        }
+        buf.push('\n');
+        self.send_raw(buf).await
+    }
+
+    /// 客户端是否已断开
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+
+    /// 等待客户端断开
+    pub async fn closed(&self) {
+        self.tx.closed().await
+    }
+
+    async fn send_raw(&self, data: String) -> bool {
+        self.tx.send(Bytes::from(data)).await.is_ok()
+    }
+}
+
+impl ServeBody {
+    /// 将处理函数的回复转换为实际发送的回复, 事件流及流式回复取出接收端作为回复体
+    pub(crate) fn from_response(mut res: Response) -> hyper::Response<ServeBody> {
+        let stream = res.extensions_mut().remove::<BodyStream>()
+            .and_then(|s| s.rx.lock().ok().and_then(|mut rx| rx.take()).map(|rx| (rx, s.len, s.aborted)));
+        let (mut parts, body) = res.into_parts();
+        match stream {
+            Some((rx, len, aborted)) => {
+                // 长度已知时由回复体的size_hint生成Content-Length
+                parts.headers.remove(hyper::header::CONTENT_LENGTH);
+                hyper::Response::from_parts(parts, ServeBody::Stream(rx, len, aborted))
+            }
+            None => hyper::Response::from_parts(parts, ServeBody::Full(body)),
+        }
+    }
+}
+
+impl Body for ServeBody {
+    type Data = Bytes;
+    type Error = std::io::Error;
+
+    fn poll_frame(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Self::Error>>> {
+        match self.get_mut() {
+            ServeBody::Full(body) => Pin::new(body).poll_frame(cx).map_err(|e| match e {}),
+            ServeBody::Stream(rx, _, aborted) => rx.poll_recv(cx).map(|v| match v {
+                Some(data) => Some(Ok(Frame::data(data))),
+                // 中止时返回错误, 服务端断开连接而不是正常结束回复
+                None if aborted.load(Ordering::Acquire) =>
+                    Some(Err(std::io::Error::new(std::io::ErrorKind::Interrupted, "response stream aborted"))),
+                None => None,
+            }),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match self {
+            ServeBody::Full(body) => body.is_end_stream(),
+            ServeBody::Stream(..) => false,
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self {
+            ServeBody::Full(body) => body.size_hint(),
+            ServeBody::Stream(_, Some(len), _) => SizeHint::with_exact(*len),
+            ServeBody::Stream(_, None, _) => SizeHint::default(),
+        }
+    }
+}
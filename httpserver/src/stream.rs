@@ -0,0 +1,130 @@
+//! 流式回复, 大文件或者边生成边发送的数据分块发送, 无需在内存中保存完整的回复体
+//!
+//! ```rust,ignore
+//! async fn download(ctx: HttpContext) -> HttpResponse {
+//!     let builder = hyper::Response::builder().header(CONTENT_TYPE, "text/csv");
+//!     let (res, tx) = StreamBody::response(builder, None)?;
+//!     tokio::task::spawn_blocking(move || {
+//!         let mut w = tx.writer();
+//!         let _ = writeln!(w, "a,b,c");
+//!     });
+//!     Ok(res)
+//! }
+//! ```
+use std::{io::Write, sync::{atomic::{AtomicBool, Ordering}, Arc}};
+
+use http_body_util::Full;
+use hyper::body::Bytes;
+use tokio::sync::mpsc;
+
+use crate::{sse::BodyStream, Response};
+
+/// 待发送数据的缓冲数量
+const CHANNEL_CAPACITY: usize = 8;
+/// `StreamWriter`每次发送的数据块大小
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// 流式回复
+pub struct StreamBody;
+
+/// 流式回复的发送端, 所有发送端释放后回复结束, 调用`abort`后以错误结束
+#[derive(Clone)]
+pub struct StreamSender {
+    tx: mpsc::Sender<Bytes>,
+    aborted: Arc<AtomicBool>,
+}
+
+/// 将写入的数据按块发送的`Write`实现, 只能在阻塞线程中使用(例如`spawn_blocking`), 释放时发送剩余的数据
+pub struct StreamWriter {
+    sender: StreamSender,
+    buf: Vec<u8>,
+}
+
+impl StreamBody {
+    /// 生成流式回复, 回复体由返回的发送端发送
+    ///
+    /// Arguments:
+    ///
+    /// * `builder`: 回复的状态码及头部
+    /// * `len`: 回复体的总长度, 为None时使用chunked编码
+    pub fn response(builder: hyper::http::response::Builder, len: Option<u64>) -> anyhow::Result<(Response, StreamSender)> {
+        let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+        let stream = BodyStream::new(rx, len);
+        let aborted = stream.aborted();
+        let mut res = builder.body(Full::new(Bytes::new()))?;
+        res.extensions_mut().insert(stream);
+        Ok((res, StreamSender { tx, aborted }))
+    }
+}
+
+impl StreamSender {
+    /// 发送一块数据
+    ///
+    /// Returns:
+    ///
+    /// 客户端已断开时返回false
+    pub async fn send(&self, data: Bytes) -> bool {
+        self.tx.send(data).await.is_ok()
+    }
+
+    /// 在阻塞线程中发送一块数据, 客户端已断开时返回false
+    pub fn blocking_send(&self, data: Bytes) -> bool {
+        self.tx.blocking_send(data).is_ok()
+    }
+
+    /// 客户端是否已断开
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+
+    /// 中止回复, 已发送的数据之后断开连接, 客户端不会把不完整的回复当成完整的
+    pub fn abort(&self) {
+        self.aborted.store(true, Ordering::Release);
+    }
+
+    /// 转换为按块发送的`Write`实现
+    pub fn writer(self) -> StreamWriter {
+        StreamWriter { sender: self, buf: Vec::with_capacity(CHUNK_SIZE) }
+    }
+}
+
+impl StreamWriter {
+    /// 丢弃未发送的数据并中止回复, 见`StreamSender::abort`
+    pub fn abort(mut self) {
+        self.buf.clear();
+        self.sender.abort();
+    }
+
+    fn send_buf(&mut self) -> std::io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let data = std::mem::replace(&mut self.buf, Vec::with_capacity(CHUNK_SIZE));
+        self.sender.tx.blocking_send(Bytes::from(data))
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::BrokenPipe))
+    }
+}
+
+impl Write for StreamWriter {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        if self.sender.is_closed() {
+            return Err(std::io::ErrorKind::BrokenPipe.into());
+        }
+        let n = data.len().min(CHUNK_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&data[..n]);
+        if self.buf.len() >= CHUNK_SIZE {
+            self.send_buf()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.send_buf()
+    }
+}
+
+impl Drop for StreamWriter {
+    fn drop(&mut self) {
+        let _ = self.send_buf();
+    }
+}
@@ -0,0 +1,122 @@
+//! https支持, 证书文件变化时无需重启即可重新加载
+use std::{
+    fs::File, io::BufReader, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex, RwLock}, time::SystemTime
+};
+
+use anyhow::{anyhow, bail, Result};
+use tokio_rustls::{
+    rustls::{self, pki_types::{CertificateDer, PrivateKeyDer}, ServerConfig},
+    TlsAcceptor,
+};
+
+/// tls配置, 保存当前使用的证书, 支持运行时替换
+pub struct TlsConfig {
+    /// 证书链文件(pem格式)
+    cert_file: String,
+    /// 私钥文件(pem格式)
+    key_file: String,
+    /// 当前使用的tls配置, 替换后只影响新建立的连接
+    config: RwLock<Arc<ServerConfig>>,
+    /// 证书文件最后修改时间, 用于检测文件变化
+    modified: Mutex<Option<SystemTime>>,
+    /// 是否通过ALPN协商http2
+    http2: AtomicBool,
+}
+
+impl TlsConfig {
+    /// 从pem格式的证书链文件及私钥文件创建tls配置
+    ///
+    /// Arguments:
+    ///
+    /// * `cert_file`: 证书链文件
+    /// * `key_file`: 私钥文件, 支持pkcs8/pkcs1/sec1格式
+    pub fn from_files(cert_file: &str, key_file: &str) -> Result<Self> {
+        let config = load_config(cert_file, key_file, false)?;
+        Ok(TlsConfig {
+            cert_file: cert_file.to_owned(),
+            key_file: key_file.to_owned(),
+            config: RwLock::new(Arc::new(config)),
+            modified: Mutex::new(files_modified(cert_file, key_file)),
+            http2: AtomicBool::new(false),
+        })
+    }
+
+    /// 当前使用的tls接收器
+    pub fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(self.config.read().unwrap().clone())
+    }
+
+    /// 设置是否通过ALPN协商http2, 由`HttpServer`根据连接参数设置
+    pub fn set_http2(&self, enable: bool) {
+        self.http2.store(enable, Ordering::Relaxed);
+        let mut config = ServerConfig::clone(&self.config.read().unwrap());
+        config.alpn_protocols = alpn_protocols(enable);
+        *self.config.write().unwrap() = Arc::new(config);
+    }
+
+    /// 重新加载证书文件, 加载失败时继续使用原来的证书
+    pub fn reload(&self) -> Result<()> {
+        let modified = files_modified(&self.cert_file, &self.key_file);
+        let config = load_config(&self.cert_file, &self.key_file, self.http2.load(Ordering::Relaxed))?;
+        *self.config.write().unwrap() = Arc::new(config);
+        *self.modified.lock().unwrap() = modified;
+
+        #[cfg(not(feature = "english"))]
+        log::info!("重新加载tls证书: {}", self.cert_file);
+        #[cfg(feature = "english")]
+        log::info!("reload tls certificate: {}", self.cert_file);
+        Ok(())
+    }
+
+    /// 证书文件发生变化时重新加载, 由定时任务调用
+    ///
+    /// Returns:
+    ///
+    /// 是否重新加载了证书
+    pub fn reload_if_changed(&self) -> Result<bool> {
+        let modified = files_modified(&self.cert_file, &self.key_file);
+        if modified.is_none() || *self.modified.lock().unwrap() == modified {
+            return Ok(false);
+        }
+        self.reload()?;
+        Ok(true)
+    }
+}
+
+/// 证书及私钥文件中较晚的修改时间
+fn files_modified(cert_file: &str, key_file: &str) -> Option<SystemTime> {
+    let mtime = |f: &str| std::fs::metadata(f).and_then(|m| m.modified()).ok();
+    mtime(cert_file).max(mtime(key_file))
+}
+
+fn load_config(cert_file: &str, key_file: &str, http2: bool) -> Result<ServerConfig> {
+    let open = |f: &str| File::open(f).map(BufReader::new)
+        .map_err(|e| anyhow!("open {f} failed: {e}"));
+
+    let certs: Vec<CertificateDer<'static>> = rustls_pemfile::certs(&mut open(cert_file)?)
+        .collect::<Result<_, _>>()?;
+    if certs.is_empty() {
+        bail!("no certificate found in {cert_file}");
+    }
+    let key: PrivateKeyDer<'static> = rustls_pemfile::private_key(&mut open(key_file)?)?
+        .ok_or_else(|| anyhow!("no private key found in {key_file}"))?;
+
+    // 显式指定加密算法实现, 避免依赖库同时启用多个实现时无法确定缺省实现
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut config = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    config.alpn_protocols = alpn_protocols(http2);
+
+    Ok(config)
+}
+
+/// ALPN协议列表, 启用http2时优先协商http2
+fn alpn_protocols(http2: bool) -> Vec<Vec<u8>> {
+    if http2 {
+        vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+    } else {
+        vec![b"http/1.1".to_vec()]
+    }
+}
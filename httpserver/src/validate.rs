@@ -0,0 +1,226 @@
+//! 请求参数校验, 收集所有字段的错误后一起回复, 回复格式与其它错误相同,
+//! http状态及错误码为400, `data`为各字段的错误信息: `[{"field":"user","message":"..."}]`
+//!
+//! ```rust,ignore
+//! httpserver::validate!(req_param,
+//!     user: length(1..=64),
+//!     url: pattern(&URL_RE),
+//!     expire: range(0..=1440),
+//!     tags: custom(|tags: &Vec<String>| check_tags(tags)),
+//! );
+//! ```
+use std::{error::Error as StdError, fmt::Display, ops::RangeInclusive};
+
+use compact_str::CompactString;
+use regex::Regex;
+use serde::Serialize;
+
+/// 校验失败时回复的错误码
+pub const VALIDATION_CODE: u32 = 400;
+
+/// 字段错误
+#[derive(Serialize, Debug, Clone)]
+pub struct FieldError {
+    /// 字段名称
+    pub field: CompactString,
+    /// 错误信息
+    pub message: String,
+}
+
+/// 请求参数校验错误, 包含所有字段的错误
+#[derive(Debug)]
+pub struct ValidationError {
+    pub errors: Vec<FieldError>,
+}
+
+/// 请求参数校验器
+#[derive(Default)]
+pub struct Validator {
+    errors: Vec<FieldError>,
+}
+
+/// 字段是否有值, 用于`required`规则
+pub trait Present {
+    fn is_present(&self) -> bool;
+}
+
+/// 字段的长度(字符串为字符数), None表示未提供, 未提供的字段不校验长度
+pub trait Measure {
+    fn measure(&self) -> Option<usize>;
+}
+
+/// 字段的文本内容, None表示未提供, 未提供或者为空的字段不校验格式
+pub trait Text {
+    fn text(&self) -> Option<&str>;
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 添加字段错误
+    pub fn error(&mut self, field: &str, message: impl Into<String>) -> &mut Self {
+        self.errors.push(FieldError { field: CompactString::new(field), message: message.into() });
+        self
+    }
+
+    /// 字段不能为空, Option为None、字符串或者数组为空时校验失败
+    pub fn required<T: ?Sized + Present>(&mut self, field: &str, value: &T) -> &mut Self {
+        if !value.is_present() {
+            #[cfg(not(feature = "english"))]
+            self.error(field, "不能为空");
+            #[cfg(feature = "english")]
+            self.error(field, "is required");
+        }
+        self
+    }
+
+    /// 字段长度必须在指定范围内, 字符串按字符计算长度, 数组按元素数量计算长度
+    pub fn length<T: ?Sized + Measure>(&mut self, field: &str, value: &T, range: RangeInclusive<usize>) -> &mut Self {
+        if let Some(len) = value.measure() {
+            if !range.contains(&len) {
+                #[cfg(not(feature = "english"))]
+                self.error(field, format!("长度必须在{}-{}之间", range.start(), range.end()));
+                #[cfg(feature = "english")]
+                self.error(field, format!("length must be between {} and {}", range.start(), range.end()));
+            }
+        }
+        self
+    }
+
+    /// 字段值必须在指定范围内, 值为None时不校验
+    pub fn range<T, V>(&mut self, field: &str, value: V, range: RangeInclusive<T>) -> &mut Self
+    where
+        T: PartialOrd + Display,
+        V: Into<Option<T>>,
+    {
+        if let Some(value) = value.into() {
+            if !range.contains(&value) {
+                #[cfg(not(feature = "english"))]
+                self.error(field, format!("必须在{}-{}之间", range.start(), range.end()));
+                #[cfg(feature = "english")]
+                self.error(field, format!("must be between {} and {}", range.start(), range.end()));
+            }
+        }
+        self
+    }
+
+    /// 字段必须匹配正则表达式, 未提供或者为空时不校验
+    pub fn pattern<T: ?Sized + Text>(&mut self, field: &str, value: &T, re: &Regex) -> &mut Self {
+        if let Some(text) = value.text().filter(|s| !s.is_empty()) {
+            if !re.is_match(text) {
+                #[cfg(not(feature = "english"))]
+                self.error(field, "格式错误");
+                #[cfg(feature = "english")]
+                self.error(field, "invalid format");
+            }
+        }
+        self
+    }
+
+    /// 自定义校验, `f`返回错误信息时校验失败
+    pub fn custom<T, F>(&mut self, field: &str, value: &T, f: F) -> &mut Self
+    where
+        T: ?Sized,
+        F: FnOnce(&T) -> Result<(), String>,
+    {
+        if let Err(message) = f(value) {
+            self.error(field, message);
+        }
+        self
+    }
+
+    /// 是否所有字段都校验通过
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// 存在字段错误时返回包含所有错误的`ValidationError`
+    pub fn finish(self) -> anyhow::Result<()> {
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+        Err(anyhow::Error::new(ValidationError { errors: self.errors }))
+    }
+}
+
+impl ValidationError {
+    /// 错误回复中的错误信息
+    pub fn message(&self) -> String {
+        let fields: Vec<&str> = self.errors.iter().map(|e| e.field.as_str()).collect();
+        #[cfg(not(feature = "english"))]
+        let prefix = "请求参数错误";
+        #[cfg(feature = "english")]
+        let prefix = "invalid request parameters";
+        format!("{prefix}: {}", fields.join(", "))
+    }
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message())?;
+        for e in self.errors.iter() {
+            write!(f, "; {} {}", e.field, e.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl StdError for ValidationError {}
+
+impl Present for str {
+    fn is_present(&self) -> bool { !self.is_empty() }
+}
+
+impl Present for String {
+    fn is_present(&self) -> bool { !self.is_empty() }
+}
+
+impl Present for CompactString {
+    fn is_present(&self) -> bool { !self.is_empty() }
+}
+
+impl<T> Present for Vec<T> {
+    fn is_present(&self) -> bool { !self.is_empty() }
+}
+
+impl<T> Present for Option<T> {
+    fn is_present(&self) -> bool { self.is_some() }
+}
+
+impl Measure for str {
+    fn measure(&self) -> Option<usize> { Some(self.chars().count()) }
+}
+
+impl Measure for String {
+    fn measure(&self) -> Option<usize> { Some(self.chars().count()) }
+}
+
+impl Measure for CompactString {
+    fn measure(&self) -> Option<usize> { Some(self.chars().count()) }
+}
+
+impl<T> Measure for Vec<T> {
+    fn measure(&self) -> Option<usize> { Some(self.len()) }
+}
+
+impl<T: Measure> Measure for Option<T> {
+    fn measure(&self) -> Option<usize> { self.as_ref().and_then(Measure::measure) }
+}
+
+impl Text for str {
+    fn text(&self) -> Option<&str> { Some(self) }
+}
+
+impl Text for String {
+    fn text(&self) -> Option<&str> { Some(self) }
+}
+
+impl Text for CompactString {
+    fn text(&self) -> Option<&str> { Some(self) }
+}
+
+impl<T: Text> Text for Option<T> {
+    fn text(&self) -> Option<&str> { self.as_ref().and_then(Text::text) }
+}
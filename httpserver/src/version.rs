@@ -0,0 +1,72 @@
+//! api版本管理
+use compact_str::CompactString;
+
+/// api版本, 弃用的版本会在响应中附加`Deprecation`/`Sunset`头
+pub struct ApiVersion {
+    /// 版本名称, 格式为`v<数字>`, 例如`v1`
+    pub(crate) name: CompactString,
+    /// `Deprecation`头的值, 例如`true`或者`@1735660800`
+    pub(crate) deprecation: Option<CompactString>,
+    /// `Sunset`头的值, http日期格式, 例如`Wed, 31 Dec 2025 23:59:59 GMT`
+    pub(crate) sunset: Option<CompactString>,
+}
+
+impl ApiVersion {
+    /// 创建api版本
+    ///
+    /// Arguments:
+    ///
+    /// * `name`: 版本名称, 格式为`v<数字>`
+    pub fn new(name: &str) -> Self {
+        debug_assert!(split_api_version(&format!("/{name}/")).is_some());
+        ApiVersion {
+            name: CompactString::new(name),
+            deprecation: None,
+            sunset: None,
+        }
+    }
+
+    /// 设置该版本已弃用
+    ///
+    /// Arguments:
+    ///
+    /// * `deprecation`: `Deprecation`头的值
+    pub fn deprecated(mut self, deprecation: &str) -> Self {
+        self.deprecation = Some(CompactString::new(deprecation));
+        self
+    }
+
+    /// 设置该版本的下线时间
+    ///
+    /// Arguments:
+    ///
+    /// * `sunset`: `Sunset`头的值, http日期格式
+    pub fn sunset(mut self, sunset: &str) -> Self {
+        self.sunset = Some(CompactString::new(sunset));
+        self
+    }
+
+    /// 版本名称
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 是否需要在响应中附加弃用相关的头
+    pub(crate) fn is_deprecated(&self) -> bool {
+        self.deprecation.is_some() || self.sunset.is_some()
+    }
+}
+
+/// 拆分路径中的版本前缀
+///
+/// Returns:
+///
+/// `/v2/list` 返回 `Some(("v2", "/list"))`, 路径不以版本开头时返回None
+pub fn split_api_version(path: &str) -> Option<(&str, &str)> {
+    let rest = path.strip_prefix("/v")?;
+    let digits = rest.bytes().take_while(|c| c.is_ascii_digit()).count();
+    if digits == 0 || rest.as_bytes().get(digits) != Some(&b'/') {
+        return None;
+    }
+    Some((&path[1..digits + 2], &rest[digits..]))
+}
@@ -0,0 +1,433 @@
+//! WebSocket支持, 通过http/1.1升级建立连接, 实现了RFC6455的基本帧编解码(不支持扩展及压缩)
+//!
+//! ```rust,ignore
+//! pub async fn ws(mut ctx: HttpContext) -> HttpResponse {
+//!     WebSocket::upgrade(&mut ctx, |mut ws| async move {
+//!         while let Ok(Some(msg)) = ws.recv().await {
+//!             if let Message::Text(text) = msg {
+//!                 let _ = ws.send_text(&text).await;
+//!             }
+//!         }
+//!     })
+//! }
+//! ```
+use std::future::Future;
+
+use anyhow::{bail, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use http_body_util::Full;
+use hyper::{
+    body::Bytes,
+    header::{HeaderValue, CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_VERSION, UPGRADE},
+    upgrade::Upgraded,
+    Method, StatusCode,
+};
+use hyper_util::rt::TokioIo;
+use sha1::{Digest, Sha1};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::{log_debug, HttpContext, HttpResponse, Resp, Response};
+
+/// 计算Sec-WebSocket-Accept使用的固定GUID
+const WS_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// 单条消息的最大长度, 超出时关闭连接
+const MAX_MESSAGE_LEN: usize = 1024 * 1024;
+/// 每次从连接读取的缓冲区大小
+const READ_BUF_LEN: usize = 4096;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xa;
+
+/// 正常关闭
+pub const CLOSE_NORMAL: u16 = 1000;
+/// 协议错误
+pub const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+/// 消息过大
+pub const CLOSE_TOO_BIG: u16 = 1009;
+
+/// WebSocket消息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+}
+
+/// 已建立的WebSocket连接(服务端)
+pub struct WebSocket {
+    io: TokioIo<Upgraded>,
+    /// 已读取但尚未解析的数据
+    buf: Vec<u8>,
+    /// 分片消息的操作码及已收到的内容
+    fragments: Option<(u8, Vec<u8>)>,
+    /// 是否已发送关闭帧
+    closed: bool,
+}
+
+/// 解析后的帧
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+impl WebSocket {
+    /// 校验升级请求并回复101, 连接升级完成后在新任务中执行`f`
+    ///
+    /// 不是合法的WebSocket升级请求时回复400
+    ///
+    /// Arguments:
+    ///
+    /// * `ctx`: 请求上下文
+    /// * `f`: 连接建立后执行的异步函数, 函数返回时连接关闭
+    pub fn upgrade<F, Fut>(ctx: &mut HttpContext, f: F) -> HttpResponse
+    where
+        F: FnOnce(WebSocket) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let key = match Self::handshake_key(ctx) {
+            Some(key) => key,
+            None => {
+                #[cfg(not(feature = "english"))]
+                let msg = "不是合法的WebSocket升级请求";
+                #[cfg(feature = "english")]
+                let msg = "invalid websocket upgrade request";
+                return Resp::fail_with_status(StatusCode::BAD_REQUEST, StatusCode::BAD_REQUEST.as_u16() as u32, msg);
+            }
+        };
+        let accept = accept_key(key.as_bytes());
+
+        let id = ctx.id;
+        let on_upgrade = hyper::upgrade::on(&mut ctx.req);
+        tokio::spawn(async move {
+            match on_upgrade.await {
+                Ok(upgraded) => f(WebSocket::new(TokioIo::new(upgraded))).await,
+                Err(e) => {
+                    #[cfg(not(feature = "english"))]
+                    log_debug!(id, "WebSocket升级失败: {e:?}");
+                    #[cfg(feature = "english")]
+                    log_debug!(id, "websocket upgrade failed: {e:?}");
+                }
+            }
+        });
+
+        let mut res = Response::new(Full::new(Bytes::new()));
+        *res.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+        let headers = res.headers_mut();
+        headers.insert(UPGRADE, HeaderValue::from_static("websocket"));
+        headers.insert(CONNECTION, HeaderValue::from_static("Upgrade"));
+        headers.insert(SEC_WEBSOCKET_ACCEPT, HeaderValue::from_str(&accept)?);
+        Ok(res)
+    }
+
+    /// 是否为WebSocket升级请求
+    pub fn is_upgrade(ctx: &HttpContext) -> bool {
+        ctx.header_str(UPGRADE).is_some_and(|v| v.eq_ignore_ascii_case("websocket"))
+    }
+
+    /// 校验升级请求, 返回客户端的Sec-WebSocket-Key
+    fn handshake_key(ctx: &HttpContext) -> Option<String> {
+        let connection = ctx.header_str(CONNECTION)?;
+        let valid = ctx.req.method() == Method::GET
+            && Self::is_upgrade(ctx)
+            && connection.split(',').any(|v| v.trim().eq_ignore_ascii_case("upgrade"))
+            && ctx.header_str(SEC_WEBSOCKET_VERSION) == Some("13");
+        if !valid {
+            return None;
+        }
+        ctx.header_str(SEC_WEBSOCKET_KEY).map(|k| k.trim().to_owned()).filter(|k| !k.is_empty())
+    }
+
+    fn new(io: TokioIo<Upgraded>) -> Self {
+        WebSocket { io, buf: Vec::with_capacity(READ_BUF_LEN), fragments: None, closed: false }
+    }
+
+    /// 接收消息, 自动回复ping及关闭帧
+    ///
+    /// 只在等待数据时挂起, 可以在`tokio::select!`中与其它事件一起等待
+    ///
+    /// Returns:
+    ///
+    /// Ok(Some(msg)): 收到的消息, Ok(None): 连接已关闭, Err(e): 读取失败或者协议错误(已发送关闭帧)
+    pub async fn recv(&mut self) -> Result<Option<Message>> {
+        loop {
+            let frame = match parse_frame(&mut self.buf) {
+                Ok(Some(frame)) => frame,
+                Ok(None) => {
+                    // 读取到临时缓冲区, 等待期间被取消时不影响已读取的数据
+                    let mut chunk = [0u8; READ_BUF_LEN];
+                    let n = self.io.read(&mut chunk).await?;
+                    if n == 0 {
+                        return Ok(None);
+                    }
+                    self.buf.extend_from_slice(&chunk[..n]);
+                    continue;
+                }
+                Err((code, e)) => {
+                    let _ = self.close(code, "").await;
+                    return Err(e);
+                }
+            };
+
+            match frame.opcode {
+                OP_PING => {
+                    self.write_frame(OP_PONG, &frame.payload).await?;
+                    return Ok(Some(Message::Ping(frame.payload)));
+                }
+                OP_PONG => return Ok(Some(Message::Pong(frame.payload))),
+                OP_CLOSE => {
+                    let code = match frame.payload.len() {
+                        0 => CLOSE_NORMAL,
+                        1 => CLOSE_PROTOCOL_ERROR,
+                        _ => u16::from_be_bytes([frame.payload[0], frame.payload[1]]),
+                    };
+                    let _ = self.close(code, "").await;
+                    return Ok(None);
+                }
+                _ => {}
+            }
+
+            let (opcode, payload) = match (frame.opcode, self.fragments.take()) {
+                (OP_CONTINUATION, Some((opcode, mut data))) => {
+                    data.extend_from_slice(&frame.payload);
+                    (opcode, data)
+                }
+                (OP_TEXT | OP_BINARY, None) => (frame.opcode, frame.payload),
+                _ => {
+                    let _ = self.close(CLOSE_PROTOCOL_ERROR, "").await;
+                    bail!("unexpected websocket frame opcode: {}", frame.opcode);
+                }
+            };
+            if payload.len() > MAX_MESSAGE_LEN {
+                let _ = self.close(CLOSE_TOO_BIG, "").await;
+                bail!("websocket message too big: {}", payload.len());
+            }
+            if !frame.fin {
+                self.fragments = Some((opcode, payload));
+                continue;
+            }
+
+            return match opcode {
+                OP_TEXT => match String::from_utf8(payload) {
+                    Ok(text) => Ok(Some(Message::Text(text))),
+                    Err(_) => {
+                        let _ = self.close(CLOSE_PROTOCOL_ERROR, "").await;
+                        bail!("websocket text message is not utf-8");
+                    }
+                },
+                _ => Ok(Some(Message::Binary(payload))),
+            };
+        }
+    }
+
+    /// 发送消息
+    pub async fn send(&mut self, msg: Message) -> Result<()> {
+        match msg {
+            Message::Text(text) => self.write_frame(OP_TEXT, text.as_bytes()).await,
+            Message::Binary(data) => self.write_frame(OP_BINARY, &data).await,
+            Message::Ping(data) => self.write_frame(OP_PING, &data).await,
+            Message::Pong(data) => self.write_frame(OP_PONG, &data).await,
+        }
+    }
+
+    /// 发送文本消息
+    pub async fn send_text(&mut self, text: &str) -> Result<()> {
+        self.write_frame(OP_TEXT, text.as_bytes()).await
+    }
+
+    /// 发送关闭帧, 已发送过时忽略
+    pub async fn close(&mut self, code: u16, reason: &str) -> Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        self.closed = true;
+        let mut payload = Vec::with_capacity(2 + reason.len());
+        payload.extend_from_slice(&code.to_be_bytes());
+        // 控制帧的内容不能超过125字节
+        payload.extend_from_slice(&reason.as_bytes()[..reason.len().min(123)]);
+        self.write_frame(OP_CLOSE, &payload).await
+    }
+
+    async fn write_frame(&mut self, opcode: u8, payload: &[u8]) -> Result<()> {
+        if self.closed && opcode != OP_CLOSE {
+            bail!("websocket already closed");
+        }
+        self.io.write_all(&encode_frame(opcode, payload)).await?;
+        self.io.flush().await?;
+        Ok(())
+    }
+}
+
+/// 从缓冲区中解析一个完整的帧并移除已解析的数据, 数据不足时返回None
+///
+/// 出错时返回应发送的关闭码及错误
+fn parse_frame(buf: &mut Vec<u8>) -> std::result::Result<Option<Frame>, (u16, anyhow::Error)> {
+    if buf.len() < 2 {
+        return Ok(None);
+    }
+    let fin = buf[0] & 0x80 != 0;
+    let opcode = buf[0] & 0x0f;
+    if buf[0] & 0x70 != 0 {
+        return Err((CLOSE_PROTOCOL_ERROR, anyhow::anyhow!("websocket extensions not supported")));
+    }
+    // 客户端发送的帧必须使用掩码
+    if buf[1] & 0x80 == 0 {
+        return Err((CLOSE_PROTOCOL_ERROR, anyhow::anyhow!("websocket client frame not masked")));
+    }
+
+    let (len, pos) = match buf[1] & 0x7f {
+        126 if buf.len() >= 4 => (u16::from_be_bytes([buf[2], buf[3]]) as u64, 4),
+        127 if buf.len() >= 10 => {
+            let mut n = [0u8; 8];
+            n.copy_from_slice(&buf[2..10]);
+            (u64::from_be_bytes(n), 10)
+        }
+        126 | 127 => return Ok(None),
+        n => (n as u64, 2),
+    };
+    if opcode >= OP_CLOSE && (!fin || len > 125) {
+        return Err((CLOSE_PROTOCOL_ERROR, anyhow::anyhow!("websocket invalid control frame")));
+    }
+    if len > MAX_MESSAGE_LEN as u64 {
+        return Err((CLOSE_TOO_BIG, anyhow::anyhow!("websocket frame too big: {len}")));
+    }
+
+    let len = len as usize;
+    if buf.len() < pos + 4 + len {
+        return Ok(None);
+    }
+    let mask = [buf[pos], buf[pos + 1], buf[pos + 2], buf[pos + 3]];
+    let start = pos + 4;
+    let payload: Vec<u8> = buf[start..start + len].iter().enumerate()
+        .map(|(i, b)| b ^ mask[i % 4])
+        .collect();
+    buf.drain(..start + len);
+
+    Ok(Some(Frame { fin, opcode, payload }))
+}
+
+/// 根据客户端的Sec-WebSocket-Key计算Sec-WebSocket-Accept
+fn accept_key(key: &[u8]) -> String {
+    let mut sha1 = Sha1::new();
+    sha1.update(key);
+    sha1.update(WS_GUID.as_bytes());
+    STANDARD.encode(sha1.finalize())
+}
+
+/// 编码服务端发送的帧, 服务端发送的帧不使用掩码
+fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let len = payload.len();
+    let mut frame = Vec::with_capacity(len + 10);
+    frame.push(0x80 | opcode);
+    if len < 126 {
+        frame.push(len as u8);
+    } else if len <= u16::MAX as usize {
+        frame.push(126);
+        frame.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        frame.push(127);
+        frame.extend_from_slice(&(len as u64).to_be_bytes());
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 按客户端的方式用掩码编码帧
+    fn masked(opcode: u8, fin: bool, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x37, 0xfa, 0x21, 0x3d];
+        let mut frame = encode_frame(opcode, payload);
+        if !fin {
+            frame[0] &= 0x7f;
+        }
+        let start = frame.len() - payload.len();
+        frame[1] |= 0x80;
+        let mut out = frame[..start].to_vec();
+        out.extend_from_slice(&mask);
+        out.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        out
+    }
+
+    #[test]
+    fn accept_key_rfc6455() {
+        // RFC 6455 1.3
+        assert_eq!(accept_key(b"dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn encode_rfc6455() {
+        // RFC 6455 5.7, 服务端发送的帧不使用掩码
+        assert_eq!(encode_frame(OP_TEXT, b"Hello"), [0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f]);
+        assert_eq!(encode_frame(OP_PONG, b"Hello"), [0x8a, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f]);
+        assert_eq!(encode_frame(OP_BINARY, &[0; 256])[..4], [0x82, 0x7e, 0x01, 0x00]);
+        assert_eq!(encode_frame(OP_BINARY, &[0; 65536])[..10], [0x82, 0x7f, 0, 0, 0, 0, 0, 0x01, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn parse_rfc6455() {
+        // RFC 6455 5.7, 使用掩码的"Hello"
+        let mut buf = vec![0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58];
+        let frame = parse_frame(&mut buf).unwrap().unwrap();
+        assert!(frame.fin);
+        assert_eq!(frame.opcode, OP_TEXT);
+        assert_eq!(frame.payload, b"Hello");
+        assert!(buf.is_empty());
+
+        // 分片消息: "Hel" + "lo"
+        let mut buf = masked(OP_TEXT, false, b"Hel");
+        buf.extend(masked(OP_CONTINUATION, true, b"lo"));
+        let first = parse_frame(&mut buf).unwrap().unwrap();
+        assert!(!first.fin && first.opcode == OP_TEXT && first.payload == b"Hel");
+        let second = parse_frame(&mut buf).unwrap().unwrap();
+        assert!(second.fin && second.opcode == OP_CONTINUATION && second.payload == b"lo");
+
+        // 16位及64位长度
+        for len in [256, 65536] {
+            let data = vec![0x5a; len];
+            let mut buf = masked(OP_BINARY, true, &data);
+            assert_eq!(parse_frame(&mut buf).unwrap().unwrap().payload, data);
+        }
+    }
+
+    #[test]
+    fn parse_partial() {
+        let full = masked(OP_BINARY, true, &[1; 300]);
+        for n in [0, 1, 3, 8, full.len() - 1] {
+            let mut buf = full[..n].to_vec();
+            assert!(parse_frame(&mut buf).unwrap().is_none());
+            assert_eq!(buf.len(), n);
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        // 超长的帧在读取内容之前即拒绝
+        let mut buf = vec![0x82, 0xff];
+        buf.extend_from_slice(&(MAX_MESSAGE_LEN as u64 + 1).to_be_bytes());
+        assert_eq!(parse_frame(&mut buf).err().map(|e| e.0), Some(CLOSE_TOO_BIG));
+        let mut buf = vec![0x82, 0xff];
+        buf.extend_from_slice(&u64::MAX.to_be_bytes());
+        assert_eq!(parse_frame(&mut buf).err().map(|e| e.0), Some(CLOSE_TOO_BIG));
+
+        // 未使用掩码
+        let mut buf = vec![0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f];
+        assert_eq!(parse_frame(&mut buf).err().map(|e| e.0), Some(CLOSE_PROTOCOL_ERROR));
+        // 使用了扩展位
+        let mut buf = masked(OP_TEXT, true, b"a");
+        buf[0] |= 0x40;
+        assert_eq!(parse_frame(&mut buf).err().map(|e| e.0), Some(CLOSE_PROTOCOL_ERROR));
+        // 控制帧超过125字节或者分片
+        let mut buf = masked(OP_PING, true, &[0; 126]);
+        assert_eq!(parse_frame(&mut buf).err().map(|e| e.0), Some(CLOSE_PROTOCOL_ERROR));
+        let mut buf = masked(OP_PING, false, b"a");
+        assert_eq!(parse_frame(&mut buf).err().map(|e| e.0), Some(CLOSE_PROTOCOL_ERROR));
+    }
+}
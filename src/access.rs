@@ -0,0 +1,251 @@
+//! 访问时间段限制, 只允许在指定的星期及时间段内登录及访问接口
+//!
+//! 规则之间以`;`分隔, 格式: `[数据库名=]星期 [开始时间-结束时间]`, 例如:
+//! `mon-fri 07:00-23:00;sat,sun 09:00-18:00;work=mon-fri 08:00-19:00`
+//!
+//! 星期可以是`*`、单日(`mon`)、范围(`mon-fri`)或者以逗号分隔的组合, 省略星期表示每天,
+//! 省略时间段表示全天, 结束时间小于开始时间表示跨越午夜. 指定了数据库名的规则只对该数据库生效,
+//! 数据库没有专属规则时使用通用规则, 没有任何适用规则时不限制
+use anyhow_ext::{anyhow, bail, Result};
+use chrono::{Datelike, Timelike};
+
+const WEEKDAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+/// 全部星期的掩码
+const ALL_DAYS: u8 = 0x7f;
+/// 一天的分钟数
+const DAY_MINUTES: u16 = 24 * 60;
+
+/// 单条访问规则
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct AccessRule {
+    /// 生效的数据库名, 为空表示通用规则
+    database: String,
+    /// 允许的星期掩码, 第0位表示星期一
+    days: u8,
+    /// 开始时间(当天的分钟数)
+    start: u16,
+    /// 结束时间(当天的分钟数, 不包含)
+    end: u16,
+}
+
+/// 访问时间段限制, 缺省值不做任何限制
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccessWindows {
+    rules: Vec<AccessRule>,
+}
+
+impl AccessWindows {
+    /// 解析访问规则, 空字符串表示不限制
+    pub fn parse(s: &str) -> Result<Self> {
+        let rules = s.split(';')
+            .map(str::trim)
+            .filter(|r| !r.is_empty())
+            .map(AccessRule::parse)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(AccessWindows { rules })
+    }
+
+    /// 当前本地时间是否允许访问指定的数据库
+    ///
+    /// * `database`: 数据库名称
+    pub fn allowed_now(&self, database: &str) -> bool {
+        if self.rules.is_empty() {
+            return true;
+        }
+        let now = chrono::Local::now();
+        let minute = (now.hour() * 60 + now.minute()) as u16;
+        self.allowed(database, now.weekday().num_days_from_monday() as u8, minute)
+    }
+
+    /// 指定的时间是否允许访问数据库
+    ///
+    /// * `database`: 数据库名称
+    /// * `weekday`: 星期(0表示星期一)
+    /// * `minute`: 当天的分钟数
+    fn allowed(&self, database: &str, weekday: u8, minute: u16) -> bool {
+        let own = self.rules.iter().any(|r| r.database == database);
+        let mut rules = self.rules.iter()
+            .filter(|r| if own { r.database == database } else { r.database.is_empty() })
+            .peekable();
+        if rules.peek().is_none() {
+            return true;
+        }
+        rules.any(|r| r.matches(weekday, minute))
+    }
+}
+
+impl AccessRule {
+    fn parse(s: &str) -> Result<Self> {
+        let (database, rest) = match s.split_once('=') {
+            Some((db, rest)) => (db.trim(), rest.trim()),
+            None => ("", s),
+        };
+        let (days, times) = match rest.split_once(char::is_whitespace) {
+            Some((days, times)) => (days, times.trim()),
+            None if rest.contains(':') => ("*", rest),
+            None => (rest, ""),
+        };
+
+        let days = parse_days(days).ok_or_else(|| anyhow!("invalid access window weekdays: {s}"))?;
+        let (start, end) = if times.is_empty() {
+            (0, DAY_MINUTES)
+        } else {
+            match times.split_once('-').and_then(|(a, b)| Some((parse_minute(a)?, parse_minute(b)?))) {
+                Some((start, end)) if start != end => (start, end),
+                _ => bail!("invalid access window time range: {s}"),
+            }
+        };
+
+        Ok(AccessRule { database: database.to_owned(), days, start, end })
+    }
+
+    /// 跨越午夜的时间段, 午夜之后的部分属于开始那一天, 例如`fri 22:00-02:00`包含星期六凌晨
+    fn matches(&self, weekday: u8, minute: u16) -> bool {
+        let has_day = |d: u8| self.days & (1 << d) != 0;
+        if self.start < self.end {
+            has_day(weekday) && minute >= self.start && minute < self.end
+        } else {
+            (has_day(weekday) && minute >= self.start) || (has_day((weekday + 6) % 7) && minute < self.end)
+        }
+    }
+}
+
+/// 解析星期, 返回星期掩码
+fn parse_days(s: &str) -> Option<u8> {
+    let weekday = |d: &str| WEEKDAYS.iter().position(|w| d.trim().eq_ignore_ascii_case(w));
+    let mut days = 0;
+    for item in s.split(',') {
+        match item.split_once('-') {
+            _ if item.trim() == "*" => days = ALL_DAYS,
+            Some((a, b)) => {
+                let (a, b) = (weekday(a)?, weekday(b)?);
+                // 范围可以跨越周末, 例如`fri-mon`
+                let mut d = a;
+                loop {
+                    days |= 1 << d;
+                    if d == b { break; }
+                    d = (d + 1) % 7;
+                }
+            }
+            None => days |= 1 << weekday(item)?,
+        }
+    }
+    Some(days)
+}
+
+/// 解析`HH:MM`格式的时间, 返回当天的分钟数, 允许`24:00`表示当天结束
+fn parse_minute(s: &str) -> Option<u16> {
+    let (h, m) = s.trim().split_once(':')?;
+    let (h, m) = (h.parse::<u16>().ok()?, m.parse::<u16>().ok()?);
+    if h > 24 || m >= 60 || h * 60 + m > DAY_MINUTES {
+        return None;
+    }
+    Some(h * 60 + m)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MON: u8 = 0;
+    const FRI: u8 = 4;
+    const SAT: u8 = 5;
+    const SUN: u8 = 6;
+
+    fn at(h: u16, m: u16) -> u16 {
+        h * 60 + m
+    }
+
+    #[test]
+    fn days() {
+        assert_eq!(parse_days("*"), Some(ALL_DAYS));
+        assert_eq!(parse_days("mon"), Some(0b1));
+        assert_eq!(parse_days("mon-fri"), Some(0b1_1111));
+        assert_eq!(parse_days("Sat,SUN"), Some(0b110_0000));
+        // 跨越周末的范围
+        assert_eq!(parse_days("fri-mon"), Some(0b111_0001));
+        assert_eq!(parse_days("mon-fri,sun"), Some(0b101_1111));
+        assert_eq!(parse_days("sun-sun"), Some(0b100_0000));
+        assert_eq!(parse_days(""), None);
+        assert_eq!(parse_days("monday"), None);
+        assert_eq!(parse_days("mon,"), None);
+        assert_eq!(parse_days("mon-xyz"), None);
+    }
+
+    #[test]
+    fn minutes() {
+        assert_eq!(parse_minute("00:00"), Some(0));
+        assert_eq!(parse_minute(" 07:30 "), Some(at(7, 30)));
+        assert_eq!(parse_minute("24:00"), Some(DAY_MINUTES));
+        assert_eq!(parse_minute("24:01"), None);
+        assert_eq!(parse_minute("23:60"), None);
+        assert_eq!(parse_minute("25:00"), None);
+        assert_eq!(parse_minute("7"), None);
+        assert_eq!(parse_minute("-1:00"), None);
+        assert_eq!(parse_minute("aa:bb"), None);
+    }
+
+    #[test]
+    fn day_range() {
+        let w = AccessWindows::parse("mon-fri 07:00-23:00; sat,sun 09:00-18:00").unwrap();
+        assert!(w.allowed("home", MON, at(7, 0)));
+        assert!(!w.allowed("home", MON, at(6, 59)));
+        // 结束时间不包含
+        assert!(!w.allowed("home", FRI, at(23, 0)));
+        assert!(w.allowed("home", SAT, at(12, 0)));
+        assert!(!w.allowed("home", SUN, at(8, 0)));
+
+        // 省略星期表示每天, 省略时间段表示全天
+        let w = AccessWindows::parse("08:00-20:00").unwrap();
+        assert!(w.allowed("home", SUN, at(8, 0)));
+        assert!(!w.allowed("home", SUN, at(20, 0)));
+        let w = AccessWindows::parse("sat").unwrap();
+        assert!(w.allowed("home", SAT, at(0, 0)));
+        assert!(w.allowed("home", SAT, at(23, 59)));
+        assert!(!w.allowed("home", FRI, at(12, 0)));
+    }
+
+    #[test]
+    fn overnight() {
+        let w = AccessWindows::parse("fri 22:00-02:00").unwrap();
+        assert!(w.allowed("home", FRI, at(22, 0)));
+        assert!(w.allowed("home", FRI, at(23, 59)));
+        // 午夜之后的部分属于星期五的时间段
+        assert!(w.allowed("home", SAT, at(1, 59)));
+        assert!(!w.allowed("home", SAT, at(2, 0)));
+        assert!(!w.allowed("home", SAT, at(22, 30)));
+        assert!(!w.allowed("home", FRI, at(1, 0)));
+
+        // 跨越周末: 星期日晚上的时间段延续到星期一凌晨
+        let w = AccessWindows::parse("sun 23:00-01:00").unwrap();
+        assert!(w.allowed("home", MON, at(0, 30)));
+        assert!(!w.allowed("home", SUN, at(0, 30)));
+    }
+
+    #[test]
+    fn database_rules() {
+        let w = AccessWindows::parse("mon-fri 07:00-23:00; work = mon-fri 08:00-19:00").unwrap();
+        // 有专属规则的数据库只使用专属规则
+        assert!(!w.allowed("work", MON, at(7, 30)));
+        assert!(w.allowed("work", MON, at(8, 0)));
+        assert!(w.allowed("home", MON, at(7, 30)));
+
+        // 没有任何适用规则时不限制
+        let w = AccessWindows::parse("work=mon-fri").unwrap();
+        assert!(w.allowed("home", SUN, at(3, 0)));
+        assert!(!w.allowed("work", SUN, at(3, 0)));
+        assert!(AccessWindows::parse("").unwrap().allowed("home", SUN, at(3, 0)));
+        assert!(AccessWindows::parse(" ; ").unwrap().allowed("home", SUN, at(3, 0)));
+    }
+
+    #[test]
+    fn bad_input() {
+        for s in ["xyz", "mon 7-9", "mon 07:00", "mon 07:00-07:00", "mon 07:00-25:00", "mon-fri 07:00-", "work=",
+            "mon,,tue", "mon 07:00-09:00-10:00"]
+        {
+            assert!(AccessWindows::parse(s).is_err(), "{s}");
+        }
+        // 一条规则错误时整体失败
+        assert!(AccessWindows::parse("mon-fri 07:00-23:00; funday").is_err());
+    }
+}
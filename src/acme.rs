@@ -0,0 +1,262 @@
+//! ACME(Let's Encrypt)证书自动申请及续期, 使用HTTP-01方式验证域名
+//!
+//! 证书、私钥及账户信息保存在数据库文件所在目录的acme子目录下
+use std::{collections::HashMap, io::Write, net::SocketAddr, path::{Path, PathBuf}, sync::Arc, time::Duration};
+
+use anyhow_ext::{anyhow, bail, Result};
+use http_body_util::Full;
+use httpserver::{HttpContext, HttpHandler, HttpResponse, HttpServer, HttpsRedirect, Resp, TlsConfig, CONTENT_TYPE};
+use instant_acme::{
+    Account, AccountCredentials, AuthorizationStatus, ChallengeType, Identifier, LetsEncrypt,
+    NewAccount, NewOrder, OrderStatus,
+};
+use parking_lot::Mutex;
+
+/// HTTP-01验证请求的路径前缀
+const CHALLENGE_PREFIX: &str = "/.well-known/acme-challenge/";
+/// 证书签发后超过该时间则续期, Let's Encrypt证书有效期为90天
+const RENEW_AFTER: Duration = Duration::from_secs(60 * 86400);
+/// 检查证书是否需要续期的时间间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(86400);
+/// 等待订单状态变化的最大轮询次数
+const MAX_POLL: u32 = 30;
+
+/// 待验证的HTTP-01令牌, key: token, value: key authorization
+static CHALLENGES: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+/// ACME配置
+pub struct AcmeConfig {
+    /// 申请证书的域名
+    pub domain: String,
+    /// 账户联系邮箱, 可以为空
+    pub email: String,
+    /// 证书保存目录
+    pub dir: PathBuf,
+    /// 使用Let's Encrypt的测试环境
+    pub staging: bool,
+}
+
+impl AcmeConfig {
+    /// 证书链文件
+    pub fn cert_file(&self) -> String {
+        self.dir.join(format!("{}.crt", self.domain)).to_string_lossy().into_owned()
+    }
+
+    /// 私钥文件
+    pub fn key_file(&self) -> String {
+        self.dir.join(format!("{}.key", self.domain)).to_string_lossy().into_owned()
+    }
+
+    fn account_file(&self) -> PathBuf {
+        let env = if self.staging { "staging" } else { "production" };
+        self.dir.join(format!("account-{env}.json"))
+    }
+
+    /// 证书不存在或者签发时间超过续期时间时需要申请新证书
+    pub fn needs_renewal(&self) -> bool {
+        match std::fs::metadata(self.cert_file()).and_then(|m| m.modified()) {
+            Ok(t) => t.elapsed().map(|d| d >= RENEW_AFTER).unwrap_or(false),
+            Err(_) => true,
+        }
+    }
+}
+
+/// HTTP-01验证请求处理函数, 其它请求跳转到https或者返回404
+struct ChallengeHandler {
+    redirect: Option<HttpsRedirect>,
+}
+
+/// 启动用于HTTP-01验证的http服务, Let's Encrypt只会访问80端口
+///
+/// * `addr`: 监听地址
+/// * `redirect`: 与http跳转服务共用端口时, 非验证请求跳转到https
+pub fn serve_challenges(addr: SocketAddr, redirect: Option<HttpsRedirect>) {
+    let mut srv = HttpServer::new();
+    srv.set_default_handler(ChallengeHandler { redirect });
+    tokio::spawn(async move {
+        if let Err(e) = srv.run(addr).await {
+            log::error!("acme challenge server {addr} error: {e:?}");
+        }
+    });
+}
+
+#[async_trait::async_trait]
+impl HttpHandler for ChallengeHandler {
+    async fn handle(&self, ctx: HttpContext) -> HttpResponse {
+        let key_auth = ctx.req.uri().path().strip_prefix(CHALLENGE_PREFIX)
+            .and_then(|token| CHALLENGES.lock().as_ref().and_then(|c| c.get(token).cloned()));
+
+        match (key_auth, &self.redirect) {
+            (Some(key_auth), _) => Ok(
+                hyper::Response::builder()
+                    .header(CONTENT_TYPE, "application/octet-stream")
+                    .body(Full::from(key_auth))?
+            ),
+            (None, Some(redirect)) => redirect.handle(ctx).await,
+            (None, None) => Resp::fail_with_status(hyper::StatusCode::NOT_FOUND, 404, "Not Found"),
+        }
+    }
+}
+
+/// 申请证书并保存到证书目录
+pub async fn obtain_certificate(cfg: &AcmeConfig) -> Result<()> {
+    std::fs::create_dir_all(&cfg.dir)?;
+    let account = load_account(cfg).await?;
+    let identifiers = [Identifier::Dns(cfg.domain.clone())];
+    let mut order = account.new_order(&NewOrder { identifiers: &identifiers }).await?;
+
+    let mut tokens = Vec::new();
+    for authz in order.authorizations().await? {
+        match authz.status {
+            AuthorizationStatus::Pending => {}
+            AuthorizationStatus::Valid => continue,
+            status => bail!("acme authorization status error: {status:?}"),
+        }
+        let challenge = authz.challenges.iter()
+            .find(|c| c.r#type == ChallengeType::Http01)
+            .ok_or_else(|| anyhow!("acme server does not support http-01 challenge"))?;
+        let key_auth = order.key_authorization(challenge);
+        CHALLENGES.lock().get_or_insert_with(HashMap::new)
+            .insert(challenge.token.clone(), key_auth.as_str().to_owned());
+        tokens.push(challenge.token.clone());
+        order.set_challenge_ready(&challenge.url).await?;
+    }
+
+    let res = finalize_order(cfg, &mut order).await;
+    if let Some(challenges) = CHALLENGES.lock().as_mut() {
+        for token in tokens.iter() {
+            challenges.remove(token);
+        }
+    }
+    res
+}
+
+/// 证书续期任务, 续期后重新加载证书
+pub async fn renew_loop(cfg: AcmeConfig, tls: Arc<TlsConfig>) {
+    loop {
+        tokio::time::sleep(CHECK_INTERVAL).await;
+        if !cfg.needs_renewal() {
+            continue;
+        }
+        log::info!("renew acme certificate for {}", cfg.domain);
+        match obtain_certificate(&cfg).await {
+            Ok(()) => if let Err(e) = tls.reload() {
+                log::error!("reload renewed certificate failed: {e:?}");
+            },
+            Err(e) => log::error!("renew acme certificate for {} failed: {e:?}", cfg.domain),
+        }
+    }
+}
+
+/// 加载已保存的账户, 不存在时注册新账户
+async fn load_account(cfg: &AcmeConfig) -> Result<Account> {
+    let file = cfg.account_file();
+    if let Ok(json) = std::fs::read_to_string(&file) {
+        let credentials: AccountCredentials = serde_json::from_str(&json)?;
+        return Ok(Account::from_credentials(credentials).await?);
+    }
+
+    let url = if cfg.staging { LetsEncrypt::Staging.url() } else { LetsEncrypt::Production.url() };
+    let contact = format!("mailto:{}", cfg.email);
+    let contacts: Vec<&str> = if cfg.email.is_empty() { Vec::new() } else { vec![contact.as_str()] };
+    let (account, credentials) = Account::create(&NewAccount {
+        contact: &contacts,
+        terms_of_service_agreed: true,
+        only_return_existing: false,
+    }, url, None).await?;
+
+    write_private(&file, serde_json::to_string(&credentials)?.as_bytes())?;
+    log::info!("acme account created: {}", file.display());
+    Ok(account)
+}
+
+/// 原子写入私钥、证书及账户凭证文件
+///
+/// 先写入同一目录下只有所有者可以读写(unix下权限为0600)的临时文件再改名,
+/// 证书热加载不会读到写了一半的文件, 私钥也不会有其它用户可读的时间窗口
+fn write_private(path: &Path, data: &[u8]) -> Result<()> {
+    let mut tmp_file = path.as_os_str().to_owned();
+    tmp_file.push(".tmp");
+    let tmp_file = PathBuf::from(tmp_file);
+
+    // 上次写入中断残留的临时文件可能权限不同, 删除后重新创建
+    let _ = std::fs::remove_file(&tmp_file);
+    let mut opts = std::fs::OpenOptions::new();
+    opts.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut opts, 0o600);
+    let res = opts.open(&tmp_file)
+        .and_then(|mut f| f.write_all(data).and_then(|_| f.sync_all()))
+        .and_then(|_| std::fs::rename(&tmp_file, path));
+    if let Err(e) = res {
+        let _ = std::fs::remove_file(&tmp_file);
+        bail!("write {} error: {e}", path.display());
+    }
+    Ok(())
+}
+
+/// 等待验证完成, 提交证书请求并保存签发的证书
+async fn finalize_order(cfg: &AcmeConfig, order: &mut instant_acme::Order) -> Result<()> {
+    let mut delay = Duration::from_millis(500);
+    let mut tries = 0;
+    loop {
+        tokio::time::sleep(delay).await;
+        let state = order.refresh().await?;
+        match state.status {
+            OrderStatus::Ready => break,
+            OrderStatus::Invalid => bail!("acme order invalid: {:?}", state.error),
+            _ => {}
+        }
+        tries += 1;
+        if tries >= MAX_POLL {
+            bail!("acme order timeout, status: {:?}", state.status);
+        }
+        delay = (delay * 2).min(Duration::from_secs(10));
+    }
+
+    let key_pair = rcgen::KeyPair::generate()?;
+    let mut params = rcgen::CertificateParams::new(vec![cfg.domain.clone()])?;
+    params.distinguished_name = rcgen::DistinguishedName::new();
+    let csr = params.serialize_request(&key_pair)?;
+    order.finalize(csr.der()).await?;
+
+    let mut tries = 0;
+    let cert_chain = loop {
+        if let Some(cert_chain) = order.certificate().await? {
+            break cert_chain;
+        }
+        tries += 1;
+        if tries >= MAX_POLL {
+            bail!("acme certificate download timeout");
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    };
+
+    // 先写私钥再写证书, 证书文件的修改时间即为签发时间
+    write_private(Path::new(&cfg.key_file()), key_pair.serialize_pem().as_bytes())?;
+    write_private(Path::new(&cfg.cert_file()), cert_chain.as_bytes())?;
+    log::info!("acme certificate issued for {}: {}", cfg.domain, cfg.cert_file());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn private_file() {
+        let file = std::env::temp_dir().join(format!("accinfo-acme-{}.key", std::process::id()));
+        write_private(&file, b"old").unwrap();
+        write_private(&file, b"new key").unwrap();
+        assert_eq!(std::fs::read(&file).unwrap(), b"new key");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&file).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        let mut tmp_file = file.clone().into_os_string();
+        tmp_file.push(".tmp");
+        assert!(!Path::new(&tmp_file).exists());
+        std::fs::remove_file(&file).unwrap();
+    }
+}
@@ -1,159 +1,1846 @@
-use std::{io::{Write, Read}, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet}, io::{Write, Read}, path::Path,
+    sync::{atomic::{AtomicBool, Ordering}, Arc, OnceLock}, time::SystemTime,
+};
 
 use anyhow_ext::{anyhow, bail, Result};
 use parking_lot::Mutex;
 use serde::{Serialize, Deserialize};
 use quick_xml::{events::Event, reader::Reader};
 use md5::{Md5, Digest, Md5Core, digest::Output};
-use aes::cipher::{KeyIvInit, StreamCipher};
+use sha2::Sha256;
+use aes::cipher::{BlockEncrypt, KeyIvInit, StreamCipher};
+use aes_gcm::{aead::{Aead, KeyInit, Payload}, Aes256Gcm, Nonce};
+use ghash::{universal_hash::UniversalHash, GHash};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use zeroize::Zeroizing;
+
+use crate::{generator::GenOptions, index::SearchIndex, policy::Policy, timing::{self, Phase}, totp::KeePassOtp};
 
 type Aes128Ctr64LE = ctr::Ctr64LE<aes::Aes128>;
 
-#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
-#[serde(rename_all = "camelCase")]
-pub struct Record {
-    pub id: String,
-    pub title: String,
-    pub user: String,
-    pub pass: String,
-    pub url: String,
-    pub notes: String,
+/// 共享的不可变字符串, 重复率高的字段(用户名、网址、分组等)在内存中只保留一份
+pub type IStr = Arc<str>;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Record {
+    pub id: String,
+    pub title: String,
+    pub user: IStr,
+    /// 口令在内存中加密保存, 只在查看口令、评估强度及导出时解密
+    pub pass: Sealed,
+    pub url: IStr,
+    /// 备注内容较大, 在内存中加密保存, 只在查看详情时解密
+    pub notes: Sealed,
+    /// keepass内置图标编号
+    #[serde(default)]
+    pub icon: u32,
+    /// 自定义图标id(对应附件区的附件id)
+    #[serde(default, skip_serializing_if = "istr_is_empty")]
+    pub custom_icon: IStr,
+    /// 所属分组id
+    #[serde(default, skip_serializing_if = "istr_is_empty")]
+    pub group: IStr,
+    /// 标签
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<IStr>,
+    /// 过期时间(unix时间戳, 单位: 秒), 0表示永不过期
+    #[serde(default)]
+    pub expire: u64,
+    /// 最后修改时间(unix时间戳, 单位: 秒)
+    #[serde(default)]
+    pub modified: u64,
+    /// 创建时间(unix时间戳, 单位: 秒), 0表示未知
+    #[serde(default)]
+    pub created: u64,
+    /// 历史版本, 按修改时间从旧到新排列, 最多保留`MAX_HISTORY`个
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<RecordVersion>,
+    /// 敏感记录, 启用二次验证时查看口令前需要重新验证主密码
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sensitive: bool,
+    /// 一次性口令的otpauth uri或者base32密钥, 在内存中加密保存, 只在服务端计算验证码时解密
+    #[serde(default, skip_serializing_if = "Sealed::is_empty")]
+    pub otp: Sealed,
+    /// 附件, 附件数据保存在附件区
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<FileRef>,
+    /// 自定义字段, 按导入或添加的顺序排列
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<RecordField>,
+    /// 评论(例如"事件X后已更换口令"), 按添加时间从旧到新排列, 最多`MAX_COMMENTS`条
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub comments: Vec<RecordComment>,
+}
+
+/// 记录的评论, 只能追加, 用于保留记录的变更原因等团队信息
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordComment {
+    /// 添加时间(unix时间戳, 单位: 秒)
+    pub time: u64,
+    /// 添加人, 可以为空
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub author: String,
+    pub text: String,
+}
+
+/// 记录的自定义字段(对应keepass条目中标准字段以外的字符串字段)
+///
+/// 受保护字段的内容在内存中加密保存, 序列化格式与客户端共用的`accinfo_api::RecordField`相同
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(from = "accinfo_api::RecordField", into = "accinfo_api::RecordField")]
+pub struct RecordField {
+    pub name: String,
+    pub value: FieldValue,
+}
+
+/// 自定义字段的内容
+#[derive(Clone, Debug)]
+pub enum FieldValue {
+    Plain(String),
+    /// 受保护的字段, 与口令一样脱敏显示, 不参与搜索, 只在查看口令及导出时解密
+    Protected(Sealed),
+}
+
+/// 记录列表中的自定义字段, 受保护字段的内容已脱敏
+#[derive(Serialize, Debug)]
+pub struct FieldSummary<'a> {
+    pub name: &'a str,
+    pub value: &'a str,
+}
+
+/// 记录附件的引用
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FileRef {
+    /// 附件区中的附件id
+    pub id: String,
+    /// 文件名
+    pub name: String,
+    /// 文件大小(单位: 字节)
+    pub size: usize,
+}
+
+/// 记录的历史版本, 保存修改前的主要内容
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordVersion {
+    /// 该版本的修改时间(unix时间戳, 单位: 秒)
+    pub modified: u64,
+    pub title: String,
+    pub user: IStr,
+    pub pass: Sealed,
+    pub url: IStr,
+    pub notes: Sealed,
+}
+
+/// 分组
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Group {
+    pub id: String,
+    pub name: String,
+    /// 上级分组id, 顶级分组为空
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub parent: String,
+    /// 分组缺省设置, 该分组下新建的记录继承这些设置
+    #[serde(default)]
+    pub defaults: GroupDefaults,
+    /// 分组口令策略, 为空时使用上级分组或全局的口令策略
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<Policy>,
+}
+
+/// 分组缺省设置, 未设置的项继承上级分组的设置
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupDefaults {
+    /// 新建记录口令为空时, 自动生成口令的规则
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<GenOptions>,
+    /// 记录有效期(单位: 天)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expire_days: Option<u32>,
+    /// 新建记录的缺省标签
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+}
+
+/// 附件, 自定义图标等二进制数据统一保存在附件区
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Attachment {
+    pub id: String,
+    pub name: String,
+    pub mime: String,
+    /// base64编码的二进制数据, 在内存中加密保存, 只在读取附件时解密
+    pub data: Sealed,
+    /// 所属记录id, 自定义图标为空
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub owner: String,
+}
+
+/// 在内存中加密保存的字符串字段, 使用进程启动时随机生成的密钥(AES-256-GCM)
+///
+/// 序列化时输出明文, 反序列化时加密, 因此数据库文件格式不受影响;
+/// 不同时刻加密的相同内容密文不同, 因此不实现`PartialEq`, 比较内容使用`same_plain`
+#[derive(Clone, Default)]
+pub struct Sealed(Vec<u8>);
+
+/// 解密后的敏感字符串, 释放时清零内存, 避免口令明文残留在已释放的堆内存中
+#[derive(Default)]
+pub struct SecretString(Zeroizing<String>);
+
+/// 口令脱敏后显示的内容
+pub const MASKED_PASS: &str = "***";
+/// 每条记录最多保留的历史版本数量, 与KeePass的缺省设置一致
+pub const MAX_HISTORY: usize = 10;
+/// 每条记录最多的评论数量, 评论只能追加, 达到上限后不能再添加
+pub const MAX_COMMENTS: usize = 100;
+/// 导入时单个二进制附件解码后的最大字节数
+const MAX_BINARY_SIZE: usize = 16 * 1024 * 1024;
+
+/// 记录列表中返回的记录摘要, 口令已脱敏, 不包含需要解密的备注内容
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordSummary<'a> {
+    pub id: &'a str,
+    pub title: &'a str,
+    pub user: &'a str,
+    pub pass: &'a str,
+    pub url: &'a str,
+    pub icon: u32,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    pub custom_icon: &'a str,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    pub group: &'a str,
+    #[serde(skip_serializing_if = "<[IStr]>::is_empty")]
+    pub tags: &'a [IStr],
+    pub expire: u64,
+    pub modified: u64,
+    /// 是否有备注, 备注内容通过单独的接口获取
+    pub has_notes: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub sensitive: bool,
+    /// 是否设置了一次性口令, 验证码通过单独的接口获取
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub has_otp: bool,
+    #[serde(skip_serializing_if = "<[FileRef]>::is_empty")]
+    pub attachments: &'a [FileRef],
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldSummary<'a>>,
+}
+
+/// aidb数据库加密保存的内容
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Database {
+    pub records: Vec<Arc<Record>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<Arc<Group>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Arc<Attachment>>,
+    /// 全局口令策略
+    #[serde(default)]
+    pub policy: Policy,
+    /// 自定义记录类型模板
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub templates: Vec<Arc<Template>>,
+}
+
+/// 记录类型模板, 定义某类记录(例如服务器、数据库、信用卡)的字段布局
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Template {
+    /// 模板id, 导入时按id合并, 不同实例间保持一致
+    pub id: String,
+    pub name: String,
+    /// keepass内置图标编号
+    #[serde(default)]
+    pub icon: u32,
+    pub fields: Vec<TemplateField>,
+}
+
+/// 模板字段
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateField {
+    pub name: String,
+    #[serde(default)]
+    pub kind: FieldKind,
+    /// 是否必填
+    #[serde(default)]
+    pub required: bool,
+    /// 缺省值
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub default: String,
+}
+
+/// 模板字段类型, 用于界面显示及输入校验
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldKind {
+    #[default]
+    Text,
+    /// 多行文本
+    Multiline,
+    /// 敏感内容, 显示时脱敏
+    Secret,
+    Url,
+    Email,
+    Number,
+    Date,
+}
+
+/// 数据库访问统计, 保存时写入数据库元数据中, 重启后继续累计
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Stats {
+    /// 累计登录次数
+    pub total_logins: u64,
+    /// 最后登录时间(unix时间戳)
+    pub last_login_time: u64,
+    /// 最后登录的客户端ip
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub last_login_ip: String,
+    /// 最后一次成功登录后的登录失败次数
+    pub failed_logins: u32,
+    /// 最后一次登录失败的时间(unix时间戳)
+    pub last_failed_time: u64,
+    /// 最后一次成功登录之前的登录信息
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_login: Option<LastLogin>,
+    /// 每条记录的访问统计, key: 记录id
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub records: BTreeMap<String, RecordStats>,
+}
+
+/// 上次登录信息
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct LastLogin {
+    /// 登录时间(unix时间戳), 0表示之前没有成功登录过
+    pub time: u64,
+    /// 登录的客户端ip
+    pub ip: String,
+    /// 该次登录之后到下一次成功登录之前的登录失败次数
+    pub failed_attempts: u32,
+}
+
+/// 记录的访问统计
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordStats {
+    /// 累计访问次数
+    pub reads: u64,
+    /// 最后访问时间(unix时间戳)
+    pub last_read: u64,
+}
+
+/// 数据库文件的解密内容, 访问统计不放入缓存中的数据库内容, 而是单独维护
+#[derive(Deserialize)]
+struct DatabaseFile {
+    #[serde(flatten)]
+    db: Database,
+    #[serde(default)]
+    stats: Stats,
+}
+
+/// 用于写入数据库文件
+#[derive(Serialize)]
+struct DatabaseFileRef<'a> {
+    #[serde(flatten)]
+    db: &'a Database,
+    stats: &'a Stats,
+}
+
+/// 数据库文件格式版本
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FormatVersion {
+    /// 头部: magic + 数据长度 + md5(口令), 数据使用AES-CTR加密
+    V1 = 1,
+    /// 头部: magic + 标志 + 版本 + argon2参数 + salt + nonce, 数据使用AES-GCM加密
+    V2 = 2,
+}
+
+impl FormatVersion {
+    /// 最新的格式版本, 保存数据库时总是使用该版本
+    pub const LATEST: FormatVersion = FormatVersion::V2;
+
+    /// 根据文件头部识别格式版本
+    pub fn detect(buf: &[u8]) -> Result<FormatVersion> {
+        if buf.len() < MAGIC_LEN + 2 || MAGIC != &buf[..MAGIC_LEN] {
+            bail!("database is not aidb format");
+        }
+        // v1格式此处为数据长度的最高字节, 不会是FORMAT_MARK
+        if buf[MAGIC_LEN] != FORMAT_MARK {
+            return Ok(FormatVersion::V1);
+        }
+        match buf[MAGIC_LEN + 1] {
+            2 => Ok(FormatVersion::V2),
+            v => bail!("unsupported database format version: {v}"),
+        }
+    }
+}
+
+impl std::fmt::Display for FormatVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "v{}", *self as u8)
+    }
+}
+
+/// 数据库文件头部信息, 用于升级前后的日志输出
+#[derive(Clone, Copy, Debug)]
+pub struct HeaderInfo {
+    pub version: FormatVersion,
+    /// 文件大小(单位: 字节)
+    pub size: u64,
+    /// argon2id参数(m/t/p), v1格式为None
+    pub kdf: Option<(u32, u32, u32)>,
+}
+
+impl std::fmt::Display for HeaderInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kdf {
+            Some((m, t, p)) => write!(f, "{}, {} bytes, argon2id(m={m}, t={t}, p={p}) + aes-256-gcm",
+                self.version, self.size),
+            None => write!(f, "{}, {} bytes, md5 + aes-128-ctr", self.version, self.size),
+        }
+    }
+}
+
+/// 导入到已存在的aidb数据库时的合并策略
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// 使用导入的内容覆盖整个数据库
+    #[default]
+    Overwrite,
+    /// 按记录id合并, 新增不存在的记录, 导入的记录修改时间更新时替换已有记录
+    Newer,
+    /// 按记录id合并, 只新增不存在的记录, 已有记录保持不变
+    Keep,
+}
+
+impl MergeStrategy {
+    /// 解析策略名称(overwrite/newer/keep, 不区分大小写)
+    pub fn parse(name: &str) -> Option<MergeStrategy> {
+        [MergeStrategy::Overwrite, MergeStrategy::Newer, MergeStrategy::Keep].into_iter()
+            .find(|s| s.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MergeStrategy::Overwrite => "overwrite",
+            MergeStrategy::Newer => "newer",
+            MergeStrategy::Keep => "keep",
+        }
+    }
+}
+
+/// 合并导入的结果
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    /// 新增的记录数量
+    pub added: usize,
+    /// 被导入内容替换的记录数量
+    pub updated: usize,
+    /// 内容相同的记录数量
+    pub unchanged: usize,
+    /// 内容不同但保留了已有内容的记录, (记录id, 标题)
+    pub conflicts: Vec<(String, String)>,
+}
+
+/// 导出文件格式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// KeePass 2 xml格式, 可以导入keepass或者再次通过`--encrypt`转换为aidb
+    Xml,
+    /// csv格式, 列: Group,Title,Username,Password,URL,Notes,Tags,Expires,Last Modified
+    Csv,
+}
+
+impl ExportFormat {
+    /// 解析格式名称(xml/csv, 不区分大小写)
+    pub fn parse(name: &str) -> Option<ExportFormat> {
+        if name.eq_ignore_ascii_case("xml") {
+            Some(ExportFormat::Xml)
+        } else if name.eq_ignore_ascii_case("csv") {
+            Some(ExportFormat::Csv)
+        } else {
+            None
+        }
+    }
+
+    /// 根据文件扩展名确定导出格式, 非csv扩展名都使用xml格式
+    pub fn from_path(path: &str) -> ExportFormat {
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some(ext) => ExportFormat::parse(ext).unwrap_or(ExportFormat::Xml),
+            None => ExportFormat::Xml,
+        }
+    }
+
+    pub fn ext(&self) -> &'static str {
+        match self {
+            ExportFormat::Xml => "xml",
+            ExportFormat::Csv => "csv",
+        }
+    }
+
+    pub fn mime(&self) -> &'static str {
+        match self {
+            ExportFormat::Xml => "application/xml; charset=utf-8",
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+        }
+    }
+}
+
+/// 字符串驻留池, 相同内容的字符串共享同一份内存
+#[derive(Default)]
+struct Interner(HashSet<IStr>);
+
+pub struct CacheRecord {
+    pub data: Arc<Database>,
+    /// 数据库内容的全文搜索索引, 随缓存内容一起创建
+    pub index: Arc<SearchIndex>,
+    time: std::time::Instant,
+}
+
+/// 单个数据库的访问统计
+#[derive(Default)]
+struct DbStats {
+    /// 访问统计, 首次读取数据库文件时初始化
+    stats: Option<Stats>,
+    /// 自上次保存后是否发生变化
+    dirty: bool,
+    /// 访问统计尚未从数据库文件载入时的登录失败次数, 载入时合并
+    pending_failed: u32,
+}
+
+type CacheRecords = HashMap<String, CacheRecord>; // key: 数据库文件名
+
+struct MyAes (Aes128Ctr64LE);
+
+const IV: &str = "The great rejuvenation of the Chinese nation";
+const MAGIC: &[u8] = b"aidb";
+const MAGIC_LEN: usize = 4;
+const HEADER_LEN: usize = MAGIC_LEN + 4;
+const ATTACH_LEN: usize = HEADER_LEN + 16;
+/// v2及以后格式的标志, 位于magic之后, v1格式此处为数据长度的最高字节, 不会是0xff
+const FORMAT_MARK: u8 = 0xff;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const GCM_TAG_LEN: usize = 16;
+/// v2格式头部长度: magic + 标志 + 版本 + argon2参数(m/t/p) + salt + nonce
+const V2_HEADER_LEN: usize = MAGIC_LEN + 2 + 12 + SALT_LEN + NONCE_LEN;
+/// argon2id参数, 内存19MiB, 迭代2次, 并行度1(OWASP推荐的最低配置)
+const ARGON2_M_COST: u32 = 19 * 1024;
+const ARGON2_T_COST: u32 = 2;
+const ARGON2_P_COST: u32 = 1;
+/// 读取文件时允许的argon2参数上限, 防止篡改的头部导致密钥派生耗尽内存或者长时间占用cpu
+const ARGON2_MAX_M_COST: u32 = 256 * 1024;
+const ARGON2_MAX_T_COST: u32 = 16;
+const ARGON2_MAX_P_COST: u32 = 8;
+const ICON_MIME: &str = "image/png";
+const FILE_MIME: &str = "application/octet-stream";
+/// keepass条目的标准字段, 其它字符串字段作为自定义字段导入
+const STANDARD_FIELDS: [&str; 10] = ["Title", "UserName", "Password", "URL", "Notes", "otp",
+    "TimeOtp-Secret-Base32", "TimeOtp-Length", "TimeOtp-Period", "TimeOtp-Algorithm"];
+
+/// 各数据库的缓存内容, 多个数据库共用同一把锁
+static REC_CACHE: Mutex<Option<CacheRecords>> = Mutex::new(None);
+/// 内存中加密字段使用的密钥, 首次使用时随机生成, 不会持久化
+static SEALED_KEY: OnceLock<Aes256Gcm> = OnceLock::new();
+/// 各数据库的访问统计, key: 数据库文件名
+static STATS: Mutex<Option<HashMap<String, DbStats>>> = Mutex::new(None);
+/// 本进程最后一次读写时数据库文件的修改时间, 用于识别外部修改, key: 数据库文件名
+static FILE_MTIMES: Mutex<Option<HashMap<String, SystemTime>>> = Mutex::new(None);
+/// 导出及接口返回的记录按分组、标题、id排序, 便于比较两次输出的差异
+static STABLE_ORDER: AtomicBool = AtomicBool::new(false);
+
+/// 设置导出及接口返回的记录是否按分组、标题、id排序
+pub fn set_stable_order(enable: bool) {
+    STABLE_ORDER.store(enable, Ordering::Relaxed);
+}
+
+/// 导出及接口返回的记录是否按分组、标题、id排序
+pub fn stable_order() -> bool {
+    STABLE_ORDER.load(Ordering::Relaxed)
+}
+
+
+pub fn recycle_cache(expire: std::time::Duration) {
+    if let Some(g_recs) = REC_CACHE.lock().as_mut() {
+        g_recs.retain(|aidb, recs| {
+            let keep = recs.time.elapsed() <= expire;
+            if !keep {
+                log::trace!("cache data of {aidb} idle for too long, freeing the memory occupied by cache data");
+            }
+            keep
+        });
+    }
+}
+
+/// 释放被外部修改过的数据库文件的缓存, 下次访问时重新读取
+///
+/// Returns:
+///
+/// 缓存被释放的数据库文件名
+pub fn reload_changed() -> Vec<String> {
+    let mut g_recs = REC_CACHE.lock();
+    let g_recs = match g_recs.as_mut() {
+        Some(g_recs) => g_recs,
+        None => return Vec::new(),
+    };
+    let mtimes = FILE_MTIMES.lock();
+    let changed: Vec<String> = g_recs.keys()
+        .filter(|aidb| {
+            let known = mtimes.as_ref().and_then(|m| m.get(aidb.as_str()));
+            file_mtime(aidb).is_some_and(|t| known != Some(&t))
+        })
+        .cloned()
+        .collect();
+    drop(mtimes);
+
+    for aidb in changed.iter() {
+        g_recs.remove(aidb);
+        log::info!("database {aidb} modified by other process, cache dropped");
+    }
+    changed
+}
+
+/// 数据库文件的修改时间
+fn file_mtime(aidb: &str) -> Option<SystemTime> {
+    std::fs::metadata(aidb).and_then(|m| m.modified()).ok()
+}
+
+/// 记录本进程读写后数据库文件的修改时间
+fn remember_mtime(aidb: &str) {
+    if let Some(t) = file_mtime(aidb) {
+        FILE_MTIMES.lock().get_or_insert_with(HashMap::new).insert(aidb.to_owned(), t);
+    }
+}
+
+/// 释放所有缓存的数据库内容
+pub fn clear_cache() {
+    REC_CACHE.lock().take();
+}
+
+/// 估算缓存的数据库内容占用的内存大小(单位: 字节), 未缓存时返回0
+pub fn cache_size() -> u64 {
+    match REC_CACHE.lock().as_ref() {
+        Some(g_recs) => g_recs.values().map(|recs| recs.data.mem_size() as u64).sum(),
+        None => 0,
+    }
+}
+
+/// Convert the xml file exported from keepass (or the kdbx database file) into an aidb database
+/// and encrypt it with the specified password
+///
+/// * `src_file`: The xml file exported from keepass, or the keepass .kdbx database file
+/// * `src_password`: The kdbx database password, ignored for xml file
+/// * `password`: Database password
+/// * `out_file`: Output aidb database filename
+/// * `check`: Validation applied to every imported record
+pub fn encrypt_database(src_file: &str, src_password: &str, password: &str, out_file: &str,
+        check: &dyn Fn(&Record) -> Result<()>) -> Result<()> {
+    let db = load_import(src_file, src_password, check)?;
+    write_database(out_file, password, &db, &Stats::default())
+}
+
+/// 将keepass导出的xml文件(或者kdbx数据库文件)按记录id合并到已存在的aidb数据库中,
+/// 分组及附件同样按id合并, 已有数据库的访问统计保留不变
+///
+/// * `src_file`: The xml file exported from keepass, or the keepass .kdbx database file
+/// * `src_password`: The kdbx database password, ignored for xml file
+/// * `password`: Database password
+/// * `aidb`: 已存在的aidb数据库文件名
+/// * `strategy`: 合并策略, 不能是`MergeStrategy::Overwrite`
+/// * `check`: 导入记录的校验函数, 任一记录校验失败时不做任何修改
+pub fn merge_database(src_file: &str, src_password: &str, password: &str, aidb: &str,
+        strategy: MergeStrategy, check: &dyn Fn(&Record) -> Result<()>) -> Result<MergeReport> {
+    if strategy == MergeStrategy::Overwrite {
+        bail!("merge strategy must be newer or keep");
+    }
+    let src = load_import(src_file, src_password, check)?;
+    let mut db = read_database(aidb, password)?;
+    let report = db.merge(src, strategy);
+    save_database(aidb, password, &db)?;
+    Ok(report)
+}
+
+/// 读取keepass导出的xml文件或者kdbx数据库文件, 使用`check`校验每条记录
+fn load_import(src_file: &str, src_password: &str, check: &dyn Fn(&Record) -> Result<()>) -> Result<Database> {
+    let is_kdbx = Path::new(src_file).extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("kdbx"));
+    let db = if is_kdbx {
+        load_kdbx(src_file, src_password)?
+    } else {
+        load_xml(&Zeroizing::new(std::fs::read(src_file)?))?
+    };
+    log::trace!("{src_file} record total: {}, group total: {}, attachment total: {}",
+        db.records.len(), db.groups.len(), db.attachments.len());
+    for rec in db.records.iter() {
+        check(rec).map_err(|e| anyhow!("record {} ({}): {e}", rec.title, rec.id))?;
+    }
+    Ok(db)
+}
+
+/// Load database content using the specified password
+///
+/// * `aidb`: Database file name
+/// * `password`: Database password
+pub fn load_database(aidb: &str, password: &str) -> Result<Arc<Database>> {
+    let mut g_recs = REC_CACHE.lock();
+    Ok(cache_record(g_recs.get_or_insert_with(HashMap::new), aidb, password)?.data.clone())
+}
+
+/// 加载数据库内容及其全文搜索索引
+///
+/// * `aidb`: Database file name
+/// * `password`: Database password
+pub fn load_search_index(aidb: &str, password: &str) -> Result<(Arc<Database>, Arc<SearchIndex>)> {
+    let mut g_recs = REC_CACHE.lock();
+    let recs = cache_record(g_recs.get_or_insert_with(HashMap::new), aidb, password)?;
+    Ok((recs.data.clone(), recs.index.clone()))
+}
+
+/// 获取缓存的数据库内容, 未缓存时读取数据库文件并创建缓存
+fn cache_record<'a>(g_recs: &'a mut CacheRecords, aidb: &str, password: &str) -> Result<&'a CacheRecord> {
+    match g_recs.get_mut(aidb) {
+        Some(recs) => recs.time = std::time::Instant::now(),
+        None => {
+            let recs = CacheRecord::new(Arc::new(read_database(aidb, password)?));
+            log::trace!("load database {aidb} record total: {}", recs.data.records.len());
+            g_recs.insert(aidb.to_owned(), recs);
+        }
+    }
+
+    // 上面已确保缓存存在
+    Ok(&g_recs[aidb])
+}
+
+/// 修改数据库内容并保存到文件, 修改期间持有缓存锁, 保证读-改-写的原子性
+///
+/// * `aidb`: aidb数据库文件名
+/// * `password`: 数据库口令
+/// * `f`: 修改函数, 返回错误时放弃修改
+pub fn update_database<T, F>(aidb: &str, password: &str, f: F) -> Result<T>
+where
+    F: FnOnce(&mut Database) -> Result<T>,
+{
+    update_database_snapshot(aidb, password, f).map(|(ret, _, _)| ret)
+}
+
+/// 同`update_database`, 同时返回修改前和修改后的数据库内容, 用于实现撤销功能
+pub fn update_database_snapshot<T, F>(aidb: &str, password: &str, f: F)
+    -> Result<(T, Arc<Database>, Arc<Database>)>
+where
+    F: FnOnce(&mut Database) -> Result<T>,
+{
+    let mut g_recs = REC_CACHE.lock();
+    let g_recs = g_recs.get_or_insert_with(HashMap::new);
+    let before = match g_recs.get(aidb) {
+        Some(recs) => recs.data.clone(),
+        None => Arc::new(read_database(aidb, password)?),
+    };
+
+    let mut db = Database::clone(&before);
+    let ret = f(&mut db)?;
+    save_database(aidb, password, &db)?;
+
+    let after = Arc::new(db);
+    g_recs.insert(aidb.to_owned(), CacheRecord::new(after.clone()));
+
+    Ok((ret, before, after))
+}
+
+/// 将数据库内容恢复为`db`, 仅当当前数据库内容仍为`expect`时才执行恢复
+///
+/// Returns:
+///
+/// Ok(true): 恢复成功, Ok(false): 数据库内容已被其它操作修改, Err(e): 其它错误
+pub fn restore_database(aidb: &str, password: &str, expect: &Arc<Database>, db: Arc<Database>) -> Result<bool> {
+    let mut g_recs = REC_CACHE.lock();
+    let g_recs = g_recs.get_or_insert_with(HashMap::new);
+    let current = match g_recs.get(aidb) {
+        Some(recs) => recs.data.clone(),
+        None => Arc::new(read_database(aidb, password)?),
+    };
+    // 缓存重新加载后内容相同但加密字段的密文不同, 按明文摘要比较
+    if !Arc::ptr_eq(&current, expect) && current.digest()? != expect.digest()? {
+        return Ok(false);
+    }
+
+    save_database(aidb, password, &db)?;
+    g_recs.insert(aidb.to_owned(), CacheRecord::new(db));
+
+    Ok(true)
+}
+
+/// 重新加密数据库内容并以原子方式覆盖写入数据库文件(先写临时文件再改名)
+///
+/// * `aidb`: aidb数据库文件名
+/// * `password`: 数据库口令
+/// * `db`: 数据库内容
+pub fn save_database(aidb: &str, password: &str, db: &Database) -> Result<()> {
+    // 合并当前的访问统计, 同时清除已删除记录的统计
+    let mut stats = current_stats(aidb);
+    let ids: HashSet<&str> = db.records.iter().map(|r| r.id.as_str()).collect();
+    stats.records.retain(|id, _| ids.contains(id.as_str()));
+
+    let tmp_file = format!("{aidb}.tmp");
+    write_database(&tmp_file, password, db, &stats)?;
+    std::fs::rename(&tmp_file, aidb).map_err(|e| {
+        let _ = std::fs::remove_file(&tmp_file);
+        e
+    })?;
+    remember_mtime(aidb);
+    if let Some(s) = STATS.lock().as_mut().and_then(|s| s.get_mut(aidb)) {
+        s.dirty = false;
+    }
+    log::trace!("save database record total: {}", db.records.len());
+    Ok(())
+}
+
+/// 访问统计有变化时, 将其写入数据库文件
+///
+/// * `aidb`: aidb数据库文件名
+/// * `password`: 数据库口令
+pub fn flush_stats(aidb: &str, password: &str) -> Result<()> {
+    let dirty = STATS.lock().as_ref().and_then(|s| s.get(aidb)).is_some_and(|s| s.dirty);
+    if !dirty {
+        return Ok(());
+    }
+
+    let g_recs = REC_CACHE.lock();
+    match g_recs.as_ref().and_then(|g| g.get(aidb)) {
+        Some(recs) => save_database(aidb, password, &recs.data),
+        None => save_database(aidb, password, &read_database(aidb, password)?),
+    }
+}
+
+/// 累计登录统计
+///
+/// * `aidb`: aidb数据库文件名
+/// * `ip`: 登录的客户端ip
+///
+/// Returns:
+///
+/// 本次登录之前的登录信息, 之前没有登录过也没有登录失败时返回None
+pub fn stats_login(aidb: &str, ip: String) -> Option<LastLogin> {
+    let mut g_stats = STATS.lock();
+    let s = db_stats(&mut g_stats, aidb);
+    s.dirty = true;
+    let stats = s.stats.get_or_insert_with(Stats::default);
+
+    let prev = if stats.total_logins > 0 || stats.failed_logins > 0 {
+        Some(LastLogin {
+            time: stats.last_login_time,
+            ip: std::mem::take(&mut stats.last_login_ip),
+            failed_attempts: stats.failed_logins,
+        })
+    } else {
+        None
+    };
+
+    stats.total_logins += 1;
+    stats.last_login_time = localtime::unix_timestamp();
+    stats.last_login_ip = ip;
+    stats.failed_logins = 0;
+    stats.previous_login = prev.clone();
+
+    prev
+}
+
+/// 累计登录失败统计
+///
+/// * `aidb`: aidb数据库文件名
+pub fn stats_login_failed(aidb: &str) {
+    let mut g_stats = STATS.lock();
+    let s = db_stats(&mut g_stats, aidb);
+    match s.stats.as_mut() {
+        Some(stats) => {
+            stats.failed_logins += 1;
+            stats.last_failed_time = localtime::unix_timestamp();
+            s.dirty = true;
+        }
+        // 口令错误时无法解密数据库载入统计, 先暂存, 载入时再合并
+        None => s.pending_failed += 1,
+    }
+}
+
+/// 累计记录访问统计
+///
+/// * `aidb`: aidb数据库文件名
+/// * `ids`: 被访问的记录id
+pub fn stats_read<'a, I: IntoIterator<Item = &'a str>>(aidb: &str, ids: I) {
+    let now = localtime::unix_timestamp();
+    let mut g_stats = STATS.lock();
+    let s = db_stats(&mut g_stats, aidb);
+    let stats = s.stats.get_or_insert_with(Stats::default);
+    for id in ids {
+        let rs = stats.records.entry(id.to_owned()).or_default();
+        rs.reads += 1;
+        rs.last_read = now;
+        s.dirty = true;
+    }
+}
+
+/// 获取数据库当前的访问统计
+///
+/// * `aidb`: aidb数据库文件名
+pub fn stats(aidb: &str) -> Stats {
+    current_stats(aidb)
+}
+
+fn current_stats(aidb: &str) -> Stats {
+    STATS.lock().as_ref()
+        .and_then(|s| s.get(aidb))
+        .and_then(|s| s.stats.clone())
+        .unwrap_or_default()
+}
+
+fn db_stats<'a>(g_stats: &'a mut Option<HashMap<String, DbStats>>, aidb: &str) -> &'a mut DbStats {
+    g_stats.get_or_insert_with(HashMap::new).entry(aidb.to_owned()).or_default()
+}
+
+/// 生成keepass格式的uuid(16字节随机数的base64编码)
+pub fn new_uuid() -> String {
+    BASE64.encode(rand::random::<[u8; 16]>())
+}
+
+/// 将数据库内容导出为明文的KeePass 2 xml或者csv格式
+///
+/// * `aidb`: aidb数据库文件名
+/// * `password`: 数据库口令
+/// * `format`: 导出格式
+/// * `out`: 导出内容的输出目标
+pub fn export_database<W: Write>(aidb: &str, password: &str, format: ExportFormat, out: &mut W) -> Result<()> {
+    export_database_with(aidb, password, format, out, &mut |_, _| Ok(()))
+}
+
+/// 导出数据库, 每输出一条记录调用一次`progress`
+///
+/// * `progress`: 进度回调, 参数为已输出的记录数及记录总数, 返回错误时中止导出
+pub fn export_database_with<W: Write>(aidb: &str, password: &str, format: ExportFormat, out: &mut W,
+        progress: &mut dyn FnMut(usize, usize) -> Result<()>) -> Result<()> {
+    let db = load_database(aidb, password)?;
+    let total = db.records.len();
+    let mut done = 0;
+    let mut step = || {
+        done += 1;
+        progress(done, total)
+    };
+    match format {
+        ExportFormat::Xml => export_xml(&db, out, &mut step)?,
+        ExportFormat::Csv => export_csv(&db, out, &mut step)?,
+    }
+    log::trace!("export database record total: {}, format: {}", db.records.len(), format.ext());
+    Ok(())
+}
+
+/// 获取数据库文件的格式版本
+pub fn format_version(aidb: &str) -> Result<FormatVersion> {
+    let mut buf = [0u8; MAGIC_LEN + 2];
+    std::fs::File::open(aidb)?.read_exact(&mut buf)?;
+    FormatVersion::detect(&buf)
+}
+
+/// 读取数据库文件的头部信息, 不需要口令
+pub fn header_info(aidb: &str) -> Result<HeaderInfo> {
+    let buf = std::fs::read(aidb)?;
+    let version = check_header(&buf)?;
+    let kdf = match version {
+        FormatVersion::V1 => None,
+        FormatVersion::V2 => {
+            let param = |i: usize| {
+                let pos = MAGIC_LEN + 2 + i * 4;
+                u32::from_be_bytes([buf[pos], buf[pos + 1], buf[pos + 2], buf[pos + 3]])
+            };
+            Some((param(0), param(1), param(2)))
+        }
+    };
+    Ok(HeaderInfo { version, size: buf.len() as u64, kdf })
+}
+
+/// 不需要口令校验数据库文件的头部格式及长度, 用于就绪检查
+///
+/// Returns:
+///
+/// 文件的格式版本, 文件不存在、不是aidb格式或者长度不符时返回错误
+pub fn check_format(aidb: &str) -> Result<FormatVersion> {
+    let buf = std::fs::read(aidb)?;
+    check_header(&buf)
+}
+
+/// 校验数据库内容的头部及长度
+fn check_header(buf: &[u8]) -> Result<FormatVersion> {
+    let ver = FormatVersion::detect(buf)?;
+    match ver {
+        FormatVersion::V2 => if buf.len() < V2_HEADER_LEN + GCM_TAG_LEN {
+            bail!("database size too small");
+        },
+        FormatVersion::V1 => {
+            if buf.len() < ATTACH_LEN {
+                bail!("database size too small");
+            }
+            let len = ((buf[4] as u32) << 24) | ((buf[5] as u32) << 16) | ((buf[6] as u32) << 8) | (buf[7] as u32);
+            if (len as usize) != buf.len() - ATTACH_LEN {
+                bail!("database size format error");
+            }
+        }
+    }
+    Ok(ver)
+}
+
+/// 将数据库文件升级为最新格式, 升级前将原文件备份为`.bak`文件
+///
+/// * `aidb`: aidb数据库文件名
+/// * `password`: 数据库口令
+///
+/// Returns:
+///
+/// Ok(Some(ver)): 升级前的格式版本, Ok(None): 已经是最新格式, 无需升级
+pub fn migrate_database(aidb: &str, password: &str) -> Result<Option<FormatVersion>> {
+    let ver = format_version(aidb)?;
+    if ver >= FormatVersion::LATEST {
+        return Ok(None);
+    }
+
+    let db = read_database(aidb, password)?;
+    let stats = current_stats(aidb);
+
+    std::fs::copy(aidb, format!("{aidb}.bak"))?;
+    let tmp_file = format!("{aidb}.tmp");
+    write_database(&tmp_file, password, &db, &stats)?;
+    std::fs::rename(&tmp_file, aidb).map_err(|e| {
+        let _ = std::fs::remove_file(&tmp_file);
+        e
+    })?;
+    log::info!("database {aidb} migrated from {ver} to {}", FormatVersion::LATEST);
+
+    Ok(Some(ver))
+}
+
+/// 修改数据库口令, 使用原口令解密后以新口令重新派生密钥加密, 以原子方式覆盖写入数据库文件
+///
+/// * `aidb`: aidb数据库文件名
+/// * `old_pass`: 原口令
+/// * `new_pass`: 新口令
+pub fn reencrypt(aidb: &str, old_pass: &str, new_pass: &str) -> Result<()> {
+    // 持有缓存锁, 避免修改过程中其它请求以原口令写入数据库
+    let mut g_recs = REC_CACHE.lock();
+    let g_recs = g_recs.get_or_insert_with(HashMap::new);
+    let db = read_database(aidb, old_pass)?;
+    save_database(aidb, new_pass, &db)?;
+    g_recs.insert(aidb.to_owned(), CacheRecord::new(Arc::new(db)));
+    log::info!("database {aidb} re-encrypted with new password");
+    Ok(())
+}
+
+/// 校验数据库密码是否正确, v2格式只校验GCM认证标签, 不解密数据库内容
+///
+/// * `aidb`: aidb数据库文件名
+/// * `password`: 数据库口令
+///
+/// Returns:
+///
+/// Ok(true): 密码正确, Ok(false) 密码错误, Err(e): 其它错误
+pub fn check_password(aidb: &str, password: &str) -> Result<bool> {
+    let buf = std::fs::read(aidb)?;
+    if check_header(&buf)? == FormatVersion::V2 {
+        let (header, data) = buf.split_at(V2_HEADER_LEN);
+        let key = header_key(header, password)?;
+        return Ok(verify_tag(&key, header, data));
+    }
+    Ok(md5_password(password).as_slice() == &buf[HEADER_LEN..ATTACH_LEN])
+}
+
+/// 在阻塞线程中校验数据库密码, Argon2密钥派生耗时较长, 异步接口中使用以免阻塞运行时
+pub async fn check_password_blocking(aidb: &str, password: &str) -> Result<bool> {
+    let (aidb, password) = (aidb.to_owned(), SecretString(Zeroizing::new(password.to_owned())));
+    tokio::task::spawn_blocking(move || check_password(&aidb, &password)).await?
+}
+
+/// 读取并解密数据库文件, 兼容旧版仅包含记录数组的数据格式
+fn read_database(aidb: &str, password: &str) -> Result<Database> {
+    let span = timing::span(Phase::Decrypt);
+    let buf = std::fs::read(aidb)?;
+    remember_mtime(aidb);
+    let data = match decrypt_database(buf, password)? {
+        Some(data) => Zeroizing::new(data),
+        None => bail!("password error"),
+    };
+    drop(span);
+
+    let _span = timing::span(Phase::Parse);
+    let data = data.as_slice();
+    let mut dbf = if data.first() == Some(&b'[') {
+        let records: Vec<Arc<Record>> = serde_json::from_slice(data)?;
+        DatabaseFile { db: Database { records, ..Default::default() }, stats: Stats::default() }
+    } else {
+        serde_json::from_slice::<DatabaseFile>(data)?
+    };
+    let saved = dbf.db.intern();
+    log::debug!("database string interning saved {saved} bytes, memory usage: {} bytes", dbf.db.mem_size());
+
+    // 进程内的访问统计比文件中的更新, 只在首次读取时初始化
+    let mut g_stats = STATS.lock();
+    let s = db_stats(&mut g_stats, aidb);
+    if s.stats.is_none() {
+        let mut file_stats = dbf.stats;
+        if s.pending_failed > 0 {
+            file_stats.failed_logins += std::mem::take(&mut s.pending_failed);
+            file_stats.last_failed_time = localtime::unix_timestamp();
+            s.dirty = true;
+        }
+        s.stats = Some(file_stats);
+    }
+
+    Ok(dbf.db)
+}
+
+/// 解密数据库文件内容, 支持v1(md5校验+AES-CTR)和v2(Argon2id+AES-GCM)格式
+///
+/// Returns:
+///
+/// Ok(Some(data)): 解密后的内容, Ok(None): 口令错误, Err(e): 文件格式错误
+fn decrypt_database(mut buf: Vec<u8>, password: &str) -> Result<Option<Vec<u8>>> {
+    // v2格式, 头部: magic + 标志 + 版本 + argon2参数 + salt + nonce
+    if check_header(&buf)? == FormatVersion::V2 {
+        let (header, data) = buf.split_at(V2_HEADER_LEN);
+        let nonce = &header[V2_HEADER_LEN - NONCE_LEN..];
+        let key = header_key(header, password)?;
+        let cipher = Aes256Gcm::new(&(*key).into());
+        // 认证失败可能是口令错误, 也可能是文件被篡改, 两者无法区分
+        return Ok(cipher.decrypt(Nonce::from_slice(nonce), Payload { msg: data, aad: header }).ok());
+    }
+
+    // v1格式, 头部: magic + 数据长度 + md5(口令)
+    if md5_password(password).as_slice() != &buf[HEADER_LEN..ATTACH_LEN] {
+        return Ok(None);
+    }
+
+    aes_decrypt(password.as_bytes(), &mut buf[ATTACH_LEN..]);
+    buf.drain(..ATTACH_LEN);
+    Ok(Some(buf))
+}
+
+/// 加密数据库内容并写入指定文件, 总是使用最新的v2格式, 旧格式的文件在保存时自动升级
+fn write_database(out_file: &str, password: &str, db: &Database, stats: &Stats) -> Result<()> {
+    let _span = timing::span(Phase::Serialize);
+    let data = Zeroizing::new(serde_json::to_vec(&DatabaseFileRef { db, stats })?);
+
+    let salt: [u8; SALT_LEN] = rand::random();
+    let nonce: [u8; NONCE_LEN] = rand::random();
+    let mut header = Vec::with_capacity(V2_HEADER_LEN);
+    header.extend_from_slice(MAGIC);
+    header.push(FORMAT_MARK);
+    header.push(FormatVersion::V2 as u8);
+    header.extend_from_slice(&ARGON2_M_COST.to_be_bytes());
+    header.extend_from_slice(&ARGON2_T_COST.to_be_bytes());
+    header.extend_from_slice(&ARGON2_P_COST.to_be_bytes());
+    header.extend_from_slice(&salt);
+    header.extend_from_slice(&nonce);
+    debug_assert!(header.len() == V2_HEADER_LEN);
+
+    let key = Zeroizing::new(derive_key(password, &salt, ARGON2_M_COST, ARGON2_T_COST, ARGON2_P_COST)?);
+    let cipher = Aes256Gcm::new(&(*key).into());
+    let data = cipher.encrypt(Nonce::from_slice(&nonce), Payload { msg: data.as_slice(), aad: &header })
+        .map_err(|_| anyhow!("encrypt database error"))?;
+
+    let mut ofile = std::fs::File::create(out_file)?;
+    ofile.write_all(&header)?;
+    ofile.write_all(&data)?;
+    ofile.sync_all()?;
+
+    Ok(())
+}
+
+/// 按v2格式头部中的argon2参数及salt从口令派生密钥, 参数超出范围时返回错误
+fn header_key(header: &[u8], password: &str) -> Result<Zeroizing<[u8; 32]>> {
+    let params = &header[MAGIC_LEN + 2..MAGIC_LEN + 14];
+    let m_cost = u32::from_be_bytes([params[0], params[1], params[2], params[3]]);
+    let t_cost = u32::from_be_bytes([params[4], params[5], params[6], params[7]]);
+    let p_cost = u32::from_be_bytes([params[8], params[9], params[10], params[11]]);
+    if !(1..=ARGON2_MAX_M_COST).contains(&m_cost) || !(1..=ARGON2_MAX_T_COST).contains(&t_cost)
+            || !(1..=ARGON2_MAX_P_COST).contains(&p_cost) {
+        bail!("argon2 params out of range: m={m_cost}, t={t_cost}, p={p_cost}");
+    }
+    let salt = &header[MAGIC_LEN + 14..MAGIC_LEN + 14 + SALT_LEN];
+    Ok(Zeroizing::new(derive_key(password, salt, m_cost, t_cost, p_cost)?))
+}
+
+/// 按AES-GCM的算法计算认证标签并与密文末尾的标签比较, 只校验不解密, 用于校验口令
+///
+/// 标签 = GHASH(H, aad, 密文) ^ E(K, nonce || 1), 其中 H = E(K, 0)
+fn verify_tag(key: &[u8; 32], header: &[u8], data: &[u8]) -> bool {
+    let (msg, tag) = data.split_at(data.len() - GCM_TAG_LEN);
+    let aes = aes::Aes256::new(key.into());
+    let mut h = ghash::Block::default();
+    aes.encrypt_block(&mut h);
+    let mut ghash = GHash::new(&h);
+    ghash.update_padded(header);
+    ghash.update_padded(msg);
+    let mut lens = ghash::Block::default();
+    lens[..8].copy_from_slice(&(header.len() as u64 * 8).to_be_bytes());
+    lens[8..].copy_from_slice(&(msg.len() as u64 * 8).to_be_bytes());
+    ghash.update(&[lens]);
+
+    let mut mask = ghash::Block::default();
+    mask[..NONCE_LEN].copy_from_slice(&header[V2_HEADER_LEN - NONCE_LEN..]);
+    mask[GCM_TAG_LEN - 1] = 1;
+    aes.encrypt_block(&mut mask);
+    let expected = ghash.finalize();
+    expected.iter().zip(mask.iter()).zip(tag).fold(0u8, |acc, ((e, m), t)| acc | (e ^ m ^ t)) == 0
+}
+
+/// 使用Argon2id从口令派生256位的加密密钥
+fn derive_key(password: &str, salt: &[u8], m_cost: u32, t_cost: u32, p_cost: u32) -> Result<[u8; 32]> {
+    let params = Params::new(m_cost, t_cost, p_cost, Some(32))
+        .map_err(|e| anyhow!("argon2 params error: {e}"))?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow!("argon2 derive key error: {e}"))?;
+    Ok(key)
+}
+
+impl Database {
+    /// 数据库内容的sha256摘要, 加密保存的字段按明文计算, 用于判断两份内容是否相同
+    pub fn digest(&self) -> Result<[u8; 32]> {
+        let mut hasher = Sha256::new();
+        serde_json::to_writer(&mut hasher, self)?;
+        Ok(hasher.finalize().into())
+    }
+
+    /// 对记录中重复率高的字符串字段进行驻留, 相同内容共享同一份内存
+    ///
+    /// Returns:
+    ///
+    /// 驻留后节省的内存大小估算值(单位: 字节)
+    pub fn intern(&mut self) -> usize {
+        let before = self.mem_size();
+        let mut pool = Interner::default();
+        for rec in self.records.iter_mut() {
+            let rec = Arc::make_mut(rec);
+            pool.intern(&mut rec.user);
+            pool.intern(&mut rec.url);
+            pool.intern(&mut rec.custom_icon);
+            pool.intern(&mut rec.group);
+            for tag in rec.tags.iter_mut() {
+                pool.intern(tag);
+            }
+        }
+        before.saturating_sub(self.mem_size())
+    }
+
+    /// 根据id查找记录所在的位置
+    pub fn record_index(&self, id: &str) -> Option<usize> {
+        self.records.iter().position(|r| r.id == id)
+    }
+
+    /// 估算数据库内容占用的内存大小(单位: 字节), 共享的字符串只计算一次
+    pub fn mem_size(&self) -> usize {
+        let mut shared = HashSet::new();
+        let mut istr_size = |s: &IStr| if shared.insert(s.as_ptr()) { s.len() } else { 0 };
+        let recs: usize = self.records.iter()
+            .map(|r| {
+                std::mem::size_of::<Record>() + r.id.len() + r.title.len() + istr_size(&r.user)
+                    + r.pass.size() + istr_size(&r.url) + r.notes.size() + r.otp.size() + istr_size(&r.custom_icon)
+                    + istr_size(&r.group)
+                    + r.tags.iter().map(|t| std::mem::size_of::<IStr>() + istr_size(t)).sum::<usize>()
+                    + r.fields.iter()
+                        .map(|f| std::mem::size_of::<RecordField>() + f.name.len() + f.size())
+                        .sum::<usize>()
+                    + r.comments.iter()
+                        .map(|c| std::mem::size_of::<RecordComment>() + c.author.len() + c.text.len())
+                        .sum::<usize>()
+                    + r.history.iter()
+                        .map(|v| std::mem::size_of::<RecordVersion>() + v.title.len() + istr_size(&v.user)
+                            + v.pass.size() + istr_size(&v.url) + v.notes.size())
+                        .sum::<usize>()
+            })
+            .sum();
+        let groups: usize = self.groups.iter()
+            .map(|g| std::mem::size_of::<Group>() + g.id.len() + g.name.len() + g.parent.len())
+            .sum();
+        let attachments: usize = self.attachments.iter()
+            .map(|a| std::mem::size_of::<Attachment>() + a.id.len() + a.name.len() + a.mime.len() + a.data.size()
+                + a.owner.len())
+            .sum();
+
+        recs + groups + attachments
+    }
+
+    /// 按id合并导入的数据库内容, 不存在的分组、记录及附件直接添加
+    ///
+    /// 已存在的记录按合并策略处理, 被替换的记录保留替换前的版本作为历史版本
+    pub fn merge(&mut self, src: Database, strategy: MergeStrategy) -> MergeReport {
+        let mut report = MergeReport::default();
+        for group in src.groups {
+            if self.group(&group.id).is_none() {
+                self.groups.push(group);
+            }
+        }
+        for rec in src.records {
+            let idx = match self.record_index(&rec.id) {
+                Some(idx) => idx,
+                None => {
+                    self.records.push(rec);
+                    report.added += 1;
+                    continue;
+                }
+            };
+            let old = &self.records[idx];
+            if old.same_content(&rec) {
+                report.unchanged += 1;
+            } else if strategy == MergeStrategy::Newer && rec.modified > old.modified {
+                let mut rec = Record::clone(&rec);
+                rec.push_history(old);
+                // 导入的文件不包含评论, 保留已有的评论
+                if rec.comments.is_empty() {
+                    rec.comments.clone_from(&old.comments);
+                }
+                self.records[idx] = Arc::new(rec);
+                report.updated += 1;
+            } else {
+                report.conflicts.push((old.id.clone(), old.title.clone()));
+            }
+        }
+        if stable_order() {
+            report.conflicts.sort_by(|a, b| (&a.1, &a.0).cmp(&(&b.1, &b.0)));
+        }
+        for attachment in src.attachments {
+            if self.attachment(&attachment.id).is_none() {
+                self.attachments.push(attachment);
+            }
+        }
+        self.prune_attachments();
+        report
+    }
+
+    /// 根据id查找附件
+    pub fn attachment(&self, id: &str) -> Option<&Arc<Attachment>> {
+        self.attachments.iter().find(|a| a.id == id)
+    }
+
+    /// 删除不再被任何记录引用的文件附件, 图标不受影响
+    pub fn prune_attachments(&mut self) {
+        let refs: HashSet<&str> = self.records.iter()
+            .flat_map(|r| r.attachments.iter().map(|f| f.id.as_str()))
+            .collect();
+        let len = self.attachments.len();
+        self.attachments.retain(|a| a.owner.is_empty() || refs.contains(a.id.as_str()));
+        if self.attachments.len() < len {
+            log::debug!("prune {} attachments", len - self.attachments.len());
+        }
+    }
+
+    /// 根据id查找记录类型模板
+    pub fn template(&self, id: &str) -> Option<&Arc<Template>> {
+        self.templates.iter().find(|t| t.id == id)
+    }
+
+    /// 根据id查找分组
+    pub fn group(&self, id: &str) -> Option<&Arc<Group>> {
+        self.groups.iter().find(|g| g.id == id)
+    }
+
+    /// 获取分组的有效缺省设置, 未设置的项沿上级分组逐级继承
+    pub fn group_defaults(&self, id: &str) -> GroupDefaults {
+        let mut defaults = GroupDefaults::default();
+        let mut curr = self.group(id);
+        let mut depth = 0;
+
+        while let Some(group) = curr {
+            defaults.inherit(&group.defaults);
+            // 防止错误的数据形成循环引用
+            depth += 1;
+            if depth > self.groups.len() {
+                break;
+            }
+            curr = self.group(&group.parent);
+        }
+
+        defaults
+    }
+
+    /// 获取分组的有效口令策略, 分组未设置时沿上级分组查找, 都未设置则使用全局策略
+    pub fn group_policy(&self, id: &str) -> &Policy {
+        let mut curr = self.group(id);
+        let mut depth = 0;
+
+        while let Some(group) = curr {
+            if let Some(policy) = &group.policy {
+                return policy;
+            }
+            depth += 1;
+            if depth > self.groups.len() {
+                break;
+            }
+            curr = self.group(&group.parent);
+        }
+
+        &self.policy
+    }
+
+    /// 按输出顺序返回记录, 启用稳定排序时按分组路径、标题、id排序, 否则按保存的顺序
+    pub fn output_records(&self) -> Vec<&Record> {
+        let mut records: Vec<&Record> = self.records.iter().map(|r| r.as_ref()).collect();
+        if stable_order() {
+            records.sort_by_cached_key(|r| (self.group_path(&r.group), r.title.as_str(), r.id.as_str()));
+        }
+        records
+    }
+
+    /// 启用稳定排序时将记录摘要按分组路径、标题、id排序
+    pub fn sort_summaries(&self, items: &mut [RecordSummary<'_>]) {
+        if stable_order() {
+            items.sort_by_cached_key(|r| (self.group_path(r.group), r.title, r.id));
+        }
+    }
+
+    /// 获取分组的完整路径, 各级分组名称以`/`分隔, 分组不存在时返回空字符串
+    pub fn group_path(&self, id: &str) -> String {
+        let mut names = Vec::new();
+        let mut curr = self.group(id);
+
+        while let Some(group) = curr {
+            names.push(group.name.as_str());
+            if names.len() > self.groups.len() {
+                break;
+            }
+            curr = self.group(&group.parent);
+        }
+
+        names.reverse();
+        names.join("/")
+    }
+}
+
+impl Template {
+    /// 校验模板内容, 返回错误描述
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if self.id.trim().is_empty() {
+            return Err(String::from("模板id不能为空"));
+        }
+        if self.name.trim().is_empty() {
+            return Err(format!("模板[{}]的名称不能为空", self.id));
+        }
+        let mut names = HashSet::new();
+        for field in self.fields.iter() {
+            if field.name.trim().is_empty() {
+                return Err(format!("模板[{}]的字段名称不能为空", self.id));
+            }
+            if !names.insert(field.name.as_str()) {
+                return Err(format!("模板[{}]的字段[{}]重复", self.id, field.name));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Interner {
+    /// 将`s`替换为池中相同内容的共享字符串, 池中不存在时加入池中
+    fn intern(&mut self, s: &mut IStr) {
+        if s.is_empty() {
+            return;
+        }
+        match self.0.get(&**s) {
+            Some(v) => *s = v.clone(),
+            None => { self.0.insert(s.clone()); },
+        }
+    }
 }
 
-pub type Records = Arc<[Arc<Record>]>;
+impl GroupDefaults {
+    /// 用上级分组的设置补全未设置的项
+    fn inherit(&mut self, parent: &GroupDefaults) {
+        if self.password.is_none() {
+            self.password = parent.password.clone();
+        }
+        if self.expire_days.is_none() {
+            self.expire_days = parent.expire_days;
+        }
+        if self.tags.is_none() {
+            self.tags = parent.tags.clone();
+        }
+    }
 
-pub struct CacheRecord {
-    pub data: Records,
-    time: std::time::Instant,
+    /// 将缺省设置应用到新建的记录, 只填充记录中未设置的项, 自动生成的口令符合口令策略
+    pub fn apply(&self, rec: &mut Record, policy: &Policy) {
+        if rec.pass.is_empty() {
+            if let Some(opts) = &self.password {
+                rec.pass = policy.generate(opts).into();
+            }
+        }
+        if rec.expire == 0 {
+            if let Some(days) = self.expire_days {
+                if days > 0 {
+                    rec.expire = localtime::unix_timestamp() + days as u64 * 86400;
+                }
+            }
+        }
+        if rec.tags.is_empty() {
+            if let Some(tags) = &self.tags {
+                rec.tags = tags.iter().map(|t| IStr::from(t.as_str())).collect();
+            }
+        }
+    }
 }
 
-struct MyAes (Aes128Ctr64LE);
+impl CacheRecord {
+    fn new(data: Arc<Database>) -> Self {
+        let span = timing::span(Phase::Search);
+        let index = Arc::new(SearchIndex::build(&data));
+        drop(span);
+        CacheRecord { data, index, time: std::time::Instant::now() }
+    }
+}
 
-const IV: &str = "The great rejuvenation of the Chinese nation";
-const MAGIC: &[u8] = b"aidb";
-const MAGIC_LEN: usize = 4;
-const HEADER_LEN: usize = MAGIC_LEN + 4;
-const ATTACH_LEN: usize = HEADER_LEN + 16;
+impl Record {
+    /// 生成记录摘要, 用于记录列表
+    pub fn summary(&self) -> RecordSummary<'_> {
+        RecordSummary {
+            id: &self.id,
+            title: &self.title,
+            user: &self.user,
+            pass: if self.pass.is_empty() { "" } else { MASKED_PASS },
+            url: &self.url,
+            icon: self.icon,
+            custom_icon: &self.custom_icon,
+            group: &self.group,
+            tags: &self.tags,
+            expire: self.expire,
+            modified: self.modified,
+            has_notes: !self.notes.is_empty(),
+            sensitive: self.sensitive,
+            has_otp: !self.otp.is_empty(),
+            attachments: &self.attachments,
+            fields: self.fields.iter()
+                .map(|f| FieldSummary { name: &f.name, value: masked_value(f) })
+                .collect(),
+        }
+    }
+}
 
-static REC_CACHE: Mutex<Option<CacheRecord>> = Mutex::new(None);
+impl Record {
+    /// 返回口令脱敏后的记录副本, 口令及受保护的自定义字段只能通过单独的接口获取, 一次性口令的密钥及历史版本不返回
+    pub fn masked(&self) -> Record {
+        let mut rec = self.clone();
+        if !rec.pass.is_empty() {
+            rec.pass = Sealed::new(MASKED_PASS);
+        }
+        for f in rec.fields.iter_mut() {
+            if let FieldValue::Protected(v) = &mut f.value {
+                if !v.is_empty() {
+                    *v = Sealed::new(MASKED_PASS);
+                }
+            }
+        }
+        rec.otp = Sealed::default();
+        rec.history = Vec::new();
+        rec
+    }
 
+    /// 主要内容(标题、用户名、口令、网址、备注)与修改前不同时, 将修改前的内容加入历史版本
+    ///
+    /// * `prev`: 修改前的记录
+    pub fn push_history(&mut self, prev: &Record) {
+        if self.title == prev.title && self.user == prev.user && self.pass.same_plain(&prev.pass)
+                && self.url == prev.url && self.notes.same_plain(&prev.notes) {
+            return;
+        }
+        self.history.push(prev.version());
+        limit_history(&mut self.history);
+    }
 
-pub fn recycle_cache(expire: std::time::Duration) {
-    let mut g_recs = REC_CACHE.lock();
-    if let Some(recs) = g_recs.as_ref() {
-        if recs.time.elapsed() > expire {
-            g_recs.take();
-            log::trace!("cache data idle for too long, freeing the memory occupied by cache data");
+    /// 两条记录的内容是否相同, 忽略修改时间及历史版本, 加密保存的内容按明文比较
+    pub fn same_content(&self, other: &Record) -> bool {
+        self.title == other.title && self.user == other.user && self.pass.same_plain(&other.pass)
+            && self.url == other.url && self.icon == other.icon && self.custom_icon == other.custom_icon
+            && self.group == other.group && self.tags == other.tags && self.expire == other.expire
+            && self.fields.len() == other.fields.len()
+            && self.fields.iter().zip(other.fields.iter()).all(|(a, b)| a.same_plain(b))
+            && self.attachments.iter().map(|f| (&f.name, f.size)).eq(other.attachments.iter().map(|f| (&f.name, f.size)))
+            && self.notes.reveal() == other.notes.reveal() && self.otp.reveal() == other.otp.reveal()
+    }
+
+    /// 记录当前内容对应的版本
+    fn version(&self) -> RecordVersion {
+        RecordVersion {
+            modified: self.modified,
+            title: self.title.clone(),
+            user: self.user.clone(),
+            pass: self.pass.clone(),
+            url: self.url.clone(),
+            notes: self.notes.clone(),
+        }
+    }
+
+    /// 记录内容的明文长度(单位: 字节), 用于限制单条记录的大小
+    pub fn data_size(&self) -> usize {
+        self.title.len() + self.user.len() + self.pass.plain_len() + self.url.len()
+            + self.notes.plain_len() + self.otp.plain_len()
+            + self.tags.iter().map(|t| t.len()).sum::<usize>()
+            + self.fields.iter().map(|f| f.name.len() + f.plain_len()).sum::<usize>()
+            + self.comments.iter().map(|c| c.author.len() + c.text.len()).sum::<usize>()
+    }
+
+    /// 追加评论, 已有的评论不会被删除
+    ///
+    /// Returns:
+    ///
+    /// 是否已追加, 评论数量达到`MAX_COMMENTS`时返回false
+    pub fn push_comment(&mut self, comment: RecordComment) -> bool {
+        if self.comments.len() >= MAX_COMMENTS {
+            return false;
         }
+        self.comments.push(comment);
+        true
     }
 }
 
-/// Convert the xml file exported from keepass into an aidb database and encrypt it with the specified password
-///
-/// * `xml_file`: The xml file exported from keepass
-/// * `password`: Database password
-/// * `out_file`: Output aidb database filename
-pub fn encrypt_database(xml_file: &str, password: &str, out_file: &str) -> Result<()> {
-    let xdata = std::fs::read(xml_file)?;
-    let recs = load_xml(&xdata)?;
-    log::trace!("{xml_file} record total: {}", recs.len());
+/// 受保护的自定义字段返回脱敏后的内容
+pub fn masked_value(f: &RecordField) -> &str {
+    match &f.value {
+        FieldValue::Plain(v) => v,
+        FieldValue::Protected(v) if v.is_empty() => "",
+        FieldValue::Protected(_) => MASKED_PASS,
+    }
+}
 
-    let mut recs_json = serde_json::to_vec(&recs)?;
-    aes_encrypt(password.as_bytes(), &mut recs_json);
+impl RecordField {
+    /// 创建自定义字段, 受保护字段的内容加密保存, 明文在加密后清零
+    pub fn new(name: String, value: String, protected: bool) -> Self {
+        let value = if protected { FieldValue::Protected(value.into()) } else { FieldValue::Plain(value) };
+        RecordField { name, value }
+    }
+
+    pub fn is_protected(&self) -> bool {
+        matches!(self.value, FieldValue::Protected(_))
+    }
 
-    let recs_json_len = recs_json.len();
-    let recs_json_len = [
-        ((recs_json_len >> 24) & 0xff) as u8,
-        ((recs_json_len >> 16) & 0xff) as u8,
-        ((recs_json_len >>  8) & 0xff) as u8,
-        ((recs_json_len      ) & 0xff) as u8,
-    ];
+    /// 非保护字段的内容, 受保护字段返回None, 用于搜索及索引
+    pub fn plain(&self) -> Option<&str> {
+        match &self.value {
+            FieldValue::Plain(v) => Some(v),
+            FieldValue::Protected(_) => None,
+        }
+    }
 
-    let check_data = &md5_password(password);
-    debug_assert!(check_data.len() == ATTACH_LEN - HEADER_LEN);
+    /// 内容的明文长度(单位: 字节)
+    pub fn plain_len(&self) -> usize {
+        match &self.value {
+            FieldValue::Plain(v) => v.len(),
+            FieldValue::Protected(v) => v.plain_len(),
+        }
+    }
 
-    let mut ofile = std::fs::File::create(out_file)?;
-    ofile.write_all(MAGIC)?;
-    ofile.write_all(&recs_json_len)?;
-    ofile.write_all(check_data.as_slice())?;
-    ofile.write_all(&recs_json)?;
+    /// 解密后的内容, 用于查看口令及导出
+    pub fn reveal(&self) -> SecretString {
+        match &self.value {
+            FieldValue::Plain(v) => SecretString(Zeroizing::new(v.clone())),
+            FieldValue::Protected(v) => v.reveal_secret(),
+        }
+    }
 
-    Ok(())
+    /// 名称、是否受保护及内容都相同, 受保护的内容按明文比较
+    pub fn same_plain(&self, other: &RecordField) -> bool {
+        self.name == other.name && match (&self.value, &other.value) {
+            (FieldValue::Plain(a), FieldValue::Plain(b)) => a == b,
+            (FieldValue::Protected(a), FieldValue::Protected(b)) => a.same_plain(b),
+            _ => false,
+        }
+    }
+
+    /// 内容占用的内存大小(单位: 字节)
+    fn size(&self) -> usize {
+        match &self.value {
+            FieldValue::Plain(v) => v.len(),
+            FieldValue::Protected(v) => v.size(),
+        }
+    }
 }
 
-/// Load database content using the specified password
-///
-/// * `aidb`: Database file name
-/// * `password`: Database password
-pub fn load_database(aidb: &str, password: &str) -> Result<Records> {
-    let mut g_recs = REC_CACHE.lock();
-    if let Some(ref mut recs) = *g_recs {
-        recs.time = std::time::Instant::now();
-        return Ok(recs.data.clone());
+impl From<accinfo_api::RecordField> for RecordField {
+    fn from(f: accinfo_api::RecordField) -> Self {
+        RecordField::new(f.name, f.value, f.protected)
     }
+}
 
-    let mut buf = std::fs::read(aidb)?;
-    if buf.len() < ATTACH_LEN {
-        bail!("database size too small");
+impl From<RecordField> for accinfo_api::RecordField {
+    fn from(f: RecordField) -> Self {
+        let protected = f.is_protected();
+        let value = match f.value {
+            FieldValue::Plain(v) => v,
+            FieldValue::Protected(v) => v.reveal(),
+        };
+        accinfo_api::RecordField { name: f.name, value, protected }
     }
-    if MAGIC != &buf[..MAGIC_LEN] {
-        bail!("database is not aidb format");
+}
+
+impl Attachment {
+    /// 创建自定义图标附件, `data`为base64编码的图标数据
+    pub fn new_icon(id: String, data: String) -> Self {
+        Attachment {
+            id,
+            name: String::from("icon"),
+            mime: String::from(ICON_MIME),
+            data: Sealed::new(&data),
+            owner: String::new(),
+        }
     }
-    let len = ((buf[4] as u32) << 24) | ((buf[5] as u32) << 16) | ((buf[6] as u32) << 8) | (buf[7] as u32);
-    if (len as usize) != buf.len() - ATTACH_LEN {
-        bail!("database size format error");
+
+    /// 创建记录的文件附件
+    ///
+    /// * `owner`: 所属记录id
+    /// * `name`: 文件名
+    /// * `data`: 文件内容
+    pub fn new_file(owner: &str, name: &str, data: &[u8]) -> Self {
+        Attachment {
+            id: new_uuid(),
+            name: name.to_owned(),
+            mime: String::from(FILE_MIME),
+            data: Sealed::new(&BASE64.encode(data)),
+            owner: owner.to_owned(),
+        }
     }
-    if md5_password(password).as_slice() != &buf[HEADER_LEN..ATTACH_LEN] {
-        bail!("password error");
+
+    /// 是否自定义图标
+    pub fn is_icon(&self) -> bool {
+        self.owner.is_empty() && self.mime.starts_with("image/")
     }
 
-    aes_decrypt(password.as_bytes(), &mut buf[ATTACH_LEN..]);
+    /// 解码附件数据
+    pub fn decode(&self) -> Result<Vec<u8>> {
+        Ok(BASE64.decode(self.data.reveal())?)
+    }
+}
 
-    let data: Vec<Arc<Record>> = serde_json::from_slice(&buf[ATTACH_LEN..])?;
-    let recs: CacheRecord = CacheRecord {
-        data: Arc::from(data),
-        time: std::time::Instant::now(),
-    };
+impl Sealed {
+    const NONCE_LEN: usize = 12;
+    const TAG_LEN: usize = 16;
+
+    /// 加密保存字符串, 空字符串不加密
+    pub fn new(plain: &str) -> Self {
+        if plain.is_empty() {
+            return Sealed::default();
+        }
+        let nonce: [u8; Self::NONCE_LEN] = rand::random();
+        let mut data = nonce.to_vec();
+        // 密钥和nonce长度固定, 加密不会失败
+        data.extend(sealed_key().encrypt(Nonce::from_slice(&nonce), plain.as_bytes())
+            .expect("sealed field encrypt error"));
+        Sealed(data)
+    }
+
+    /// 解密得到明文
+    pub fn reveal(&self) -> String {
+        if self.0.is_empty() {
+            return String::new();
+        }
+        let (nonce, data) = self.0.split_at(Self::NONCE_LEN);
+        match sealed_key().decrypt(Nonce::from_slice(nonce), data) {
+            Ok(plain) => String::from_utf8(plain).unwrap_or_default(),
+            Err(_) => {
+                log::error!("sealed field decrypt error");
+                String::new()
+            }
+        }
+    }
+
+    /// 解密得到明文, 返回的明文释放时清零内存
+    pub fn reveal_secret(&self) -> SecretString {
+        SecretString(Zeroizing::new(self.reveal()))
+    }
+
+    /// 明文是否相同, 密文相同时无需解密
+    pub fn same_plain(&self, other: &Sealed) -> bool {
+        self.0 == other.0 || self.reveal_secret() == other.reveal_secret()
+    }
 
-    log::trace!("load database record total: {}", recs.data.len());
-    let ret = recs.data.clone();
-    *g_recs = Some(recs);
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// 密文占用的内存大小
+    pub fn size(&self) -> usize {
+        self.0.len()
+    }
 
-    Ok(ret)
+    /// 明文长度, 由密文长度减去nonce及认证标签的长度得到, 无需解密
+    pub fn plain_len(&self) -> usize {
+        self.0.len().saturating_sub(Self::NONCE_LEN + Self::TAG_LEN)
+    }
 }
 
-/// 校验数据库密码是否正确
-///
-/// * `aidb`: aidb数据库文件名
-/// * `password`: 数据库口令
-///
-/// Returns:
-///
-/// Ok(true): 密码正确, Ok(false) 密码错误, Err(e): 其它错误
-pub fn check_password(aidb: &str, password: &str) -> Result<bool> {
-    let mut f = std::fs::File::open(aidb)?;
-    let flen = f.metadata()?.len();
+impl From<String> for Sealed {
+    fn from(value: String) -> Self {
+        Sealed::new(&Zeroizing::new(value))
+    }
+}
+
+impl std::ops::Deref for SecretString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.0.as_str()
+    }
+}
 
-    if (flen as usize) < ATTACH_LEN {
-        bail!("database size too small");
+impl PartialEq for SecretString {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
     }
+}
+
+impl Eq for SecretString {}
 
-    let mut buf = [0_u8; ATTACH_LEN];
-    f.read_exact(&mut buf)?;
-    if MAGIC != &buf[..MAGIC_LEN] {
-        bail!("database is not aidb format");
+impl std::hash::Hash for SecretString {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.as_str().hash(state)
     }
+}
 
-    let len = ((buf[4] as u32) << 24) | ((buf[5] as u32) << 16) | ((buf[6] as u32) << 8) | (buf[7] as u32);
-    if (len as usize) != (flen as usize) - ATTACH_LEN {
-        bail!("database size format error");
+impl std::fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SecretString(***)")
     }
+}
 
-    if md5_password(password).as_slice() != &buf[HEADER_LEN..ATTACH_LEN] {
-        return Ok(false);
+impl Serialize for SecretString {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
     }
+}
 
-    Ok(true)
+impl std::fmt::Debug for Sealed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Sealed({} bytes)", self.0.len())
+    }
+}
+
+impl Serialize for Sealed {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.reveal())
+    }
+}
+
+impl<'de> Deserialize<'de> for Sealed {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let plain = std::borrow::Cow::<str>::deserialize(deserializer)?;
+        let sealed = Sealed::new(&plain);
+        // 含转义字符的内容反序列化时会复制, 复制的明文同样需要清零
+        if let std::borrow::Cow::Owned(plain) = plain {
+            drop(Zeroizing::new(plain));
+        }
+        Ok(sealed)
+    }
 }
 
 impl MyAes {
@@ -172,56 +1859,227 @@ impl MyAes {
     }
 }
 
-fn load_xml(xml: &[u8]) -> Result<Vec<Record>> {
+/// 读取xml节点的属性值
+fn xml_attr(e: &quick_xml::events::BytesStart, name: &str) -> Result<Option<String>> {
+    match e.try_get_attribute(name)? {
+        Some(attr) => Ok(Some(attr.unescape_value()?.into_owned())),
+        None => Ok(None),
+    }
+}
+
+/// 解码keepass xml中Meta的二进制数据, base64编码, 可能经过gzip压缩
+fn decode_binary(text: &str, compressed: bool) -> Result<Vec<u8>> {
+    let data = BASE64.decode(text.trim())?;
+    if !compressed {
+        if data.len() > MAX_BINARY_SIZE {
+            bail!("binary size exceeds {MAX_BINARY_SIZE} bytes");
+        }
+        return Ok(data);
+    }
+    // 限制解压后的长度, 防止压缩炸弹耗尽内存
+    let mut out = Vec::new();
+    flate2::read::GzDecoder::new(data.as_slice())
+        .take(MAX_BINARY_SIZE as u64 + 1)
+        .read_to_end(&mut out)?;
+    if out.len() > MAX_BINARY_SIZE {
+        bail!("binary size exceeds {MAX_BINARY_SIZE} bytes after decompression");
+    }
+    Ok(out)
+}
+
+/// 历史版本按修改时间排序, 只保留最新的`MAX_HISTORY`个
+fn limit_history(history: &mut Vec<RecordVersion>) {
+    history.sort_by_key(|v| v.modified);
+    if history.len() > MAX_HISTORY {
+        history.drain(..history.len() - MAX_HISTORY);
+    }
+}
+
+fn load_xml(xml: &[u8]) -> Result<Database> {
     // xml节点类型
     #[derive(PartialEq, Eq, Debug)]
-    enum ElType { None, Entry, Id, String, Key, Value }
+    enum ElType {
+        None, Entry, Id, String, Key, Value, IconId, CustomIcon, Icon, IconUuid, IconData,
+        Group, GroupId, GroupName, Tags, Times, Expires, ExpiryTime, Modified, Created, History,
+        Binary, BinaryKey, MetaBinary,
+    }
     // xml数据节点类型
     #[derive(PartialEq, Eq, Debug)]
-    enum KVType { None, Title, User, Pass, Url, Notes }
+    enum KVType { None, Title, User, Pass, Url, Notes, Otp, KeePassOtp(String), Custom(String) }
 
     let mut reader = Reader::from_str(std::str::from_utf8(xml)?);
-    let mut recs = Vec::new();
+    let mut db = Database::default();
     let mut rec = Record::default();
+    let mut icon = Attachment::new_icon(String::new(), String::new());
+    // 当前所在的分组, 分组可以嵌套
+    let mut groups: Vec<Group> = Vec::new();
     let mut e_type = ElType::None;
     let mut kv_type = KVType::None;
     let mut value = String::new();
+    // 当前字符串字段的内容是否受保护
+    let mut protected = false;
+    let (mut expires, mut expiry_time) = (false, 0);
+    // 解析历史版本时暂存的当前记录及其过期设置
+    let mut outer: Option<(Record, bool, u64)> = None;
+    // Meta中的二进制数据(key: Binary ID), 当前Meta Binary的ID及是否压缩
+    let mut binaries: HashMap<String, Vec<u8>> = HashMap::new();
+    let (mut binary_id, mut compressed) = (String::new(), false);
+    // 当前记录的附件, (文件名, 引用的Binary ID)
+    let mut files: Vec<(String, String)> = Vec::new();
+    // 当前记录在TimeOtp-*字段中的TOTP设置(KeePass 2.47+)
+    let mut keepass_otp = KeePassOtp::default();
 
     loop {
         match reader.read_event() {
             Ok(event) => match event {
                 Event::Start(e) => match e.name().as_ref() {
+                    b"Group" if e_type == ElType::None || e_type == ElType::Group => {
+                        let parent = match groups.last() {
+                            Some(g) => g.id.clone(),
+                            None => String::new(),
+                        };
+                        groups.push(Group { parent, ..Default::default() });
+                        e_type = ElType::Group;
+                    },
+                    b"UUID" if e_type == ElType::Group => e_type = ElType::GroupId,
+                    b"Name" if e_type == ElType::Group => e_type = ElType::GroupName,
+                    b"Entry" if e_type == ElType::History => {
+                        let rec = std::mem::take(&mut rec);
+                        outer = Some((rec, expires, expiry_time));
+                        e_type = ElType::Entry;
+                    },
                     b"Entry" => e_type = ElType::Entry,
+                    b"History" if e_type == ElType::Entry => e_type = ElType::History,
                     b"UUID" if e_type == ElType::Entry => e_type = ElType::Id,
                     b"String" if e_type == ElType::Entry => e_type = ElType::String,
                     b"Key" if e_type == ElType::String => e_type = ElType::Key,
-                    b"Value" if e_type == ElType::String => e_type = ElType::Value,
+                    b"Value" if e_type == ElType::String => {
+                        // xml导出文件使用ProtectInMemory, kdbx内部xml使用Protected
+                        protected = xml_attr(&e, "ProtectInMemory")?.or(xml_attr(&e, "Protected")?)
+                            .is_some_and(|v| v.eq_ignore_ascii_case("true"));
+                        e_type = ElType::Value;
+                    },
+                    b"IconID" if e_type == ElType::Entry => e_type = ElType::IconId,
+                    b"CustomIconUUID" if e_type == ElType::Entry => e_type = ElType::CustomIcon,
+                    b"Tags" if e_type == ElType::Entry => e_type = ElType::Tags,
+                    b"Times" if e_type == ElType::Entry => e_type = ElType::Times,
+                    b"Expires" if e_type == ElType::Times => e_type = ElType::Expires,
+                    b"ExpiryTime" if e_type == ElType::Times => e_type = ElType::ExpiryTime,
+                    b"LastModificationTime" if e_type == ElType::Times => e_type = ElType::Modified,
+                    b"CreationTime" if e_type == ElType::Times => e_type = ElType::Created,
+                    b"Icon" if e_type == ElType::None => e_type = ElType::Icon,
+                    b"Binary" if e_type == ElType::None => {
+                        binary_id = xml_attr(&e, "ID")?.unwrap_or_default();
+                        compressed = xml_attr(&e, "Compressed")?.is_some_and(|v| v.eq_ignore_ascii_case("true"));
+                        e_type = ElType::MetaBinary;
+                    },
+                    // 忽略历史版本中的附件
+                    b"Binary" if e_type == ElType::Entry && outer.is_none() => {
+                        files.push((String::new(), String::new()));
+                        e_type = ElType::Binary;
+                    },
+                    b"Key" if e_type == ElType::Binary => e_type = ElType::BinaryKey,
+                    b"Value" if e_type == ElType::Binary => {
+                        if let (Some(file), Some(id)) = (files.last_mut(), xml_attr(&e, "Ref")?) {
+                            file.1 = id;
+                        }
+                    },
+                    b"UUID" if e_type == ElType::Icon => e_type = ElType::IconUuid,
+                    b"Data" if e_type == ElType::Icon => e_type = ElType::IconData,
                     _ => {},
                 },
                 Event::End(e) => match e.name().as_ref() {
+                    b"Group" if e_type == ElType::Group => {
+                        if let Some(group) = groups.pop() {
+                            db.groups.push(Arc::new(group));
+                        }
+                        e_type = if groups.is_empty() { ElType::None } else { ElType::Group };
+                    },
+                    b"UUID" if e_type == ElType::GroupId => e_type = ElType::Group,
+                    b"Name" if e_type == ElType::GroupName => e_type = ElType::Group,
+                    b"Binary" if e_type == ElType::MetaBinary => e_type = ElType::None,
+                    b"Binary" if e_type == ElType::Binary => e_type = ElType::Entry,
+                    b"Key" if e_type == ElType::BinaryKey => e_type = ElType::Binary,
+                    b"Entry" if outer.is_some() => {
+                        if let Some((prev, prev_expires, prev_expiry_time)) = outer.take() {
+                            let version = rec.version();
+                            rec = prev;
+                            rec.history.push(version);
+                            (expires, expiry_time) = (prev_expires, prev_expiry_time);
+                        }
+                        e_type = ElType::History;
+                    },
+                    b"History" if e_type == ElType::History => {
+                        limit_history(&mut rec.history);
+                        e_type = ElType::Entry;
+                    },
                     b"Entry" => {
                         if !rec.title.is_empty() {
-                            recs.push(rec);
+                            if let Some(g) = groups.last() {
+                                rec.group = g.id.as_str().into();
+                            }
+                            if expires {
+                                rec.expire = expiry_time;
+                            }
+                            if rec.otp.is_empty() {
+                                rec.otp = Sealed::new(&keepass_otp_value(&keepass_otp));
+                            }
+                            for (name, id) in files.drain(..) {
+                                if let Some(data) = binaries.get(&id) {
+                                    let file = Attachment::new_file(&rec.id, &name, data);
+                                    rec.attachments.push(FileRef { id: file.id.clone(), name, size: data.len() });
+                                    db.attachments.push(Arc::new(file));
+                                }
+                            }
+                            db.records.push(Arc::new(rec));
                             rec = Record::default();
                         }
-                        e_type = ElType::None;
+                        (expires, expiry_time) = (false, 0);
+                        files.clear();
+                        keepass_otp = KeePassOtp::default();
+                        e_type = if groups.is_empty() { ElType::None } else { ElType::Group };
                     },
                     b"UUID" if e_type == ElType::Id => e_type = ElType::Entry,
                     b"String" if e_type == ElType::String => {
                         e_type = ElType::Entry;
                         match kv_type {
                             KVType::Title => rec.title = value,
-                            KVType::User => rec.user = value,
-                            KVType::Pass => rec.pass = value,
-                            KVType::Url => rec.url = value,
-                            KVType::Notes => rec.notes = value,
+                            KVType::User => rec.user = value.into(),
+                            KVType::Pass => rec.pass = value.into(),
+                            KVType::Url => rec.url = value.into(),
+                            KVType::Notes => rec.notes = Sealed::new(&value),
+                            KVType::Otp => rec.otp = Sealed::new(&value),
+                            // 历史版本不保存一次性口令
+                            KVType::KeePassOtp(name) if outer.is_none() => {
+                                keepass_otp.set(&name, &value);
+                            },
+                            KVType::KeePassOtp(_) => {},
+                            KVType::Custom(name) => rec.fields.push(RecordField::new(name, value, protected)),
                             KVType::None => {},
                         };
                         kv_type = KVType::None;
                         value = String::new();
+                        protected = false;
                     },
                     b"Key" if e_type == ElType::Key => e_type = ElType::String,
                     b"Value" if e_type == ElType::Value => e_type = ElType::String,
+                    b"IconID" if e_type == ElType::IconId => e_type = ElType::Entry,
+                    b"CustomIconUUID" if e_type == ElType::CustomIcon => e_type = ElType::Entry,
+                    b"Tags" if e_type == ElType::Tags => e_type = ElType::Entry,
+                    b"Times" if e_type == ElType::Times => e_type = ElType::Entry,
+                    b"Expires" if e_type == ElType::Expires => e_type = ElType::Times,
+                    b"ExpiryTime" if e_type == ElType::ExpiryTime => e_type = ElType::Times,
+                    b"LastModificationTime" if e_type == ElType::Modified => e_type = ElType::Times,
+                    b"CreationTime" if e_type == ElType::Created => e_type = ElType::Times,
+                    b"Icon" if e_type == ElType::Icon => {
+                        if !icon.id.is_empty() && !icon.data.is_empty() {
+                            db.attachments.push(Arc::new(icon));
+                        }
+                        icon = Attachment::new_icon(String::new(), String::new());
+                        e_type = ElType::None;
+                    },
+                    b"UUID" if e_type == ElType::IconUuid => e_type = ElType::Icon,
+                    b"Data" if e_type == ElType::IconData => e_type = ElType::Icon,
                     _ => {},
                 },
                 Event::Text(e) => match e_type {
@@ -233,12 +2091,63 @@ fn load_xml(xml: &[u8]) -> Result<Vec<Record>> {
                             b"Password" => kv_type = KVType::Pass,
                             b"URL" => kv_type = KVType::Url,
                             b"Notes" => kv_type = KVType::Notes,
-                            _ => {},
+                            // KeePassXC使用otp保存uri, KeePass 2.47+使用TimeOtp-*字段保存密钥及设置
+                            b"otp" => kv_type = KVType::Otp,
+                            _ => {
+                                let key = e.unescape()?.to_string();
+                                kv_type = match KeePassOtp::FIELDS.contains(&key.as_str()) {
+                                    true => KVType::KeePassOtp(key),
+                                    false => KVType::Custom(key),
+                                };
+                            },
                         };
                     },
                     ElType::Value => value = e.unescape()?.to_string(),
+                    ElType::IconId => rec.icon = e.unescape()?.trim().parse().unwrap_or(0),
+                    ElType::CustomIcon => rec.custom_icon = e.unescape()?.as_ref().into(),
+                    ElType::Tags => {
+                        rec.tags = e.unescape()?
+                            .split([';', ','])
+                            .map(|v| v.trim())
+                            .filter(|v| !v.is_empty())
+                            .map(IStr::from)
+                            .collect();
+                    },
+                    ElType::Expires => expires = e.unescape()?.trim().eq_ignore_ascii_case("true"),
+                    ElType::ExpiryTime => expiry_time = parse_xml_time(&e.unescape()?).unwrap_or(0),
+                    ElType::Modified => rec.modified = parse_xml_time(&e.unescape()?).unwrap_or(0),
+                    ElType::Created => rec.created = parse_xml_time(&e.unescape()?).unwrap_or(0),
+                    ElType::GroupId => {
+                        if let Some(g) = groups.last_mut() {
+                            g.id = e.unescape()?.to_string();
+                        }
+                    },
+                    ElType::GroupName => {
+                        if let Some(g) = groups.last_mut() {
+                            g.name = e.unescape()?.to_string();
+                        }
+                    },
+                    ElType::IconUuid => icon.id = e.unescape()?.to_string(),
+                    ElType::IconData => icon.data = Sealed::new(&e.unescape()?),
+                    ElType::MetaBinary => {
+                        let data = decode_binary(&e.unescape()?, compressed)
+                            .map_err(|err| anyhow!("decode binary {binary_id} error: {err}"))?;
+                        binaries.insert(std::mem::take(&mut binary_id), data);
+                    },
+                    ElType::BinaryKey => {
+                        if let Some(file) = files.last_mut() {
+                            file.0 = e.unescape()?.to_string();
+                        }
+                    },
                     _ => {},
                 },
+                Event::Empty(e) => {
+                    if e.name().as_ref() == b"Value" && e_type == ElType::Binary {
+                        if let (Some(file), Some(id)) = (files.last_mut(), xml_attr(&e, "Ref")?) {
+                            file.1 = id;
+                        }
+                    }
+                },
                 Event::Eof => break,
                 _ => {},
             },
@@ -246,12 +2155,337 @@ fn load_xml(xml: &[u8]) -> Result<Vec<Record>> {
         }
     }
 
-    Ok(recs)
+    Ok(db)
 }
 
-fn aes_encrypt(key: &[u8], data: &mut [u8]) {
-    let mut cipher = MyAes::new(key);
-    cipher.encrypt(data);
+/// KeePass的TOTP设置转换为记录的otp值, 设置无效时保留原始密钥
+fn keepass_otp_value(otp: &KeePassOtp) -> String {
+    otp.to_otp().unwrap_or_else(|e| {
+        log::warn!("import keepass totp settings error: {e}");
+        otp.secret.clone()
+    })
+}
+
+/// 直接读取keepass的kdbx数据库文件(支持kdbx3/kdbx4), 解密内容只保存在内存中
+fn load_kdbx(kdbx_file: &str, password: &str) -> Result<Database> {
+    use keepass::{db::Node, DatabaseKey};
+
+    // 与xml导出格式一致, 按分组嵌套顺序转换, 上级分组排在下级分组之后
+    fn convert_group(group: &keepass::db::Group, parent: &str, db: &mut Database) {
+        let id = BASE64.encode(group.uuid.as_bytes());
+        for node in group.children.iter() {
+            match node {
+                Node::Group(g) => convert_group(g, &id, db),
+                Node::Entry(e) => {
+                    let title = e.get_title().unwrap_or_default();
+                    if title.is_empty() {
+                        continue;
+                    }
+                    let to_ts = |t: &chrono::NaiveDateTime| u64::try_from(t.and_utc().timestamp()).unwrap_or(0);
+                    let mut history: Vec<RecordVersion> = e.history.iter()
+                        .flat_map(|h| h.get_entries().iter())
+                        .map(|h| RecordVersion {
+                            modified: h.times.get_last_modification().map(to_ts).unwrap_or(0),
+                            title: h.get_title().unwrap_or_default().to_owned(),
+                            user: h.get_username().unwrap_or_default().into(),
+                            pass: Sealed::new(h.get_password().unwrap_or_default()),
+                            url: h.get("URL").unwrap_or_default().into(),
+                            notes: Sealed::new(h.get("Notes").unwrap_or_default()),
+                        })
+                        .collect();
+                    limit_history(&mut history);
+                    db.records.push(Arc::new(Record {
+                        id: BASE64.encode(e.uuid.as_bytes()),
+                        title: title.to_owned(),
+                        user: e.get_username().unwrap_or_default().into(),
+                        pass: Sealed::new(e.get_password().unwrap_or_default()),
+                        url: e.get("URL").unwrap_or_default().into(),
+                        notes: Sealed::new(e.get("Notes").unwrap_or_default()),
+                        icon: e.icon_id.unwrap_or(0) as u32,
+                        custom_icon: e.custom_icon_uuid
+                            .map(|u| IStr::from(BASE64.encode(u.as_bytes())))
+                            .unwrap_or_default(),
+                        group: id.as_str().into(),
+                        tags: e.tags.iter()
+                            .map(|v| v.trim())
+                            .filter(|v| !v.is_empty())
+                            .map(IStr::from)
+                            .collect(),
+                        expire: if e.times.expires { e.times.get_expiry().map(to_ts).unwrap_or(0) } else { 0 },
+                        modified: e.times.get_last_modification().map(to_ts).unwrap_or(0),
+                        created: e.times.get_creation().map(to_ts).unwrap_or(0),
+                        history,
+                        sensitive: false,
+                        otp: Sealed::new(&match e.get("otp") {
+                            Some(otp) => otp.to_owned(),
+                            None => {
+                                let mut otp = KeePassOtp::default();
+                                for key in KeePassOtp::FIELDS {
+                                    otp.set(key, e.get(key).unwrap_or_default());
+                                }
+                                keepass_otp_value(&otp)
+                            }
+                        }),
+                        // kdbx读取库不提供条目附件的访问接口, 需要附件时先导出为xml再导入
+                        attachments: Vec::new(),
+                        fields: custom_fields(e),
+                        comments: Vec::new(),
+                    }));
+                }
+            }
+        }
+        db.groups.push(Arc::new(Group {
+            id,
+            name: group.name.clone(),
+            parent: parent.to_owned(),
+            ..Default::default()
+        }));
+    }
+
+    // 标准字段以外的字符串字段, 按名称排序
+    fn custom_fields(e: &keepass::db::Entry) -> Vec<RecordField> {
+        let mut fields: Vec<RecordField> = e.fields.iter()
+            .filter(|(name, _)| !STANDARD_FIELDS.contains(&name.as_str()))
+            .filter_map(|(name, v)| e.get(name).map(|value| RecordField::new(name.clone(), value.to_owned(),
+                matches!(v, keepass::db::Value::Protected(_)))))
+            .collect();
+        fields.sort_by(|a, b| a.name.cmp(&b.name));
+        fields
+    }
+
+    let mut file = std::fs::File::open(kdbx_file)?;
+    let kdbx = keepass::Database::open(&mut file, DatabaseKey::new().with_password(password))
+        .map_err(|e| anyhow!("open kdbx file {kdbx_file} error: {e}"))?;
+
+    let mut db = Database::default();
+    convert_group(&kdbx.root, "", &mut db);
+    for icon in kdbx.meta.custom_icons.icons.iter() {
+        db.attachments.push(Arc::new(Attachment::new_icon(
+            BASE64.encode(icon.uuid.as_bytes()), BASE64.encode(&icon.data))));
+    }
+
+    Ok(db)
+}
+
+/// 按KeePass 2 xml格式输出数据库内容, 格式与`load_xml`的解析保持一致
+fn export_xml<W: Write>(db: &Database, out: &mut W, step: &mut dyn FnMut() -> Result<()>) -> Result<()> {
+    use quick_xml::escape::escape;
+
+    fn write_fields<W: Write>(fields: &[(&str, &str)], out: &mut W) -> Result<()> {
+        for &(key, value) in fields {
+            if key == "otp" && value.is_empty() {
+                continue;
+            }
+            let protect = if key == "Password" || key == "otp" { r#" ProtectInMemory="True""# } else { "" };
+            write!(out, "<String><Key>{key}</Key><Value{protect}>{}</Value></String>", escape(value))?;
+        }
+        Ok(())
+    }
+
+    fn write_group<W: Write>(tree: &ExportTree, id: &str, name: &str, out: &mut W,
+            step: &mut dyn FnMut() -> Result<()>) -> Result<()> {
+        write!(out, "<Group><UUID>{}</UUID><Name>{}</Name>", escape(id), escape(name))?;
+        for rec in tree.records(id) {
+            step()?;
+            write!(out, "<Entry><UUID>{}</UUID><IconID>{}</IconID>", escape(&rec.id), rec.icon)?;
+            if !rec.custom_icon.is_empty() {
+                write!(out, "<CustomIconUUID>{}</CustomIconUUID>", escape(&rec.custom_icon))?;
+            }
+            if !rec.tags.is_empty() {
+                write!(out, "<Tags>{}</Tags>", escape(&rec.tags.join(";")))?;
+            }
+            write!(out, "<Times><LastModificationTime>{}</LastModificationTime>", format_xml_time(rec.modified))?;
+            if rec.created > 0 {
+                write!(out, "<CreationTime>{}</CreationTime>", format_xml_time(rec.created))?;
+            }
+            if rec.expire > 0 {
+                write!(out, "<Expires>True</Expires><ExpiryTime>{}</ExpiryTime>", format_xml_time(rec.expire))?;
+            } else {
+                write!(out, "<Expires>False</Expires>")?;
+            }
+            write!(out, "</Times>")?;
+            let (pass, notes, otp) = (rec.pass.reveal_secret(), rec.notes.reveal(), rec.otp.reveal());
+            let fields = [("Title", rec.title.as_str()), ("UserName", &*rec.user),
+                ("Password", &*pass), ("URL", &*rec.url), ("Notes", notes.as_str()), ("otp", otp.as_str())];
+            write_fields(&fields, out)?;
+            for f in rec.fields.iter() {
+                let protect = if f.is_protected() { r#" ProtectInMemory="True""# } else { "" };
+                let value = f.reveal();
+                write!(out, "<String><Key>{}</Key><Value{protect}>{}</Value></String>", escape(&f.name), escape(&*value))?;
+            }
+            for file in rec.attachments.iter() {
+                if let Some(i) = tree.file_ref(&file.id) {
+                    write!(out, r#"<Binary><Key>{}</Key><Value Ref="{i}" /></Binary>"#, escape(&file.name))?;
+                }
+            }
+            if !rec.history.is_empty() {
+                write!(out, "<History>")?;
+                for v in rec.history.iter() {
+                    write!(out, "<Entry><UUID>{}</UUID><Times><LastModificationTime>{}</LastModificationTime></Times>",
+                        escape(&rec.id), format_xml_time(v.modified))?;
+                    let (pass, notes) = (v.pass.reveal_secret(), v.notes.reveal());
+                    let fields = [("Title", v.title.as_str()), ("UserName", &*v.user),
+                        ("Password", &*pass), ("URL", &*v.url), ("Notes", notes.as_str())];
+                    write_fields(&fields, out)?;
+                    write!(out, "</Entry>")?;
+                }
+                write!(out, "</History>")?;
+            }
+            write!(out, "</Entry>")?;
+        }
+        for g in tree.groups(id) {
+            write_group(tree, &g.id, &g.name, out, step)?;
+        }
+        write!(out, "</Group>")?;
+        Ok(())
+    }
+
+    writeln!(out, r#"<?xml version="1.0" encoding="utf-8" standalone="yes"?>"#)?;
+    write!(out, "<KeePassFile><Meta><Generator>accinfo</Generator><CustomIcons>")?;
+    for icon in db.attachments.iter().filter(|a| a.is_icon()) {
+        write!(out, "<Icon><UUID>{}</UUID><Data>{}</Data></Icon>", escape(&icon.id), escape(&icon.data.reveal()))?;
+    }
+    write!(out, "</CustomIcons><Binaries>")?;
+    let tree = ExportTree::new(db);
+    for (i, file) in tree.files.iter().enumerate() {
+        write!(out, r#"<Binary ID="{i}" Compressed="False">{}</Binary>"#, escape(&file.data.reveal()))?;
+    }
+    write!(out, "</Binaries></Meta><Root>")?;
+    write_group(&tree, &tree.root_id, &tree.root_name, out, step)?;
+    writeln!(out, "</Root></KeePassFile>")?;
+
+    Ok(())
+}
+
+/// 按csv格式输出数据库内容, 分组以`/`分隔的路径表示
+fn export_csv<W: Write>(db: &Database, out: &mut W, step: &mut dyn FnMut() -> Result<()>) -> Result<()> {
+    fn quote(s: &str) -> String {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    }
+
+    let time = |ts: u64| if ts > 0 { format_xml_time(ts) } else { String::new() };
+    writeln!(out, r#""Group","Title","Username","Password","URL","Notes","Tags","Expires","Last Modified""#)?;
+    for rec in db.output_records() {
+        step()?;
+        let fields = [db.group_path(&rec.group), rec.title.clone(), rec.user.to_string(),
+            rec.pass.reveal(), rec.url.to_string(), rec.notes.reveal(), rec.tags.join(";"),
+            time(rec.expire), time(rec.modified)];
+        let line: Vec<String> = fields.iter().map(|f| quote(f)).collect();
+        writeln!(out, "{}", line.join(","))?;
+    }
+
+    Ok(())
+}
+
+/// 导出xml时使用的分组树, keepass要求只有一个根分组
+struct ExportTree<'a> {
+    root_id: String,
+    root_name: String,
+    groups: HashMap<String, Vec<&'a Group>>,
+    records: HashMap<String, Vec<&'a Record>>,
+    /// 记录的文件附件, 序号即xml中的Binary ID
+    files: Vec<&'a Attachment>,
+}
+
+impl<'a> ExportTree<'a> {
+    fn new(db: &'a Database) -> Self {
+        let tops: Vec<&Group> = db.groups.iter()
+            .filter(|g| db.group(&g.parent).is_none())
+            .map(|g| g.as_ref())
+            .collect();
+        // 只有一个顶级分组时作为根分组, 否则创建一个根分组容纳所有的顶级分组
+        let (root_id, root_name) = match tops.as_slice() {
+            [g] => (g.id.clone(), g.name.clone()),
+            _ => (new_uuid(), String::from("accinfo")),
+        };
+
+        let mut groups: HashMap<String, Vec<&Group>> = HashMap::new();
+        for g in db.groups.iter().filter(|g| g.id != root_id) {
+            let parent = if db.group(&g.parent).is_some() { &g.parent } else { &root_id };
+            groups.entry(parent.clone()).or_default().push(g);
+        }
+        let mut records: HashMap<String, Vec<&Record>> = HashMap::new();
+        for r in db.records.iter() {
+            let group = if db.group(&r.group).is_some() { &*r.group } else { root_id.as_str() };
+            records.entry(group.to_owned()).or_default().push(r);
+        }
+        // 稳定排序时同一分组下的子分组按名称、记录按标题排序
+        if stable_order() {
+            for list in groups.values_mut() {
+                list.sort_by(|a, b| (&a.name, &a.id).cmp(&(&b.name, &b.id)));
+            }
+            for list in records.values_mut() {
+                list.sort_by(|a, b| (&a.title, &a.id).cmp(&(&b.title, &b.id)));
+            }
+        }
+
+        let files = db.attachments.iter().filter(|a| !a.owner.is_empty()).map(|a| a.as_ref()).collect();
+
+        ExportTree { root_id, root_name, groups, records, files }
+    }
+
+    /// 附件在xml中的Binary ID
+    fn file_ref(&self, id: &str) -> Option<usize> {
+        self.files.iter().position(|a| a.id == id)
+    }
+
+    fn groups(&self, id: &str) -> &[&'a Group] {
+        self.groups.get(id).map(|v| v.as_slice()).unwrap_or_default()
+    }
+
+    fn records(&self, id: &str) -> &[&'a Record] {
+        self.records.get(id).map(|v| v.as_slice()).unwrap_or_default()
+    }
+}
+
+/// 将unix时间戳格式化为keepass导出的时间格式(如: 2016-06-28T14:41:52Z), 与`parse_xml_time`互逆
+fn format_xml_time(ts: u64) -> String {
+    let (days, secs) = ((ts / 86400) as i64, ts % 86400);
+
+    // 1970-01-01开始的天数转换为公历日期
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+
+    format!("{y:04}-{m:02}-{d:02}T{:02}:{:02}:{:02}Z", secs / 3600, secs % 3600 / 60, secs % 60)
+}
+
+/// 解析keepass导出的时间格式(如: 2016-06-28T14:41:52Z)为unix时间戳
+fn parse_xml_time(s: &str) -> Option<u64> {
+    let s = s.trim().trim_end_matches('Z');
+    let (date, time) = s.split_once('T')?;
+
+    let mut ds = date.splitn(3, '-').map(|v| v.parse::<i64>().ok());
+    let (y, m, d) = (ds.next()??, ds.next()??, ds.next()??);
+    let mut ts = time.splitn(3, ':').map(|v| v.parse::<i64>().ok());
+    let (hh, mm, ss) = (ts.next()??, ts.next()??, ts.next()??);
+    if !(1..=12).contains(&m) || !(1..=31).contains(&d) {
+        return None;
+    }
+
+    // 公历日期转换为1970-01-01开始的天数
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = (if y >= 0 { y } else { y - 399 }) / 400;
+    let yoe = y - era * 400;
+    let doy = (153 * ((m + 9) % 12) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    u64::try_from(days * 86400 + hh * 3600 + mm * 60 + ss).ok()
+}
+
+fn sealed_key() -> &'static Aes256Gcm {
+    SEALED_KEY.get_or_init(|| Aes256Gcm::new(&rand::random::<[u8; 32]>().into()))
+}
+
+fn istr_is_empty(s: &IStr) -> bool {
+    s.is_empty()
 }
 
 fn aes_decrypt(key: &[u8], data: &mut [u8]) {
@@ -265,3 +2499,95 @@ fn md5_password(password: &str) -> Output<Md5Core> {
     hash_md5.update(IV);
     hash_md5.finalize()
 }
+
+/// 测试用的临时数据库文件, 位于系统临时目录, 释放时清除缓存并删除文件
+#[cfg(test)]
+pub struct TempDatabase(pub String);
+
+#[cfg(test)]
+impl TempDatabase {
+    /// 文件名为`accinfo-<name>-<进程id>.aidb`, 不同测试使用不同的`name`
+    pub fn new(name: &str) -> Self {
+        let file = std::env::temp_dir().join(format!("accinfo-{name}-{}.aidb", std::process::id()));
+        Self(file.to_string_lossy().into_owned())
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDatabase {
+    fn drop(&mut self) {
+        clear_cache();
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reject_oversized_kdf_params() {
+        let db = TempDatabase::new("kdf");
+        save_database(&db.0, "secret", &Database::default()).unwrap();
+        clear_cache();
+
+        // 篡改头部中的argon2内存参数(magic + 标志 + 版本之后), 派生密钥之前即拒绝
+        let mut buf = std::fs::read(&db.0).unwrap();
+        buf[6..10].copy_from_slice(&u32::MAX.to_be_bytes());
+        std::fs::write(&db.0, &buf).unwrap();
+        assert!(check_password(&db.0, "secret").is_err());
+    }
+
+    #[test]
+    fn check_password_by_tag() {
+        let db = TempDatabase::new("tag");
+        save_database(&db.0, "secret", &Database::default()).unwrap();
+        assert!(check_password(&db.0, "secret").unwrap());
+        assert!(!check_password(&db.0, "wrong").unwrap());
+
+        // 密文被篡改时认证标签不符, 与口令错误无法区分
+        let mut buf = std::fs::read(&db.0).unwrap();
+        let last = buf.len() - 1;
+        buf[last] ^= 1;
+        std::fs::write(&db.0, &buf).unwrap();
+        assert!(!check_password(&db.0, "secret").unwrap());
+    }
+
+    #[test]
+    fn protected_fields_are_sealed() {
+        let json = r#"[{"name":"host","value":"db"},{"name":"pin","value":"1234","protected":true}]"#;
+        let fields: Vec<RecordField> = serde_json::from_str(json).unwrap();
+        assert_eq!(fields[0].plain(), Some("db"));
+        assert!(fields[1].is_protected() && fields[1].plain().is_none());
+        assert_eq!(&*fields[1].reveal(), "1234");
+        assert_eq!(masked_value(&fields[1]), MASKED_PASS);
+        // 序列化时输出明文, 数据库文件格式不变
+        assert_eq!(serde_json::to_string(&fields).unwrap(), json);
+    }
+
+    #[test]
+    fn restore_after_reload() {
+        let tmp = TempDatabase::new("restore");
+        let mut db = Database::default();
+        db.records.push(Arc::new(Record {
+            id: "1".to_owned(),
+            title: "github".to_owned(),
+            pass: Sealed::new("p@ss"),
+            notes: Sealed::new("note"),
+            ..Default::default()
+        }));
+        save_database(&tmp.0, "secret", &db).unwrap();
+
+        let (_, before, after) = update_database_snapshot(&tmp.0, "secret", |db| {
+            Arc::make_mut(&mut db.records[0]).title = "gitee".to_owned();
+            Ok(())
+        }).unwrap();
+        // 重新加载后加密字段的密文与快照不同, 内容相同时仍可恢复
+        clear_cache();
+        assert!(restore_database(&tmp.0, "secret", &after, before.clone()).unwrap());
+        assert_eq!(load_database(&tmp.0, "secret").unwrap().records[0].title, "github");
+        // 内容已被其它操作修改时拒绝恢复
+        clear_cache();
+        assert!(!restore_database(&tmp.0, "secret", &after, before).unwrap());
+    }
+}
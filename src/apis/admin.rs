@@ -0,0 +1,106 @@
+use http_body_util::Full;
+use httpserver::{HttpContext, HttpResponse, Resp, CONTENT_TYPE};
+use hyper::header::CONTENT_DISPOSITION;
+use serde::Serialize;
+use serde_json::Value;
+use crate::{apis::Authentication, state::AppState, AppConf};
+
+/// 脱敏后显示的值
+const REDACTED: &str = "******";
+
+/// 配置项
+#[derive(Serialize)]
+struct ConfItem {
+    /// 配置项名称(命令行参数名)
+    name: &'static str,
+    /// 生效的值, 敏感项已脱敏
+    value: Value,
+    /// 值的来源: default(缺省值), file(配置文件), cli(命令行参数)
+    source: &'static str,
+}
+
+/// 可转换为json的配置值
+trait ConfValue {
+    fn to_value(&self) -> Value;
+    fn is_empty_value(&self) -> bool;
+}
+
+impl ConfValue for String {
+    fn to_value(&self) -> Value { Value::String(self.clone()) }
+    fn is_empty_value(&self) -> bool { self.is_empty() }
+}
+
+impl ConfValue for bool {
+    fn to_value(&self) -> Value { Value::Bool(*self) }
+    fn is_empty_value(&self) -> bool { !*self }
+}
+
+/// 生成配置项列表, 敏感项以`secret`标记
+macro_rules! conf_items {
+    (@value $val:expr) => { $val.to_value() };
+    (@value $val:expr, secret) => {
+        if $val.is_empty_value() { $val.to_value() } else { Value::String(String::from(REDACTED)) }
+    };
+    ($ac:expr, $def:expr, $args:expr, $($field:ident: $short:literal, $long:literal $(, $secret:ident)?;)*) => {
+        vec![$(
+            ConfItem {
+                name: $long,
+                value: conf_items!(@value $ac.$field $(, $secret)?),
+                source: conf_source($args, $short, $long, $ac.$field == $def.$field),
+            },
+        )*]
+    };
+}
+
+/// 判断配置值的来源, appconfig按 缺省值 < 配置文件 < 命令行参数 的顺序覆盖
+fn conf_source(args: &[String], short: &str, long: &str, is_default: bool) -> &'static str {
+    if crate::cli::arg_present(args, short, long) {
+        "cli"
+    } else if is_default {
+        "default"
+    } else {
+        "file"
+    }
+}
+
+/// 获取当前生效的配置接口, 敏感信息已脱敏, 只有管理员可以访问
+pub async fn admin_config(ctx: HttpContext) -> HttpResponse {
+    let st = AppState::from_ctx(&ctx)?;
+    if !Authentication::is_admin(&ctx, st) {
+        log::warn!(target: "audit", "config dump by {} from {} rejected: not admin", ctx.uid, ctx.remote_ip());
+        return Authentication::admin_required();
+    }
+    let ac = AppConf::get();
+    let def = AppConf::default();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let items = crate::with_conf_fields!(conf_items!(ac, def, &args,));
+
+    Resp::ok(&items)
+}
+
+/// 反向代理熔断状态接口, 未启用反向代理时返回null, 只有管理员可以访问
+pub async fn admin_proxy(ctx: HttpContext) -> HttpResponse {
+    let st = AppState::from_ctx(&ctx)?;
+    if !Authentication::is_admin(&ctx, st) {
+        log::warn!(target: "audit", "proxy status by {} from {} rejected: not admin", ctx.uid, ctx.remote_ip());
+        return Authentication::admin_required();
+    }
+    Resp::ok(&super::web::proxy_status())
+}
+
+/// 导出接口访问统计接口, 按天、按接口返回请求次数及耗时百分位数(csv格式), 只有管理员可以访问
+pub async fn admin_access_stats_export(ctx: HttpContext) -> HttpResponse {
+    let st = AppState::from_ctx(&ctx)?;
+    if !Authentication::is_admin(&ctx, st) {
+        log::warn!(target: "audit", "access stats export by {} from {} rejected: not admin", ctx.uid, ctx.remote_ip());
+        return Authentication::admin_required();
+    }
+    log::info!(target: "audit", "access stats exported by {}", ctx.remote_ip());
+    Ok(
+        hyper::Response::builder()
+            .header(CONTENT_TYPE, "text/csv; charset=utf-8")
+            .header(CONTENT_DISPOSITION, "attachment; filename=\"access-stats.csv\"")
+            .body(Full::from(crate::metrics::export_csv()))?
+    )
+}
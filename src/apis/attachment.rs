@@ -0,0 +1,146 @@
+use std::sync::Arc;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use http_body_util::Full;
+use httpserver::{HttpContext, HttpResponse, Resp, CONTENT_TYPE};
+use hyper::header::CONTENT_DISPOSITION;
+use serde::Deserialize;
+use crate::aidb::{self, Attachment, FileRef, Record};
+use super::{authentication::Authentication, service, undo};
+
+/// 单个附件最大字节数
+const MAX_ATTACHMENT_SIZE: usize = 4 * 1024 * 1024;
+/// 每条记录最多的附件数量
+const MAX_ATTACHMENTS: usize = 16;
+
+/// 上传记录附件接口, 附件数据为base64编码
+pub async fn attachment_upload(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    struct ReqParam {
+        id: String,
+        name: String,
+        data: String,
+    }
+
+    let req_param = ctx.parse_json::<ReqParam>()?;
+    let name = req_param.name.trim();
+    httpserver::fail_if!(name.is_empty(), "文件名不能为空");
+    let data = match BASE64.decode(req_param.data.trim()) {
+        Ok(data) => data,
+        Err(_) => httpserver::http_bail!("附件数据格式错误"),
+    };
+    httpserver::fail_if!(data.is_empty() || data.len() > MAX_ATTACHMENT_SIZE,
+        "附件大小超出限制, 最大{}字节", MAX_ATTACHMENT_SIZE);
+
+    let file = undo::update_database(&ctx, "record/attachment/upload", |db| {
+        let idx = match db.record_index(&req_param.id) {
+            Some(idx) => idx,
+            None => httpserver::http_bail!("记录不存在"),
+        };
+        let mut rec = Record::clone(&db.records[idx]);
+        httpserver::fail_if!(rec.attachments.len() >= MAX_ATTACHMENTS, "附件数量超出限制");
+
+        let attachment = Attachment::new_file(&rec.id, name, &data);
+        let file = FileRef { id: attachment.id.clone(), name: name.to_owned(), size: data.len() };
+        rec.attachments.push(file.clone());
+        rec.modified = localtime::unix_timestamp();
+        db.attachments.push(Arc::new(attachment));
+        db.records[idx] = Arc::new(rec);
+        Ok(file)
+    })?;
+
+    log::info!(target: "audit", "upload attachment {} [{}] to record {} by {}",
+        file.id, file.name, req_param.id, ctx.remote_ip());
+    Resp::ok(&file)
+}
+
+/// 下载记录附件接口, 以附件下载的方式返回文件内容, 敏感记录需要先重新验证主密码
+pub async fn attachment_download(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    struct ReqParam {
+        id: String,
+        file: String,
+    }
+
+    let req_param = ctx.parse_json::<ReqParam>()?;
+    let (database, pass) = service::session_db(&ctx)?;
+    let db = aidb::load_database(database, &pass)?;
+
+    let rec = match db.records.iter().find(|r| r.id == req_param.id) {
+        Some(rec) => rec,
+        None => httpserver::http_bail!("记录不存在"),
+    };
+    let file = match rec.attachments.iter().find(|f| f.id == req_param.file) {
+        Some(file) => file,
+        None => httpserver::http_bail!("附件不存在"),
+    };
+    if rec.sensitive && !Authentication::check_step_up(&ctx)? {
+        log::warn!(target: "audit", "download attachment of record {} by {} rejected: step-up required",
+            rec.id, ctx.remote_ip());
+        return Authentication::step_up_required();
+    }
+    let data = match db.attachment(&file.id).map(|a| a.decode()) {
+        Some(Ok(data)) => data,
+        Some(Err(e)) => {
+            log::error!("decode attachment {} error: {e:?}", file.id);
+            httpserver::http_bail!("附件数据已损坏");
+        }
+        None => httpserver::http_bail!("附件不存在"),
+    };
+
+    log::info!(target: "audit", "download attachment {} [{}] of record {} by {}",
+        file.id, file.name, rec.id, ctx.remote_ip());
+    aidb::stats_read(database, [rec.id.as_str()]);
+    Ok(
+        hyper::Response::builder()
+            .header(CONTENT_TYPE, "application/octet-stream")
+            .header(CONTENT_DISPOSITION, content_disposition(&file.name))
+            .body(Full::from(data))?
+    )
+}
+
+/// 删除记录附件接口
+pub async fn attachment_delete(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    struct ReqParam {
+        id: String,
+        file: String,
+    }
+
+    let req_param = ctx.parse_json::<ReqParam>()?;
+    undo::update_database(&ctx, "record/attachment/delete", |db| {
+        let idx = match db.record_index(&req_param.id) {
+            Some(idx) => idx,
+            None => httpserver::http_bail!("记录不存在"),
+        };
+        let mut rec = Record::clone(&db.records[idx]);
+        let pos = match rec.attachments.iter().position(|f| f.id == req_param.file) {
+            Some(pos) => pos,
+            None => httpserver::http_bail!("附件不存在"),
+        };
+        rec.attachments.remove(pos);
+        rec.modified = localtime::unix_timestamp();
+        db.attachments.retain(|a| a.id != req_param.file);
+        db.records[idx] = Arc::new(rec);
+        Ok(())
+    })?;
+
+    log::info!(target: "audit", "delete attachment {} of record {} by {}",
+        req_param.file, req_param.id, ctx.remote_ip());
+    Resp::ok_with_empty()
+}
+
+/// 附件下载的Content-Disposition, 非ascii文件名使用RFC 5987编码
+fn content_disposition(name: &str) -> String {
+    let ascii: String = name.chars()
+        .map(|c| if (c.is_ascii_graphic() && c != '"' && c != '\\') || c == ' ' { c } else { '_' })
+        .collect();
+    let mut encoded = String::with_capacity(name.len() * 3);
+    for b in name.bytes() {
+        if b.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{b:02X}"));
+        }
+    }
+    format!("attachment; filename=\"{ascii}\"; filename*=UTF-8''{encoded}")
+}
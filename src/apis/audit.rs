@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use httpserver::{HttpContext, HttpResponse, Resp};
+use serde::{Deserialize, Serialize};
+use crate::{aidb::{self, Record, SecretString}, strength};
+use super::service;
+
+/// 缺省的弱口令得分上限, 得分不超过该值的口令视为弱口令
+const DEFAULT_WEAK_SCORE: u8 = 2;
+/// 缺省的陈旧记录天数
+const DEFAULT_OLD_DAYS: u32 = 365;
+
+/// 审计报告中的记录
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AuditItem<'a> {
+    id: &'a str,
+    title: &'a str,
+    user: &'a str,
+    /// 口令强度得分, 只在弱口令列表中返回
+    #[serde(skip_serializing_if = "Option::is_none")]
+    score: Option<u8>,
+    modified: u64,
+}
+
+impl<'a> AuditItem<'a> {
+    fn new(rec: &'a Record, score: Option<u8>) -> Self {
+        AuditItem { id: &rec.id, title: &rec.title, user: &rec.user, score, modified: rec.modified }
+    }
+}
+
+/// 口令健康审计接口, 在缓存的数据库上分析弱口令、重复使用的口令及长期未修改的记录
+///
+/// 记录所属分组的口令策略设置了最长使用天数时, 超期的记录同样视为陈旧记录
+pub async fn audit(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase", default)]
+    struct ReqParam {
+        /// 弱口令得分上限(0~4)
+        weak_score: u8,
+        /// 超过该天数未修改的记录视为陈旧记录, 0表示只按口令策略判断
+        old_days: u32,
+    }
+
+    impl Default for ReqParam {
+        fn default() -> Self {
+            ReqParam { weak_score: DEFAULT_WEAK_SCORE, old_days: DEFAULT_OLD_DAYS }
+        }
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ResData<'a> {
+        /// 参与审计的记录数量(口令不为空)
+        total: usize,
+        weak: Vec<AuditItem<'a>>,
+        /// 使用相同口令的记录分组
+        reused: Vec<Vec<AuditItem<'a>>>,
+        old: Vec<AuditItem<'a>>,
+    }
+
+    let req_param = ctx.parse_json_opt::<ReqParam>()?.unwrap_or_default();
+    httpserver::fail_if!(req_param.weak_score > 4, "弱口令得分上限必须在0-4之间");
+    let (database, pass) = service::session_db(&ctx)?;
+    let db = aidb::load_database(database, &pass)?;
+    log::info!(target: "audit", "password audit of {database} by {}", ctx.remote_ip());
+
+    // 强度评估较慢, 放到阻塞线程池中执行, 避免阻塞其它请求
+    tokio::task::spawn_blocking(move || {
+        let now = localtime::unix_timestamp();
+        let mut res = ResData { total: 0, weak: Vec::new(), reused: Vec::new(), old: Vec::new() };
+        let mut same_pass: HashMap<SecretString, Vec<&Record>> = HashMap::new();
+
+        for rec in db.records.iter().filter(|r| !r.pass.is_empty()) {
+            res.total += 1;
+            let pass = rec.pass.reveal_secret();
+            let score = strength::estimate(&pass, &[&rec.title, &rec.user]).score;
+            if score <= req_param.weak_score {
+                res.weak.push(AuditItem::new(rec, Some(score)));
+            }
+            same_pass.entry(pass).or_default().push(rec);
+
+            // 修改时间未知的记录不做判断
+            let expired = rec.modified > 0 && (db.group_policy(&rec.group).is_expired(rec.modified, now)
+                || (req_param.old_days > 0 && now > rec.modified + req_param.old_days as u64 * 86400));
+            if expired {
+                res.old.push(AuditItem::new(rec, None));
+            }
+        }
+
+        res.reused = same_pass.into_values()
+            .filter(|recs| recs.len() > 1)
+            .map(|recs| recs.into_iter().map(|r| AuditItem::new(r, None)).collect())
+            .collect();
+        res.reused.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a[0].title.cmp(b[0].title)));
+        res.weak.sort_by_key(|item| item.score);
+        res.old.sort_by_key(|item| item.modified);
+
+        Resp::ok(&res)
+    }).await?
+}
@@ -1,120 +1,701 @@
-use std::{
-    collections::HashMap, net::Ipv4Addr,
-    sync::{atomic::{AtomicU64, Ordering}, OnceLock}
-};
+use std::{borrow::Cow, collections::HashMap, net::Ipv4Addr, sync::{Arc, OnceLock}};
 
 use anyhow_ext::{bail, Result};
+use compact_str::CompactString;
+use hyper::header::{HeaderName, AUTHORIZATION};
 use parking_lot::Mutex;
 use httpserver::{HttpContext, Resp, Response, Next};
 
-use crate::AppGlobal;
+use crate::state::AppState;
+use super::{service, token::{self, Claims, TokenError}};
 
-pub struct Authentication;
+/// 登录校验中间件
+///
+/// 默认使用内存中的会话, 配置了共享密钥(`--token-secret`)时签发无状态令牌,
+/// 令牌中包含有效期及客户端地址, 服务重启或多实例部署时无需重新登录.
+/// 启用`--basic-auth`时, 脚本等简单客户端可以直接使用basic认证访问接口.
+/// 校验通过后将会话绑定的数据库名称写入`HttpContext.uid`.
+/// 白名单中的接口无需登录, 内置的白名单之外可以通过`--auth-whitelist`追加
+#[derive(Clone)]
+pub struct Authentication {
+    /// 无需登录的接口路径(不含`/api`及版本前缀), `*`匹配任意字符
+    whitelist: Arc<[CompactString]>,
+}
+
+/// 内置的无需登录的接口路径.
+/// 浏览器无法为WebSocket连接设置请求头, 推送连接建立后通过第一条消息发送令牌
+const DEFAULT_WHITELIST: [&str; 8] = ["/ping", "/health", "/ready", "/login", "/logout", "/share/open", "/ws", "/backup"];
+
+/// `--auth-whitelist`中的路径必须位于该前缀之下, 内置接口都不使用该前缀,
+/// 因此无论怎样配置(例如`/*`)都不会使需要登录的接口变成公开访问
+const PUBLIC_PREFIX: &str = "/public/";
+
+/// 会话有效期
+#[derive(Clone, Copy)]
+struct Session {
+    /// 空闲过期时间(unix时间戳), 每次访问或者客户端保活时延长
+    idle_exp: u64,
+    /// 绝对过期时间(unix时间戳), 登录时确定, 刷新令牌也不会延长
+    max_exp: u64,
+    /// 登录的数据库id
+    db: u64,
+}
+
+/// 新建会话的令牌及有效期
+pub struct SessionToken {
+    /// 会话id
+    pub id: u128,
+    /// 签名后的令牌
+    pub token: String,
+    /// 空闲过期时间(unix时间戳)
+    pub expire: u64,
+    /// 绝对过期时间(unix时间戳)
+    pub max_expire: u64,
+}
+
+/// 请求中携带的已校验凭证
+enum Credential {
+    /// 内存会话的id
+    Session(u128),
+    /// 无状态令牌的声明
+    Stateless(Claims),
+}
+
+/// 登录失败记录
+#[derive(Clone, Copy, Default)]
+struct LoginFailure {
+    /// 连续失败次数
+    count: u32,
+    /// 最后一次失败的时间(unix时间戳)
+    last: u64,
+    /// 允许再次尝试登录的时间(unix时间戳)
+    next_allowed: u64,
+}
+
+/// 登录防暴力破解的阈值配置
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LoginGuard {
+    /// 单个ip连续失败达到该次数后锁定, 0表示不限制
+    pub max_failures: u32,
+    /// 所有ip累计失败达到该次数后锁定全部登录, 0表示不限制
+    pub global_max_failures: u32,
+    /// 锁定时间, 同时也是失败计数的有效期(单位: 秒)
+    pub lockout: u64,
+}
+
+/// 登录失败统计
+#[derive(Default)]
+struct LoginFailures {
+    ips: HashMap<Ipv4Addr, LoginFailure>,
+    global: LoginFailure,
+    /// 最近使用的锁定时间, 用于清理过期记录
+    lockout: u64,
+}
 
-type Sessions = HashMap<u64, u64>; // key: id, value: exp
-type CurrentLimitings = HashMap<u32, u32>; // key: ipv4, value: count
+type Sessions = HashMap<u128, Session>; // key: id
 type GlobalValue<T> = OnceLock<Mutex<T>>;
 
-const AUTHORIZATION: &str = "Authorization";
-const SESSION: &str = "session ";
-const MAX_CURRENT_LIMITING: u32 = 3;
+/// Authorization头中会话令牌的缺省认证方式
+const SESSION: &str = "session";
+/// basic认证失败时的认证质询
+const BASIC_CHALLENGE: &str = "Basic realm=\"accinfo\", charset=\"UTF-8\"";
+/// 需要二次验证时回复的错误码, 与其它403错误区分
+const STEP_UP_REQUIRED: u32 = 4031;
+/// 不在允许访问的时间段内时回复的错误码, 与其它403错误区分
+const OUTSIDE_WINDOW: u32 = 4032;
+/// 非管理员访问管理接口时回复的错误码, 与其它403错误区分
+const ADMIN_REQUIRED: u32 = 4033;
+
+/// 会话令牌的传递方式
+struct TokenTransport {
+    /// 携带令牌的请求头
+    header: HeaderName,
+    /// 请求头中令牌前的认证方式, 为空时整个请求头的值即令牌
+    scheme: String,
+    /// 携带令牌的url参数名称, 为空时不接受url参数中的令牌
+    query: String,
+}
+
+/// basic认证的校验结果
+enum BasicAuth {
+    /// 认证成功, 通过认证的数据库名称(使用胁迫密码时为诱饵数据库)
+    Passed(CompactString),
+    /// 用户名或密码错误
+    Failed,
+    /// 失败次数过多, 需要等待的秒数
+    Locked(u64),
+}
 
-/// 限流统计时间(当前分钟)，1分钟变更1次，按分钟限流
-static STATIS_TIME: AtomicU64 = AtomicU64::new(0);
 /// 当前登录用户的session
 static SESSIONS: GlobalValue<Sessions> = OnceLock::new();
-/// 当前访问统计，用于限流
-static CURRENT_LIMITINGS: GlobalValue<CurrentLimitings> = OnceLock::new();
+/// 各会话二次验证的有效期(unix时间戳), key: 会话id
+static STEP_UPS: Mutex<Option<HashMap<u128, u64>>> = Mutex::new(None);
+/// 登录失败统计, 用于防暴力破解
+static LOGIN_FAILURES: Mutex<Option<LoginFailures>> = Mutex::new(None);
+/// 会话令牌的传递方式, 未设置时使用`Authorization: session <令牌>`
+static TOKEN_TRANSPORT: OnceLock<TokenTransport> = OnceLock::new();
+/// 修改口令的时刻(unix时间戳), 登录该数据库且签发时间早于该时刻的无状态令牌无效, key: 数据库id
+static REVOKED: Mutex<Option<HashMap<u64, u64>>> = Mutex::new(None);
+/// 刷新或者退出登录后作废的无状态令牌, key: 会话id, value: 令牌的绝对过期时间(unix时间戳), 过期后清理
+static REVOKED_IDS: Mutex<Option<HashMap<u128, u64>>> = Mutex::new(None);
 
 
 impl Authentication {
+    /// 创建登录校验中间件
+    ///
+    /// * `whitelist`: 内置白名单之外无需登录的接口路径(不含`/api`前缀), `*`匹配任意字符, 例如`/public/*`
+    pub fn new<S: AsRef<str>>(whitelist: &[S]) -> Self {
+        let list = DEFAULT_WHITELIST.iter().copied()
+            .chain(whitelist.iter().map(|s| s.as_ref().trim()).filter(|s| !s.is_empty()))
+            .map(CompactString::new)
+            .collect();
+        Authentication { whitelist: list }
+    }
+
+    /// 校验白名单路径的格式, 路径需位于`PUBLIC_PREFIX`之下, 前缀部分不能包含通配符
+    pub fn check_whitelist<S: AsRef<str>>(whitelist: &[S]) -> Result<()> {
+        for path in whitelist.iter().map(|s| s.as_ref().trim()).filter(|s| !s.is_empty()) {
+            if !path.starts_with(PUBLIC_PREFIX) {
+                bail!("path {path} must start with {PUBLIC_PREFIX}");
+            }
+        }
+        Ok(())
+    }
+
     pub fn recycle() {
         let now = localtime::unix_timestamp();
         let mut sessions = get_sessions().lock();
         let old_len = sessions.len();
         // 删除过期项
-        sessions.retain(|_, v| *v > now);
+        sessions.retain(|_, v| v.idle_exp > now && v.max_exp > now);
         if old_len > sessions.len() {
             log::trace!("recycle {} session item", old_len - sessions.len());
         }
+        drop(sessions);
+
+        if let Some(step_ups) = STEP_UPS.lock().as_mut() {
+            step_ups.retain(|_, exp| *exp > now);
+        }
+
+        if let Some(revoked) = REVOKED_IDS.lock().as_mut() {
+            revoked.retain(|_, exp| *exp > now);
+        }
+
+        // 删除已经解除锁定且失败计数已失效的登录失败记录
+        if let Some(failures) = LOGIN_FAILURES.lock().as_mut() {
+            let lockout = failures.lockout;
+            failures.ips.retain(|_, f| f.next_allowed > now || f.last + lockout > now);
+        }
+    }
+
+    /// 检查客户端是否允许尝试登录
+    ///
+    /// Returns:
+    ///
+    /// 被锁定或处于退避等待中时返回Err(需要等待的秒数)
+    pub fn check_login(ip: Ipv4Addr, guard: &LoginGuard) -> Result<(), u64> {
+        let now = localtime::unix_timestamp();
+        let mut failures = LOGIN_FAILURES.lock();
+        let failures = failures.get_or_insert_with(LoginFailures::default);
+
+        let mut wait = 0;
+        if guard.global_max_failures > 0 && failures.global.next_allowed > now {
+            wait = failures.global.next_allowed - now;
+        }
+        if let Some(f) = failures.ips.get(&ip) {
+            wait = wait.max(f.next_allowed.saturating_sub(now));
+        }
+
+        if wait > 0 { Err(wait) } else { Ok(()) }
+    }
+
+    /// 记录一次登录失败, 等待时间随连续失败次数指数增长, 达到阈值后锁定
+    pub fn login_failed(ip: Ipv4Addr, guard: &LoginGuard) {
+        let now = localtime::unix_timestamp();
+        let mut failures = LOGIN_FAILURES.lock();
+        let failures = failures.get_or_insert_with(LoginFailures::default);
+        failures.lockout = guard.lockout;
+
+        if guard.max_failures > 0 {
+            let f = failures.ips.entry(ip).or_default();
+            Self::add_failure(f, now, guard.max_failures, guard.lockout);
+            if f.count >= guard.max_failures {
+                log::warn!("login locked for {ip} after {} failed attempts", f.count);
+            }
+        }
+
+        if guard.global_max_failures > 0 {
+            let g = &mut failures.global;
+            Self::add_failure(g, now, guard.global_max_failures, guard.lockout);
+            // 全局计数只用于锁定, 不做退避, 避免单个客户端的失败影响其它客户端
+            if g.count < guard.global_max_failures {
+                g.next_allowed = 0;
+            } else {
+                log::warn!("all logins locked after {} failed attempts", g.count);
+            }
+        }
     }
 
-    fn check_session(id: u64) -> bool {
+    /// 登录成功后清除该客户端的失败记录
+    pub fn login_succeeded(ip: Ipv4Addr) {
+        if let Some(failures) = LOGIN_FAILURES.lock().as_mut() {
+            failures.ips.remove(&ip);
+        }
+    }
+
+    /// 累计失败次数, 失败计数超过锁定时间未更新时重新计数
+    fn add_failure(f: &mut LoginFailure, now: u64, max_failures: u32, lockout: u64) {
+        if f.last + lockout <= now && f.next_allowed <= now {
+            f.count = 0;
+        }
+        f.count += 1;
+        f.last = now;
+        f.next_allowed = if f.count >= max_failures {
+            now + lockout
+        } else {
+            // 1, 2, 4, 8...秒, 不超过锁定时间
+            now + (1u64 << (f.count - 1).min(16)).min(lockout)
+        };
+    }
+
+    /// 当前会话数量
+    pub fn session_count() -> usize {
+        get_sessions().lock().len()
+    }
+
+    /// 校验会话, 返回会话绑定的数据库id
+    ///
+    /// * `session_expire`: 空闲超时时间(单位: 秒)
+    /// * `sliding`: 是否延长会话的空闲过期时间, 禁用时只有客户端调用保活接口才延长
+    fn check_session(id: u128, session_expire: u64, sliding: bool) -> Option<u64> {
         let mut sessions = get_sessions().lock();
         let now = localtime::unix_timestamp();
-        if let Some(exp) = sessions.get_mut(&id) {
-            if *exp > now {
-                *exp = now + AppGlobal::get().session_expire;
-                return true;
+        if let Some(s) = sessions.get_mut(&id) {
+            if s.idle_exp > now && s.max_exp > now {
+                if sliding {
+                    s.idle_exp = (now + session_expire).min(s.max_exp);
+                }
+                return Some(s.db);
             }
         }
 
-        false
+        None
+    }
+
+    /// 延长当前请求会话的空闲过期时间, 不超过绝对过期时间
+    ///
+    /// 内存会话的令牌保持不变; 无状态令牌的有效期写在令牌中, 签发会话id不变的新令牌
+    ///
+    /// Returns:
+    ///
+    /// Ok(Some(会话令牌)), 会话不存在或者已过期时返回Ok(None)
+    pub fn keep_alive(ctx: &HttpContext, session_expire: u64) -> Result<Option<SessionToken>> {
+        let now = localtime::unix_timestamp();
+        let id = match Self::verify_session(ctx) {
+            Ok(Credential::Session(id)) => id,
+            Ok(Credential::Stateless(c)) => {
+                let claims = Claims { exp: (now + session_expire).min(c.max_exp), ..c };
+                return match token::sign_claims(&claims) {
+                    Some(token) => Ok(Some(SessionToken { id: c.id, token, expire: claims.exp, max_expire: c.max_exp })),
+                    None => bail!("token secret not set"),
+                };
+            }
+            Err(_) => return Ok(None),
+        };
+
+        let mut sessions = get_sessions().lock();
+        let s = match sessions.get_mut(&id) {
+            Some(s) if s.idle_exp > now && s.max_exp > now => s,
+            _ => return Ok(None),
+        };
+        s.idle_exp = (now + session_expire).min(s.max_exp);
+        let token = Self::session_token(ctx).map(Cow::into_owned).unwrap_or_default();
+        Ok(Some(SessionToken { id, token, expire: s.idle_exp, max_expire: s.max_exp }))
     }
 
-    fn require_authentication(path: &str) -> bool {
-        path.starts_with("/api/") && path != "/api/ping"
-                && path != "/api/login" && path != "/api/logout"
+    /// 请求路径是否需要登录
+    pub fn require_authentication(&self, path: &str) -> bool {
+        match Self::api_path(path) {
+            Some(path) => !self.whitelist.iter().any(|p| wildcard_match(p.as_bytes(), path.as_bytes())),
+            None => false,
+        }
     }
 
-    pub fn session_id() -> Result<String> {
-        const MAX_TRY: u16 = 10_000;
+    /// 去掉`/api`及版本前缀(例如/api/v1/login)后的接口路径, 不是接口请求时返回None
+    pub fn api_path(path: &str) -> Option<&str> {
+        let path = match path.strip_prefix("/api") {
+            Some(p) if p.starts_with('/') => p,
+            _ => return None,
+        };
+        Some(httpserver::split_api_version(path).map(|(_, p)| p).unwrap_or(path))
+    }
 
+    /// 新建会话, 启用无状态令牌时不保存会话
+    ///
+    /// * `ip`: 客户端地址, 写入无状态令牌
+    /// * `db`: 登录的数据库id
+    /// * `session_expire`: 空闲超时时间(单位: 秒)
+    /// * `session_max_age`: 会话最长有效时间(单位: 秒)
+    pub fn session_id(ip: Ipv4Addr, db: u64, session_expire: u64, session_max_age: u64) -> Result<SessionToken> {
+        let now = localtime::unix_timestamp();
+        let max_exp = now + session_max_age;
+        let idle_exp = (now + session_expire).min(max_exp);
+        if token::is_stateless() {
+            return Self::new_stateless(ip, db, idle_exp, max_exp);
+        }
         let mut sessions = get_sessions().lock();
-        let mut id = rand::random::<u64>();
+        Self::new_session(&mut sessions, Session { idle_exp, max_exp, db })
+    }
+
+    /// 刷新当前请求的会话, 返回新的令牌, 旧令牌立即失效, 新会话的绝对过期时间保持不变
+    ///
+    /// Returns:
+    ///
+    /// Ok(Some((旧会话id, 新会话令牌))), 会话不存在或者已过期时返回Ok(None)
+    pub fn refresh_session(ctx: &HttpContext, session_expire: u64) -> Result<Option<(u128, SessionToken)>> {
+        let now = localtime::unix_timestamp();
+        let old_id = match Self::verify_session(ctx) {
+            Ok(Credential::Session(id)) => id,
+            Ok(Credential::Stateless(c)) => {
+                let st = Self::new_stateless(c.ip, c.db, (now + session_expire).min(c.max_exp), c.max_exp)?;
+                Self::revoke_token(&c);
+                return Ok(Some((c.id, st)));
+            }
+            Err(_) => return Ok(None),
+        };
+
+        let mut sessions = get_sessions().lock();
+        let old = match sessions.get(&old_id) {
+            Some(s) if s.idle_exp > now && s.max_exp > now => *s,
+            _ => return Ok(None),
+        };
+        sessions.remove(&old_id);
+
+        let session = Session { idle_exp: (now + session_expire).min(old.max_exp), ..old };
+        let st = Self::new_session(&mut sessions, session)?;
+        Ok(Some((old_id, st)))
+    }
+
+    fn new_session(sessions: &mut Sessions, session: Session) -> Result<SessionToken> {
+        const MAX_TRY: u16 = 10_000;
+
+        let mut id = token::next_token();
         let mut count = 0;
 
         loop {
             if !sessions.contains_key(&id) { break; }
-            id = rand::random();
+            id = token::next_token();
             if count >= MAX_TRY {
                 bail!("create session id has maximum try");
             }
             count += 1;
         }
 
-        let exp = localtime::unix_timestamp() + AppGlobal::get().session_expire;
-        sessions.insert(id, exp);
+        sessions.insert(id, session);
 
-        Ok(format!("{:016x}", id))
+        Ok(SessionToken { id, token: token::sign(id), expire: session.idle_exp, max_expire: session.max_exp })
     }
 
-    fn check_limit(ip: Ipv4Addr) -> bool {
-        let now = localtime::unix_timestamp();
-        let now_minute = now / 60;
-        let statis_time = STATIS_TIME.load(Ordering::Acquire);
+    fn new_stateless(ip: Ipv4Addr, db: u64, idle_exp: u64, max_exp: u64) -> Result<SessionToken> {
+        let id = token::next_token();
+        let claims = Claims { id, iat: localtime::unix_timestamp(), exp: idle_exp, max_exp, ip, db };
+        match token::sign_claims(&claims) {
+            Some(token) => Ok(SessionToken { id, token, expire: idle_exp, max_expire: max_exp }),
+            None => bail!("token secret not set"),
+        }
+    }
 
-        let mut limits = get_current_limitings().lock();
+    pub fn get_session_id(ctx: &HttpContext) -> Option<u128> {
+        match Self::verify_session(ctx) {
+            Ok(Credential::Session(id)) => Some(id),
+            Ok(Credential::Stateless(c)) => Some(c.id),
+            Err(_) => None,
+        }
+    }
 
-        // 每隔1秒钟，重新计算限流值
-        if now_minute > statis_time {
-            STATIS_TIME.store(now_minute, Ordering::Release);
-            limits.clear();
+    /// 设置会话令牌的传递方式, 只能在服务启动前设置一次
+    ///
+    /// Arguments:
+    ///
+    /// * `header`: 携带令牌的请求头名称
+    /// * `scheme`: 令牌前的认证方式, 为空时整个请求头的值即令牌
+    /// * `query`: 携带令牌的url参数名称, 用于不方便设置请求头的SSE/WebSocket连接, 为空时不启用
+    pub fn set_token_transport(header: &str, scheme: &str, query: &str) -> Result<()> {
+        let header = match HeaderName::from_bytes(header.trim().as_bytes()) {
+            Ok(h) => h,
+            Err(_) => bail!("invalid header name: {header}"),
+        };
+        let scheme = scheme.trim();
+        if scheme.contains(char::is_whitespace) {
+            bail!("scheme must not contain whitespace: {scheme}");
+        }
+        let query = query.trim();
+        if !query.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'_' || c == b'-') {
+            bail!("invalid query parameter name: {query}");
         }
+        let transport = TokenTransport { header, scheme: scheme.to_owned(), query: query.to_owned() };
+        if TOKEN_TRANSPORT.set(transport).is_err() {
+            bail!("token transport already set");
+        }
+        Ok(())
+    }
 
-        let ip: u32 = ip.into();
-        let visit_count = limits.entry(ip).or_insert(0);
-        *visit_count += 1;
+    /// 客户端携带会话令牌使用的请求头名称及认证方式
+    pub fn token_header() -> (&'static str, &'static str) {
+        let t = token_transport();
+        (t.header.as_str(), &t.scheme)
+    }
 
-        *visit_count <= MAX_CURRENT_LIMITING
+    /// 携带会话令牌的请求头及url参数名称(未启用时为空), 反向代理转发请求时需去除
+    pub fn token_carriers() -> (&'static HeaderName, &'static str) {
+        let t = token_transport();
+        (&t.header, &t.query)
     }
 
-    fn get_session_id(ctx: &HttpContext) -> Option<u64> {
-        if let Some(auth) = ctx.req.headers().get(AUTHORIZATION) {
-            if let Ok(auth) = auth.to_str() {
-                if let Some(session) = auth.strip_prefix(SESSION) {
-                    if let Ok(id) = u64::from_str_radix(session, 16) {
-                        return Some(id);
-                    }
+    /// 获取请求中的会话令牌, 优先使用请求头, 请求头中没有时使用url参数
+    pub fn session_token(ctx: &HttpContext) -> Option<Cow<'_, str>> {
+        let t = token_transport();
+        if let Some(token) = ctx.header_token(&t.header, &t.scheme) {
+            return Some(Cow::Borrowed(token));
+        }
+        if t.query.is_empty() {
+            return None;
+        }
+        ctx.get_url_param_str(&t.query).filter(|s| !s.is_empty())
+    }
+
+    /// 校验推送连接发送的会话令牌, 不延长会话的空闲过期时间
+    ///
+    /// Returns:
+    ///
+    /// (会话id, 数据库id, 会话过期时间), 令牌无效或者会话已过期时返回None
+    pub fn verify_token(token: &str, ip: Ipv4Addr) -> Option<(u128, u64, u64)> {
+        match Self::verify_credential(token, ip).ok()? {
+            Credential::Session(id) => {
+                let now = localtime::unix_timestamp();
+                let s = *get_sessions().lock().get(&id)?;
+                (s.idle_exp > now && s.max_exp > now).then_some((id, s.db, s.idle_exp.min(s.max_exp)))
+            }
+            Credential::Stateless(c) => Some((c.id, c.db, c.exp.min(c.max_exp))),
+        }
+    }
+
+    /// 校验请求中的令牌, 无状态令牌同时校验有效期及客户端地址
+    fn verify_session(ctx: &HttpContext) -> Result<Credential, TokenError> {
+        let session = Self::session_token(ctx).ok_or(TokenError::Format)?;
+        Self::verify_credential(&session, ctx.remote_ip())
+    }
+
+    /// 校验令牌, 无状态令牌同时校验有效期及客户端地址
+    fn verify_credential(session: &str, ip: Ipv4Addr) -> Result<Credential, TokenError> {
+        match token::verify_claims(session) {
+            Ok(c) if c.ip != ip => Err(TokenError::Address),
+            Ok(c) if Self::is_revoked(&c) => Err(TokenError::Expired),
+            Ok(c) => Ok(Credential::Stateless(c)),
+            // 长度不符或未启用无状态令牌, 按会话令牌校验
+            Err(TokenError::Format) => token::verify(session).map(Credential::Session),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// 校验basic认证的用户名及密码, 用户名即数据库名称, 与登录接口共用防暴力破解的失败计数
+    ///
+    /// 认证成功后保存主密码, 后续请求与内存中的主密码比较, 无需每次解密数据库
+    async fn check_basic(ctx: &HttpContext, st: &AppState, user: &str, pass: &str) -> Result<BasicAuth> {
+        let ip = ctx.remote_ip();
+        if let Err(wait) = Self::check_login(ip, &st.login_guard) {
+            return Ok(BasicAuth::Locked(wait));
+        }
+
+        let database = st.find_database(user);
+        let passed = match database {
+            Some((id, db)) => Self::verify_password(ctx, st, id, db, pass).await?,
+            None => None,
+        };
+
+        let database = match (passed, database) {
+            (Some((_, db)), _) => db,
+            (None, database) => {
+                if let Some((_, db)) = database {
+                    crate::aidb::stats_login_failed(db);
                 }
+                Self::login_failed(ip, &st.login_guard);
+                log::warn!("basic auth failed, user: {user}, client: {ip}");
+                return Ok(BasicAuth::Failed);
             }
+        };
+
+        Self::login_succeeded(ip);
+        service::set_password(database, pass);
+        Ok(BasicAuth::Passed(CompactString::new(AppState::database_name(database))))
+    }
+
+    /// 校验数据库主密码, 主密码错误时再校验对应诱饵数据库的主密码(即胁迫密码)
+    ///
+    /// 使用胁迫密码时悄悄记录审计日志并推送`duressLogin`事件给真实数据库的其它会话,
+    /// 客户端看到的结果与正常登录相同
+    ///
+    /// Returns:
+    ///
+    /// 通过校验的(数据库id, 数据库文件名), 密码错误时返回None
+    pub async fn verify_password<'a>(ctx: &HttpContext, st: &'a AppState, id: u64, database: &'a str,
+            pass: &str) -> Result<Option<(u64, &'a str)>> {
+        if Self::password_matches(database, pass).await? {
+            return Ok(Some((id, database)));
         }
-        None
+        if let Some((decoy_id, decoy)) = st.decoy_of(database) {
+            if Self::password_matches(decoy, pass).await? {
+                log::warn!(target: "audit", "duress password used for database {} by {}, decoy database {} opened",
+                    AppState::database_name(database), ctx.remote_ip(), AppState::database_name(decoy));
+                crate::events::publish(crate::events::Event::DuressLogin {
+                    database: CompactString::new(AppState::database_name(database)),
+                    ip: ctx.remote_ip().to_string().into(),
+                });
+                return Ok(Some((decoy_id, decoy)));
+            }
+        }
+        Ok(None)
     }
 
+    /// 密码与内存中保存的主密码相同时无需派生密钥校验数据库文件
+    async fn password_matches(database: &str, pass: &str) -> Result<bool> {
+        let saved = service::password(database);
+        if !saved.is_empty() && secure_eq(saved.as_bytes(), pass.as_bytes()) {
+            return Ok(true);
+        }
+        crate::aidb::check_password_blocking(database, pass).await
+    }
+
+    /// 删除当前请求的会话, 无状态令牌记录为已作废, 直到其过期
     pub fn remove_session_id(ctx: &HttpContext) {
         if let Some(id) = Self::get_session_id(ctx) {
-            get_sessions().lock().remove(&id);
+            if let Some(step_ups) = STEP_UPS.lock().as_mut() {
+                step_ups.remove(&id);
+            }
+        }
+        match Self::verify_session(ctx) {
+            Ok(Credential::Session(id)) => { get_sessions().lock().remove(&id); },
+            Ok(Credential::Stateless(c)) => Self::revoke_token(&c),
+            Err(_) => {},
+        }
+    }
+
+    /// 使登录到指定数据库的所有会话失效, 用于修改口令后强制重新登录
+    ///
+    /// 无状态令牌无法逐个吊销, 记录吊销时刻, 之前签发的令牌均视为过期, 之后重新登录签发的令牌不受影响
+    ///
+    /// * `db`: 数据库id
+    /// * `session_max_age`: 会话最长有效时间(单位: 秒)
+    pub fn revoke_database(db: u64, session_max_age: u64) {
+        let mut sessions = get_sessions().lock();
+        let ids: Vec<u128> = sessions.iter().filter(|(_, s)| s.db == db).map(|(id, _)| *id).collect();
+        for id in ids.iter() {
+            sessions.remove(id);
+        }
+        drop(sessions);
+
+        if let Some(step_ups) = STEP_UPS.lock().as_mut() {
+            step_ups.retain(|id, _| !ids.contains(id));
+        }
+        if token::is_stateless() {
+            Self::revoke_tokens(db, localtime::unix_timestamp(), session_max_age);
+        }
+        log::info!(target: "audit", "{} sessions of database #{db:016x} revoked", ids.len());
+    }
+
+    /// 记录数据库的吊销时刻, 吊销时刻之前签发的令牌超过最长有效时间后都已过期, 同时清理这些记录
+    fn revoke_tokens(db: u64, now: u64, session_max_age: u64) {
+        let mut revoked = REVOKED.lock();
+        let revoked = revoked.get_or_insert_with(HashMap::new);
+        revoked.retain(|_, at| *at + session_max_age > now);
+        revoked.insert(db, now);
+    }
+
+    /// 作废单个无状态令牌, 同一会话id的令牌(包括保活续签的令牌)都不再有效
+    fn revoke_token(c: &Claims) {
+        REVOKED_IDS.lock().get_or_insert_with(HashMap::new).insert(c.id, c.max_exp);
+    }
+
+    /// 无状态令牌是否已因修改口令、刷新或者退出登录被吊销
+    fn is_revoked(c: &Claims) -> bool {
+        REVOKED.lock().as_ref().and_then(|r| r.get(&c.db)).is_some_and(|at| c.iat < *at)
+            || REVOKED_IDS.lock().as_ref().is_some_and(|r| r.contains_key(&c.id))
+    }
+
+    /// 记录当前会话通过了二次验证(重新输入主密码)
+    ///
+    /// Returns:
+    ///
+    /// 二次验证的有效期(unix时间戳), 未启用二次验证或者没有会话时返回None
+    pub fn step_up(ctx: &HttpContext) -> Result<Option<u64>> {
+        let window = AppState::from_ctx(ctx)?.stepup_window;
+        let id = match Self::get_session_id(ctx) {
+            Some(id) if window > 0 => id,
+            _ => return Ok(None),
+        };
+        let exp = localtime::unix_timestamp() + window;
+        STEP_UPS.lock().get_or_insert_with(HashMap::new).insert(id, exp);
+        Ok(Some(exp))
+    }
+
+    /// 检查当前会话最近是否通过了二次验证, 未启用二次验证时总是通过
+    ///
+    /// basic认证的请求每次都携带主密码, 视为已通过二次验证
+    pub fn check_step_up(ctx: &HttpContext) -> Result<bool> {
+        if AppState::from_ctx(ctx)?.stepup_window == 0 {
+            return Ok(true);
+        }
+        let id = match Self::get_session_id(ctx) {
+            Some(id) => id,
+            None => return Ok(ctx.basic_auth().is_some()),
+        };
+        let now = localtime::unix_timestamp();
+        Ok(STEP_UPS.lock().as_ref().and_then(|s| s.get(&id)).is_some_and(|exp| *exp > now))
+    }
+
+    /// 需要二次验证时的回复, 客户端据此提示用户重新输入主密码
+    pub fn step_up_required() -> httpserver::HttpResponse {
+        const FORBIDDEN: hyper::StatusCode = hyper::StatusCode::FORBIDDEN;
+        Resp::fail_with_status(FORBIDDEN, STEP_UP_REQUIRED, "该操作需要重新验证主密码")
+    }
+
+    /// 检查当前时间是否允许访问数据库, 不允许时记录审计日志
+    ///
+    /// * `database`: 数据库名称
+    pub fn check_access_window(ctx: &HttpContext, st: &AppState, database: &str) -> bool {
+        if st.access_windows.allowed_now(database) {
+            return true;
+        }
+        log::warn!(target: "audit", "access to database {database} by {} rejected: outside allowed hours",
+            ctx.remote_ip());
+        false
+    }
+
+    /// 不在允许访问的时间段内时的回复
+    pub fn outside_window() -> httpserver::HttpResponse {
+        const FORBIDDEN: hyper::StatusCode = hyper::StatusCode::FORBIDDEN;
+        Resp::fail_with_status(FORBIDDEN, OUTSIDE_WINDOW, "当前时间不在允许访问的时间段内")
+    }
+
+    /// 当前请求是否为管理员, 只有登录缺省数据库的会话是管理员
+    ///
+    /// 白名单中的路径没有会话, 胁迫密码登录的会话绑定诱饵数据库, 都不是管理员
+    pub fn is_admin(ctx: &HttpContext, st: &AppState) -> bool {
+        !ctx.uid.is_empty() && AppState::database_name(&st.database) == ctx.uid
+    }
+
+    /// 非管理员访问管理接口时的回复
+    pub fn admin_required() -> httpserver::HttpResponse {
+        const FORBIDDEN: hyper::StatusCode = hyper::StatusCode::FORBIDDEN;
+        Resp::fail_with_status(FORBIDDEN, ADMIN_REQUIRED, "该操作需要管理员权限")
+    }
+
+    /// 刷新令牌后, 二次验证的有效期转移到新会话
+    pub fn move_step_up(old_id: u128, new_id: u128) {
+        if let Some(step_ups) = STEP_UPS.lock().as_mut() {
+            if let Some(exp) = step_ups.remove(&old_id) {
+                step_ups.insert(new_id, exp);
+            }
         }
     }
 
@@ -122,31 +703,201 @@ impl Authentication {
 
 #[async_trait::async_trait]
 impl httpserver::HttpMiddleware for Authentication {
-    async fn handle<'a>(&'a self, ctx: HttpContext, next: Next<'a>) -> Result<Response> {
-        if !Self::require_authentication(ctx.req.uri().path()) {
+    async fn handle<'a>(&'a self, mut ctx: HttpContext, next: Next<'a>) -> Result<Response> {
+        if !self.require_authentication(ctx.req.uri().path()) {
             return next.run(ctx).await
         }
 
-        if let Some(id) = Self::get_session_id(&ctx) {
-            // 限流校验
-            if Self::check_limit(ctx.remote_ip()) {
-                // 登录校验
-                if Self::check_session(id) {
+        const UNAUTHORIZED: hyper::StatusCode = hyper::StatusCode::UNAUTHORIZED;
+
+        match Self::verify_session(&ctx) {
+            Ok(cred) => {
+                // 登录校验, 无状态令牌的有效期已在签名校验时检查
+                let st = AppState::from_ctx(&ctx)?;
+                let db = match cred {
+                    Credential::Session(id) => Self::check_session(id, st.session_expire, !st.no_sliding_session),
+                    Credential::Stateless(c) => Some(c.db),
+                };
+                // 数据库改名或移除后, id对应的数据库不存在时视为会话无效
+                let uid = db.and_then(|db| st.database_by_id(db))
+                    .map(|db| CompactString::new(AppState::database_name(db)));
+                if let Some(uid) = uid {
+                    // 诱饵数据库的会话按真实数据库的时间段限制, 与正常登录的行为一致
+                    if !Self::check_access_window(&ctx, st, st.display_name(&uid)) {
+                        return Self::outside_window();
+                    }
+                    ctx.uid = uid;
                     return next.run(ctx).await
                 }
             }
+            // 签名错误的令牌直接返回具体原因, 无需查找会话
+            Err(e @ (TokenError::Signature | TokenError::IssuedAt
+                    | TokenError::Expired | TokenError::Address)) => {
+                return Resp::fail_with_status(UNAUTHORIZED, UNAUTHORIZED.as_u16() as u32,
+                    &e.to_string());
+            }
+            Err(TokenError::Format) => {}
         }
 
-        Resp::fail_with_status(hyper::StatusCode::UNAUTHORIZED,
-            hyper::StatusCode::UNAUTHORIZED.as_u16() as u32,
-            hyper::StatusCode::UNAUTHORIZED.as_str())
+        // 没有会话令牌时尝试basic认证
+        let st = AppState::from_ctx(&ctx)?;
+        if let (true, Some((user, pass))) = (st.basic_auth, ctx.basic_auth()) {
+            match Self::check_basic(&ctx, st, &user, &pass).await? {
+                BasicAuth::Passed(uid) => {
+                    if !Self::check_access_window(&ctx, st, &user) {
+                        return Self::outside_window();
+                    }
+                    ctx.uid = uid;
+                    return next.run(ctx).await
+                }
+                BasicAuth::Locked(wait) => {
+                    const TOO_MANY_REQUESTS: hyper::StatusCode = hyper::StatusCode::TOO_MANY_REQUESTS;
+                    let mut res = Resp::fail_with_status(TOO_MANY_REQUESTS, TOO_MANY_REQUESTS.as_u16() as u32,
+                        &format!("登录失败次数过多, 请{wait}秒后重试"))?;
+                    res.headers_mut().insert(hyper::header::RETRY_AFTER, wait.into());
+                    return Ok(res);
+                }
+                BasicAuth::Failed => {
+                    // 只在客户端使用basic认证时返回质询, 避免浏览器对页面请求弹出登录框
+                    let mut res = Resp::fail_with_status(UNAUTHORIZED, UNAUTHORIZED.as_u16() as u32,
+                        "用户名或密码错误")?;
+                    res.headers_mut().insert(hyper::header::WWW_AUTHENTICATE,
+                        hyper::header::HeaderValue::from_static(BASIC_CHALLENGE));
+                    return Ok(res);
+                }
+            }
+        }
+
+        Resp::fail_with_status(UNAUTHORIZED, UNAUTHORIZED.as_u16() as u32, UNAUTHORIZED.as_str())
+    }
+}
+
+/// 通配符匹配, `*`匹配任意字符(包括`/`)
+fn wildcard_match(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // 最近一个`*`的位置及其匹配到的文本位置, 用于回溯
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            star = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((sp, st)) = star {
+            p = sp + 1;
+            t = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
     }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// 比较长度相同的数据时耗时与内容无关, 避免通过响应时间推测密码
+pub(super) fn secure_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn token_transport() -> &'static TokenTransport {
+    TOKEN_TRANSPORT.get_or_init(|| TokenTransport {
+        header: AUTHORIZATION,
+        scheme: SESSION.to_owned(),
+        query: String::new(),
+    })
 }
 
 fn get_sessions() -> &'static Mutex<Sessions> {
     SESSIONS.get_or_init(|| Mutex::new(Sessions::new()))
 }
 
-fn get_current_limitings() -> &'static Mutex<CurrentLimitings> {
-    CURRENT_LIMITINGS.get_or_init(|| Mutex::new(CurrentLimitings::new()))
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn login_after_password_change() {
+        let db = AppState::database_id("/data/revoke.aidb");
+        let now = localtime::unix_timestamp();
+        let old = Claims { id: 1, iat: now - 10, exp: now + 60, max_exp: now + 600, ip: Ipv4Addr::LOCALHOST, db };
+        Authentication::revoke_tokens(db, now, 3600);
+        assert!(Authentication::is_revoked(&old));
+
+        // 修改口令后立即重新登录, 签发时间与吊销时刻相同的令牌有效
+        let fresh = Claims { id: 2, iat: now, ..old };
+        assert!(!Authentication::is_revoked(&fresh));
+        // 其它数据库的令牌不受影响
+        let other = Claims { db: AppState::database_id("/data/other.aidb"), ..old };
+        assert!(!Authentication::is_revoked(&other));
+    }
+
+    #[test]
+    fn revoke_refreshed_token() {
+        let db = AppState::database_id("/data/refresh.aidb");
+        let now = localtime::unix_timestamp();
+        let old = Claims { id: 0x5eed, iat: now, exp: now + 60, max_exp: now + 600, ip: Ipv4Addr::LOCALHOST, db };
+        assert!(!Authentication::is_revoked(&old));
+
+        // 刷新或退出登录后, 同一会话id保活续签的令牌同样失效, 其它会话不受影响
+        Authentication::revoke_token(&old);
+        assert!(Authentication::is_revoked(&old));
+        assert!(Authentication::is_revoked(&Claims { exp: now + 120, ..old }));
+        assert!(!Authentication::is_revoked(&Claims { id: 0x5eee, ..old }));
+    }
+
+    #[test]
+    fn admin() {
+        let st = AppState {
+            database: String::from("/data/home.aidb"),
+            databases: vec![String::from("/data/home.aidb"), String::from("/data/work.aidb")],
+            decoys: vec![(String::from("/data/home.aidb"), String::from("/data/home-decoy.aidb"))],
+            ..Default::default()
+        };
+        let ctx = |uid: &str| HttpContext::test_builder().path("/api/admin/config").uid(uid).build();
+        assert!(Authentication::is_admin(&ctx("home"), &st));
+        // 其它数据库、诱饵数据库及没有会话的请求不是管理员
+        assert!(!Authentication::is_admin(&ctx("work"), &st));
+        assert!(!Authentication::is_admin(&ctx("home-decoy"), &st));
+        assert!(!Authentication::is_admin(&ctx(""), &st));
+    }
+
+    #[test]
+    fn auth_whitelist() {
+        let auth = Authentication::new(&["/public/*", "/public/*/icon", ""]);
+        assert!(!auth.require_authentication("/api/login"));
+        assert!(!auth.require_authentication("/api/v1/ping"));
+        assert!(!auth.require_authentication("/api/public/a/b"));
+        assert!(!auth.require_authentication("/api/public/12/icon"));
+        assert!(auth.require_authentication("/api/record/12/reveal"));
+        assert!(auth.require_authentication("/api/list"));
+        assert!(auth.require_authentication("/api/publicity"));
+        assert!(!auth.require_authentication("/index.html"));
+    }
+
+    #[test]
+    fn wildcard() {
+        // `*`可以匹配`/`及空串
+        assert!(wildcard_match(b"/public/*", b"/public/a/b/c"));
+        assert!(wildcard_match(b"/public/*", b"/public/"));
+        assert!(wildcard_match(b"/*/icon", b"/record/12/icon"));
+        // 需要回溯: 第一个`/icon`之后还有内容
+        assert!(wildcard_match(b"/*/icon", b"/a/icon/b/icon"));
+        assert!(!wildcard_match(b"/*/icon", b"/a/icon/b"));
+        assert!(wildcard_match(b"/a*b*c", b"/aXbYbZc"));
+        assert!(!wildcard_match(b"/a*b*c", b"/aXbYcZ"));
+        assert!(wildcard_match(b"/**", b"/x"));
+        assert!(!wildcard_match(b"/public", b"/public/a"));
+    }
+
+    #[test]
+    fn check_whitelist() {
+        assert!(Authentication::check_whitelist(&["/public/*", "/public/*/icon", ""]).is_ok());
+        assert!(Authentication::check_whitelist(&["public/*"]).is_err());
+        assert!(Authentication::check_whitelist(&["/*"]).is_err());
+        assert!(Authentication::check_whitelist(&["/pub*"]).is_err());
+        assert!(Authentication::check_whitelist(&["/*/public/*"]).is_err());
+        assert!(Authentication::check_whitelist(&["/export"]).is_err());
+        assert!(Authentication::check_whitelist(&["/record/*/icon"]).is_err());
+    }
 }
@@ -0,0 +1,70 @@
+use std::sync::Arc;
+use httpserver::{HttpContext, HttpResponse, Resp};
+use serde::Deserialize;
+use crate::{aidb::{Record, RecordComment, MAX_COMMENTS}, state::AppState};
+use super::{record::limit_response, undo};
+
+/// 评论内容最大字符数
+const MAX_COMMENT_LEN: usize = 1000;
+/// 评论人最大字符数
+const MAX_AUTHOR_LEN: usize = 64;
+
+/// 添加记录评论接口, 评论只能追加, 不修改记录的最后修改时间, 通过记录详情接口返回
+pub async fn record_comment(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    struct ReqParam {
+        id: String,
+        text: String,
+        #[serde(default)]
+        author: String,
+    }
+
+    let req_param = ctx.parse_json::<ReqParam>()?;
+    let text = req_param.text.trim();
+    let author = req_param.author.trim();
+    httpserver::fail_if!(text.is_empty(), "评论内容不能为空");
+    httpserver::fail_if!(text.chars().count() > MAX_COMMENT_LEN, "评论内容不能超过{}个字符", MAX_COMMENT_LEN);
+    httpserver::fail_if!(author.chars().count() > MAX_AUTHOR_LEN, "评论人不能超过{}个字符", MAX_AUTHOR_LEN);
+
+    let limits = AppState::from_ctx(&ctx)?.record_limits;
+
+    let comment = undo::update_database(&ctx, "record/comment", |db| {
+        let idx = match db.record_index(&req_param.id) {
+            Some(idx) => idx,
+            None => httpserver::http_bail!("记录不存在"),
+        };
+        let comment = RecordComment {
+            time: localtime::unix_timestamp(),
+            author: author.to_owned(),
+            text: text.to_owned(),
+        };
+        let mut rec = Record::clone(&db.records[idx]);
+        httpserver::fail_if!(!rec.push_comment(comment.clone()), "评论数量已达上限{}条", MAX_COMMENTS);
+        limits.check(&rec)?;
+        db.records[idx] = Arc::new(rec);
+        Ok(comment)
+    });
+
+    limit_response(comment, |comment| {
+        log::info!(target: "audit", "comment on record {} by {}", req_param.id, ctx.remote_ip());
+        Resp::ok(&comment)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comments_are_not_truncated() {
+        let mut rec = Record::default();
+        let comment = |i: usize| RecordComment { time: i as u64, author: String::new(), text: i.to_string() };
+        for i in 0..MAX_COMMENTS {
+            assert!(rec.push_comment(comment(i)));
+        }
+        // 达到上限后拒绝追加, 最早的评论仍然保留
+        assert!(!rec.push_comment(comment(MAX_COMMENTS)));
+        assert_eq!(rec.comments.len(), MAX_COMMENTS);
+        assert_eq!(rec.comments[0].text, "0");
+    }
+}
@@ -0,0 +1,190 @@
+use std::{io::Write, sync::Arc};
+use accinfo_api::JobCreated;
+use httpserver::{HttpContext, HttpResponse, Resp, StreamBody, CONTENT_TYPE};
+use hyper::{header::CONTENT_DISPOSITION, StatusCode};
+use serde::Deserialize;
+use zeroize::Zeroizing;
+use crate::{aidb::{self, ExportFormat, SecretString}, jobs::{self, JobOutput}, recipient::Recipients, state::AppState};
+use super::{authentication::{secure_eq, Authentication}, jobs::job_owner, service};
+
+/// 导出数据库接口, 以附件下载的方式返回明文的KeePass 2 xml或者csv文件
+///
+/// `encrypted`为true时加密给配置的接收者, `recipient`为age接收者、ssh公钥或者armor格式的OpenPGP公钥时
+/// 加密给该接收者, 加密后的文件以`.age`/`.asc`结尾;
+/// `background`为true时创建后台任务并返回任务id, 通过任务接口查询进度及下载结果,
+/// 启用二次验证时需要先重新验证主密码
+pub async fn export(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize, Default)]
+    struct ReqParam {
+        format: Option<String>,
+        #[serde(default)]
+        background: bool,
+        #[serde(default)]
+        encrypted: bool,
+        recipient: Option<String>,
+    }
+
+    let req_param = ctx.parse_json_opt::<ReqParam>()?.unwrap_or_default();
+    if !Authentication::check_step_up(&ctx)? {
+        log::warn!(target: "audit", "export database by {} rejected: step-up required", ctx.remote_ip());
+        return Authentication::step_up_required();
+    }
+    let format = parse_format(req_param.format.as_deref())?;
+    let st = AppState::from_ctx(&ctx)?;
+    // 请求中提供的接收者优先, 其次为配置的接收者
+    let recipients = match req_param.recipient.as_deref().map(str::trim) {
+        Some(r) if !r.is_empty() => match Recipients::parse_inline(r) {
+            Ok(r) if !r.is_empty() => Some(Arc::new(r)),
+            Ok(_) => httpserver::http_bail!("加密接收者不能为空"),
+            Err(e) => httpserver::http_bail!("加密接收者格式错误: {}", e),
+        },
+        _ if req_param.encrypted => {
+            httpserver::fail_if!(st.export_recipients.is_empty(), "未配置导出文件的加密接收者");
+            Some(st.export_recipients.clone())
+        }
+        _ => None,
+    };
+
+    let (database, pass) = service::session_db(&ctx)?;
+    let file = ExportName::new(st.display_name(database), format, recipients.as_deref());
+    let (database, ip) = (database.to_owned(), ctx.remote_ip());
+    if req_param.background {
+        let job_id = jobs::spawn("export", job_owner(&ctx), move |job| {
+            job.check_cancel()?;
+            // 每条记录检查一次取消, 进度按已导出的记录数计算, 加密阶段占最后的10%
+            let mut progress = |done: usize, total: usize| {
+                job.progress((done * 90 / total.max(1)) as u8);
+                job.check_cancel()
+            };
+            let mut data = Zeroizing::new(Vec::new());
+            export_to(&database, &pass, format, recipients.as_deref(), &file.plain, &mut *data, &mut progress)?;
+            job.check_cancel()?;
+            log::info!("export database {database} by {ip}, format: {}", format.ext());
+            Ok(JobOutput::File { name: file.name, mime: file.mime, data })
+        });
+        return match job_id {
+            Ok(job_id) => Resp::ok(&JobCreated { job_id }),
+            Err(e) => httpserver::http_bail!(e.to_string()),
+        };
+    }
+
+    let res = stream_export(database.clone(), pass, format, recipients, file)?;
+    log::info!("export database {database} by {ip}, format: {}", format.ext());
+    Ok(res)
+}
+
+/// 备份接口, 供自动备份任务拉取加密给配置的接收者的导出文件, 无需登录及主密码
+///
+/// 使用`Authorization: Bearer <--backup-token>`认证, 数据库需在服务启动后登录过一次(内存中有主密码),
+/// 参数`database`为数据库名称(缺省为第一个数据库), `format`为导出格式(缺省为xml)
+pub async fn backup(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize, Default)]
+    struct ReqParam {
+        database: Option<String>,
+        format: Option<String>,
+    }
+
+    let st = AppState::from_ctx(&ctx)?;
+    let ip = ctx.remote_ip();
+    if st.backup_token.is_empty() || st.export_recipients.is_empty() {
+        return Resp::fail_with_status(StatusCode::NOT_FOUND, 404, "备份接口未启用");
+    }
+    let passed = ctx.bearer_token().is_some_and(|t| secure_eq(t.as_bytes(), st.backup_token.as_bytes()));
+    if !passed {
+        log::warn!(target: "audit", "backup by {ip} rejected: invalid token");
+        return Resp::fail_with_status(StatusCode::UNAUTHORIZED, 401, "备份令牌错误");
+    }
+
+    let req_param = match ctx.parse_json_opt::<ReqParam>() {
+        Ok(Some(p)) => p,
+        // GET请求使用url参数
+        _ => ReqParam {
+            database: ctx.get_url_param_str("database").map(|s| s.into_owned()),
+            format: ctx.get_url_param_str("format").map(|s| s.into_owned()),
+        },
+    };
+    let format = parse_format(req_param.format.as_deref())?;
+    let database = match req_param.database.as_deref() {
+        Some(name) => match st.find_database(name) {
+            Some((_, db)) => db,
+            None => httpserver::http_bail!("数据库不存在"),
+        },
+        None => st.database.as_str(),
+    };
+    let pass = service::password(database);
+    if pass.is_empty() {
+        log::warn!(target: "audit", "backup database {database} by {ip} rejected: database locked");
+        return Resp::fail_with_status(StatusCode::SERVICE_UNAVAILABLE, 503, "数据库尚未解锁, 需要登录一次后才能备份");
+    }
+
+    let file = ExportName::new(AppState::database_name(database), format, Some(&st.export_recipients));
+    let res = stream_export(database.to_owned(), pass, format, Some(st.export_recipients.clone()), file)?;
+    log::info!(target: "audit", "backup database {database} by {ip}, format: {}", format.ext());
+    Ok(res)
+}
+
+/// 导出文件的名称及内容类型
+struct ExportName {
+    /// 下载的文件名, 加密时以`.age`/`.asc`结尾
+    name: String,
+    /// 加密前的文件名, 写入OpenPGP消息中
+    plain: String,
+    mime: &'static str,
+}
+
+impl ExportName {
+    /// * `name`: 导出文件名(不含扩展名), 诱饵数据库使用真实数据库的名称
+    fn new(name: &str, format: ExportFormat, recipients: Option<&Recipients>) -> Self {
+        let plain = format!("{name}.{}", format.ext());
+        match recipients {
+            Some(r) if !r.is_empty() => ExportName { name: format!("{plain}.{}", r.ext()), plain, mime: r.mime() },
+            _ => ExportName { name: plain.clone(), plain, mime: format.mime() },
+        }
+    }
+}
+
+/// 导出数据库到`out`, 指定接收者时加密导出的内容
+///
+/// * `plain`: 加密前的文件名
+/// * `progress`: 每条记录的进度回调, 见`aidb::export_database_with`
+fn export_to(database: &str, password: &str, format: ExportFormat, recipients: Option<&Recipients>, plain: &str,
+        mut out: &mut dyn Write, progress: &mut dyn FnMut(usize, usize) -> anyhow_ext::Result<()>)
+        -> anyhow_ext::Result<()> {
+    match recipients {
+        Some(r) if !r.is_empty() => r.encrypt_to(plain, out, &mut |mut w| {
+            aidb::export_database_with(database, password, format, &mut w, progress)
+        }),
+        _ => aidb::export_database_with(database, password, format, &mut out, progress),
+    }
+}
+
+/// 以附件下载的方式流式回复导出文件, 导出在阻塞线程中进行, 边导出边发送
+///
+/// 回复头发送后无法再返回错误, 因此先加载数据库, 口令错误等在回复前返回, 导出中途失败时中止回复
+fn stream_export(database: String, password: SecretString, format: ExportFormat,
+        recipients: Option<Arc<Recipients>>, file: ExportName) -> HttpResponse {
+    aidb::load_database(&database, &password)?;
+    let builder = hyper::Response::builder()
+        .header(CONTENT_TYPE, file.mime)
+        .header(CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", file.name));
+    let (res, tx) = StreamBody::response(builder, None)?;
+    tokio::task::spawn_blocking(move || {
+        let mut out = tx.writer();
+        if let Err(e) = export_to(&database, &password, format, recipients.as_deref(), &file.plain,
+                &mut out, &mut |_, _| Ok(())) {
+            log::error!("stream export of database {database} aborted: {e:?}");
+            out.abort();
+        }
+    });
+    Ok(res)
+}
+
+fn parse_format(format: Option<&str>) -> anyhow_ext::Result<ExportFormat> {
+    match format {
+        Some(f) => match ExportFormat::parse(f) {
+            Some(format) => Ok(format),
+            None => httpserver::http_bail!("不支持的导出格式"),
+        },
+        None => Ok(ExportFormat::Xml),
+    }
+}
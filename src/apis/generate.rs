@@ -0,0 +1,86 @@
+use httpserver::{HttpContext, HttpResponse, Resp};
+use serde::{Serialize, Deserialize};
+use crate::{aidb, generator::{self, GenOptions}, state::AppState};
+
+use super::service;
+
+/// 生成口令的最大长度
+const MAX_GEN_LENGTH: usize = 256;
+/// 口令短语的最大单词数量
+const MAX_GEN_WORDS: usize = 32;
+
+/// 账号生成类型
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+enum IdentityKind {
+    /// 随机用户名
+    #[default]
+    Username,
+    /// 基于`--email-base`的加号邮箱别名
+    Email,
+}
+
+/// 生成口令接口, 用于新建记录时生成随机口令, 指定分组时生成的口令符合分组的口令策略
+pub async fn generate(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize, Default)]
+    #[serde(rename_all = "camelCase")]
+    struct ReqParam {
+        #[serde(flatten)]
+        opts: GenOptions,
+        /// 分组id, 为空时不检查口令策略
+        #[serde(default)]
+        group: String,
+    }
+
+    #[derive(Serialize)]
+    struct ResData {
+        pass: String,
+    }
+
+    let req_param = ctx.parse_json_opt::<ReqParam>()?.unwrap_or_default();
+    let opts = req_param.opts;
+    httpserver::fail_if!(opts.length == 0 || opts.length > MAX_GEN_LENGTH,
+        "口令长度必须在1-{}之间", MAX_GEN_LENGTH);
+    httpserver::fail_if!(opts.words == 0 || opts.words > MAX_GEN_WORDS,
+        "单词数量必须在1-{}之间", MAX_GEN_WORDS);
+
+    let pass = if req_param.group.is_empty() {
+        generator::generate(&opts)
+    } else {
+        let (database, pass) = service::session_db(&ctx)?;
+        let db = aidb::load_database(database, &pass)?;
+        httpserver::fail_if!(db.group(&req_param.group).is_none(), "分组不存在");
+        db.group_policy(&req_param.group).generate(&opts)
+    };
+
+    Resp::ok(&ResData { pass })
+}
+
+/// 生成账号接口, 用于新建记录时生成唯一的用户名或者邮箱别名
+pub async fn identity(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize, Default)]
+    struct ReqParam {
+        #[serde(default)]
+        kind: IdentityKind,
+        /// 邮箱别名标签, 通常是网站名称
+        #[serde(default)]
+        tag: String,
+    }
+
+    #[derive(Serialize)]
+    struct ResData {
+        user: String,
+    }
+
+    let req_param = ctx.parse_json_opt::<ReqParam>()?.unwrap_or_default();
+    let user = match req_param.kind {
+        IdentityKind::Username => generator::generate_username(),
+        IdentityKind::Email => {
+            let st = AppState::from_ctx(&ctx)?;
+            httpserver::fail_if!(st.email_base.is_empty(), "未配置基础邮箱地址");
+            generator::email_alias(&st.email_base, &req_param.tag)?
+        }
+    };
+
+    Resp::ok(&ResData { user })
+}
@@ -0,0 +1,44 @@
+use std::sync::Arc;
+use httpserver::{HttpContext, HttpResponse, Resp};
+use serde::{Serialize, Deserialize};
+use crate::aidb::{self, Group, GroupDefaults};
+use super::{service, undo};
+
+/// 分组查询接口
+pub async fn group_list(ctx: HttpContext) -> HttpResponse {
+    #[derive(Serialize)]
+    struct ResData<'a> {
+        total: usize,
+        groups: &'a [Arc<Group>],
+    }
+
+    let (database, pass) = service::session_db(&ctx)?;
+    let db = aidb::load_database(database, &pass)?;
+
+    Resp::ok(&ResData { total: db.groups.len(), groups: &db.groups })
+}
+
+/// 设置分组缺省设置接口, 返回合并上级分组设置后的有效缺省设置
+pub async fn group_defaults(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    struct ReqParam {
+        id: String,
+        defaults: GroupDefaults,
+    }
+
+    let req_param = ctx.parse_json::<ReqParam>()?;
+    let defaults = undo::update_database(&ctx, "group/defaults", |db| {
+        let idx = match db.groups.iter().position(|g| g.id == req_param.id) {
+            Some(idx) => idx,
+            None => httpserver::http_bail!("分组不存在"),
+        };
+
+        let mut group = Group::clone(&db.groups[idx]);
+        group.defaults = req_param.defaults;
+        db.groups[idx] = Arc::new(group);
+
+        Ok(db.group_defaults(&req_param.id))
+    })?;
+
+    Resp::ok(&defaults)
+}
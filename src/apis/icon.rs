@@ -0,0 +1,108 @@
+use std::sync::Arc;
+use httpserver::{HttpContext, HttpResponse, Resp};
+use serde::{Serialize, Deserialize};
+use crate::aidb::{self, Attachment};
+use super::{service, undo};
+
+/// 自定义图标最大字节数
+const MAX_ICON_SIZE: usize = 64 * 1024;
+
+/// 获取所有自定义图标接口
+pub async fn icon_list(ctx: HttpContext) -> HttpResponse {
+    #[derive(Serialize)]
+    struct ResData<'a> {
+        total: usize,
+        icons: Vec<&'a Arc<Attachment>>,
+    }
+
+    let (database, pass) = service::session_db(&ctx)?;
+    let db = aidb::load_database(database, &pass)?;
+    let icons: Vec<_> = db.attachments.iter()
+        .filter(|a| a.is_icon())
+        .collect();
+
+    Resp::ok(&ResData { total: icons.len(), icons })
+}
+
+/// 获取指定的自定义图标接口
+pub async fn icon_get(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    struct ReqParam {
+        id: String,
+    }
+
+    let req_param = ctx.parse_json::<ReqParam>()?;
+    let (database, pass) = service::session_db(&ctx)?;
+    let db = aidb::load_database(database, &pass)?;
+
+    match db.attachment(&req_param.id) {
+        Some(icon) if icon.is_icon() => Resp::ok(icon.as_ref()),
+        _ => Resp::fail("图标不存在"),
+    }
+}
+
+/// 上传自定义图标接口, 图标数据为base64编码
+pub async fn icon_upload(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    struct ReqParam {
+        data: String,
+    }
+
+    #[derive(Serialize)]
+    struct ResData {
+        id: String,
+    }
+
+    let req_param = ctx.parse_json::<ReqParam>()?;
+    let icon = Attachment::new_icon(aidb::new_uuid(), req_param.data);
+    let size = match icon.decode() {
+        Ok(data) => data.len(),
+        Err(_) => httpserver::http_bail!("图标数据格式错误"),
+    };
+    httpserver::fail_if!(size == 0 || size > MAX_ICON_SIZE, "图标大小超出限制");
+
+    let id = icon.id.clone();
+    undo::update_database(&ctx, "icon/upload", |db| {
+        db.attachments.push(Arc::new(icon));
+        Ok(())
+    })?;
+
+    Resp::ok(&ResData { id })
+}
+
+/// 设置记录图标接口
+pub async fn icon_assign(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ReqParam {
+        id: String,
+        icon: Option<u32>,
+        custom_icon: Option<String>,
+    }
+
+    let req_param = ctx.parse_json::<ReqParam>()?;
+    undo::update_database(&ctx, "icon/assign", |db| {
+        let idx = match db.record_index(&req_param.id) {
+            Some(idx) => idx,
+            None => httpserver::http_bail!("记录不存在"),
+        };
+        if let Some(custom_icon) = &req_param.custom_icon {
+            httpserver::fail_if!(!custom_icon.is_empty() && !db.attachment(custom_icon).is_some_and(|a| a.is_icon()),
+                "图标不存在");
+        }
+
+        let mut rec = aidb::Record::clone(&db.records[idx]);
+        if let Some(icon) = req_param.icon {
+            rec.icon = icon;
+        }
+        if let Some(custom_icon) = req_param.custom_icon {
+            rec.custom_icon = custom_icon.into();
+        }
+        rec.modified = localtime::unix_timestamp();
+        db.records[idx] = Arc::new(rec);
+
+        Ok(())
+    })?;
+
+    Resp::ok_with_empty()
+}
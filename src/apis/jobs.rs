@@ -0,0 +1,68 @@
+use httpserver::{Bytes, HttpContext, HttpResponse, Resp, StreamBody, CONTENT_TYPE};
+use hyper::header::CONTENT_DISPOSITION;
+use crate::jobs::{self, JobOutput, JobOwner};
+use super::authentication::Authentication;
+
+/// 任务列表接口, 只返回当前会话创建的任务
+pub async fn job_list(ctx: HttpContext) -> HttpResponse {
+    Resp::ok(&jobs::list(&job_owner(&ctx)))
+}
+
+/// 任务状态接口, 返回进度及完成状态
+pub async fn job_get(ctx: HttpContext) -> HttpResponse {
+    match jobs::get(job_id(&ctx)?, &job_owner(&ctx)) {
+        Some(job) => Resp::ok(&job.status()),
+        None => httpserver::http_bail!("任务不存在"),
+    }
+}
+
+/// 文件结果每次发送的数据块大小
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// 任务结果接口, json结果直接返回, 文件结果以附件下载的方式返回, 结果下载后任务即删除
+pub async fn job_result(ctx: HttpContext) -> HttpResponse {
+    let output = match jobs::take_output(job_id(&ctx)?, &job_owner(&ctx)) {
+        Some(Some(output)) => output,
+        Some(None) => httpserver::http_bail!("任务尚未完成"),
+        None => httpserver::http_bail!("任务不存在"),
+    };
+
+    match output {
+        JobOutput::Json(value) => Resp::ok(&value),
+        JobOutput::File { name, mime, data } => {
+            // 文件内容分块发送, 发送结束后随缓冲区一起清零, 不复制出完整的明文
+            let builder = hyper::Response::builder()
+                .header(CONTENT_TYPE, mime)
+                .header(CONTENT_DISPOSITION, format!("attachment; filename=\"{name}\""));
+            let (res, tx) = StreamBody::response(builder, Some(data.len() as u64))?;
+            tokio::spawn(async move {
+                for chunk in data.chunks(CHUNK_SIZE) {
+                    // 客户端已断开
+                    if !tx.send(Bytes::copy_from_slice(chunk)).await {
+                        break;
+                    }
+                }
+            });
+            Ok(res)
+        }
+    }
+}
+
+/// 取消任务接口, 已结束的任务同时删除其结果
+pub async fn job_cancel(ctx: HttpContext) -> HttpResponse {
+    httpserver::fail_if!(!jobs::cancel(job_id(&ctx)?, &job_owner(&ctx)), "任务不存在");
+    Resp::ok_with_empty()
+}
+
+/// 当前请求所属的会话, 用于创建及查询任务
+pub(super) fn job_owner(ctx: &HttpContext) -> JobOwner {
+    JobOwner { database: ctx.uid.clone(), session: Authentication::get_session_id(ctx) }
+}
+
+/// 路径参数中的任务id
+fn job_id(ctx: &HttpContext) -> anyhow_ext::Result<u64> {
+    match ctx.params.get("id").and_then(|id| id.parse().ok()) {
+        Some(id) => Ok(id),
+        None => httpserver::http_bail!("任务id格式错误"),
+    }
+}
@@ -1,11 +1,102 @@
 mod web;
-pub use web::default_handler;
+pub use web::default_chain;
 
 mod authentication;
 pub use authentication::Authentication;
+pub use authentication::LoginGuard;
+
+mod token;
+pub use token::set_shared_secret;
 
 mod service;
 pub use service::ping;
+pub use service::health;
+pub use service::ready;
 pub use service::login;
 pub use service::logout;
+pub use service::refresh;
+pub use service::keepalive;
+pub use service::step_up;
+pub use service::change_password;
 pub use service::list;
+pub use service::search;
+pub use service::suggest;
+pub use service::stats;
+pub use service::flush_stats;
+
+mod icon;
+pub use icon::icon_list;
+pub use icon::icon_get;
+pub use icon::icon_upload;
+pub use icon::icon_assign;
+
+mod group;
+pub use group::group_list;
+pub use group::group_defaults;
+
+mod record;
+pub use record::record_create;
+pub use record::record_get;
+pub use record::record_reveal;
+pub use record::record_update;
+pub use record::record_delete;
+pub use record::record_notes;
+pub use record::record_totp;
+pub use record::record_history;
+pub use record::RecordLimits;
+pub use record::records_bulk;
+
+mod attachment;
+pub use attachment::attachment_upload;
+pub use attachment::attachment_download;
+pub use attachment::attachment_delete;
+
+mod comment;
+pub use comment::record_comment;
+
+mod share;
+pub use share::share_create;
+pub use share::share_open;
+pub use share::recycle_shares;
+pub use share::SharePage;
+
+mod policy;
+pub use policy::policy_get;
+pub use policy::policy_set;
+pub use policy::policy_strength;
+
+mod template;
+pub use template::template_list;
+pub use template::template_export;
+pub use template::template_import;
+
+mod generate;
+pub use generate::identity;
+pub use generate::generate;
+
+mod audit;
+pub use audit::audit;
+
+mod export;
+pub use export::{backup, export};
+
+mod jobs;
+pub use jobs::job_list;
+pub use jobs::job_get;
+pub use jobs::job_result;
+pub use jobs::job_cancel;
+
+mod admin;
+pub use admin::admin_config;
+pub use admin::admin_access_stats_export;
+pub use admin::admin_proxy;
+
+mod shadow;
+pub use shadow::new_shadow;
+
+mod undo;
+pub use undo::undo;
+pub use undo::recycle_undo;
+
+mod push;
+pub use push::{events, ws};
@@ -0,0 +1,75 @@
+use std::sync::Arc;
+use httpserver::{ApiReply, HttpContext, HttpResponse, Json, Resp};
+use serde::Deserialize;
+use crate::{aidb::{self, Group}, policy::{Policy, MAX_MIN_LENGTH}, strength};
+use super::{service, undo};
+
+/// 查询口令策略接口, 指定分组时返回该分组的有效口令策略, 否则返回全局口令策略
+pub async fn policy_get(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize, Default)]
+    struct ReqParam {
+        group: Option<String>,
+    }
+
+    let req_param = ctx.parse_json_opt::<ReqParam>()?.unwrap_or_default();
+    let (database, pass) = service::session_db(&ctx)?;
+    let db = aidb::load_database(database, &pass)?;
+
+    let policy = match &req_param.group {
+        Some(group) => {
+            httpserver::fail_if!(db.group(group).is_none(), "分组不存在");
+            db.group_policy(group)
+        },
+        None => &db.policy,
+    };
+
+    Resp::ok(policy)
+}
+
+/// 设置口令策略接口, 指定分组时设置该分组的口令策略(策略为空表示取消分组策略), 否则设置全局口令策略
+pub async fn policy_set(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    struct ReqParam {
+        group: Option<String>,
+        policy: Option<Policy>,
+    }
+
+    let req_param = ctx.parse_json::<ReqParam>()?;
+    if let Some(policy) = &req_param.policy {
+        httpserver::fail_if!(policy.min_strength > 4, "口令最低强度得分必须在0~4之间");
+        httpserver::fail_if!(policy.min_length > MAX_MIN_LENGTH, "口令最小长度不能超过{}", MAX_MIN_LENGTH);
+    }
+    undo::update_database(&ctx, "policy/set", |db| {
+        match &req_param.group {
+            Some(id) => {
+                let idx = match db.groups.iter().position(|g| &g.id == id) {
+                    Some(idx) => idx,
+                    None => httpserver::http_bail!("分组不存在"),
+                };
+                let mut group = Group::clone(&db.groups[idx]);
+                group.policy = req_param.policy;
+                db.groups[idx] = Arc::new(group);
+            },
+            None => db.policy = req_param.policy.unwrap_or_default(),
+        }
+        Ok(())
+    })?;
+
+    Resp::ok_with_empty()
+}
+
+/// 口令强度评估接口的请求参数
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StrengthParam {
+    pass: String,
+    /// 与口令相关的用户信息(标题、用户名等), 口令包含这些内容时降低得分
+    #[serde(default)]
+    user_inputs: Vec<String>,
+}
+
+/// 口令强度评估接口, 返回得分、破解时间估算及改进建议
+pub async fn policy_strength(Json(req_param): Json<StrengthParam>) -> ApiReply<strength::Strength> {
+    let inputs: Vec<&str> = req_param.user_inputs.iter().map(|s| s.as_str()).collect();
+    Ok(strength::estimate(&req_param.pass, &inputs))
+}
@@ -0,0 +1,300 @@
+//! 推送连接, 客户端通过WebSocket或SSE接收数据库被修改、会话即将过期等通知
+use std::{collections::HashMap, net::Ipv4Addr, sync::Arc, time::Duration};
+
+use compact_str::CompactString;
+use httpserver::{HttpContext, HttpResponse, Message, Resp, Sse, SseSender, WebSocket, CLOSE_NORMAL};
+use parking_lot::Mutex;
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{events::{self, Event}, state::AppState};
+use super::Authentication;
+
+/// 等待客户端发送令牌的超时时间
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+/// 心跳间隔, 避免空闲连接被代理断开
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+/// 会话过期前提前通知的时间(单位: 秒)
+const EXPIRE_NOTICE: u64 = 60;
+/// 令牌无效时的关闭码(policy violation)
+const CLOSE_UNAUTHORIZED: u16 = 1008;
+/// 尚未发送令牌的连接的最大数量, 避免大量空连接占用资源
+const MAX_PENDING: u32 = 256;
+/// 每个客户端地址尚未发送令牌的连接的最大数量
+const MAX_PENDING_PER_IP: u32 = 8;
+
+/// 尚未发送令牌的连接数量, key: 客户端地址
+static PENDING: Mutex<Option<HashMap<Ipv4Addr, u32>>> = Mutex::new(None);
+
+/// 尚未发送令牌的连接的计数, 认证结束(释放)时减少计数
+struct PendingGuard(Ipv4Addr);
+
+/// 客户端发送的令牌, 连接建立后及刷新令牌后发送
+#[derive(Deserialize)]
+struct ClientMessage {
+    token: String,
+}
+
+/// 推送连接绑定的会话
+struct PushSession {
+    token: String,
+    /// 会话id, 用于跳过本会话自身的修改
+    id: u128,
+    /// 数据库名称, 胁迫密码登录的会话为诱饵数据库的名称, 用于匹配事件
+    database: CompactString,
+    /// 返回给客户端的数据库名称, 见`AppState::display_name`
+    display: CompactString,
+    /// 会话过期时间(unix时间戳)
+    expire: u64,
+    /// 是否已发送即将过期的通知
+    notified: bool,
+}
+
+/// WebSocket推送接口
+///
+/// 连接建立后客户端需在10秒内发送`{"token":"..."}`, 刷新令牌后再次发送新令牌;
+/// 服务端推送`{"type":"recordChanged",...}`等事件, 不推送本会话自身的修改
+pub async fn ws(mut ctx: HttpContext) -> HttpResponse {
+    let st = AppState::from_ctx(&ctx)?.clone();
+    let ip = ctx.remote_ip();
+    let pending = match PendingGuard::acquire(ip) {
+        Some(guard) => guard,
+        None => {
+            log::warn!("too many unauthenticated push connections, rejected {ip}");
+            const SERVICE_UNAVAILABLE: hyper::StatusCode = hyper::StatusCode::SERVICE_UNAVAILABLE;
+            return Resp::fail_with_status(SERVICE_UNAVAILABLE, SERVICE_UNAVAILABLE.as_u16() as u32,
+                "推送连接过多, 请稍后重试");
+        }
+    };
+    WebSocket::upgrade(&mut ctx, move |ws| serve(ws, st, ip, pending))
+}
+
+async fn serve(mut ws: WebSocket, st: Arc<AppState>, ip: Ipv4Addr, pending: PendingGuard) {
+    let auth = tokio::time::timeout(AUTH_TIMEOUT, authenticate(&mut ws, &st, ip)).await;
+    drop(pending);
+    let mut session = match auth {
+        Ok(Some(session)) => session,
+        _ => {
+            let _ = ws.close(CLOSE_UNAUTHORIZED, "unauthorized").await;
+            return;
+        }
+    };
+    log::debug!("push connection of database {} from {ip} established", session.database);
+
+    let mut rx = events::subscribe();
+    let mut ping = tokio::time::interval(PING_INTERVAL);
+    ping.tick().await;
+
+    loop {
+        tokio::select! {
+            msg = ws.recv() => match msg {
+                // 刷新令牌后客户端发送新令牌
+                Ok(Some(Message::Text(text))) => {
+                    let token = serde_json::from_str::<ClientMessage>(&text).ok().map(|m| m.token);
+                    match token.and_then(|token| bind(&st, token, ip)) {
+                        Some(s) => session = s,
+                        None => {
+                            let _ = ws.close(CLOSE_UNAUTHORIZED, "unauthorized").await;
+                            break;
+                        }
+                    }
+                }
+                Ok(Some(_)) => {}
+                Ok(None) | Err(_) => break,
+            },
+            event = rx.recv() => match event {
+                Ok(event) => {
+                    if session.is_visible(&event) && !send(&mut ws, &session.present(event)).await {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(n)) => log::debug!("push connection from {ip} lagged, {n} events dropped"),
+                Err(RecvError::Closed) => break,
+            },
+            _ = ping.tick() => {
+                if ws.send(Message::Ping(Vec::new())).await.is_err() {
+                    break;
+                }
+            }
+            _ = session.timer() => match session.check_expire(ip) {
+                Some(event @ Event::SessionExpired) => {
+                    send(&mut ws, &event).await;
+                    let _ = ws.close(CLOSE_NORMAL, "session expired").await;
+                    break;
+                }
+                Some(event) => {
+                    if !send(&mut ws, &event).await {
+                        break;
+                    }
+                }
+                None => {}
+            },
+        }
+    }
+
+    log::debug!("push connection of database {} from {ip} closed", session.database);
+}
+
+/// SSE推送接口
+///
+/// 与WebSocket推送相同的事件, 通过请求头(或者启用`--token-query`时的url参数)携带会话令牌,
+/// 事件名称为事件类型, 数据为事件的json, 会话过期后结束事件流
+pub async fn events(ctx: HttpContext) -> HttpResponse {
+    let st = AppState::from_ctx(&ctx)?.clone();
+    let ip = ctx.remote_ip();
+    let token = Authentication::session_token(&ctx).map(|t| t.into_owned()).unwrap_or_default();
+    // 认证中间件已校验令牌, 此处失败时为数据库不可用或者不在允许访问的时间段内
+    let session = match bind(&st, token, ip) {
+        Some(session) => session,
+        None => {
+            const UNAUTHORIZED: hyper::StatusCode = hyper::StatusCode::UNAUTHORIZED;
+            return Resp::fail_with_status(UNAUTHORIZED, UNAUTHORIZED.as_u16() as u32, UNAUTHORIZED.as_str());
+        }
+    };
+    Sse::response(move |sse| stream(sse, session, ip))
+}
+
+async fn stream(sse: SseSender, mut session: PushSession, ip: Ipv4Addr) {
+    log::debug!("event stream of database {} from {ip} established", session.database);
+    let mut rx = events::subscribe();
+
+    loop {
+        tokio::select! {
+            event = rx.recv() => match event {
+                Ok(event) => {
+                    if session.is_visible(&event) && !sse.send_json(event.name(), &session.present(event)).await {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(n)) => log::debug!("event stream from {ip} lagged, {n} events dropped"),
+                Err(RecvError::Closed) => break,
+            },
+            _ = session.timer() => match session.check_expire(ip) {
+                Some(event @ Event::SessionExpired) => {
+                    sse.send_json(event.name(), &event).await;
+                    break;
+                }
+                Some(event) => {
+                    if !sse.send_json(event.name(), &event).await {
+                        break;
+                    }
+                }
+                None => {}
+            },
+            _ = sse.closed() => break,
+        }
+    }
+
+    log::debug!("event stream of database {} from {ip} closed", session.database);
+}
+
+/// 等待客户端发送令牌并校验
+async fn authenticate(ws: &mut WebSocket, st: &AppState, ip: Ipv4Addr) -> Option<PushSession> {
+    loop {
+        match ws.recv().await {
+            Ok(Some(Message::Text(text))) => {
+                let msg: ClientMessage = serde_json::from_str(&text).ok()?;
+                return bind(st, msg.token, ip);
+            }
+            Ok(Some(_)) => {}
+            Ok(None) | Err(_) => return None,
+        }
+    }
+}
+
+impl PendingGuard {
+    /// 增加计数, 超过总数或者单个地址的上限时返回None
+    fn acquire(ip: Ipv4Addr) -> Option<Self> {
+        let mut pending = PENDING.lock();
+        let pending = pending.get_or_insert_with(HashMap::new);
+        if pending.values().sum::<u32>() >= MAX_PENDING {
+            return None;
+        }
+        let count = pending.entry(ip).or_default();
+        if *count >= MAX_PENDING_PER_IP {
+            return None;
+        }
+        *count += 1;
+        Some(PendingGuard(ip))
+    }
+}
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        if let Some(pending) = PENDING.lock().as_mut() {
+            if let Some(count) = pending.get_mut(&self.0) {
+                *count -= 1;
+                if *count == 0 {
+                    pending.remove(&self.0);
+                }
+            }
+        }
+    }
+}
+
+/// 校验令牌, 返回令牌对应的会话
+fn bind(st: &AppState, token: String, ip: Ipv4Addr) -> Option<PushSession> {
+    let (id, db, expire) = Authentication::verify_token(&token, ip)?;
+    let file = st.database_by_id(db)?;
+    let display = CompactString::new(st.display_name(file));
+    if !st.access_windows.allowed_now(&display) {
+        return None;
+    }
+    let database = CompactString::new(AppState::database_name(file));
+    Some(PushSession { token, id, database, display, expire, notified: false })
+}
+
+impl PushSession {
+    /// 事件是否推送给该会话, 只推送同一数据库的事件, 且不推送本会话自身的修改
+    fn is_visible(&self, event: &Event) -> bool {
+        if event.database() != Some(self.database.as_str()) {
+            return false;
+        }
+        !matches!(event, Event::RecordChanged { origin: Some(id), .. } if *id == self.id)
+    }
+
+    /// 推送给客户端的事件, 数据库名称替换为返回给客户端的名称
+    fn present(&self, event: Event) -> Event {
+        if self.display == self.database { event } else { event.with_database(&self.display) }
+    }
+
+    /// 等待到下一次需要检查会话过期的时间: 未通知时为过期前1分钟, 已通知时为过期时间
+    fn timer(&self) -> tokio::time::Sleep {
+        let now = localtime::unix_timestamp();
+        let wake = if self.notified { self.expire } else { self.expire.saturating_sub(EXPIRE_NOTICE) };
+        tokio::time::sleep(Duration::from_secs(wake.saturating_sub(now)))
+    }
+
+    /// 检查会话是否即将过期或者已过期
+    ///
+    /// Returns:
+    ///
+    /// 需要推送的会话事件, 会话被其它请求延长或者已通知过时返回None
+    fn check_expire(&mut self, ip: Ipv4Addr) -> Option<Event> {
+        let now = localtime::unix_timestamp();
+        match Authentication::verify_token(&self.token, ip) {
+            // 其它请求延长了会话的空闲过期时间
+            Some((_, _, expire)) if expire > self.expire => {
+                self.expire = expire;
+                self.notified = false;
+                None
+            }
+            Some(_) if now < self.expire => {
+                if self.notified {
+                    return None;
+                }
+                self.notified = true;
+                Some(Event::SessionExpiring { expire: self.expire })
+            }
+            _ => Some(Event::SessionExpired),
+        }
+    }
+}
+
+/// 发送事件, 发送失败时返回false
+async fn send(ws: &mut WebSocket, event: &Event) -> bool {
+    match serde_json::to_string(event) {
+        Ok(text) => ws.send_text(&text).await.is_ok(),
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,642 @@
+use std::{collections::HashSet, fmt::Display, net::Ipv4Addr, sync::Arc};
+use httpserver::{Fields, HttpContext, HttpResponse, Resp, Sparse};
+use hyper::StatusCode;
+use parking_lot::Mutex;
+use accinfo_api::{NewRecord, RecordUpdate};
+use anyhow_ext::Result;
+use serde::{Serialize, Deserialize};
+use crate::{aidb::{self, Database, IStr, Record, RecordField, Sealed, SecretString}, policy::Policy, state::AppState, totp::Totp};
+use super::{authentication::Authentication, service, undo};
+
+/// 每条记录的最大标签数量
+const MAX_TAGS: usize = 64;
+
+/// 记录的长度限制(单位: 字节), 0表示不限制
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RecordLimits {
+    /// 标题、用户名及网址的最大长度
+    pub max_field_len: usize,
+    /// 备注的最大长度
+    pub max_notes_len: usize,
+    /// 整条记录的最大长度
+    pub max_record_size: usize,
+}
+
+/// 记录超出长度限制的错误, 接口以对应的http状态码回复
+#[derive(Debug)]
+struct LimitExceeded {
+    status: StatusCode,
+    message: String,
+}
+
+impl std::error::Error for LimitExceeded {}
+
+impl Display for LimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl RecordLimits {
+    /// 检查记录是否超出长度限制, 单项超长回复400, 整条记录超长回复413
+    ///
+    /// 所有写入记录的途径(新建、修改、评论、导入及合并)都通过该方法校验,
+    /// 自定义字段名按单项长度限制, 字段内容按备注长度限制
+    pub fn check(&self, rec: &Record) -> Result<()> {
+        let fields = [("标题", rec.title.len()), ("用户名", rec.user.len()), ("网址", rec.url.len())];
+        if self.max_field_len > 0 {
+            let custom = rec.fields.iter().map(|f| ("自定义字段名", f.name.len()));
+            if let Some((name, _)) = fields.into_iter().chain(custom).find(|(_, len)| *len > self.max_field_len) {
+                return Err(LimitExceeded {
+                    status: StatusCode::BAD_REQUEST,
+                    message: format!("{name}长度不能超过{}字节", self.max_field_len),
+                }.into());
+            }
+        }
+        if self.max_notes_len > 0 {
+            let custom = rec.fields.iter().map(|f| ("自定义字段", f.plain_len()));
+            let notes = std::iter::once(("备注", rec.notes.plain_len()));
+            if let Some((name, _)) = notes.chain(custom).find(|(_, len)| *len > self.max_notes_len) {
+                return Err(LimitExceeded {
+                    status: StatusCode::BAD_REQUEST,
+                    message: format!("{name}长度不能超过{}字节", self.max_notes_len),
+                }.into());
+            }
+        }
+        if self.max_record_size > 0 && rec.data_size() > self.max_record_size {
+            return Err(LimitExceeded {
+                status: StatusCode::PAYLOAD_TOO_LARGE,
+                message: format!("记录大小不能超过{}字节", self.max_record_size),
+            }.into());
+        }
+        Ok(())
+    }
+}
+
+/// 回复新建或修改后的记录, 超出长度限制的错误以对应的http状态码回复, 其它错误按原样返回
+fn record_response(res: Result<Arc<Record>>) -> HttpResponse {
+    limit_response(res, |rec| Resp::ok(rec.as_ref()))
+}
+
+/// 成功时使用`ok`生成回复, 超出长度限制的错误以对应的http状态码回复, 其它错误按原样返回
+pub(super) fn limit_response<T, F: FnOnce(T) -> HttpResponse>(res: Result<T>, ok: F) -> HttpResponse {
+    match res {
+        Ok(v) => ok(v),
+        Err(e) => match e.downcast::<LimitExceeded>() {
+            Ok(e) => Resp::fail_with_status(e.status, e.status.as_u16() as u32, &e.message),
+            Err(e) => Err(e),
+        },
+    }
+}
+
+/// 新建记录接口, 未填写的项继承所属分组的缺省设置
+pub async fn record_create(ctx: HttpContext) -> HttpResponse {
+    let req_param = ctx.parse_json::<NewRecord>()?;
+    httpserver::validate!(req_param,
+        title: required(),
+        tags: length(0..=MAX_TAGS),
+        otp: custom(|otp: &String| check_otp(otp)),
+        fields: custom(|fields: &Vec<accinfo_api::RecordField>| check_fields(fields)),
+    );
+    let limits = AppState::from_ctx(&ctx)?.record_limits;
+
+    let rec = undo::update_database(&ctx, "record/create", |db| {
+        httpserver::fail_if!(!req_param.group.is_empty() && db.group(&req_param.group).is_none(),
+            "分组不存在");
+        let now = localtime::unix_timestamp();
+
+        let mut rec = Record {
+            id: aidb::new_uuid(),
+            title: req_param.title,
+            user: req_param.user.into(),
+            pass: req_param.pass.into(),
+            url: req_param.url.into(),
+            notes: req_param.notes.into(),
+            group: req_param.group.into(),
+            tags: req_param.tags.into_iter().map(IStr::from).collect(),
+            expire: req_param.expire,
+            modified: now,
+            created: now,
+            sensitive: req_param.sensitive,
+            otp: Sealed::new(req_param.otp.trim()),
+            fields: req_param.fields.into_iter().map(RecordField::from).collect(),
+            ..Default::default()
+        };
+        let policy = db.group_policy(&rec.group);
+        db.group_defaults(&rec.group).apply(&mut rec, policy);
+        check_policy(policy, &rec.pass.reveal_secret(), &[&rec.title, &rec.user])?;
+        limits.check(&rec)?;
+
+        let rec = Arc::new(rec);
+        db.records.push(rec.clone());
+        Ok(rec)
+    });
+
+    record_response(rec)
+}
+
+/// 修改记录接口, 只修改请求中提供的项
+pub async fn record_update(ctx: HttpContext) -> HttpResponse {
+    let req_param = ctx.parse_json::<RecordUpdate>()?;
+    httpserver::validate!(req_param,
+        id: required(),
+        title: custom(|title: &Option<String>| match title {
+            Some(title) if title.is_empty() => Err("不能为空".to_owned()),
+            _ => Ok(()),
+        }),
+        tags: length(0..=MAX_TAGS),
+        otp: custom(|otp: &Option<String>| otp.as_deref().map_or(Ok(()), check_otp)),
+        fields: custom(|fields: &Option<Vec<accinfo_api::RecordField>>| fields.as_deref().map_or(Ok(()), check_fields)),
+    );
+    let limits = AppState::from_ctx(&ctx)?.record_limits;
+
+    let rec = undo::update_database(&ctx, "record/update", |db| {
+        let idx = match db.record_index(&req_param.id) {
+            Some(idx) => idx,
+            None => httpserver::http_bail!("记录不存在"),
+        };
+        if let Some(group) = &req_param.group {
+            httpserver::fail_if!(!group.is_empty() && db.group(group).is_none(), "分组不存在");
+        }
+
+        let mut rec = Record::clone(&db.records[idx]);
+        if let Some(group) = req_param.group { rec.group = group.into(); }
+        if let Some(title) = req_param.title { rec.title = title; }
+        if let Some(user) = req_param.user { rec.user = user.into(); }
+        if let Some(url) = req_param.url { rec.url = url.into(); }
+        if let Some(notes) = req_param.notes { rec.notes = notes.into(); }
+        if let Some(tags) = req_param.tags { rec.tags = tags.into_iter().map(IStr::from).collect(); }
+        if let Some(expire) = req_param.expire { rec.expire = expire; }
+        if let Some(sensitive) = req_param.sensitive { rec.sensitive = sensitive; }
+        if let Some(otp) = req_param.otp { rec.otp = Sealed::new(otp.trim()); }
+        if let Some(fields) = req_param.fields {
+            // 受保护字段未修改时客户端提交的是脱敏后的内容, 保留原来的值
+            rec.fields = fields.into_iter().map(|f| {
+                let masked = f.protected && f.value == aidb::MASKED_PASS;
+                match rec.fields.iter().find(|old| masked && old.name == f.name && old.is_protected()) {
+                    Some(old) => RecordField { name: f.name, value: old.value.clone() },
+                    None => RecordField::from(f),
+                }
+            }).collect();
+        }
+        if let Some(pass) = req_param.pass {
+            if *rec.pass.reveal_secret() != *pass {
+                check_policy(db.group_policy(&rec.group), &pass, &[&rec.title, &rec.user])?;
+                rec.pass = pass.into();
+            }
+        }
+        rec.push_history(&db.records[idx]);
+        rec.modified = localtime::unix_timestamp();
+        limits.check(&rec)?;
+
+        let rec = Arc::new(rec);
+        db.records[idx] = rec.clone();
+        Ok(rec)
+    });
+
+    record_response(rec)
+}
+
+/// 每个客户端每分钟最多查看口令的次数
+const MAX_REVEAL_PER_MINUTE: u32 = 10;
+
+/// 查看口令的限流统计, (当前分钟, [(客户端ip, 次数)])
+static REVEAL_LIMITS: Mutex<(u64, Vec<(Ipv4Addr, u32)>)> = Mutex::new((0, Vec::new()));
+
+/// 获取记录详情接口, 返回记录的所有字段(口令已脱敏), 支持url参数`fields`只返回指定字段
+pub async fn record_get(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    struct ReqParam {
+        id: String,
+    }
+
+    let req_param = ctx.parse_json::<ReqParam>()?;
+    let (database, pass) = service::session_db(&ctx)?;
+    let db = aidb::load_database(database, &pass)?;
+
+    match db.records.iter().find(|r| r.id == req_param.id) {
+        Some(rec) => {
+            aidb::stats_read(database, [rec.id.as_str()]);
+            Resp::ok(&Sparse::new(&rec.masked(), Fields::from_ctx(&ctx).as_ref()))
+        }
+        None => Resp::fail("记录不存在"),
+    }
+}
+
+/// 查看记录口令接口, 按客户端限流, 每次调用都记录审计日志, 敏感记录需要二次验证
+pub async fn record_reveal(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    struct ReqParam {
+        id: String,
+    }
+
+    #[derive(Serialize)]
+    struct ResData<'a> {
+        pass: &'a str,
+        /// 受保护的自定义字段
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        fields: Vec<ProtectedField<'a>>,
+    }
+
+    #[derive(Serialize)]
+    struct ProtectedField<'a> {
+        name: &'a str,
+        value: SecretString,
+        protected: bool,
+    }
+
+    let req_param = ctx.parse_json::<ReqParam>()?;
+    let ip = ctx.remote_ip();
+    if !check_reveal_limit(ip) {
+        log::warn!(target: "audit", "reveal password of record {} by {ip} rejected: too many requests", req_param.id);
+        return Resp::fail_with_status(StatusCode::TOO_MANY_REQUESTS, 429, "查看口令过于频繁, 请稍后再试");
+    }
+
+    let (database, pass) = service::session_db(&ctx)?;
+    let db = aidb::load_database(database, &pass)?;
+
+    match db.records.iter().find(|r| r.id == req_param.id) {
+        Some(rec) if rec.sensitive && !Authentication::check_step_up(&ctx)? => {
+            log::warn!(target: "audit", "reveal password of record {} by {ip} rejected: step-up required", rec.id);
+            Authentication::step_up_required()
+        }
+        Some(rec) => {
+            log::info!(target: "audit", "reveal password of record {} [{}] by {ip}", rec.id, rec.title);
+            aidb::stats_read(database, [rec.id.as_str()]);
+            let pass = rec.pass.reveal_secret();
+            let fields = rec.fields.iter().filter(|f| f.is_protected())
+                .map(|f| ProtectedField { name: &f.name, value: f.reveal(), protected: true })
+                .collect();
+            Resp::ok(&ResData { pass: &pass, fields })
+        }
+        None => Resp::fail("记录不存在"),
+    }
+}
+
+/// 记录历史版本接口, 路径参数为记录id, 按修改时间从新到旧返回
+///
+/// 口令已脱敏, 只返回与下一个版本相比口令是否有修改, 历史备注为明文, 敏感记录需要二次验证
+pub async fn record_history(ctx: HttpContext) -> HttpResponse {
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Version<'a> {
+        modified: u64,
+        title: &'a str,
+        user: &'a str,
+        pass: &'a str,
+        url: &'a str,
+        notes: String,
+        pass_changed: bool,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ResData<'a> {
+        id: &'a str,
+        created: u64,
+        modified: u64,
+        versions: Vec<Version<'a>>,
+    }
+
+    let id = match ctx.params.get("id") {
+        Some(id) if !id.is_empty() => id,
+        _ => httpserver::http_bail!("记录id不能为空"),
+    };
+    let (database, pass) = service::session_db(&ctx)?;
+    let db = aidb::load_database(database, &pass)?;
+    let rec = match db.records.iter().find(|r| r.id == id) {
+        Some(rec) => rec,
+        None => httpserver::http_bail!("记录不存在"),
+    };
+    if rec.sensitive && !Authentication::check_step_up(&ctx)? {
+        log::warn!(target: "audit", "history of record {} by {} rejected: step-up required", rec.id, ctx.remote_ip());
+        return Authentication::step_up_required();
+    }
+
+    let versions = rec.history.iter().enumerate().rev()
+        .map(|(i, v)| {
+            let next_pass = rec.history.get(i + 1).map(|n| &n.pass).unwrap_or(&rec.pass);
+            Version {
+                modified: v.modified,
+                title: &v.title,
+                user: &v.user,
+                pass: if v.pass.is_empty() { "" } else { aidb::MASKED_PASS },
+                url: &v.url,
+                notes: v.notes.reveal(),
+                pass_changed: !v.pass.same_plain(next_pass),
+            }
+        })
+        .collect();
+
+    Resp::ok(&ResData { id: &rec.id, created: rec.created, modified: rec.modified, versions })
+}
+
+/// 获取记录一次性口令接口, 在服务端计算当前的验证码, 密钥不会返回给客户端
+pub async fn record_totp(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    struct ReqParam {
+        id: String,
+    }
+
+    #[derive(Serialize)]
+    struct ResData {
+        code: String,
+        /// 验证码剩余的有效时间(单位: 秒)
+        remaining: u64,
+        /// 验证码的更新周期(单位: 秒)
+        period: u64,
+    }
+
+    let req_param = ctx.parse_json::<ReqParam>()?;
+    let (database, pass) = service::session_db(&ctx)?;
+    let db = aidb::load_database(database, &pass)?;
+
+    let rec = match db.records.iter().find(|r| r.id == req_param.id) {
+        Some(rec) => rec,
+        None => httpserver::http_bail!("记录不存在"),
+    };
+    httpserver::fail_if!(rec.otp.is_empty(), "记录没有设置一次性口令");
+    let totp = match Totp::parse(&rec.otp.reveal()) {
+        Ok(totp) => totp,
+        Err(e) => {
+            log::warn!("parse otp of record {} error: {e:?}", rec.id);
+            httpserver::http_bail!("一次性口令格式错误");
+        }
+    };
+
+    let now = localtime::unix_timestamp();
+    log::info!(target: "audit", "generate totp of record {} [{}] by {}", rec.id, rec.title, ctx.remote_ip());
+    aidb::stats_read(database, [rec.id.as_str()]);
+    Resp::ok(&ResData { code: totp.code(now), remaining: totp.remaining(now), period: totp.period })
+}
+
+/// 获取记录备注接口, 备注在内存中加密保存, 只在此时解密, 敏感记录需要二次验证
+pub async fn record_notes(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    struct ReqParam {
+        id: String,
+    }
+
+    #[derive(Serialize)]
+    struct ResData {
+        notes: String,
+    }
+
+    let req_param = ctx.parse_json::<ReqParam>()?;
+    let (database, pass) = service::session_db(&ctx)?;
+    let db = aidb::load_database(database, &pass)?;
+
+    match db.records.iter().find(|r| r.id == req_param.id) {
+        Some(rec) if rec.sensitive && !Authentication::check_step_up(&ctx)? => {
+            log::warn!(target: "audit", "notes of record {} by {} rejected: step-up required", rec.id, ctx.remote_ip());
+            Authentication::step_up_required()
+        }
+        Some(rec) => Resp::ok(&ResData { notes: rec.notes.reveal() }),
+        None => Resp::fail("记录不存在"),
+    }
+}
+
+/// 删除记录接口
+pub async fn record_delete(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    struct ReqParam {
+        id: String,
+    }
+
+    let req_param = ctx.parse_json::<ReqParam>()?;
+    undo::update_database(&ctx, "record/delete", |db| {
+        match db.record_index(&req_param.id) {
+            Some(idx) => db.records.remove(idx),
+            None => httpserver::http_bail!("记录不存在"),
+        };
+        db.prune_attachments();
+        Ok(())
+    })?;
+
+    Resp::ok_with_empty()
+}
+
+/// 批量操作记录接口, 所有记录校验通过后才统一执行并保存, 任一记录校验失败则不做任何修改
+pub async fn records_bulk(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    struct ReqParam {
+        op: String,
+        ids: Vec<String>,
+        group: Option<String>,
+        tag: Option<String>,
+        expire: Option<u64>,
+    }
+
+    #[derive(Serialize)]
+    struct ItemResult {
+        id: String,
+        ok: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        message: Option<String>,
+    }
+
+    #[derive(Serialize)]
+    struct ResData {
+        applied: bool,
+        results: Vec<ItemResult>,
+    }
+
+    let req_param = ctx.parse_json::<ReqParam>()?;
+    let op = match req_param.op.as_str() {
+        "move" => BulkOp::Move(req_param.group.unwrap_or_default()),
+        "addTag" | "removeTag" => {
+            let tag = match req_param.tag {
+                Some(tag) if !tag.is_empty() => tag,
+                _ => httpserver::http_bail!("标签不能为空"),
+            };
+            if req_param.op == "addTag" { BulkOp::AddTag(tag) } else { BulkOp::RemoveTag(tag) }
+        },
+        "delete" => BulkOp::Delete,
+        "setExpire" => BulkOp::SetExpire(req_param.expire.unwrap_or(0)),
+        _ => httpserver::http_bail!("不支持的批量操作: {}", req_param.op),
+    };
+    httpserver::fail_if!(req_param.ids.is_empty(), "记录id不能为空");
+
+    // 先校验所有记录, 生成每条记录的处理结果
+    let (database, pass) = service::session_db(&ctx)?;
+    let db = aidb::load_database(database, &pass)?;
+    if let BulkOp::Move(group) = &op {
+        httpserver::fail_if!(!group.is_empty() && db.group(group).is_none(), "分组不存在");
+    }
+    let results: Vec<ItemResult> = req_param.ids.iter()
+        .map(|id| {
+            let found = db.record_index(id).is_some();
+            ItemResult {
+                id: id.clone(),
+                ok: found,
+                message: if found { None } else { Some(String::from("记录不存在")) },
+            }
+        })
+        .collect();
+    drop(db);
+
+    let applied = results.iter().all(|r| r.ok);
+    if applied {
+        let limits = AppState::from_ctx(&ctx)?.record_limits;
+        undo::update_database(&ctx, "records/bulk", |db| {
+            apply_bulk(db, &op, &req_param.ids, &limits)
+        })?;
+    }
+
+    Resp::ok(&ResData { applied, results })
+}
+
+/// 批量操作类型
+enum BulkOp {
+    /// 移动到指定分组
+    Move(String),
+    /// 添加标签
+    AddTag(String),
+    /// 删除标签
+    RemoveTag(String),
+    /// 删除记录
+    Delete,
+    /// 设置过期时间
+    SetExpire(u64),
+}
+
+/// 执行批量操作, 任一记录不存在或者修改后超出限制时返回错误, 此时不会保存任何修改
+fn apply_bulk(db: &mut Database, op: &BulkOp, ids: &[String], limits: &RecordLimits) -> Result<()> {
+    let ids: HashSet<&str> = ids.iter().map(|id| id.as_str()).collect();
+    let count = db.records.iter().filter(|r| ids.contains(r.id.as_str())).count();
+    httpserver::fail_if!(count != ids.len(), "记录已被修改, 请重试");
+
+    if let BulkOp::Delete = op {
+        db.records.retain(|r| !ids.contains(r.id.as_str()));
+        db.prune_attachments();
+        return Ok(());
+    }
+
+    let now = localtime::unix_timestamp();
+    for item in db.records.iter_mut() {
+        if !ids.contains(item.id.as_str()) {
+            continue;
+        }
+
+        let mut rec = Record::clone(item);
+        match op {
+            BulkOp::Move(group) => rec.group = group.as_str().into(),
+            BulkOp::AddTag(tag) => {
+                if !rec.tags.iter().any(|t| &**t == tag.as_str()) {
+                    httpserver::fail_if!(rec.tags.len() >= MAX_TAGS, "标签数量不能超过{}个", MAX_TAGS);
+                    rec.tags.push(tag.as_str().into());
+                }
+            },
+            BulkOp::RemoveTag(tag) => rec.tags.retain(|t| &**t != tag.as_str()),
+            BulkOp::SetExpire(expire) => rec.expire = *expire,
+            BulkOp::Delete => {},
+        }
+        // 与单条修改一致, 历史版本只记录标题、用户名、口令、网址及备注的变化
+        rec.push_history(item);
+        limits.check(&rec)?;
+        rec.modified = now;
+        *item = Arc::new(rec);
+    }
+
+    Ok(())
+}
+
+/// 校验一次性口令的格式, 空字符串表示清除
+fn check_otp(otp: &str) -> std::result::Result<(), String> {
+    if !otp.trim().is_empty() && Totp::parse(otp).is_err() {
+        return Err("格式错误, 应为otpauth://totp/...格式的uri或者base32编码的密钥".to_owned());
+    }
+    Ok(())
+}
+
+/// 校验自定义字段, 字段名不能为空且不能重复
+fn check_fields(fields: &[accinfo_api::RecordField]) -> std::result::Result<(), String> {
+    let mut names = HashSet::new();
+    for f in fields {
+        let name = f.name.trim();
+        if name.is_empty() {
+            return Err("自定义字段名不能为空".to_owned());
+        }
+        if !names.insert(name) {
+            return Err(format!("自定义字段名重复: {name}"));
+        }
+    }
+    Ok(())
+}
+
+/// 校验口令是否符合口令策略
+pub(super) fn check_policy(policy: &Policy, pass: &str, user_inputs: &[&str]) -> Result<()> {
+    let errs = policy.check_with_inputs(pass, user_inputs);
+    if !errs.is_empty() {
+        httpserver::http_bail!(errs.join(", "));
+    }
+    Ok(())
+}
+
+/// 查看口令限流检查, 超出每分钟限制时返回false
+fn check_reveal_limit(ip: Ipv4Addr) -> bool {
+    let minute = localtime::unix_timestamp() / 60;
+    let mut limits = REVEAL_LIMITS.lock();
+    if limits.0 != minute {
+        *limits = (minute, Vec::new());
+    }
+
+    match limits.1.iter_mut().find(|(v, _)| *v == ip) {
+        Some((_, count)) => {
+            *count += 1;
+            *count <= MAX_REVEAL_PER_MINUTE
+        }
+        None => {
+            limits.1.push((ip, 1));
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_limits_cover_fields_and_comments() {
+        let limits = RecordLimits { max_field_len: 8, max_notes_len: 16, max_record_size: 64 };
+        let mut rec = Record { id: "1".to_owned(), title: "github".to_owned(), ..Default::default() };
+        assert!(limits.check(&rec).is_ok());
+
+        rec.fields.push(RecordField::new("very long name".to_owned(), String::new(), false));
+        assert!(limits.check(&rec).is_err());
+        rec.fields[0] = RecordField::new("host".to_owned(), "x".repeat(17), false);
+        assert!(limits.check(&rec).is_err());
+        // 受保护字段按明文长度限制
+        rec.fields[0] = RecordField::new("pin".to_owned(), "x".repeat(17), true);
+        assert!(limits.check(&rec).is_err());
+        rec.fields.clear();
+
+        // 评论计入整条记录的大小
+        rec.push_comment(aidb::RecordComment { time: 0, author: String::new(), text: "x".repeat(64) });
+        assert!(limits.check(&rec).is_err());
+    }
+
+    #[test]
+    fn bulk_add_tag_respects_limits() {
+        let mut db = Database::default();
+        db.records.push(Arc::new(Record {
+            id: "1".to_owned(),
+            title: "github".to_owned(),
+            tags: (0..MAX_TAGS).map(|i| IStr::from(i.to_string().as_str())).collect(),
+            ..Default::default()
+        }));
+        let ids = ["1".to_owned()];
+        let limits = RecordLimits::default();
+
+        // 已有的标签不计入数量限制
+        assert!(apply_bulk(&mut db, &BulkOp::AddTag("0".to_owned()), &ids, &limits).is_ok());
+        assert!(apply_bulk(&mut db, &BulkOp::AddTag("new".to_owned()), &ids, &limits).is_err());
+        assert_eq!(db.records[0].tags.len(), MAX_TAGS);
+
+        // 修改后的记录同样按长度限制校验
+        Arc::make_mut(&mut db.records[0]).tags.clear();
+        let limits = RecordLimits { max_record_size: 16, ..Default::default() };
+        assert!(apply_bulk(&mut db, &BulkOp::AddTag("x".repeat(32)), &ids, &limits).is_err());
+    }
+}
@@ -1,11 +1,44 @@
-use std::{sync::Arc, path::Path};
-use httpserver::{HttpContext, HttpResponse, Resp};
+use std::{collections::HashMap, path::Path};
+use accinfo_api::{ListRequest, LoginRequest};
+use anyhow_ext::Result;
+use httpserver::{Fields, HttpContext, HttpResponse, Resp, Shadow, Sparse};
 use localtime::LocalTime;
 use serde::{Serialize, Deserialize};
 use parking_lot::Mutex;
-use crate::{aidb, apis::authentication::Authentication, AppGlobal};
+use zeroize::Zeroizing;
+use crate::{aidb::{self, Sealed, SecretString}, apis::authentication::{Authentication, SessionToken}, state::AppState, timing::{self, Phase}};
 
-static PASSWORD: Mutex<String> = Mutex::new(String::new());
+/// 用户名及数据库名的最大长度
+const MAX_NAME_LEN: usize = 128;
+/// 口令的最大长度
+const MAX_PASS_LEN: usize = 1024;
+/// 搜索关键字的最大长度
+const MAX_QUERY_LEN: usize = 256;
+
+/// 登录成功后保存的数据库口令, 在内存中加密保存, key: 数据库文件名
+static PASSWORDS: Mutex<Option<HashMap<String, Sealed>>> = Mutex::new(None);
+
+/// 获取登录成功后保存的数据库口令, 尚未登录过时返回空字符串
+pub(super) fn password(database: &str) -> SecretString {
+    PASSWORDS.lock().as_ref()
+        .and_then(|p| p.get(database).map(Sealed::reveal_secret))
+        .unwrap_or_default()
+}
+
+/// 保存数据库口令
+pub(super) fn set_password(database: &str, pass: &str) {
+    let mut passwords = PASSWORDS.lock();
+    let passwords = passwords.get_or_insert_with(HashMap::new);
+    if passwords.get(database).map(|p| *p.reveal_secret() == *pass) != Some(true) {
+        passwords.insert(database.to_owned(), Sealed::new(pass));
+    }
+}
+
+/// 当前会话绑定的数据库文件名及其口令
+pub(super) fn session_db(ctx: &HttpContext) -> Result<(&str, SecretString)> {
+    let database = AppState::from_ctx(ctx)?.session_database(ctx)?;
+    Ok((database, password(database)))
+}
 
 pub async fn ping(ctx: HttpContext) -> HttpResponse {
     #[derive(Deserialize, Default)] struct ReqParam { reply: Option<String> }
@@ -29,92 +62,786 @@ pub async fn ping(ctx: HttpContext) -> HttpResponse {
     })
 }
 
-/// 登录接口
-pub async fn login(ctx: HttpContext) -> HttpResponse {
-    #[derive(Deserialize)]
+/// 存活检查接口, 进程能够处理请求即返回成功, 供systemd/k8s的存活探针使用
+pub async fn health(ctx: HttpContext) -> HttpResponse {
+    #[derive(Serialize)]
     #[serde(rename_all = "camelCase")]
-    struct ReqParam {
-        user: String,
-        pass: String,
+    struct ResData {
+        status: &'static str,
+        server: String,
+        uptime: u64,
     }
 
+    let st = AppState::from_ctx(&ctx)?;
+    Resp::ok(&ResData {
+        status: "up",
+        server: format!("{}/{}", crate::APP_NAME, crate::APP_VER),
+        uptime: localtime::unix_timestamp().saturating_sub(st.startup_time),
+    })
+}
+
+/// 就绪检查接口, 校验所有数据库文件存在且格式正确(不需要口令), 未就绪时返回503
+pub async fn ready(ctx: HttpContext) -> HttpResponse {
     #[derive(Serialize)]
     #[serde(rename_all = "camelCase")]
     struct ResData {
+        status: &'static str,
+        uptime: u64,
+        format_version: String,
+    }
+
+    let st = AppState::from_ctx(&ctx)?;
+    // 返回缺省数据库(第一个)的格式版本, 任意一个数据库校验失败都视为未就绪
+    let mut format_version = None;
+    for db in st.databases() {
+        match aidb::check_format(db) {
+            Ok(ver) => { format_version.get_or_insert(ver); }
+            Err(e) => {
+                log::warn!("readiness check failed, database: {db}, error: {e:?}");
+                const SERVICE_UNAVAILABLE: hyper::StatusCode = hyper::StatusCode::SERVICE_UNAVAILABLE;
+                return Resp::fail_with_status(SERVICE_UNAVAILABLE, SERVICE_UNAVAILABLE.as_u16() as u32,
+                    "数据库文件不存在或格式错误");
+            }
+        }
+    }
+
+    Resp::ok(&ResData {
+        status: "ready",
+        uptime: localtime::unix_timestamp().saturating_sub(st.startup_time),
+        format_version: format_version.map(|ver| ver.to_string()).unwrap_or_default(),
+    })
+}
+
+/// 登录接口, 会话绑定登录的数据库, 未指定数据库时使用与用户名同名的数据库
+pub async fn login(ctx: HttpContext) -> HttpResponse {
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ResData<'a> {
+        database: &'a str,
         token: String,
         expire: LocalTime,
         refresh_time: LocalTime,
+        max_expire: LocalTime,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        last_login: Option<LocalTime>,
+        #[serde(skip_serializing_if = "String::is_empty")]
+        last_login_ip: String,
+        failed_attempts: u32,
+        /// 携带令牌的请求头名称
+        token_header: &'static str,
+        /// 令牌前的认证方式, 为空时整个请求头的值即令牌
+        token_scheme: &'static str,
     }
 
-    let req_param = ctx.parse_json::<ReqParam>()?;
+    let req_param = ctx.parse_json::<LoginRequest>()?;
+    httpserver::validate!(req_param,
+        user: length(1..=MAX_NAME_LEN),
+        pass: length(1..=MAX_PASS_LEN),
+        database: length(1..=MAX_NAME_LEN),
+    );
     let (user, pass) = (&req_param.user, &req_param.pass);
 
-    let ac = crate::AppConf::get();
-    let fpath = Path::new(&ac.database);
-    let username = fpath.file_stem().unwrap();
+    let st = AppState::from_ctx(&ctx)?;
 
-    httpserver::fail_if!(!fpath.exists(), "数据库丢失");
-    httpserver::fail_if!(username.to_str().unwrap() != user, "用户名错误");
-    httpserver::fail_if!(!crate::aidb::check_password(&ac.database, pass)?, "密码错误");
+    // 防暴力破解, 连续失败后需等待一段时间才能再次尝试
+    let ip = ctx.remote_ip();
+    if let Err(wait) = Authentication::check_login(ip, &st.login_guard) {
+        return login_locked(wait);
+    }
+
+    let (db_id, database) = match st.find_database(req_param.database.as_deref().unwrap_or(user)) {
+        Some(db) => db,
+        None => {
+            Authentication::login_failed(ip, &st.login_guard);
+            match req_param.database {
+                Some(_) => httpserver::http_bail!("数据库不存在"),
+                None => httpserver::http_bail!("用户名错误"),
+            }
+        }
+    };
+    httpserver::fail_if!(!Path::new(database).exists(), "数据库丢失");
+    if !Authentication::check_access_window(&ctx, st, AppState::database_name(database)) {
+        return Authentication::outside_window();
+    }
+
+    // 使用胁迫密码时登录到诱饵数据库, 回复中的数据库名称仍然是请求的数据库
+    let name = AppState::database_name(database);
+    let (db_id, database) = match Authentication::verify_password(&ctx, st, db_id, database, pass).await? {
+        Some(db) => db,
+        None => {
+            aidb::stats_login_failed(database);
+            Authentication::login_failed(ip, &st.login_guard);
+            httpserver::http_bail!("密码错误");
+        }
+    };
+    Authentication::login_succeeded(ip);
 
     // 保存用户密码
-    let mut p = PASSWORD.lock();
-    if pass != p.as_str() {
-        *p = String::from(pass);
+    set_password(database, pass);
+
+    // 加载数据库(同时载入持久化的访问统计), 然后累计登录统计, 首次加载需要派生密钥, 在阻塞线程中进行
+    let (db_file, db_pass) = (database.to_owned(), Zeroizing::new(req_param.pass.clone()));
+    tokio::task::spawn_blocking(move || aidb::load_database(&db_file, &db_pass)).await??;
+    let prev = aidb::stats_login(database, ip.to_string()).unwrap_or_default();
+
+    let tk = Authentication::session_id(ip, db_id, st.session_expire, st.session_max_age)?;
+    let (token_header, token_scheme) = Authentication::token_header();
+
+    Resp::ok(&ResData {
+        database: name,
+        token: tk.token.clone(),
+        expire: LocalTime::from_unix_timestamp(tk.expire as i64),
+        refresh_time: refresh_time(&tk),
+        max_expire: LocalTime::from_unix_timestamp(tk.max_expire as i64),
+        last_login: if prev.time > 0 { Some(LocalTime::from_unix_timestamp(prev.time as i64)) } else { None },
+        last_login_ip: prev.ip,
+        failed_attempts: prev.failed_attempts,
+        token_header,
+        token_scheme,
+    })
+}
+
+/// 刷新令牌接口, 返回新的令牌并使旧令牌失效, 会话的最长有效时间不会因刷新而延长
+pub async fn refresh(ctx: HttpContext) -> HttpResponse {
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ResData {
+        token: String,
+        expire: LocalTime,
+        refresh_time: LocalTime,
+        max_expire: LocalTime,
     }
-    drop(p);
 
-    let token = Authentication::session_id()?;
-    let now = localtime::unix_timestamp() as i64;
-    let expire = LocalTime::from_unix_timestamp(now + AppGlobal::get().session_expire as i64);
-    let refresh_time = LocalTime::from_unix_timestamp(now + AppGlobal::get().session_expire as i64 / 2);
+    let st = AppState::from_ctx(&ctx)?;
+    let (old_id, tk) = match Authentication::refresh_session(&ctx, st.session_expire)? {
+        Some(v) => v,
+        None => httpserver::http_bail!("会话已过期, 请重新登录"),
+    };
+    super::undo::move_undo(old_id, tk.id);
+    Authentication::move_step_up(old_id, tk.id);
+    crate::jobs::move_session(old_id, tk.id);
 
-    Resp::ok(&ResData { token, expire, refresh_time })
+    Resp::ok(&ResData {
+        token: tk.token.clone(),
+        expire: LocalTime::from_unix_timestamp(tk.expire as i64),
+        refresh_time: refresh_time(&tk),
+        max_expire: LocalTime::from_unix_timestamp(tk.max_expire as i64),
+    })
+}
+
+/// 会话保活接口, 延长会话的空闲过期时间, 不超过会话的最长有效时间
+///
+/// 禁用请求自动延长会话(`--no-sliding-session`)时, 客户端在用户有操作时调用该接口保持会话,
+/// 内存会话的令牌不变, 无状态令牌返回新签发的令牌
+pub async fn keepalive(ctx: HttpContext) -> HttpResponse {
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ResData {
+        token: String,
+        expire: LocalTime,
+        refresh_time: LocalTime,
+        max_expire: LocalTime,
+    }
+
+    let st = AppState::from_ctx(&ctx)?;
+    let tk = match Authentication::keep_alive(&ctx, st.session_expire)? {
+        Some(tk) => tk,
+        None => httpserver::http_bail!("会话已过期, 请重新登录"),
+    };
+
+    Resp::ok(&ResData {
+        token: tk.token.clone(),
+        expire: LocalTime::from_unix_timestamp(tk.expire as i64),
+        refresh_time: refresh_time(&tk),
+        max_expire: LocalTime::from_unix_timestamp(tk.max_expire as i64),
+    })
+}
+
+/// 二次验证接口, 重新输入会话绑定的数据库的主密码, 有效期内允许导出及查看敏感记录的口令
+pub async fn step_up(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    struct ReqParam {
+        pass: String,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ResData {
+        /// 二次验证的有效期, 未启用二次验证时为空
+        #[serde(skip_serializing_if = "Option::is_none")]
+        expire: Option<LocalTime>,
+    }
+
+    let req_param = ctx.parse_json::<ReqParam>()?;
+    let st = AppState::from_ctx(&ctx)?;
+    let ip = ctx.remote_ip();
+    if let Err(wait) = Authentication::check_login(ip, &st.login_guard) {
+        return login_locked(wait);
+    }
+
+    // 优先与保存的主密码比较, 没有保存时(例如重启后使用无状态令牌)解密数据库校验
+    let (database, saved) = session_db(&ctx)?;
+    let pass = req_param.pass.as_str();
+    let passed = (!saved.is_empty() && super::authentication::secure_eq(saved.as_bytes(), pass.as_bytes()))
+        || aidb::check_password_blocking(database, pass).await?;
+    if !passed {
+        aidb::stats_login_failed(database);
+        Authentication::login_failed(ip, &st.login_guard);
+        log::warn!(target: "audit", "step-up authentication failed by {ip}");
+        httpserver::http_bail!("密码错误");
+    }
+    Authentication::login_succeeded(ip);
+    set_password(database, pass);
+
+    let expire = Authentication::step_up(&ctx)?;
+    log::info!(target: "audit", "step-up authentication passed by {ip}");
+    Resp::ok(&ResData { expire: expire.map(|exp| LocalTime::from_unix_timestamp(exp as i64)) })
+}
+
+/// 修改主密码接口, 使用新口令重新加密会话绑定的数据库, 成功后登录到该数据库的所有会话失效, 需重新登录
+pub async fn change_password(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ReqParam {
+        old_pass: String,
+        new_pass: String,
+    }
+
+    let req_param = ctx.parse_json::<ReqParam>()?;
+    httpserver::validate!(req_param,
+        old_pass: length(1..=MAX_PASS_LEN),
+        new_pass: length(1..=MAX_PASS_LEN),
+    );
+    let st = AppState::from_ctx(&ctx)?;
+    let ip = ctx.remote_ip();
+    if let Err(wait) = Authentication::check_login(ip, &st.login_guard) {
+        return login_locked(wait);
+    }
+
+    let (database, saved) = session_db(&ctx)?;
+    let (old_pass, new_pass) = (req_param.old_pass.as_str(), req_param.new_pass.as_str());
+    let passed = (!saved.is_empty() && super::authentication::secure_eq(saved.as_bytes(), old_pass.as_bytes()))
+        || aidb::check_password_blocking(database, old_pass).await?;
+    if !passed {
+        aidb::stats_login_failed(database);
+        Authentication::login_failed(ip, &st.login_guard);
+        log::warn!(target: "audit", "change password failed by {ip}");
+        httpserver::http_bail!("原密码错误");
+    }
+    Authentication::login_succeeded(ip);
+    httpserver::fail_if!(old_pass == new_pass, "新密码不能与原密码相同");
+
+    let (db_file, old, new) = (database.to_owned(), Zeroizing::new(req_param.old_pass.clone()),
+        Zeroizing::new(req_param.new_pass.clone()));
+    tokio::task::spawn_blocking(move || aidb::reencrypt(&db_file, &old, &new)).await??;
+    set_password(database, new_pass);
+    super::undo::clear_undo(&ctx);
+    Authentication::revoke_database(AppState::database_id(database), st.session_max_age);
+
+    log::info!(target: "audit", "password of database {} changed by {ip}", AppState::database_name(database));
+    Resp::ok_with_empty()
+}
+
+/// 登录失败次数过多时的回复
+fn login_locked(wait: u64) -> HttpResponse {
+    const TOO_MANY_REQUESTS: hyper::StatusCode = hyper::StatusCode::TOO_MANY_REQUESTS;
+    let mut res = Resp::fail_with_status(TOO_MANY_REQUESTS, TOO_MANY_REQUESTS.as_u16() as u32,
+        &format!("登录失败次数过多, 请{wait}秒后重试"))?;
+    res.headers_mut().insert(hyper::header::RETRY_AFTER, wait.into());
+    Ok(res)
+}
+
+/// 建议的令牌刷新时间, 空闲有效期过半时刷新
+fn refresh_time(tk: &SessionToken) -> LocalTime {
+    let now = localtime::unix_timestamp();
+    LocalTime::from_unix_timestamp((now + (tk.expire.saturating_sub(now)) / 2) as i64)
 }
 
 /// 退出登录接口
 pub async fn logout(ctx: HttpContext) -> HttpResponse {
+    super::undo::clear_undo(&ctx);
     Authentication::remove_session_id(&ctx);
     Resp::ok_with_empty()
 }
 
-/// 数据查询接口
+/// 数据查询接口, 只在标题/账号/网址、未受保护的自定义字段及评论中搜索, 返回的记录不包含备注内容
+///
+/// 支持url参数`fields=id,title,url`只返回记录的指定字段
 pub async fn list(ctx: HttpContext) -> HttpResponse {
+    list_records(&ctx, true)
+}
+
+/// 顺序扫描的列表接口, 作为影子路由的候选实现, 用于验证并行扫描的结果
+pub async fn list_sequential(ctx: HttpContext) -> HttpResponse {
+    list_records(&ctx, false)
+}
+
+fn list_records(ctx: &HttpContext, parallel: bool) -> HttpResponse {
+    #[derive(Serialize)]
+    struct ResData<'a> {
+        total: usize,
+        records: Sparse<'a, Vec<aidb::RecordSummary<'a>>>,
+    }
+
+    let req_param = ctx.parse_json_opt::<ListRequest>()?.unwrap_or_default();
+    httpserver::validate!(req_param, q: length(0..=MAX_QUERY_LEN));
+    let fields = Fields::from_ctx(ctx);
+    let (database, pass) = session_db(ctx)?;
+    let db = crate::aidb::load_database(database, &pass)?;
+
+    let q = req_param.q.unwrap_or_default();
+
+    let span = timing::span(Phase::Search);
+    let mut vec_record: Vec<_> = if q.is_empty() {
+        db.records.iter().map(|item| item.summary()).collect()
+    } else if parallel {
+        // 记录较多时在多线程版本中分片并行扫描
+        crate::parallel::filter_map(&db.records, |item| record_matches(item, &q))
+    } else {
+        db.records.iter().filter_map(|item| record_matches(item, &q)).collect()
+    };
+    db.sort_summaries(&mut vec_record);
+
+    // 只统计通过搜索命中的记录, 影子请求不重复统计
+    if !q.is_empty() && !Shadow::is_shadow(ctx) {
+        aidb::stats_read(database, vec_record.iter().map(|r| r.id));
+    }
+
+    drop(span);
+
+    let total = vec_record.len();
+    let _span = timing::span(Phase::Serialize);
+    Resp::ok(&ResData { records: Sparse::new(&vec_record, fields.as_ref()), total })
+}
+
+/// 记录的标题、用户名、网址、非保护字段、评论或备注包含`q`时返回记录摘要
+///
+/// 备注加密保存, 其它字段都不匹配时才解密比较, 解密的明文用后清零
+fn record_matches<'a>(item: &'a aidb::Record, q: &str) -> Option<aidb::RecordSummary<'a>> {
+    let hit = item.title.contains(q) || item.user.contains(q) || item.url.contains(q)
+        || item.fields.iter().filter_map(|f| f.plain()).any(|v| v.contains(q))
+        || item.comments.iter().any(|c| c.text.contains(q))
+        || (!item.notes.is_empty() && item.notes.reveal_secret().contains(q));
+    hit.then(|| item.summary())
+}
+
+/// 全文搜索接口, 使用倒排索引, 支持`title:github user:kiven`格式的字段限定查询, 支持url参数`fields`
+pub async fn search(ctx: HttpContext) -> HttpResponse {
     #[derive(Deserialize)]
     struct ReqParam {
-        q: Option<String>,
+        q: String,
     }
 
     #[derive(Serialize)]
-    struct ResData {
+    struct ResData<'a> {
         total: usize,
-        records: aidb::Records,
+        records: Sparse<'a, Vec<aidb::RecordSummary<'a>>>,
+    }
+
+    let req_param = ctx.parse_json::<ReqParam>()?;
+    let fields = Fields::from_ctx(&ctx);
+    let (database, pass) = session_db(&ctx)?;
+    let (db, index) = aidb::load_search_index(database, &pass)?;
+
+    let span = timing::span(Phase::Search);
+    let mut records: Vec<_> = index.search(&req_param.q).into_iter()
+        .filter_map(|i| db.records.get(i as usize))
+        .map(|r| r.summary())
+        .collect();
+    db.sort_summaries(&mut records);
+    drop(span);
+
+    aidb::stats_read(database, records.iter().map(|r| r.id));
+
+    let _span = timing::span(Phase::Serialize);
+    Resp::ok(&ResData { total: records.len(), records: Sparse::new(&records, fields.as_ref()) })
+}
+
+/// 搜索框输入提示接口, 返回以`q`开头的标题/网址补全项
+pub async fn suggest(ctx: HttpContext) -> HttpResponse {
+    const DEFAULT_LIMIT: usize = 10;
+    const MAX_LIMIT: usize = 50;
+
+    #[derive(Serialize)]
+    struct ResData<'a> {
+        items: Vec<&'a str>,
     }
 
-    let req_param = ctx.parse_json_opt::<ReqParam>()?;
-    let ac = crate::AppConf::get();
-    let pass = PASSWORD.lock();
-    let recs = crate::aidb::load_database(&ac.database, pass.as_str())?;
-    let mut vec_record = Vec::with_capacity(recs.len());
+    let q = ctx.get_url_param_str("q").unwrap_or_default();
+    let limit = ctx.get_url_param::<_, usize>("limit")?.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let q = q.trim();
+    if q.is_empty() {
+        return Resp::ok(&ResData { items: Vec::new() });
+    }
+
+    let (database, pass) = session_db(&ctx)?;
+    let db = aidb::load_database(database, &pass)?;
+    let span = timing::span(Phase::Search);
+    let index = crate::index::suggest_index(&db);
+    let items = index.suggest(q, limit);
+    drop(span);
+
+    Resp::ok(&ResData { items })
+}
+
+/// 访问统计查询接口, 返回会话绑定的数据库的统计
+pub async fn stats(ctx: HttpContext) -> HttpResponse {
+    let database = AppState::from_ctx(&ctx)?.session_database(&ctx)?;
+    Resp::ok(&aidb::stats(database))
+}
 
-    let q = match req_param {
-        Some(rp) => match rp.q {
-            Some(q) => q,
-            None => String::with_capacity(0),
+/// 将内存中的访问统计保存到各数据库文件, 尚未登录过的数据库忽略
+pub fn flush_stats(st: &AppState) {
+    for database in st.databases() {
+        let pass = password(database);
+        if pass.is_empty() {
+            continue;
         }
-        None => String::with_capacity(0),
-    };
+        if let Err(e) = aidb::flush_stats(database, &pass) {
+            log::error!("flush database {database} statistics error: {e:?}");
+        }
+    }
+}
 
-    for item in recs.iter() {
-        if !q.is_empty() {
-            if item.title.contains(&q) || item.url.contains(&q) || item.notes.contains(&q) {
-                vec_record.push(item.clone());
-            }
-        } else {
-            vec_record.push(item.clone());
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use http_body_util::BodyExt;
+    use httpserver::{HttpContext, HttpHandler, Response};
+    use serde_json::{json, Value};
+    use crate::{aidb, state::AppState};
+
+    async fn resp_json(resp: Response) -> Value {
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn new_record(id: &str, title: &str) -> Arc<aidb::Record> {
+        Arc::new(aidb::Record {
+            id: id.to_owned(),
+            title: title.to_owned(),
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn ping_reply() {
+        let ctx = HttpContext::test_builder()
+            .path("/api/ping")
+            .json(&json!({"reply": "hello"}))
+            .build();
+        let res = resp_json(super::ping(ctx).await.unwrap()).await;
+
+        assert_eq!(res["code"], 200);
+        assert_eq!(res["data"]["reply"], "hello");
+        assert_eq!(res["data"]["clientIp"], "127.0.0.1:0");
+    }
+
+    #[tokio::test]
+    async fn typed_handler() {
+        let handler = httpserver::typed(crate::apis::policy_strength);
+
+        let ctx = HttpContext::test_builder()
+            .path("/api/policy/strength")
+            .json(&json!({"pass": "correct horse battery staple"}))
+            .build();
+        let res = resp_json(handler.handle(ctx).await.unwrap()).await;
+        assert_eq!(res["code"], 200);
+        assert!(res["data"]["score"].is_number());
+
+        // 缺少必需的参数时回复400
+        for body in [json!({}), json!({"pass": 1})] {
+            let ctx = HttpContext::test_builder().path("/api/policy/strength").json(&body).build();
+            let res = handler.handle(ctx).await.unwrap();
+            assert_eq!(res.status(), hyper::StatusCode::BAD_REQUEST);
+            assert_eq!(resp_json(res).await["code"], 400);
         }
+        let ctx = HttpContext::test_builder().path("/api/policy/strength").build();
+        assert_eq!(handler.handle(ctx).await.unwrap().status(), hyper::StatusCode::BAD_REQUEST);
     }
 
-    let total = vec_record.len();
-    Resp::ok(&ResData{records: Arc::from(vec_record), total})
+    #[tokio::test]
+    async fn login_validation() {
+        let ctx = HttpContext::test_builder()
+            .path("/api/login")
+            .json(&json!({"user": "", "pass": "", "database": "x".repeat(200)}))
+            .build();
+        let e = super::login(ctx).await.unwrap_err();
+        let e = e.downcast_ref::<httpserver::ValidationError>().unwrap();
+        let fields: Vec<_> = e.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["user", "pass", "database"]);
+    }
+
+    #[tokio::test]
+    async fn ready_checks_database() {
+        let tmp = aidb::TempDatabase::new("ready");
+        let database = tmp.0.clone();
+        let state = Arc::new(AppState { database: database.clone(), ..Default::default() });
+        let ctx = || HttpContext::test_builder().path("/api/ready").state(state.clone()).build();
+
+        let res = super::ready(ctx()).await.unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::SERVICE_UNAVAILABLE);
+
+        aidb::save_database(&database, "secret", &aidb::Database::default()).unwrap();
+        let res = resp_json(super::ready(ctx()).await.unwrap()).await;
+        assert_eq!(res["code"], 200);
+        assert_eq!(res["data"]["status"], "ready");
+        // 匿名可访问的接口不返回是否已有会话加载数据库
+        assert!(res["data"].get("loaded").is_none());
+
+        // 截断的文件不能通过头部校验
+        let buf = std::fs::read(&database).unwrap();
+        std::fs::write(&database, &buf[..16]).unwrap();
+        let res = super::ready(ctx()).await.unwrap();
+        assert_eq!(res.status(), hyper::StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn change_password() {
+        let tmp = aidb::TempDatabase::new("passwd");
+        let database = tmp.0.clone();
+        let mut db = aidb::Database::default();
+        db.records.push(new_record("1", "github"));
+        aidb::save_database(&database, "secret", &db).unwrap();
+
+        let state = Arc::new(AppState { database: database.clone(), ..Default::default() });
+        let ctx = |body: Value| HttpContext::test_builder()
+            .path("/api/admin/change-password")
+            .state(state.clone())
+            .uid(AppState::database_name(&database))
+            .json(&body)
+            .build();
+
+        assert!(super::change_password(ctx(json!({"oldPass": "wrong", "newPass": "secret2"}))).await.is_err());
+        assert!(aidb::check_password(&database, "secret").unwrap());
+
+        let res = resp_json(super::change_password(ctx(json!({"oldPass": "secret", "newPass": "secret2"}))).await.unwrap()).await;
+        assert_eq!(res["code"], 200);
+        assert!(!aidb::check_password(&database, "secret").unwrap());
+        assert!(aidb::check_password(&database, "secret2").unwrap());
+        assert_eq!(aidb::load_database(&database, "secret2").unwrap().records.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn login_and_list() {
+        let tmp = aidb::TempDatabase::new("test");
+        let database = tmp.0.clone();
+        let user = AppState::database_name(&database).to_owned();
+
+        let mut db = aidb::Database::default();
+        db.records.push(new_record("1", "github"));
+        db.records.push(new_record("2", "gitee"));
+        db.records.push(Arc::new(aidb::Record {
+            id: "3".to_owned(),
+            title: "server".to_owned(),
+            fields: vec![
+                aidb::RecordField::new("host".to_owned(), "db.example.com".to_owned(), false),
+                aidb::RecordField::new("pin".to_owned(), "1234".to_owned(), true),
+            ],
+            notes: aidb::Sealed::new("rack 42"),
+            ..Default::default()
+        }));
+        aidb::save_database(&database, "secret", &db).unwrap();
+
+        let state = Arc::new(AppState {
+            session_expire: 1800,
+            session_max_age: 43200,
+            database: database.clone(),
+            ..Default::default()
+        });
+
+        // 口令错误
+        let ctx = HttpContext::test_builder()
+            .path("/api/login")
+            .state(state.clone())
+            .json(&json!({"user": user, "pass": "wrong"}))
+            .build();
+        assert!(super::login(ctx).await.is_err());
+
+        let ctx = HttpContext::test_builder()
+            .path("/api/login")
+            .state(state.clone())
+            .json(&json!({"user": user, "pass": "secret"}))
+            .build();
+        let res = resp_json(super::login(ctx).await.unwrap()).await;
+        assert_eq!(res["code"], 200);
+        assert!(res["data"]["token"].as_str().is_some_and(|t| !t.is_empty()));
+
+        let ctx = HttpContext::test_builder()
+            .path("/api/list")
+            .state(state.clone())
+            .uid(&user)
+            .json(&json!({"q": "hub"}))
+            .build();
+        let res = resp_json(super::list(ctx).await.unwrap()).await;
+        assert_eq!(res["data"]["total"], 1);
+        assert_eq!(res["data"]["records"][0]["title"], "github");
+
+        // 搜索自定义字段, 受保护字段的内容脱敏且不参与搜索
+        let ctx = HttpContext::test_builder()
+            .path("/api/list")
+            .state(state.clone())
+            .uid(&user)
+            .json(&json!({"q": "example"}))
+            .build();
+        let res = resp_json(super::list(ctx).await.unwrap()).await;
+        assert_eq!(res["data"]["total"], 1);
+        assert_eq!(res["data"]["records"][0]["fields"][0]["value"], "db.example.com");
+        assert_eq!(res["data"]["records"][0]["fields"][1]["value"], aidb::MASKED_PASS);
+        let ctx = HttpContext::test_builder()
+            .path("/api/list")
+            .state(state.clone())
+            .uid(&user)
+            .json(&json!({"q": "1234"}))
+            .build();
+        let res = resp_json(super::list(ctx).await.unwrap()).await;
+        assert_eq!(res["data"]["total"], 0);
+        // 加密保存的备注同样参与搜索
+        let ctx = HttpContext::test_builder()
+            .path("/api/list")
+            .state(state.clone())
+            .uid(&user)
+            .json(&json!({"q": "rack"}))
+            .build();
+        let res = resp_json(super::list(ctx).await.unwrap()).await;
+        assert_eq!(res["data"]["total"], 1);
+        assert_eq!(res["data"]["records"][0]["title"], "server");
+
+        // 只返回指定的字段
+        let ctx = HttpContext::test_builder()
+            .path("/api/list?fields=id,title")
+            .state(state.clone())
+            .uid(&user)
+            .json(&json!({"q": "git"}))
+            .build();
+        let res = resp_json(super::list(ctx).await.unwrap()).await;
+        assert_eq!(res["data"]["total"], 2);
+        assert_eq!(res["data"]["records"][0], json!({"id": "1", "title": "github"}));
+    }
+
+    #[tokio::test]
+    async fn login_selects_database() {
+        let (work_db, home_db) = (aidb::TempDatabase::new("work"), aidb::TempDatabase::new("home"));
+        let (work, home) = (work_db.0.clone(), home_db.0.clone());
+
+        let mut db = aidb::Database::default();
+        db.records.push(new_record("1", "jira"));
+        aidb::save_database(&work, "work-pass", &db).unwrap();
+        let mut db = aidb::Database::default();
+        db.records.push(new_record("1", "netflix"));
+        aidb::save_database(&home, "home-pass", &db).unwrap();
+
+        let state = Arc::new(AppState {
+            session_expire: 1800,
+            session_max_age: 43200,
+            database: work.clone(),
+            databases: vec![work.clone(), home.clone()],
+            ..Default::default()
+        });
+        let home_name = AppState::database_name(&home).to_owned();
+
+        // 口令属于另一个数据库
+        let ctx = HttpContext::test_builder()
+            .path("/api/login")
+            .state(state.clone())
+            .json(&json!({"user": "me", "pass": "work-pass", "database": home_name}))
+            .build();
+        assert!(super::login(ctx).await.is_err());
+
+        let ctx = HttpContext::test_builder()
+            .path("/api/login")
+            .state(state.clone())
+            .json(&json!({"user": "me", "pass": "home-pass", "database": home_name}))
+            .build();
+        let res = resp_json(super::login(ctx).await.unwrap()).await;
+        assert_eq!(res["code"], 200);
+        assert_eq!(res["data"]["database"], home_name.as_str());
+
+        // 认证中间件将会话绑定的数据库写入uid
+        let ctx = HttpContext::test_builder()
+            .path("/api/list")
+            .state(state.clone())
+            .uid(&home_name)
+            .build();
+        let res = resp_json(super::list(ctx).await.unwrap()).await;
+        assert_eq!(res["data"]["total"], 1);
+        assert_eq!(res["data"]["records"][0]["title"], "netflix");
+    }
+
+    #[tokio::test]
+    async fn duress_login_opens_decoy() {
+        let (real_db, decoy_db) = (aidb::TempDatabase::new("real"), aidb::TempDatabase::new("decoy"));
+        let (real, decoy) = (real_db.0.clone(), decoy_db.0.clone());
+
+        let mut db = aidb::Database::default();
+        db.records.push(new_record("1", "bank"));
+        aidb::save_database(&real, "real-pass", &db).unwrap();
+        let mut db = aidb::Database::default();
+        db.records.push(new_record("1", "forum"));
+        aidb::save_database(&decoy, "duress-pass", &db).unwrap();
+
+        let state = Arc::new(AppState {
+            session_expire: 1800,
+            session_max_age: 43200,
+            database: real.clone(),
+            databases: vec![real.clone()],
+            decoys: vec![(real.clone(), decoy.clone())],
+            ..Default::default()
+        });
+        let real_name = AppState::database_name(&real).to_owned();
+        let decoy_id = AppState::database_id(&decoy);
+        let decoy_name = AppState::database_name(&decoy).to_owned();
+        assert_eq!(state.decoy_of(&real), Some((decoy_id, decoy.as_str())));
+        assert_eq!(state.database_by_id(decoy_id), Some(decoy.as_str()));
+        // 诱饵数据库不能按名称直接登录, 返回给客户端的名称为真实数据库的名称
+        assert_eq!(state.find_database(&decoy_name), None);
+        assert_eq!(state.display_name(&decoy), real_name.as_str());
+        assert_eq!(state.display_name(&decoy_name), real_name.as_str());
+        let ctx = HttpContext::test_builder()
+            .path("/api/login")
+            .state(state.clone())
+            .json(&json!({"user": decoy_name, "pass": "duress-pass"}))
+            .build();
+        assert!(super::login(ctx).await.is_err());
+
+        // 使用胁迫密码登录, 回复的数据库名称与正常登录相同
+        let ctx = HttpContext::test_builder()
+            .path("/api/login")
+            .state(state.clone())
+            .json(&json!({"user": real_name, "pass": "duress-pass"}))
+            .build();
+        let res = resp_json(super::login(ctx).await.unwrap()).await;
+        assert_eq!(res["code"], 200);
+        assert_eq!(res["data"]["database"], real_name.as_str());
+
+        let ctx = HttpContext::test_builder()
+            .path("/api/list")
+            .state(state.clone())
+            .uid(AppState::database_name(&decoy))
+            .build();
+        let res = resp_json(super::list(ctx).await.unwrap()).await;
+        assert_eq!(res["data"]["records"][0]["title"], "forum");
+    }
+
+    #[test]
+    fn database_id_is_stable() {
+        let state = AppState {
+            database: "/data/work.aidb".to_owned(),
+            databases: vec!["/data/work.aidb".to_owned(), "/data/home.aidb".to_owned()],
+            ..Default::default()
+        };
+        let home = AppState::database_id("/data/home.aidb");
+        assert_eq!(state.find_database("home"), Some((home, "/data/home.aidb")));
+
+        // 调整顺序或者增加数据库后, 原有的id仍然对应同一个数据库
+        let reordered = AppState {
+            database: "/data/new.aidb".to_owned(),
+            databases: vec!["/data/new.aidb".to_owned(), "/backup/home.aidb".to_owned(), "/data/work.aidb".to_owned()],
+            ..Default::default()
+        };
+        assert_eq!(reordered.database_by_id(home), Some("/backup/home.aidb"));
+        // 数据库移除后id无法解析, 令牌视为无效
+        let removed = AppState { database: "/data/work.aidb".to_owned(), ..Default::default() };
+        assert_eq!(removed.database_by_id(home), None);
+    }
 }
@@ -0,0 +1,35 @@
+//! 影子路由的候选实现, 接口重写时在此登记新的实现, 通过`--shadow`按比例镜像生产请求进行验证
+use anyhow_ext::{bail, Result};
+use httpserver::Shadow;
+
+use super::service;
+
+/// 根据配置创建影子路由中间件, 未配置时返回None
+///
+/// * `spec`: 逗号分隔的`接口=百分比`, 例如`list=10`
+/// * `prefixes`: 接口的路径前缀, 同一接口在各前缀下都进行镜像, 例如`/api`、`/api/v1`
+pub fn new_shadow(spec: &str, prefixes: &[&str]) -> Result<Option<Shadow>> {
+    let mut shadow = Shadow::new();
+
+    for item in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let (name, percent) = match item.split_once('=') {
+            Some((name, percent)) => (name.trim(), percent.trim()),
+            None => bail!("shadow route format error, expect api=percent: {item}"),
+        };
+        let percent = match percent.parse::<u32>() {
+            Ok(p) if (1..=100).contains(&p) => p,
+            _ => bail!("shadow percent must be in 1-100: {item}"),
+        };
+
+        for prefix in prefixes {
+            let path = format!("{prefix}/{name}");
+            shadow = match name {
+                // 顺序扫描, 验证多线程版本中并行扫描的结果
+                "list" => shadow.route(&path, percent, service::list_sequential),
+                _ => bail!("no shadow candidate for api: {name}"),
+            };
+        }
+    }
+
+    Ok((!shadow.is_empty()).then_some(shadow))
+}
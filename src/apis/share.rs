@@ -0,0 +1,176 @@
+//! 记录的一次性分享链接, 链接只能打开一次, 打开后或者过期后立即失效
+use std::collections::HashMap;
+use http_body_util::Full;
+use httpserver::{ChainHandler, ChainResult, HttpContext, HttpResponse, Resp, CONTENT_TYPE};
+use localtime::LocalTime;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use crate::{aidb::{self, Sealed}, state::AppState};
+use super::{authentication::Authentication, service, token};
+
+/// 缺省的分享链接有效时间(单位: 分钟)
+const DEFAULT_EXPIRE_MINUTES: u64 = 15;
+/// 分享链接最长有效时间(单位: 分钟)
+const MAX_EXPIRE_MINUTES: u64 = 24 * 60;
+/// 最多同时存在的分享链接数量
+const MAX_SHARES: usize = 256;
+
+/// 分享页面, 打开页面不会使链接失效, 点击按钮后才通过接口获取内容
+const SHARE_HTML: &str = r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><meta name="viewport" content="width=device-width, initial-scale=1">
+<meta name="robots" content="noindex"><title>分享的账号</title></head>
+<body style="font-family: sans-serif; max-width: 40em; margin: 3em auto;">
+<p>该链接只能查看一次, 查看后立即失效</p>
+<button id="open">查看</button>
+<pre id="out" style="white-space: pre-wrap; word-wrap: break-word;"></pre>
+<script>
+document.getElementById('open').onclick = async function() {
+  this.disabled = true;
+  const token = location.pathname.split('/').pop();
+  const out = document.getElementById('out');
+  try {
+    const res = await (await fetch('../api/share/open', { method: 'POST',
+      headers: { 'Content-Type': 'application/json' }, body: JSON.stringify({ token }) })).json();
+    const d = res.data;
+    out.textContent = d ? `标题: ${d.title}\n用户名: ${d.user}\n口令: ${d.pass}\n网址: ${d.url}` : res.message;
+  } catch (e) {
+    out.textContent = '获取失败: ' + e;
+  }
+};
+</script>
+</body></html>"#;
+
+/// 分享的记录内容
+#[derive(Serialize, Deserialize)]
+struct ShareData {
+    title: String,
+    user: String,
+    pass: String,
+    url: String,
+}
+
+/// 分享项, 内容在内存中加密保存
+struct Share {
+    /// 过期时间(unix时间戳)
+    expire: u64,
+    /// 序列化后的`ShareData`
+    data: Sealed,
+}
+
+type Shares = HashMap<u128, Share>; // key: 分享令牌中的id
+
+static SHARES: Mutex<Option<Shares>> = Mutex::new(None);
+
+/// 分享页面处理器, 处理`/share/:token`请求
+pub struct SharePage;
+
+/// 创建记录的一次性分享链接接口, 创建时保存记录当前的内容, 敏感记录需要先重新验证主密码
+pub async fn share_create(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    struct ReqParam {
+        id: String,
+        /// 有效时间(单位: 分钟)
+        expire: Option<u64>,
+    }
+
+    #[derive(Serialize)]
+    struct ResData {
+        token: String,
+        url: String,
+        expire: LocalTime,
+    }
+
+    let req_param = ctx.parse_json::<ReqParam>()?;
+    let minutes = req_param.expire.unwrap_or(DEFAULT_EXPIRE_MINUTES);
+    httpserver::fail_if!(minutes == 0 || minutes > MAX_EXPIRE_MINUTES,
+        "有效时间必须在1-{}分钟之间", MAX_EXPIRE_MINUTES);
+
+    let (database, pass) = service::session_db(&ctx)?;
+    let db = aidb::load_database(database, &pass)?;
+    let rec = match db.records.iter().find(|r| r.id == req_param.id) {
+        Some(rec) => rec,
+        None => httpserver::http_bail!("记录不存在"),
+    };
+    if rec.sensitive && !Authentication::check_step_up(&ctx)? {
+        log::warn!(target: "audit", "share record {} by {} rejected: step-up required", rec.id, ctx.remote_ip());
+        return Authentication::step_up_required();
+    }
+
+    let data = ShareData {
+        title: rec.title.clone(),
+        user: rec.user.to_string(),
+        pass: rec.pass.reveal(),
+        url: rec.url.to_string(),
+    };
+    let expire = localtime::unix_timestamp() + minutes * 60;
+    let id = token::next_token();
+    {
+        let mut shares = SHARES.lock();
+        let shares = shares.get_or_insert_with(HashMap::new);
+        let now = localtime::unix_timestamp();
+        shares.retain(|_, s| s.expire > now);
+        httpserver::fail_if!(shares.len() >= MAX_SHARES, "分享链接数量过多, 请稍后再试");
+        shares.insert(id, Share { expire, data: Sealed::new(&serde_json::to_string(&data)?) });
+    }
+
+    log::info!(target: "audit", "share record {} [{}] by {}, expire in {minutes} minutes",
+        rec.id, rec.title, ctx.remote_ip());
+    let token = token::sign(id);
+    Resp::ok(&ResData {
+        url: format!("{}/share/{token}", AppState::from_ctx(&ctx)?.base_path),
+        token,
+        expire: LocalTime::from_unix_timestamp(expire as i64),
+    })
+}
+
+/// 打开分享链接接口, 无需登录, 返回分享的内容后链接立即失效
+pub async fn share_open(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    struct ReqParam {
+        token: String,
+    }
+
+    let req_param = ctx.parse_json::<ReqParam>()?;
+    let share = token::verify(&req_param.token).ok()
+        .and_then(|id| SHARES.lock().as_mut().and_then(|s| s.remove(&id)))
+        .filter(|s| s.expire > localtime::unix_timestamp());
+    let share = match share {
+        Some(share) => share,
+        None => {
+            log::warn!(target: "audit", "open invalid share link by {}", ctx.remote_ip());
+            httpserver::http_bail!("分享链接无效或已失效");
+        }
+    };
+
+    let data: ShareData = serde_json::from_str(&share.data.reveal())?;
+    log::info!(target: "audit", "open share link of [{}] by {}", data.title, ctx.remote_ip());
+    Resp::ok(&data)
+}
+
+/// 删除过期的分享链接
+pub fn recycle_shares() {
+    let now = localtime::unix_timestamp();
+    if let Some(shares) = SHARES.lock().as_mut() {
+        shares.retain(|_, s| s.expire > now);
+    }
+}
+
+#[async_trait::async_trait]
+impl ChainHandler for SharePage {
+    async fn handle(&self, ctx: HttpContext) -> ChainResult {
+        let is_share = ctx.req.uri().path().strip_prefix("/share/")
+            .is_some_and(|token| !token.is_empty() && !token.contains('/'));
+        if is_share { ChainResult::Done(share_page()) } else { ChainResult::pass(ctx) }
+    }
+}
+
+/// 分享页面不缓存, 也不通过Referer泄露链接
+fn share_page() -> HttpResponse {
+    Ok(
+        hyper::Response::builder()
+            .header(CONTENT_TYPE, "text/html; charset=utf-8")
+            .header(hyper::header::CACHE_CONTROL, "no-store")
+            .header(hyper::header::REFERRER_POLICY, "no-referrer")
+            .body(Full::from(SHARE_HTML))?
+    )
+}
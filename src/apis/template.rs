@@ -0,0 +1,94 @@
+use std::sync::Arc;
+use http_body_util::Full;
+use httpserver::{HttpContext, HttpResponse, Resp, CONTENT_TYPE};
+use hyper::header::CONTENT_DISPOSITION;
+use serde::{Serialize, Deserialize};
+use crate::aidb::{self, Template};
+use super::{service, undo};
+
+/// 模板导出文件的格式版本
+const TEMPLATE_FILE_VERSION: u32 = 1;
+
+/// 模板导入导出文件的内容
+#[derive(Serialize, Deserialize)]
+struct TemplateFile<T> {
+    #[serde(default)]
+    version: u32,
+    templates: Vec<T>,
+}
+
+/// 记录类型模板查询接口
+pub async fn template_list(ctx: HttpContext) -> HttpResponse {
+    let (database, pass) = service::session_db(&ctx)?;
+    let db = aidb::load_database(database, &pass)?;
+
+    Resp::ok(&db.templates)
+}
+
+/// 记录类型模板导出接口, 以附件下载的方式返回json文件, 可导入到其它实例
+pub async fn template_export(ctx: HttpContext) -> HttpResponse {
+    let (database, pass) = service::session_db(&ctx)?;
+    let db = aidb::load_database(database, &pass)?;
+    let data = serde_json::to_vec_pretty(&TemplateFile {
+        version: TEMPLATE_FILE_VERSION,
+        templates: db.templates.iter().map(|t| &**t).collect(),
+    })?;
+
+    Ok(
+        hyper::Response::builder()
+            .header(CONTENT_TYPE, "application/json")
+            .header(CONTENT_DISPOSITION, format!("attachment; filename=\"{}-templates.json\"", crate::APP_NAME))
+            .body(Full::from(data))?
+    )
+}
+
+/// 记录类型模板导入接口, 请求内容为导出的模板文件, 按模板id合并
+///
+/// 已存在的模板只有在`overwrite`为true时才会被覆盖, 否则跳过
+pub async fn template_import(ctx: HttpContext) -> HttpResponse {
+    #[derive(Deserialize)]
+    struct ReqParam {
+        #[serde(flatten)]
+        file: TemplateFile<Template>,
+        #[serde(default)]
+        overwrite: bool,
+    }
+
+    #[derive(Serialize, Default)]
+    struct ResData {
+        added: usize,
+        updated: usize,
+        skipped: usize,
+    }
+
+    let req_param = ctx.parse_json::<ReqParam>()?;
+    httpserver::fail_if!(req_param.file.version > TEMPLATE_FILE_VERSION, "不支持的模板文件版本");
+    for (i, t) in req_param.file.templates.iter().enumerate() {
+        if let Err(e) = t.validate() {
+            httpserver::http_bail!(e);
+        }
+        httpserver::fail_if!(req_param.file.templates[..i].iter().any(|p| p.id == t.id),
+            "导入的模板id重复");
+    }
+
+    let overwrite = req_param.overwrite;
+    let res = undo::update_database(&ctx, "template/import", |db| {
+        let mut res = ResData::default();
+        for t in req_param.file.templates {
+            match db.templates.iter().position(|p| p.id == t.id) {
+                Some(idx) if overwrite => {
+                    db.templates[idx] = Arc::new(t);
+                    res.updated += 1;
+                }
+                Some(_) => res.skipped += 1,
+                None => {
+                    db.templates.push(Arc::new(t));
+                    res.added += 1;
+                }
+            }
+        }
+        Ok(res)
+    })?;
+
+    Resp::ok(&res)
+}
@@ -0,0 +1,343 @@
+//! 会话令牌生成与校验
+//!
+//! 令牌格式: base64url(id[16] + issued_at[8] + hmac_sha256(id + issued_at)[32]),
+//! 签名密钥在进程启动时随机生成, 因此其它实例或重启前签发的令牌都会被拒绝
+//!
+//! 配置了共享密钥时签发无状态令牌:
+//! base64url(id[16] + iat[8] + exp[8] + max_exp[8] + ip[4] + db[8] + hmac_sha256(共享密钥, 前述内容)[32]),
+//! 服务端无需保存会话, 重启后或者使用相同密钥的其它实例都能校验通过
+use std::{fmt::Display, net::Ipv4Addr, sync::OnceLock};
+
+use anyhow_ext::bail;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use parking_lot::Mutex;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// 令牌中id和签发时间的字节长度
+const PAYLOAD_LEN: usize = 16 + 8;
+/// 令牌的字节长度
+const TOKEN_LEN: usize = PAYLOAD_LEN + 32;
+/// 允许的签发时间误差(单位: 秒)
+const MAX_CLOCK_SKEW: u64 = 60;
+/// 无状态令牌中声明部分的字节长度
+const CLAIMS_LEN: usize = 16 + 8 + 8 + 8 + 4 + 8;
+/// 无状态令牌的字节长度
+const STATELESS_LEN: usize = CLAIMS_LEN + 32;
+/// 共享密钥的最小长度
+const MIN_SECRET_LEN: usize = 16;
+
+/// 无状态令牌的共享签名密钥, 未设置时不签发无状态令牌
+static SHARED_SECRET: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// 会话令牌生成器
+pub trait TokenGenerator: Send {
+    /// 生成一个新的128位令牌
+    fn next_token(&mut self) -> u128;
+}
+
+/// 基于ChaCha20的密码学安全随机数令牌生成器
+pub struct ChaChaTokenGenerator(ChaCha20Rng);
+
+impl ChaChaTokenGenerator {
+    /// 使用系统熵源初始化的生成器
+    pub fn new() -> Self {
+        Self(ChaCha20Rng::from_entropy())
+    }
+
+    /// 使用固定种子初始化的生成器, 生成的令牌序列是确定的, 仅用于测试
+    #[cfg(test)]
+    pub fn with_seed(seed: u64) -> Self {
+        Self(ChaCha20Rng::seed_from_u64(seed))
+    }
+}
+
+impl Default for ChaChaTokenGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TokenGenerator for ChaChaTokenGenerator {
+    fn next_token(&mut self) -> u128 {
+        let mut buf = [0u8; 16];
+        self.0.fill_bytes(&mut buf);
+        u128::from_le_bytes(buf)
+    }
+}
+
+/// 当前使用的令牌生成器, 未设置时使用ChaChaTokenGenerator
+static TOKEN_GENERATOR: Mutex<Option<Box<dyn TokenGenerator>>> = Mutex::new(None);
+
+/// 替换令牌生成器, 用于测试时注入确定性的生成器
+#[cfg(test)]
+pub fn set_token_generator<T: TokenGenerator + 'static>(gen: T) {
+    *TOKEN_GENERATOR.lock() = Some(Box::new(gen));
+}
+
+/// 生成一个新的令牌
+pub fn next_token() -> u128 {
+    TOKEN_GENERATOR.lock()
+        .get_or_insert_with(|| Box::new(ChaChaTokenGenerator::new()))
+        .next_token()
+}
+
+/// 令牌校验错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenError {
+    /// 格式错误
+    Format,
+    /// 签名不匹配, 通常是令牌被篡改, 或者由其它实例/重启前的服务签发
+    Signature,
+    /// 签发时间晚于当前时间
+    IssuedAt,
+    /// 令牌已过期
+    Expired,
+    /// 请求的客户端地址与令牌签发时不一致
+    Address,
+}
+
+/// 无状态令牌中的声明
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Claims {
+    /// 会话id
+    pub id: u128,
+    /// 签发时间(unix时间戳), 保活续签时保持不变
+    pub iat: u64,
+    /// 空闲过期时间(unix时间戳)
+    pub exp: u64,
+    /// 绝对过期时间(unix时间戳)
+    pub max_exp: u64,
+    /// 签发时的客户端地址
+    pub ip: Ipv4Addr,
+    /// 登录的数据库id, 见`AppState::database_id`
+    pub db: u64,
+}
+
+impl Display for TokenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TokenError::Format => "令牌格式错误",
+            TokenError::Signature => "令牌签名无效, 请重新登录",
+            TokenError::IssuedAt => "令牌签发时间无效",
+            TokenError::Expired => "令牌已过期, 请重新登录",
+            TokenError::Address => "令牌与客户端地址不匹配, 请重新登录",
+        })
+    }
+}
+
+/// 生成签名后的令牌字符串
+pub fn sign(id: u128) -> String {
+    let mut buf = [0u8; TOKEN_LEN];
+    buf[..16].copy_from_slice(&id.to_be_bytes());
+    buf[16..PAYLOAD_LEN].copy_from_slice(&localtime::unix_timestamp().to_be_bytes());
+    let tag = new_mac(&buf[..PAYLOAD_LEN]).finalize().into_bytes();
+    buf[PAYLOAD_LEN..].copy_from_slice(&tag);
+    URL_SAFE_NO_PAD.encode(buf)
+}
+
+/// 校验令牌签名, 返回令牌中的id
+pub fn verify(token: &str) -> Result<u128, TokenError> {
+    let data = URL_SAFE_NO_PAD.decode(token).map_err(|_| TokenError::Format)?;
+    if data.len() != TOKEN_LEN {
+        return Err(TokenError::Format);
+    }
+
+    new_mac(&data[..PAYLOAD_LEN])
+        .verify_slice(&data[PAYLOAD_LEN..])
+        .map_err(|_| TokenError::Signature)?;
+
+    let mut iat = [0u8; 8];
+    iat.copy_from_slice(&data[16..PAYLOAD_LEN]);
+    if u64::from_be_bytes(iat) > localtime::unix_timestamp() + MAX_CLOCK_SKEW {
+        return Err(TokenError::IssuedAt);
+    }
+
+    let mut id = [0u8; 16];
+    id.copy_from_slice(&data[..16]);
+    Ok(u128::from_be_bytes(id))
+}
+
+/// 设置无状态令牌的共享签名密钥, 只能设置一次
+pub fn set_shared_secret(secret: &str) -> anyhow_ext::Result<()> {
+    check_secret(secret)?;
+    if SHARED_SECRET.set(secret.as_bytes().to_vec()).is_err() {
+        bail!("token secret already set");
+    }
+    Ok(())
+}
+
+/// 是否启用了无状态令牌
+pub fn is_stateless() -> bool {
+    SHARED_SECRET.get().is_some()
+}
+
+/// 校验共享密钥的长度
+fn check_secret(secret: &str) -> anyhow_ext::Result<()> {
+    if secret.len() < MIN_SECRET_LEN {
+        bail!("token secret must be at least {MIN_SECRET_LEN} characters");
+    }
+    Ok(())
+}
+
+/// 使用共享密钥签发无状态令牌, 未设置共享密钥时返回None
+pub fn sign_claims(claims: &Claims) -> Option<String> {
+    SHARED_SECRET.get().map(|secret| sign_claims_with(secret, claims))
+}
+
+/// 校验无状态令牌的签名及有效期, 返回令牌中的声明
+///
+/// 未设置共享密钥或者令牌长度不符时返回`TokenError::Format`,
+/// 调用者可据此回退到会话令牌的校验
+pub fn verify_claims(token: &str) -> Result<Claims, TokenError> {
+    let secret = SHARED_SECRET.get().ok_or(TokenError::Format)?;
+    verify_claims_with(secret, token)
+}
+
+/// 使用指定的密钥签发无状态令牌
+fn sign_claims_with(secret: &[u8], claims: &Claims) -> String {
+    let mut buf = [0u8; STATELESS_LEN];
+    buf[..16].copy_from_slice(&claims.id.to_be_bytes());
+    buf[16..24].copy_from_slice(&claims.iat.to_be_bytes());
+    buf[24..32].copy_from_slice(&claims.exp.to_be_bytes());
+    buf[32..40].copy_from_slice(&claims.max_exp.to_be_bytes());
+    buf[40..44].copy_from_slice(&claims.ip.octets());
+    buf[44..CLAIMS_LEN].copy_from_slice(&claims.db.to_be_bytes());
+    let tag = shared_mac(secret, &buf[..CLAIMS_LEN]).finalize().into_bytes();
+    buf[CLAIMS_LEN..].copy_from_slice(&tag);
+    URL_SAFE_NO_PAD.encode(buf)
+}
+
+/// 使用指定的密钥校验无状态令牌
+fn verify_claims_with(secret: &[u8], token: &str) -> Result<Claims, TokenError> {
+    let data = URL_SAFE_NO_PAD.decode(token).map_err(|_| TokenError::Format)?;
+    if data.len() != STATELESS_LEN {
+        return Err(TokenError::Format);
+    }
+
+    shared_mac(secret, &data[..CLAIMS_LEN])
+        .verify_slice(&data[CLAIMS_LEN..])
+        .map_err(|_| TokenError::Signature)?;
+
+    let u64_at = |pos: usize| {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(&data[pos..pos + 8]);
+        u64::from_be_bytes(buf)
+    };
+    let mut id = [0u8; 16];
+    id.copy_from_slice(&data[..16]);
+    let mut ip = [0u8; 4];
+    ip.copy_from_slice(&data[40..44]);
+
+    let claims = Claims {
+        id: u128::from_be_bytes(id),
+        iat: u64_at(16),
+        exp: u64_at(24),
+        max_exp: u64_at(32),
+        ip: Ipv4Addr::from(ip),
+        db: u64_at(44),
+    };
+
+    let now = localtime::unix_timestamp();
+    if claims.iat > now + MAX_CLOCK_SKEW {
+        return Err(TokenError::IssuedAt);
+    }
+    if claims.exp <= now || claims.max_exp <= now {
+        return Err(TokenError::Expired);
+    }
+
+    Ok(claims)
+}
+
+fn shared_mac(secret: &[u8], payload: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret).unwrap();
+    mac.update(payload);
+    mac
+}
+
+fn new_mac(payload: &[u8]) -> HmacSha256 {
+    static SECRET: OnceLock<[u8; 32]> = OnceLock::new();
+    let secret = SECRET.get_or_init(|| {
+        let mut secret = [0u8; 32];
+        ChaCha20Rng::from_entropy().fill_bytes(&mut secret);
+        secret
+    });
+
+    // hmac支持任意长度的密钥, 不会出错
+    let mut mac = HmacSha256::new_from_slice(secret).unwrap();
+    mac.update(payload);
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded_generator_is_deterministic() {
+        let mut g1 = ChaChaTokenGenerator::with_seed(42);
+        let mut g2 = ChaChaTokenGenerator::with_seed(42);
+        let t1 = g1.next_token();
+        assert_eq!(t1, g2.next_token());
+        assert_ne!(t1, g1.next_token());
+    }
+
+    #[test]
+    fn injected_generator() {
+        struct Fixed;
+        impl TokenGenerator for Fixed {
+            fn next_token(&mut self) -> u128 { 0x1234 }
+        }
+
+        set_token_generator(Fixed);
+        assert_eq!(next_token(), 0x1234);
+        set_token_generator(ChaChaTokenGenerator::new());
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let token = sign(0xabcdef);
+        assert_eq!(verify(&token), Ok(0xabcdef));
+
+        let mut data = URL_SAFE_NO_PAD.decode(&token).unwrap();
+        data[0] ^= 1;
+        assert_eq!(verify(&URL_SAFE_NO_PAD.encode(&data)), Err(TokenError::Signature));
+        assert_eq!(verify("0123456789abcdef"), Err(TokenError::Format));
+    }
+
+    /// 测试使用的共享密钥, 不设置进程全局的`SHARED_SECRET`, 测试结果与执行顺序无关
+    const SECRET: &[u8] = b"0123456789abcdef0123";
+
+    #[test]
+    fn stateless_claims() {
+        let now = localtime::unix_timestamp();
+        let claims = Claims { id: 0x55aa, iat: now, exp: now + 60, max_exp: now + 600, ip: Ipv4Addr::new(10, 0, 0, 1), db: 1 };
+
+        let token = sign_claims_with(SECRET, &claims);
+        assert_eq!(verify_claims_with(SECRET, &token), Ok(claims));
+        // 会话令牌长度不同, 不会被当作无状态令牌
+        assert_eq!(verify_claims_with(SECRET, &sign(1)), Err(TokenError::Format));
+        // 其它实例使用不同的密钥签发
+        assert_eq!(verify_claims_with(b"fedcba9876543210fedc", &token), Err(TokenError::Signature));
+
+        let mut data = URL_SAFE_NO_PAD.decode(&token).unwrap();
+        data[35] ^= 1;
+        assert_eq!(verify_claims_with(SECRET, &URL_SAFE_NO_PAD.encode(&data)), Err(TokenError::Signature));
+
+        let expired = Claims { exp: now - 1, ..claims };
+        assert_eq!(verify_claims_with(SECRET, &sign_claims_with(SECRET, &expired)), Err(TokenError::Expired));
+        let future = Claims { iat: now + MAX_CLOCK_SKEW + 10, ..claims };
+        assert_eq!(verify_claims_with(SECRET, &sign_claims_with(SECRET, &future)), Err(TokenError::IssuedAt));
+    }
+
+    #[test]
+    fn shared_secret() {
+        assert!(check_secret("short").is_err());
+        assert!(check_secret("0123456789abcdef").is_ok());
+    }
+}
@@ -0,0 +1,162 @@
+use std::{collections::{HashMap, VecDeque}, sync::Arc};
+use anyhow_ext::Result;
+use httpserver::{HttpContext, HttpResponse, Resp};
+use localtime::LocalTime;
+use parking_lot::Mutex;
+use serde::Serialize;
+use crate::{aidb::{self, Database}, events::{self, Event}, state::AppState};
+use super::{authentication::Authentication, service};
+
+/// 每个会话最多保留的可撤销操作数量
+const MAX_UNDO: usize = 10;
+
+/// 可撤销的写操作
+struct UndoItem {
+    /// 操作名称
+    action: &'static str,
+    /// 操作时间(unix时间戳)
+    time: u64,
+    /// 操作前的数据库内容
+    before: Arc<Database>,
+    /// 操作后的数据库内容
+    after: Arc<Database>,
+}
+
+type UndoStacks = HashMap<u128, VecDeque<UndoItem>>; // key: session id
+
+/// 每个会话的撤销操作栈
+static UNDO_STACKS: Mutex<Option<UndoStacks>> = Mutex::new(None);
+
+/// 修改数据库内容并保存, 同时将本次操作记录到当前会话的撤销栈中, 并通知其它客户端
+///
+/// * `ctx`: 当前请求上下文, 用于获取会话id
+/// * `action`: 操作名称
+/// * `f`: 修改函数, 返回错误时放弃修改
+pub(super) fn update_database<T, F>(ctx: &HttpContext, action: &'static str, f: F) -> Result<T>
+where
+    F: FnOnce(&mut Database) -> Result<T>,
+{
+    let (database, pass) = service::session_db(ctx)?;
+    let (ret, before, after) = aidb::update_database_snapshot(database, &pass, f)?;
+
+    let session = Authentication::get_session_id(ctx);
+    events::publish(Event::RecordChanged { database: ctx.uid.clone(), action, origin: session });
+    if let Some(id) = session {
+        let mut stacks = UNDO_STACKS.lock();
+        let stack = stacks.get_or_insert_with(HashMap::new).entry(id).or_default();
+        if stack.len() >= MAX_UNDO {
+            stack.pop_front();
+        }
+        stack.push_back(UndoItem { action, time: localtime::unix_timestamp(), before, after });
+    }
+
+    Ok(ret)
+}
+
+/// 删除过期会话的撤销记录
+pub fn recycle_undo(st: &AppState) {
+    let expire = localtime::unix_timestamp().saturating_sub(st.session_expire);
+    let mut stacks = UNDO_STACKS.lock();
+    if let Some(stacks) = stacks.as_mut() {
+        stacks.retain(|_, v| v.back().map(|item| item.time > expire).unwrap_or(false));
+    }
+}
+
+/// 刷新令牌后, 将旧会话的撤销记录转移到新会话
+pub(super) fn move_undo(from: u128, to: u128) {
+    if let Some(stacks) = UNDO_STACKS.lock().as_mut() {
+        if let Some(stack) = stacks.remove(&from) {
+            stacks.insert(to, stack);
+        }
+    }
+}
+
+/// 删除指定会话的撤销记录
+pub(super) fn clear_undo(ctx: &HttpContext) {
+    if let Some(id) = Authentication::get_session_id(ctx) {
+        if let Some(stacks) = UNDO_STACKS.lock().as_mut() {
+            stacks.remove(&id);
+        }
+    }
+}
+
+/// 撤销当前会话最近一次的写操作
+pub async fn undo(ctx: HttpContext) -> HttpResponse {
+    #[derive(Serialize)]
+    struct ResData {
+        action: &'static str,
+        time: LocalTime,
+        remain: usize,
+    }
+
+    let id = match Authentication::get_session_id(&ctx) {
+        Some(id) => id,
+        None => httpserver::http_bail!("会话不存在"),
+    };
+
+    // 先查看最近一次操作, 恢复成功后才从撤销栈中移除, 恢复失败时撤销记录保持不变
+    let (action, time, before, after) = {
+        let stacks = UNDO_STACKS.lock();
+        match stacks.as_ref().and_then(|v| v.get(&id)).and_then(|s| s.back()) {
+            Some(item) => (item.action, item.time, item.before.clone(), item.after.clone()),
+            None => httpserver::http_bail!("没有可撤销的操作"),
+        }
+    };
+
+    let (database, pass) = service::session_db(&ctx)?;
+    let restored = aidb::restore_database(database, &pass, &after, before)?;
+    httpserver::fail_if!(!restored, "数据已被其它操作修改, 无法撤销");
+    let remain = pop_undo(id, &after);
+    events::publish(Event::RecordChanged { database: ctx.uid.clone(), action: "undo", origin: Some(id) });
+
+    Resp::ok(&ResData {
+        action,
+        time: LocalTime::from_unix_timestamp(time as i64),
+        remain,
+    })
+}
+
+/// 撤销成功后从会话的撤销栈中移除对应的操作
+///
+/// * `after`: 已撤销操作的操作后数据库内容, 用于确认栈顶仍是该操作
+///
+/// Returns:
+///
+/// 撤销栈中剩余的操作数量
+fn pop_undo(id: u128, after: &Arc<Database>) -> usize {
+    let mut stacks = UNDO_STACKS.lock();
+    match stacks.as_mut().and_then(|v| v.get_mut(&id)) {
+        Some(stack) => {
+            if stack.back().is_some_and(|item| Arc::ptr_eq(&item.after, after)) {
+                stack.pop_back();
+            }
+            stack.len()
+        }
+        None => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pop_only_undone_item() {
+        let id = 0x5eed_0001;
+        let (first, second) = (Arc::new(Database::default()), Arc::new(Database::default()));
+        {
+            let mut stacks = UNDO_STACKS.lock();
+            let stack = stacks.get_or_insert_with(HashMap::new).entry(id).or_default();
+            for after in [&first, &second] {
+                stack.push_back(UndoItem { action: "test", time: 0, before: Arc::default(), after: after.clone() });
+            }
+        }
+
+        // 栈顶已不是撤销的操作时(例如并发的撤销请求已移除)保持不变
+        assert_eq!(pop_undo(id, &first), 2);
+        assert_eq!(pop_undo(id, &second), 1);
+        assert_eq!(pop_undo(id, &first), 0);
+        assert_eq!(pop_undo(0x5eed_0002, &first), 0);
+        UNDO_STACKS.lock().as_mut().unwrap().remove(&id);
+    }
+}
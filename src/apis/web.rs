@@ -1,7 +1,20 @@
-use http_body_util::Full;
-use httpserver::{Bytes, HttpContext, HttpResponse, CONTENT_TYPE};
-use hyper::StatusCode;
+use std::{
+    borrow::Cow, collections::HashMap, ops::Range, path::{Path, PathBuf}, sync::{Arc, OnceLock},
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+use anyhow_ext::{bail, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use http_body_util::{BodyExt, Full};
+use httpserver::{Bytes, ChainHandler, ChainResult, HandlerChain, HttpContext, HttpResponse, NotFoundFormat, CONTENT_TYPE};
+use hyper::{header::{self, HeaderName, HeaderValue}, StatusCode, Uri};
+use hyper_util::{client::legacy::{connect::HttpConnector, Client}, rt::TokioExecutor};
+use parking_lot::Mutex;
 use rust_embed::RustEmbed;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use super::Authentication;
 
 #[derive(RustEmbed)]
 #[folder = "resources/"]
@@ -10,25 +23,665 @@ use rust_embed::RustEmbed;
 #[exclude = "js/*"]
 struct Asset;
 
-pub async fn default_handler(ctx: HttpContext) -> HttpResponse {
-    debug_assert!(!ctx.req.uri().path().is_empty());
-    let ac = crate::AppConf::get();
-    let mut path = &ctx.req.uri().path()[1..];
-    if !ac.no_root && path.is_empty() {
-        path = &"index.html";
-    }
+/// 内嵌的静态资源, 支持ETag条件请求及Range请求
+pub struct Assets {
+    /// 访问根路径时返回index.html
+    root_index: bool,
+    /// 各资源的ETag, 启动时根据内容哈希计算, key: 资源路径
+    etags: HashMap<String, HeaderValue>,
+    /// 替换路径前缀占位符后的index.html
+    index: Option<Bytes>,
+}
+
+/// 磁盘目录中的静态资源, 用于不重新编译即可定制前端页面
+pub struct Files {
+    /// 资源根目录(规范化后的绝对路径)
+    root: PathBuf,
+    /// 访问目录时返回目录下的index.html
+    root_index: bool,
+}
+
+/// 反向代理, 将请求转发到指定的http服务, 例如开发时的前端服务
+pub struct ReverseProxy {
+    /// 目标地址, 不含结尾的`/`
+    target: String,
+    client: Client<HttpConnector, Full<Bytes>>,
+    /// 请求超时时间, 包含读取回复内容的时间
+    timeout: Duration,
+    /// 目标服务连续失败时熔断, 冷却期内直接返回502
+    breaker: Arc<CircuitBreaker>,
+}
+
+/// 熔断器, 连续失败达到阈值后打开, 冷却时间过后放行一个试探请求,
+/// 试探成功则关闭, 失败则重新打开
+pub struct CircuitBreaker {
+    /// 打开熔断的连续失败次数, 0表示不熔断
+    threshold: u32,
+    /// 熔断打开后的冷却时间
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+/// 熔断器放行的请求, 通过`succeeded`或`failed`报告结果
+///
+/// 试探请求未报告结果就被丢弃时(例如客户端断开导致处理中止)复位试探状态, 由下一个请求重新试探,
+/// 避免熔断器一直停留在试探中
+struct Permit<'a> {
+    breaker: &'a CircuitBreaker,
+    /// 是否为冷却结束后的试探请求
+    probe: bool,
+}
+
+#[derive(Default)]
+struct BreakerState {
+    /// 连续失败次数
+    failures: u32,
+    /// 熔断打开的截止时间, None表示关闭
+    open_until: Option<Instant>,
+    /// 冷却结束后是否已放行试探请求
+    probing: bool,
+    /// 熔断累计打开次数
+    trips: u64,
+    /// 最后一次失败的原因
+    last_error: String,
+}
+
+/// 熔断器状态, 用于管理接口展示
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BreakerStatus {
+    /// 代理目标地址
+    pub target: String,
+    /// 状态: closed(正常)、open(熔断中)、half-open(试探中)
+    pub state: &'static str,
+    /// 连续失败次数
+    pub failures: u32,
+    /// 熔断剩余时间(单位: 秒)
+    pub retry_after: u64,
+    /// 熔断累计打开次数
+    pub trips: u64,
+    /// 最后一次失败的原因
+    pub last_error: String,
+}
 
-    let f = match Asset::get(path) {
+/// 缺省处理链中的反向代理, 用于管理接口查询熔断状态
+static PROXY: OnceLock<(String, Arc<CircuitBreaker>)> = OnceLock::new();
+
+/// 不转发的逐跳请求头及认证请求头, 配置的会话令牌请求头在转发时另外去除
+const SKIP_HEADERS: [HeaderName; 9] = [
+    header::CONNECTION, header::PROXY_AUTHENTICATE, header::PROXY_AUTHORIZATION, header::TE,
+    header::TRAILER, header::TRANSFER_ENCODING, header::UPGRADE, header::HOST, header::AUTHORIZATION,
+];
+const X_FORWARDED_FOR: &str = "X-Forwarded-For";
+/// 文件名中带内容哈希的资源, 内容变化时文件名随之变化, 可以永久缓存
+const CACHE_IMMUTABLE: &str = "public, max-age=31536000, immutable";
+/// 其它资源每次使用前都需要通过ETag校验
+const CACHE_NO_CACHE: &str = "no-cache";
+/// 文件名中哈希段的最小长度
+const MIN_HASH_LEN: usize = 8;
+/// Range请求支持的单位
+const RANGE_UNIT: &str = "bytes";
+/// 磁盘文件每次读取发送的数据块大小
+const FILE_CHUNK_SIZE: usize = 64 * 1024;
+/// 首页文件名
+const INDEX_HTML: &str = "index.html";
+/// index.html中的路径前缀占位符, 启动时替换为`--base-path`
+const BASE_PATH_PLACEHOLDER: &str = "%BASE_PATH%";
+
+/// 根据配置创建缺省处理链
+///
+/// * `steps`: 逗号分隔的处理步骤, 按顺序尝试, 支持`assets`(内嵌资源)、`proxy`(反向代理)
+/// * `proxy`: 反向代理的目标地址, 使用`proxy`步骤时必须设置
+/// * `not_found`: 404回复的格式(auto/json/html)
+/// * `www_dir`: 磁盘资源目录, 设置时`assets`步骤优先使用该目录中的文件
+/// * `base_path`: 子路径部署时的路径前缀, 替换内嵌index.html中的占位符
+pub fn default_chain(steps: &str, proxy: &str, not_found: &str, www_dir: &str, base_path: &str) -> Result<HandlerChain> {
+    let format = match NotFoundFormat::parse(not_found) {
         Some(f) => f,
-        None => return resp(hyper::StatusCode::NOT_FOUND, "plain", "Not Found"),
+        None => bail!("unsupported not found format: {not_found}"),
     };
+    // 分享页面总是可用, 不受处理链配置影响
+    let mut chain = HandlerChain::new().not_found(format).then(super::SharePage);
+
+    for step in steps.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        chain = match step {
+            "assets" => {
+                let root_index = !crate::AppConf::get().no_root;
+                if !www_dir.is_empty() {
+                    chain = chain.then(Files::new(www_dir, root_index)?);
+                }
+                chain.then(Assets::new(root_index, base_path))
+            }
+            "proxy" => {
+                if proxy.is_empty() {
+                    bail!("fallback step proxy requires a proxy target");
+                }
+                let ac = crate::AppConf::get();
+                let (threshold, cooldown) = match (ac.proxy_failures.parse(), ac.proxy_cooldown.parse()) {
+                    (Ok(t), Ok(c)) => (t, Duration::from_secs(c)),
+                    _ => bail!("proxy-failures or proxy-cooldown format error"),
+                };
+                let timeout = match ac.proxy_timeout.parse() {
+                    Ok(t) if t > 0 => Duration::from_secs(t),
+                    _ => bail!("proxy-timeout format error"),
+                };
+                let proxy = ReverseProxy::new(proxy, timeout, CircuitBreaker::new(threshold, cooldown))?;
+                let _ = PROXY.set((proxy.target.clone(), proxy.breaker.clone()));
+                chain.then(proxy)
+            }
+            _ => bail!("unsupported fallback step: {step}"),
+        };
+    }
+
+    Ok(chain)
+}
+
+impl Assets {
+    /// 创建内嵌资源处理器, 同时计算所有资源的ETag
+    ///
+    /// * `root_index`: 访问根路径时返回index.html
+    /// * `base_path`: 子路径部署时的路径前缀, 替换index.html中的占位符
+    pub fn new(root_index: bool, base_path: &str) -> Self {
+        let mut etags: HashMap<String, HeaderValue> = Asset::iter()
+            .filter_map(|path| {
+                let f = Asset::get(&path)?;
+                Some((path.into_owned(), content_etag(&f.metadata.sha256_hash())?))
+            })
+            .collect();
+
+        // index.html的内容随路径前缀变化, 按替换后的内容计算ETag
+        let index = Asset::get(INDEX_HTML).map(|f| {
+            let html = String::from_utf8_lossy(&f.data).replace(BASE_PATH_PLACEHOLDER, base_path);
+            Bytes::from(html)
+        });
+        if let Some(etag) = index.as_ref().and_then(|data| content_etag(&Sha256::digest(data))) {
+            etags.insert(INDEX_HTML.to_owned(), etag);
+        }
+        Assets { root_index, etags, index }
+    }
+}
+
+/// 根据内容的sha256哈希生成ETag
+fn content_etag(hash: &[u8]) -> Option<HeaderValue> {
+    HeaderValue::from_str(&format!("\"{}\"", URL_SAFE_NO_PAD.encode(&hash[..16]))).ok()
+}
+
+/// 请求头If-None-Match中是否包含指定的ETag
+fn is_not_modified(ctx: &HttpContext, etag: &HeaderValue) -> bool {
+    let inm = match ctx.header_str(header::IF_NONE_MATCH) {
+        Some(v) => v,
+        None => return false,
+    };
+    let etag = etag.to_str().unwrap_or_default();
+    inm.split(',')
+        .map(|t| t.trim())
+        .any(|t| t == "*" || t.strip_prefix("W/").unwrap_or(t) == etag)
+}
 
-    let ext = match std::path::Path::new(&path).extension() {
-        Some(s) => s.to_str().unwrap(),
+/// If-Range条件是否成立, 没有该请求头时成立, 只支持强ETag比较
+fn is_range_valid(ctx: &HttpContext, etag: Option<&HeaderValue>) -> bool {
+    match ctx.header_value(header::IF_RANGE) {
+        Some(v) => etag.is_some_and(|etag| v == etag),
+        None => true,
+    }
+}
+
+/// Range请求的解析结果
+enum ByteRange {
+    /// 请求头不存在或者格式不支持, 返回完整内容
+    Full,
+    /// 单个有效的范围
+    Partial(Range<usize>),
+    /// 范围超出内容长度
+    Unsatisfiable,
+}
+
+/// 解析Range请求头, 只支持单个范围, 多个范围时返回完整内容
+///
+/// * `value`: 请求头的值, 形如`bytes=0-99`、`bytes=100-`、`bytes=-100`
+/// * `len`: 内容长度
+fn parse_range(value: &str, len: usize) -> ByteRange {
+    let spec = match value.trim().strip_prefix(RANGE_UNIT).and_then(|s| s.strip_prefix('=')) {
+        Some(s) if !s.contains(',') => s.trim(),
+        _ => return ByteRange::Full,
+    };
+    let (start, end) = match spec.split_once('-') {
+        Some(v) => v,
+        None => return ByteRange::Full,
+    };
+
+    let (start, end) = match (start.parse::<usize>().ok(), end.parse::<usize>().ok()) {
+        // bytes=-n, 最后n个字节
+        (None, Some(n)) if start.is_empty() => match n {
+            0 => return ByteRange::Unsatisfiable,
+            n => (len.saturating_sub(n), len),
+        },
+        (Some(s), None) if end.is_empty() => (s, len),
+        (Some(s), Some(e)) if s <= e => (s, len.min(e.saturating_add(1))),
+        _ => return ByteRange::Full,
+    };
+
+    if start >= len {
+        ByteRange::Unsatisfiable
+    } else {
+        ByteRange::Partial(start..end)
+    }
+}
+
+impl Files {
+    /// 创建磁盘资源处理器, 目录必须存在
+    pub fn new(root: &str, root_index: bool) -> Result<Self> {
+        let root = match Path::new(root).canonicalize() {
+            Ok(p) if p.is_dir() => p,
+            Ok(p) => bail!("www dir {} is not a directory", p.display()),
+            Err(e) => bail!("www dir {root} error: {e}"),
+        };
+        Ok(Files { root, root_index })
+    }
+
+    /// 将请求路径转换为根目录下的文件路径, 拒绝任何试图访问根目录之外的路径
+    fn resolve(&self, uri_path: &str) -> Option<PathBuf> {
+        let mut path = self.root.clone();
+        for seg in uri_path.split('/').filter(|s| !s.is_empty()) {
+            // 不解码路径, 含有编码字符、上级目录、隐藏文件或者windows路径分隔符的一律拒绝
+            if seg.starts_with('.') || seg.contains(['%', '\\', ':', '\0']) {
+                return None;
+            }
+            path.push(seg);
+        }
+        if path.is_dir() {
+            if !self.root_index {
+                return None;
+            }
+            path.push("index.html");
+        }
+        // 通过符号链接指向根目录之外的文件同样拒绝
+        let path = path.canonicalize().ok()?;
+        (path.starts_with(&self.root) && path.is_file()).then_some(path)
+    }
+}
+
+/// 磁盘文件的ETag, 由文件长度及修改时间生成
+fn file_etag(path: &Path) -> Option<HeaderValue> {
+    let meta = std::fs::metadata(path).ok()?;
+    let mtime = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    HeaderValue::from_str(&format!("\"{:x}-{:x}\"", meta.len(), mtime.as_nanos())).ok()
+}
+
+/// 资源的相对路径(不含开头的`/`), 注册为通配符路由(`www/*path`)时为通配符捕获的剩余路径
+fn request_path(ctx: &HttpContext) -> &str {
+    match ctx.params.wildcard() {
+        Some(path) => path,
+        None => ctx.req.uri().path().trim_start_matches('/'),
+    }
+}
+
+/// 去除url参数中名称为`name`的参数, `name`为空时原样返回
+fn strip_query_param<'a>(query: &'a str, name: &str) -> Cow<'a, str> {
+    let matched = |kv: &str| kv.split('=').next() == Some(name);
+    if name.is_empty() || !query.split('&').any(matched) {
+        return Cow::Borrowed(query);
+    }
+    let kept: Vec<&str> = query.split('&').filter(|kv| !matched(kv)).collect();
+    Cow::Owned(kept.join("&"))
+}
+
+/// 文件名是否包含内容哈希, 例如`app.3f2a1b9c.js`、`chunk-5d41402a.css`
+fn is_hashed_name(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    let stem = name.rsplit_once('.').map(|(s, _)| s).unwrap_or(name);
+    stem.split(['.', '-', '_'])
+        .skip(1)
+        .any(|s| s.len() >= MIN_HASH_LEN && s.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+impl ReverseProxy {
+    /// 创建反向代理, 目标地址只支持http协议
+    ///
+    /// * `timeout`: 请求超时时间, 包含读取回复内容的时间
+    pub fn new(target: &str, timeout: Duration, breaker: CircuitBreaker) -> Result<Self> {
+        let uri: Uri = match target.parse() {
+            Ok(uri) => uri,
+            Err(e) => bail!("proxy target format error: {e}"),
+        };
+        if uri.scheme_str() != Some("http") || uri.authority().is_none() {
+            bail!("proxy target must be http://host[:port][/path]");
+        }
+
+        Ok(ReverseProxy {
+            target: target.trim_end_matches('/').to_owned(),
+            client: Client::builder(TokioExecutor::new()).build_http(),
+            timeout,
+            breaker: Arc::new(breaker),
+        })
+    }
+
+    async fn forward(&self, ctx: &HttpContext) -> HttpResponse {
+        // 会话令牌不转发给代理目标
+        let (token_header, token_query) = Authentication::token_carriers();
+        let query = ctx.req.uri().query().map(|q| strip_query_param(q, token_query)).unwrap_or_default();
+        let uri = if query.is_empty() {
+            format!("{}/{}", self.target, request_path(ctx))
+        } else {
+            format!("{}/{}?{query}", self.target, request_path(ctx))
+        };
+        let mut builder = hyper::Request::builder()
+            .method(ctx.req.method().clone())
+            .uri(uri);
+        for (name, value) in ctx.req.headers() {
+            if !SKIP_HEADERS.contains(name) && name != token_header {
+                builder = builder.header(name, value);
+            }
+        }
+        builder = builder.header(X_FORWARDED_FOR, ctx.remote_ip().to_string());
+
+        let res = self.client.request(builder.body(Full::new(ctx.body.clone()))?).await?;
+        let (parts, body) = res.into_parts();
+        let body = body.collect().await?.to_bytes();
+
+        let mut builder = hyper::Response::builder().status(parts.status);
+        for (name, value) in parts.headers.iter() {
+            if !SKIP_HEADERS.contains(name) {
+                builder = builder.header(name, value);
+            }
+        }
+        Ok(builder.body(Full::new(body))?)
+    }
+}
+
+#[async_trait::async_trait]
+impl ChainHandler for Assets {
+    async fn handle(&self, ctx: HttpContext) -> ChainResult {
+        let mut path = request_path(&ctx);
+        if self.root_index && path.is_empty() {
+            path = INDEX_HTML;
+        }
+        if path == INDEX_HTML {
+            if let Some(data) = &self.index {
+                return ChainResult::Done(serve_content(&ctx, path, data.clone(), self.etags.get(path)));
+            }
+        }
+
+        let f = match Asset::get(path) {
+            Some(f) => f,
+            None => return ChainResult::pass(ctx),
+        };
+
+        // 发布版本的资源数据是静态引用, 直接使用无需复制
+        let data = match f.data {
+            Cow::Borrowed(data) => Bytes::from_static(data),
+            Cow::Owned(data) => Bytes::from(data),
+        };
+        ChainResult::Done(serve_content(&ctx, path, data, self.etags.get(path)))
+    }
+}
+
+#[async_trait::async_trait]
+impl ChainHandler for Files {
+    async fn handle(&self, ctx: HttpContext) -> ChainResult {
+        let path = match self.resolve(request_path(&ctx)) {
+            Some(path) => path,
+            None => return ChainResult::pass(ctx),
+        };
+        let len = match tokio::fs::metadata(&path).await {
+            Ok(meta) => meta.len() as usize,
+            Err(e) => {
+                log::warn!("read www file {} error: {e:?}", path.display());
+                return ChainResult::pass(ctx);
+            }
+        };
+        let etag = file_etag(&path);
+        let name = path.file_name().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        // 文件内容分块读取发送, 大文件无需完整读入内存
+        ChainResult::Done(serve_range(&ctx, &name, len, etag.as_ref(), |status, ext, range| {
+            stream_file(path, status, ext, range)
+        }))
+    }
+}
+
+/// 流式回复磁盘文件的指定范围, 后台任务按块读取并发送, 读取失败时中止回复
+fn stream_file(path: PathBuf, status: StatusCode, content_type: &str, range: Range<usize>) -> HttpResponse {
+    let builder = hyper::Response::builder().status(status).header(CONTENT_TYPE, map_content_type(content_type));
+    let (res, tx) = httpserver::StreamBody::response(builder, Some(range.len() as u64))?;
+    tokio::spawn(async move {
+        if let Err(e) = send_file(&path, range, &tx).await {
+            log::warn!("send www file {} error: {e:?}", path.display());
+            tx.abort();
+        }
+    });
+    Ok(res)
+}
+
+async fn send_file(path: &Path, range: Range<usize>, tx: &httpserver::StreamSender) -> Result<()> {
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+    let mut file = tokio::fs::File::open(path).await?;
+    file.seek(std::io::SeekFrom::Start(range.start as u64)).await?;
+    let mut remain = range.len();
+    while remain > 0 {
+        let mut buf = vec![0; remain.min(FILE_CHUNK_SIZE)];
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            bail!("file truncated");
+        }
+        buf.truncate(n);
+        remain -= n;
+        // 客户端已断开
+        if !tx.send(Bytes::from(buf)).await {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl ChainHandler for ReverseProxy {
+    async fn handle(&self, ctx: HttpContext) -> ChainResult {
+        let permit = match self.breaker.acquire() {
+            Ok(permit) => permit,
+            Err(wait) => {
+                let res = resp(StatusCode::BAD_GATEWAY, "plain", "Bad Gateway").map(|mut res| {
+                    res.headers_mut().insert(header::RETRY_AFTER, wait.into());
+                    res
+                });
+                return ChainResult::Done(res);
+            }
+        };
+
+        match tokio::time::timeout(self.timeout, self.forward(&ctx)).await {
+            Ok(Ok(res)) => {
+                // 网关类错误说明目标服务不可用, 同样计为失败
+                let status = res.status();
+                if matches!(status, StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE
+                        | StatusCode::GATEWAY_TIMEOUT) {
+                    permit.failed(&self.target, status.as_str());
+                } else {
+                    permit.succeeded();
+                }
+                ChainResult::Done(Ok(res))
+            }
+            Ok(Err(e)) => {
+                log::warn!("reverse proxy {} error: {e:?}", ctx.req.uri().path());
+                permit.failed(&self.target, &e.to_string());
+                ChainResult::Done(resp(StatusCode::BAD_GATEWAY, "plain", "Bad Gateway"))
+            }
+            Err(_) => {
+                log::warn!("reverse proxy {} timeout after {}s", ctx.req.uri().path(), self.timeout.as_secs());
+                permit.failed(&self.target, "timeout");
+                ChainResult::Done(resp(StatusCode::GATEWAY_TIMEOUT, "plain", "Gateway Timeout"))
+            }
+        }
+    }
+}
+
+impl CircuitBreaker {
+    /// 创建熔断器
+    ///
+    /// * `threshold`: 打开熔断的连续失败次数, 0表示不熔断
+    /// * `cooldown`: 熔断打开后的冷却时间
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        CircuitBreaker { threshold, cooldown, state: Mutex::new(BreakerState::default()) }
+    }
+
+    /// 请求前检查是否允许访问目标服务
+    ///
+    /// Returns:
+    ///
+    /// 熔断中返回Err(需要等待的秒数), 冷却结束后只放行一个试探请求
+    fn acquire(&self) -> std::result::Result<Permit<'_>, u64> {
+        let mut state = self.state.lock();
+        let until = match state.open_until {
+            Some(until) => until,
+            None => return Ok(Permit { breaker: self, probe: false }),
+        };
+        let now = Instant::now();
+        if until > now {
+            return Err((until - now).as_secs().max(1));
+        }
+        if state.probing {
+            // 试探请求尚未返回, 其它请求继续快速失败
+            return Err(1);
+        }
+        state.probing = true;
+        Ok(Permit { breaker: self, probe: true })
+    }
+
+    fn succeeded(&self) {
+        let mut state = self.state.lock();
+        if state.open_until.is_some() {
+            log::info!("reverse proxy circuit closed");
+        }
+        state.failures = 0;
+        state.open_until = None;
+        state.probing = false;
+    }
+
+    fn failed(&self, target: &str, reason: &str) {
+        let mut state = self.state.lock();
+        state.failures += 1;
+        state.last_error = reason.to_owned();
+        if self.threshold == 0 || (state.failures < self.threshold && !state.probing) {
+            return;
+        }
+        // 达到阈值或者试探请求失败时(重新)打开熔断
+        state.open_until = Some(Instant::now() + self.cooldown);
+        state.probing = false;
+        state.trips += 1;
+        log::warn!("reverse proxy circuit opened for {}s, target: {target}, failures: {}, error: {reason}",
+            self.cooldown.as_secs(), state.failures);
+    }
+
+    /// 当前状态
+    pub fn status(&self, target: &str) -> BreakerStatus {
+        let state = self.state.lock();
+        let now = Instant::now();
+        let (name, retry_after) = match state.open_until {
+            None => ("closed", 0),
+            Some(until) if until > now => ("open", (until - now).as_secs().max(1)),
+            Some(_) => ("half-open", 0),
+        };
+        BreakerStatus {
+            target: target.to_owned(),
+            state: name,
+            failures: state.failures,
+            retry_after,
+            trips: state.trips,
+            last_error: state.last_error.clone(),
+        }
+    }
+}
+
+impl Permit<'_> {
+    /// 请求成功
+    fn succeeded(mut self) {
+        self.probe = false;
+        self.breaker.succeeded();
+    }
+
+    /// 请求失败
+    fn failed(mut self, target: &str, reason: &str) {
+        self.probe = false;
+        self.breaker.failed(target, reason);
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if self.probe {
+            self.breaker.state.lock().probing = false;
+        }
+    }
+}
+
+/// 缺省处理链中反向代理的熔断状态, 未启用反向代理时返回None
+pub fn proxy_status() -> Option<BreakerStatus> {
+    PROXY.get().map(|(target, breaker)| breaker.status(target))
+}
+
+/// 回复静态资源内容, 处理ETag条件请求及Range请求
+///
+/// * `path`: 资源路径, 用于确定内容类型及缓存策略
+/// * `data`: 资源内容
+/// * `etag`: 资源的ETag
+fn serve_content(ctx: &HttpContext, path: &str, data: Bytes, etag: Option<&HeaderValue>) -> HttpResponse {
+    serve_range(ctx, path, data.len(), etag, |status, ext, range| resp(status, ext, data.slice(range)))
+}
+
+/// 处理ETag条件请求及Range请求, 由`body`生成指定范围内容的回复
+///
+/// * `len`: 资源长度
+/// * `body`: 参数为状态码、资源的扩展名及回复的内容范围
+fn serve_range<F>(ctx: &HttpContext, path: &str, len: usize, etag: Option<&HeaderValue>, body: F) -> HttpResponse
+where
+    F: FnOnce(StatusCode, &str, Range<usize>) -> HttpResponse,
+{
+    let ext = match std::path::Path::new(path).extension() {
+        Some(s) => s.to_str().unwrap_or_default(),
         None => "",
     };
+    let cache_control = if is_hashed_name(path) { CACHE_IMMUTABLE } else { CACHE_NO_CACHE };
+
+    if let Some(etag) = etag {
+        if is_not_modified(ctx, etag) {
+            return Ok(
+                hyper::Response::builder()
+                    .status(StatusCode::NOT_MODIFIED)
+                    .header(header::ETAG, etag)
+                    .header(header::CACHE_CONTROL, cache_control)
+                    .body(Full::new(Bytes::new()))?
+            );
+        }
+    }
+
+    let range = match ctx.header_str(header::RANGE) {
+        Some(v) if is_range_valid(ctx, etag) => parse_range(v, len),
+        _ => ByteRange::Full,
+    };
 
-    resp(StatusCode::OK, ext, f.data.to_vec())
+    let mut res = match range {
+        ByteRange::Full => body(StatusCode::OK, ext, 0..len)?,
+        ByteRange::Partial(r) => {
+            let content_range = format!("{RANGE_UNIT} {}-{}/{len}", r.start, r.end - 1);
+            let mut res = body(StatusCode::PARTIAL_CONTENT, ext, r)?;
+            res.headers_mut().insert(header::CONTENT_RANGE, HeaderValue::from_str(&content_range)?);
+            res
+        }
+        ByteRange::Unsatisfiable => {
+            return Ok(
+                hyper::Response::builder()
+                    .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .header(header::CONTENT_RANGE, format!("{RANGE_UNIT} */{len}"))
+                    .body(Full::new(Bytes::new()))?
+            );
+        }
+    };
+    let headers = res.headers_mut();
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static(RANGE_UNIT));
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static(cache_control));
+    if let Some(etag) = etag {
+        headers.insert(header::ETAG, etag.clone());
+    }
+    Ok(res)
 }
 
 fn resp<T: Into<Bytes>>(status: StatusCode, content_type: &str, body: T) -> HttpResponse {
@@ -50,6 +703,45 @@ fn map_content_type(file_type: &str) -> &'static str {
         "png"  => "image/png",
         "jpg"  => "image/jpeg",
         "gif"  => "image/gif",
+        "svg"  => "image/svg+xml",
+        "webp" => "image/webp",
+        "json" => "application/json",
+        "txt"  => "text/plain",
+        "woff2" => "font/woff2",
+        "wasm" => "application/wasm",
         _      => "text/plain",
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn breaker_probe() {
+        let breaker = CircuitBreaker::new(1, Duration::ZERO);
+        breaker.acquire().unwrap().failed("http://127.0.0.1:1", "connect refused");
+        assert_eq!(breaker.status("").state, "half-open");
+
+        // 试探请求进行中, 其它请求快速失败
+        let probe = breaker.acquire().unwrap();
+        assert!(probe.probe);
+        assert_eq!(breaker.acquire().err(), Some(1));
+        // 试探请求未报告结果就被丢弃, 下一个请求重新试探
+        drop(probe);
+        let probe = breaker.acquire().unwrap();
+        assert!(probe.probe);
+        probe.succeeded();
+        assert_eq!(breaker.status("").state, "closed");
+        assert!(!breaker.acquire().unwrap().probe);
+    }
+
+    #[test]
+    fn strip_token_query() {
+        assert_eq!(strip_query_param("a=1&token=x&b=2", "token"), "a=1&b=2");
+        assert_eq!(strip_query_param("token=x", "token"), "");
+        assert_eq!(strip_query_param("token", "token"), "");
+        assert_eq!(strip_query_param("tokens=x&a=1", "token"), "tokens=x&a=1");
+        assert_eq!(strip_query_param("token=x", ""), "token=x");
+    }
+}
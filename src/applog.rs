@@ -0,0 +1,127 @@
+//! 应用日志, 时间戳与访问日志一样按`--log-timezone`及`--log-time-format`格式化
+//!
+//! 时区使用显式的utc/本地偏移计算, 不依赖进程的TZ环境变量;
+//! 日志同时输出到控制台及日志文件, 日志文件超过上限时改名为`<日志文件>.bak`后重新创建
+use std::{fs::{File, OpenOptions}, io::{BufWriter, Write}, path::PathBuf};
+
+use anyhow_ext::{anyhow, bail, Context, Result};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use parking_lot::{Mutex, RwLock};
+
+struct AppLogger {
+    level: LevelFilter,
+    /// 按日志目标(模块路径前缀)设置的级别
+    targets: RwLock<Vec<(String, LevelFilter)>>,
+    console: bool,
+    file: Option<Mutex<LogFile>>,
+}
+
+struct LogFile {
+    path: PathBuf,
+    out: BufWriter<File>,
+    size: u64,
+    /// 文件大小上限, 0表示不限制
+    max: u64,
+}
+
+static LOGGER: std::sync::OnceLock<AppLogger> = std::sync::OnceLock::new();
+
+/// 初始化应用日志, 只能调用一次
+///
+/// * `level`: 日志级别
+/// * `file`: 日志文件名, 为空时不输出到文件
+/// * `max`: 日志文件大小上限, 0表示不限制
+/// * `console`: 是否输出到控制台
+pub fn init_log(level: Level, file: &str, max: u64, console: bool) -> Result<()> {
+    let file = match file {
+        "" => None,
+        f => Some(Mutex::new(LogFile::open(PathBuf::from(f), max)?)),
+    };
+    let level = level.to_level_filter();
+    let logger = AppLogger { level, targets: RwLock::new(Vec::new()), console, file };
+    if LOGGER.set(logger).is_err() {
+        bail!("log already initialized");
+    }
+    log::set_logger(LOGGER.get().unwrap()).map_err(|e| anyhow!("set logger error: {e}"))?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+/// 设置指定日志目标(及其子模块)的级别
+pub fn set_level(target: &str, level: LevelFilter) {
+    if let Some(logger) = LOGGER.get() {
+        let mut targets = logger.targets.write();
+        targets.retain(|(t, _)| t != target);
+        targets.push((target.to_owned(), level));
+        // 前缀长的优先匹配
+        targets.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+    }
+}
+
+impl AppLogger {
+    fn level_of(&self, target: &str) -> LevelFilter {
+        self.targets.read().iter()
+            .find(|(t, _)| target.strip_prefix(t.as_str()).is_some_and(|s| s.is_empty() || s.starts_with("::")))
+            .map(|(_, level)| *level)
+            .unwrap_or(self.level)
+    }
+}
+
+impl Log for AppLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level_of(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format!("[{}] [{:5}] [{}] {}\n", httpserver::log_time(false), record.level(),
+            record.target(), record.args());
+        if self.console {
+            let _ = std::io::stdout().lock().write_all(line.as_bytes());
+        }
+        if let Some(file) = &self.file {
+            if let Err(e) = file.lock().write(line.as_bytes()) {
+                eprintln!("write log file error: {e}");
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file {
+            let _ = file.lock().out.flush();
+        }
+    }
+}
+
+impl LogFile {
+    fn open(path: PathBuf, max: u64) -> Result<Self> {
+        let f = OpenOptions::new().create(true).append(true).open(&path)
+            .with_context(|| format!("open log file {}", path.display()))?;
+        let size = f.metadata()?.len();
+        Ok(LogFile { path, out: BufWriter::new(f), size, max })
+    }
+
+    fn write(&mut self, line: &[u8]) -> std::io::Result<()> {
+        if self.max > 0 && self.size > 0 && self.size + line.len() as u64 > self.max {
+            self.rotate()?;
+        }
+        self.out.write_all(line)?;
+        self.out.flush()?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// 当前日志文件改名为`.bak`后重新创建
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.out.flush()?;
+        let mut bak = self.path.clone().into_os_string();
+        bak.push(".bak");
+        std::fs::rename(&self.path, bak)?;
+        let f = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.out = BufWriter::new(f);
+        self.size = 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,252 @@
+//! 命令行执行失败时的退出码及错误输出
+//!
+//! 退出码参考sysexits.h, 各版本保持不变, 脚本可以据此判断失败原因;
+//! 使用`--json-errors`时错误以单行json输出到标准错误: `{"code":78,"error":"config","message":"...","hint":"...","issues":[]}`
+use std::{error::Error, fmt::Display, sync::atomic::{AtomicBool, Ordering}};
+
+use serde::Serialize;
+
+/// 进程退出码
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitCode {
+    /// 命令行参数错误, 例如缺少必需的参数或者参数组合错误
+    Usage = 64,
+    /// 输入数据格式错误, 例如导入文件或者数据库文件内容错误
+    DataErr = 65,
+    /// 输入文件不存在或者无法读取
+    NoInput = 66,
+    /// 内部错误
+    Software = 70,
+    /// 无法创建输出文件
+    CantCreat = 73,
+    /// 读写文件或者网络错误
+    IoErr = 74,
+    /// 临时错误, 例如数据库被其它进程锁定, 稍后重试可能成功
+    TempFail = 75,
+    /// 口令错误或者没有权限
+    NoPerm = 77,
+    /// 配置项的值错误
+    Config = 78,
+}
+
+/// 命令行执行失败的错误
+#[derive(Debug)]
+pub struct CliError {
+    pub code: ExitCode,
+    pub message: String,
+    /// 解决问题的提示, 可以为空
+    pub hint: String,
+    /// 启动诊断发现的配置项错误
+    pub issues: Vec<ConfIssue>,
+}
+
+/// 配置项错误
+#[derive(Debug, Serialize)]
+pub struct ConfIssue {
+    /// 配置项的命令行参数名(不含`--`)
+    pub key: String,
+    pub value: String,
+    pub message: String,
+}
+
+/// 启动诊断, 收集所有配置项的错误后一起输出, 避免每次只能发现一个错误
+#[derive(Default)]
+pub struct Diagnostics {
+    issues: Vec<ConfIssue>,
+}
+
+/// 以json格式输出错误
+static JSON_ERRORS: AtomicBool = AtomicBool::new(false);
+
+/// 设置是否以json格式输出错误
+pub fn set_json_errors(json: bool) {
+    JSON_ERRORS.store(json, Ordering::Relaxed);
+}
+
+/// 命令行参数中是否指定了该配置项
+///
+/// * `args`: 命令行参数(不含程序名)
+/// * `short`: 短参数名, 为空表示没有短参数
+/// * `long`: 长参数名
+pub fn arg_present(args: &[String], short: &str, long: &str) -> bool {
+    args.iter().any(|a| {
+        match a.strip_prefix("--") {
+            Some(a) => a == long || a.strip_prefix(long).is_some_and(|v| v.starts_with('=')),
+            None => !short.is_empty() && a.strip_prefix('-') == Some(short),
+        }
+    })
+}
+
+/// 命令行参数中该参数的值, 支持`-x value`、`--name value`及`--name=value`
+///
+/// * `args`: 命令行参数(不含程序名)
+/// * `short`: 短参数名, 为空表示没有短参数
+/// * `long`: 长参数名
+pub fn arg_value<'a>(args: &'a [String], short: &str, long: &str) -> Option<&'a str> {
+    let mut iter = args.iter();
+    while let Some(a) = iter.next() {
+        match a.strip_prefix("--") {
+            Some(a) if a == long => return iter.next().map(String::as_str),
+            Some(a) => {
+                if let Some(v) = a.strip_prefix(long).and_then(|v| v.strip_prefix('=')) {
+                    return Some(v);
+                }
+            }
+            None if !short.is_empty() && a.strip_prefix('-') == Some(short) => return iter.next().map(String::as_str),
+            None => {}
+        }
+    }
+    None
+}
+
+impl ExitCode {
+    /// 退出码的名称, 用于json输出
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExitCode::Usage => "usage",
+            ExitCode::DataErr => "data",
+            ExitCode::NoInput => "no_input",
+            ExitCode::Software => "software",
+            ExitCode::CantCreat => "cant_create",
+            ExitCode::IoErr => "io",
+            ExitCode::TempFail => "temp_fail",
+            ExitCode::NoPerm => "no_perm",
+            ExitCode::Config => "config",
+        }
+    }
+}
+
+impl CliError {
+    pub fn new(code: ExitCode, message: impl Display) -> Self {
+        CliError { code, message: message.to_string(), hint: String::new(), issues: Vec::new() }
+    }
+
+    /// 设置解决问题的提示
+    pub fn hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = hint.into();
+        self
+    }
+
+    /// 配置项的值格式错误
+    ///
+    /// * `key`: 配置项的命令行参数名(不含`--`)
+    /// * `value`: 配置项的值
+    pub fn config(key: &str, value: &str) -> Self {
+        CliError::new(ExitCode::Config, format!("--{key} {value:?} format error"))
+            .hint(format!("run with --help to see the format of --{key}"))
+    }
+
+    /// 缺少必需的命令行参数
+    pub fn missing(key: &str, usage: &str) -> Self {
+        CliError::new(ExitCode::Usage, format!("must use --{key} {usage}"))
+    }
+
+    /// 根据错误原因确定退出码, 文件读写错误按错误类型区分, 其它错误使用`default`
+    ///
+    /// * `default`: 非文件读写错误时的退出码
+    /// * `context`: 错误的上下文描述, 例如正在执行的操作
+    /// * `err`: 错误, 支持anyhow及其它可以解引用为标准错误的类型
+    pub fn from_error<E>(default: ExitCode, context: impl Display, err: E) -> Self
+    where
+        E: std::ops::Deref<Target = dyn Error + Send + Sync + 'static>,
+    {
+        let mut message = format!("{context}: {}", &*err);
+        let mut code = default;
+        let mut source: Option<&(dyn Error + 'static)> = Some(&*err);
+        while let Some(e) = source {
+            if let Some(io) = e.downcast_ref::<std::io::Error>() {
+                code = match io.kind() {
+                    std::io::ErrorKind::NotFound => ExitCode::NoInput,
+                    std::io::ErrorKind::PermissionDenied => ExitCode::NoPerm,
+                    _ if default == ExitCode::CantCreat => ExitCode::CantCreat,
+                    _ => ExitCode::IoErr,
+                };
+                break;
+            }
+            source = e.source();
+            if let Some(e) = source {
+                message.push_str(&format!(": {e}"));
+            }
+        }
+        CliError { code, message, hint: String::new(), issues: Vec::new() }
+    }
+
+    /// 输出错误信息, 返回对应的进程退出码
+    pub fn report(&self) -> std::process::ExitCode {
+        if JSON_ERRORS.load(Ordering::Relaxed) {
+            let json = serde_json::json!({
+                "code": self.code as u8,
+                "error": self.code.name(),
+                "message": self.message,
+                "hint": self.hint,
+                "issues": self.issues,
+            });
+            eprintln!("{json}");
+        } else {
+            eprintln!("error: {}", self.message);
+            for issue in self.issues.iter() {
+                eprintln!("  --{} {:?}: {}", issue.key, issue.value, issue.message);
+            }
+            if !self.hint.is_empty() {
+                eprintln!("hint: {}", self.hint);
+            }
+        }
+        std::process::ExitCode::from(self.code as u8)
+    }
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Diagnostics {
+    /// 记录配置项错误
+    pub fn add(&mut self, key: &str, value: &str, message: impl Display) {
+        self.issues.push(ConfIssue { key: key.to_owned(), value: value.to_owned(), message: message.to_string() });
+    }
+
+    /// 存在配置项错误时返回包含所有错误的`CliError`
+    pub fn finish(self) -> Result<(), CliError> {
+        if self.issues.is_empty() {
+            return Ok(());
+        }
+        let mut e = CliError::new(ExitCode::Config, format!("{} configuration error(s)", self.issues.len()))
+            .hint("run with --help to see the format of each option");
+        e.issues = self.issues;
+        Err(e)
+    }
+}
+
+/// 将配置项的解析结果转换为命令行错误
+pub trait ConfResult<T> {
+    /// 解析失败时返回`CliError::config`
+    fn conf(self, key: &str, value: &str) -> Result<T, CliError>;
+
+    /// 解析失败时记录到启动诊断中并返回None
+    fn check(self, diag: &mut Diagnostics, key: &str, value: &str) -> Option<T>;
+}
+
+impl<T, E> ConfResult<T> for Result<T, E> {
+    fn conf(self, key: &str, value: &str) -> Result<T, CliError> {
+        self.map_err(|_| CliError::config(key, value))
+    }
+
+    fn check(self, diag: &mut Diagnostics, key: &str, value: &str) -> Option<T> {
+        self.ok().check(diag, key, value)
+    }
+}
+
+impl<T> ConfResult<T> for Option<T> {
+    fn conf(self, key: &str, value: &str) -> Result<T, CliError> {
+        self.ok_or_else(|| CliError::config(key, value))
+    }
+
+    fn check(self, diag: &mut Diagnostics, key: &str, value: &str) -> Option<T> {
+        if self.is_none() {
+            diag.add(key, value, "format error");
+        }
+        self
+    }
+}
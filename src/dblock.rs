@@ -0,0 +1,74 @@
+//! 数据库文件锁, 防止多个进程同时写入同一个数据库文件
+//!
+//! 在数据库文件旁创建`.lock`文件并加排它的建议锁, 锁在进程退出时由操作系统自动释放,
+//! 文件内容记录持有者信息, 加锁失败时用于提示是哪个进程在使用
+use std::{fs::{File, OpenOptions, TryLockError}, io::{Read, Seek, Write}};
+
+use anyhow_ext::{bail, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+/// 已持有的锁文件, 进程退出前一直持有
+static LOCKS: Mutex<Vec<File>> = Mutex::new(Vec::new());
+
+/// 锁的持有者信息
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct LockHolder {
+    pid: u32,
+    host: String,
+    /// 加锁时间(unix时间戳)
+    since: u64,
+    /// 持有者的服务监听地址, 命令行操作时为空
+    listen: String,
+}
+
+/// 对数据库文件加锁, 已被其它进程锁定时返回包含持有者信息的错误
+///
+/// * `aidb`: aidb数据库文件名
+/// * `listen`: 本进程的服务监听地址, 写入持有者信息
+pub fn acquire(aidb: &str, listen: &str) -> Result<()> {
+    let lock_file = format!("{aidb}.lock");
+    let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&lock_file)?;
+
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            // windows下加锁的区域不允许其它进程读取, 此时无法获取持有者信息
+            let mut text = String::new();
+            let _ = file.read_to_string(&mut text);
+            let holder: LockHolder = serde_json::from_str(&text).unwrap_or_default();
+            if holder.pid == 0 {
+                bail!("database {aidb} is locked by another process");
+            }
+            let since = chrono::DateTime::from_timestamp(holder.since as i64, 0)
+                .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_default();
+            let listen = if holder.listen.is_empty() { String::new() } else { format!(", listen: {}", holder.listen) };
+            bail!("database {aidb} is locked by pid {} on {} since {since}{listen}", holder.pid, holder.host);
+        }
+        Err(TryLockError::Error(e)) => bail!("lock database {aidb} error: {e}"),
+    }
+
+    let holder = LockHolder {
+        pid: std::process::id(),
+        host: hostname(),
+        since: localtime::unix_timestamp(),
+        listen: listen.to_owned(),
+    };
+    file.set_len(0)?;
+    file.rewind()?;
+    file.write_all(&serde_json::to_vec(&holder)?)?;
+    file.flush()?;
+
+    log::info!("database {aidb} locked by pid {}", holder.pid);
+    LOCKS.lock().push(file);
+    Ok(())
+}
+
+/// 主机名, 取自环境变量, 无法获取时返回"unknown"
+fn hostname() -> String {
+    ["HOSTNAME", "COMPUTERNAME"].iter()
+        .find_map(|key| std::env::var(key).ok().filter(|v| !v.is_empty()))
+        .unwrap_or_else(|| String::from("unknown"))
+}
@@ -0,0 +1,93 @@
+//! 服务端推送的事件, 通过广播通道分发给WebSocket及SSE推送连接
+use std::sync::OnceLock;
+
+use compact_str::CompactString;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// 广播通道的容量, 接收端处理过慢时丢弃最早的事件
+const CHANNEL_CAPACITY: usize = 64;
+
+/// 推送事件, 序列化为`{"type":"recordChanged",...}`
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Event {
+    /// 数据库文件被外部修改, 缓存已失效, 客户端需要重新查询
+    DatabaseReloaded {
+        /// 数据库名称
+        database: CompactString,
+    },
+    /// 数据库内容被修改
+    RecordChanged {
+        /// 数据库名称
+        database: CompactString,
+        /// 操作名称, 例如record/update
+        action: &'static str,
+        /// 执行修改的会话id, 推送时跳过该会话自身的连接
+        #[serde(skip)]
+        origin: Option<u128>,
+    },
+    /// 使用了胁迫密码登录, 推送给真实数据库的其它会话, 提醒数据库所有者
+    DuressLogin {
+        /// 真实数据库名称
+        database: CompactString,
+        /// 客户端地址
+        ip: CompactString,
+    },
+    /// 会话即将过期, 只推送给对应会话的连接
+    #[serde(rename_all = "camelCase")]
+    SessionExpiring {
+        /// 过期时间(unix时间戳)
+        expire: u64,
+    },
+    /// 会话已过期, 推送后关闭连接
+    SessionExpired,
+}
+
+/// 事件广播通道
+static CHANNEL: OnceLock<broadcast::Sender<Event>> = OnceLock::new();
+
+fn sender() -> &'static broadcast::Sender<Event> {
+    CHANNEL.get_or_init(|| broadcast::channel(CHANNEL_CAPACITY).0)
+}
+
+/// 发布事件, 没有推送连接时直接丢弃
+pub fn publish(event: Event) {
+    let _ = sender().send(event);
+}
+
+/// 订阅事件
+pub fn subscribe() -> broadcast::Receiver<Event> {
+    sender().subscribe()
+}
+
+impl Event {
+    /// 事件所属的数据库, 会话相关的事件返回None
+    pub fn database(&self) -> Option<&str> {
+        match self {
+            Event::DatabaseReloaded { database } | Event::RecordChanged { database, .. }
+                | Event::DuressLogin { database, .. } => Some(database),
+            Event::SessionExpiring { .. } | Event::SessionExpired => None,
+        }
+    }
+
+    /// 事件名称, 与序列化的`type`相同, 用作SSE的事件名称
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::DatabaseReloaded { .. } => "databaseReloaded",
+            Event::RecordChanged { .. } => "recordChanged",
+            Event::DuressLogin { .. } => "duressLogin",
+            Event::SessionExpiring { .. } => "sessionExpiring",
+            Event::SessionExpired => "sessionExpired",
+        }
+    }
+
+    /// 替换事件中的数据库名称, 诱饵数据库的会话收到的事件使用真实数据库的名称
+    pub fn with_database(mut self, name: &str) -> Event {
+        if let Event::DatabaseReloaded { database } | Event::RecordChanged { database, .. }
+                | Event::DuressLogin { database, .. } = &mut self {
+            *database = CompactString::new(name);
+        }
+        self
+    }
+}
@@ -0,0 +1,305 @@
+//! 随机口令、用户名及邮箱别名生成
+use std::sync::OnceLock;
+
+use anyhow_ext::{bail, Result};
+use rand::{seq::SliceRandom, Rng};
+use serde::{Serialize, Deserialize};
+
+const LOWER: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+const UPPER: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGIT: &[u8] = b"0123456789";
+const SYMBOL: &[u8] = b"!@#$%^&*()-_=+[]{};:,.<>/?~";
+/// 容易混淆的字符
+const AMBIGUOUS: &[u8] = b"0O1lI|;:,.";
+/// 可发音口令使用的辅音字母
+const CONSONANT: &[u8] = b"bcdfghjklmnprstvwxz";
+/// 可发音口令使用的元音字母
+const VOWEL: &[u8] = b"aeiou";
+/// 自定义词表的最少单词数量, 单词太少时口令短语的强度不足
+const MIN_WORDLIST_LEN: usize = 1024;
+/// 随机用户名的单词数量
+const USERNAME_WORDS: usize = 2;
+/// 随机用户名末尾的数字位数
+const USERNAME_DIGITS: usize = 4;
+/// 随机用户名单词部分的最少字母数
+const USERNAME_MIN_LETTERS: usize = 3;
+/// 随机用户名重新选择单词的最大次数, 自定义词表中的单词可能不含ascii字母
+const USERNAME_MAX_TRIES: usize = 8;
+/// 邮箱别名标签的最大长度
+const MAX_ALIAS_TAG_LEN: usize = 32;
+
+/// 通过`--wordlist`加载的自定义词表, 未设置时使用内置的EFF长词表
+static WORDLIST: OnceLock<Vec<String>> = OnceLock::new();
+
+/// 口令生成模式
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GenMode {
+    /// 随机字符
+    #[default]
+    Random,
+    /// 从词表中随机选择单词组成的口令短语(diceware)
+    Passphrase,
+    /// 辅音元音交替组成的可发音口令
+    Pronounceable,
+}
+
+/// 口令生成选项
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct GenOptions {
+    /// 生成模式
+    pub mode: GenMode,
+    /// 口令长度
+    pub length: usize,
+    /// 包含小写字母
+    pub lower: bool,
+    /// 包含大写字母
+    pub upper: bool,
+    /// 包含数字
+    pub digit: bool,
+    /// 包含特殊符号
+    pub symbol: bool,
+    /// 口令短语的单词数量
+    pub words: usize,
+    /// 口令短语的单词分隔符
+    pub separator: String,
+    /// 口令短语的单词首字母大写
+    pub capitalize: bool,
+    /// 避免使用容易混淆的字符, 例如`0O1lI`
+    pub avoid_ambiguous: bool,
+}
+
+impl Default for GenOptions {
+    fn default() -> Self {
+        GenOptions {
+            mode: GenMode::Random,
+            length: 16,
+            lower: true,
+            upper: true,
+            digit: true,
+            symbol: true,
+            words: 6,
+            separator: String::from("-"),
+            capitalize: false,
+            avoid_ambiguous: false,
+        }
+    }
+}
+
+/// 加载自定义词表文件, 每行一个单词, 兼容diceware格式(骰子编号 单词)
+///
+/// Returns:
+///
+/// 词表的单词数量
+pub fn load_wordlist(file: &str) -> Result<usize> {
+    let text = std::fs::read_to_string(file)?;
+    let mut words: Vec<String> = text.lines()
+        .filter_map(|line| line.split_whitespace().last())
+        .filter(|w| !w.starts_with('#'))
+        .map(String::from)
+        .collect();
+    words.sort_unstable();
+    words.dedup();
+
+    if words.len() < MIN_WORDLIST_LEN {
+        bail!("wordlist {file} has only {} words, at least {MIN_WORDLIST_LEN} required", words.len());
+    }
+    let len = words.len();
+    if WORDLIST.set(words).is_err() {
+        bail!("wordlist already loaded");
+    }
+
+    Ok(len)
+}
+
+/// 按照指定选项生成口令
+pub fn generate(opts: &GenOptions) -> String {
+    match opts.mode {
+        GenMode::Random => generate_random(opts),
+        GenMode::Passphrase => generate_passphrase(opts),
+        GenMode::Pronounceable => generate_pronounceable(opts),
+    }
+}
+
+/// 生成随机用户名, 格式: 单词+单词+4位数字, 例如`tundraspoon4821`
+pub fn generate_username() -> String {
+    username_from_words(&mut rand::thread_rng(), |rng| random_words(rng, USERNAME_WORDS))
+}
+
+/// 使用`words`选择的单词生成随机用户名, 只保留单词中的ascii字母,
+/// 多次选择后字母仍然不足时(例如非英文词表)以随机的可发音字母补足
+fn username_from_words<R: Rng>(rng: &mut R, mut words: impl FnMut(&mut R) -> Vec<String>) -> String {
+    let mut name = String::new();
+    for _ in 0..USERNAME_MAX_TRIES {
+        name = words(rng).iter()
+            .flat_map(|w| w.chars().filter(char::is_ascii_alphabetic))
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        if name.len() >= USERNAME_MIN_LETTERS {
+            break;
+        }
+    }
+    while name.len() < USERNAME_MIN_LETTERS {
+        let set = if name.len() % 2 == 0 { CONSONANT } else { VOWEL };
+        name.push(char::from(set[rng.gen_range(0..set.len())]));
+    }
+    for _ in 0..USERNAME_DIGITS {
+        name.push(char::from(DIGIT[rng.gen_range(0..DIGIT.len())]));
+    }
+    name
+}
+
+/// 根据基础邮箱地址生成加号别名, 例如`me@example.com` -> `me+github@example.com`
+///
+/// * `base`: 基础邮箱地址, 已有的加号后缀会被替换
+/// * `tag`: 别名标签, 只保留字母数字及`-_.`, 为空时使用随机用户名
+pub fn email_alias(base: &str, tag: &str) -> Result<String> {
+    let (local, domain) = match base.trim().split_once('@') {
+        Some((l, d)) if !l.is_empty() && !d.is_empty() && !d.contains('@') => (l, d),
+        _ => bail!("invalid email address: {base}"),
+    };
+    let local = local.split_once('+').map(|(l, _)| l).unwrap_or(local);
+
+    let mut tag: String = tag.chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        .map(|c| c.to_ascii_lowercase())
+        .take(MAX_ALIAS_TAG_LEN)
+        .collect();
+    let trimmed = tag.trim_matches('.');
+    if trimmed.len() != tag.len() {
+        tag = trimmed.to_owned();
+    }
+    if tag.is_empty() {
+        tag = generate_username();
+    }
+
+    Ok(format!("{local}+{tag}@{domain}"))
+}
+
+/// 从词表中随机选择指定数量的单词
+fn random_words<R: Rng>(rng: &mut R, count: usize) -> Vec<String> {
+    match WORDLIST.get() {
+        Some(list) => (0..count).map(|_| list[rng.gen_range(0..list.len())].clone()).collect(),
+        None => {
+            let list = &eff_wordlist::large::LIST;
+            (0..count).map(|_| list[rng.gen_range(0..list.len())].1.to_owned()).collect()
+        }
+    }
+}
+
+/// 生成口令短语, 选中数字时在随机一个单词后追加一位数字
+fn generate_passphrase(opts: &GenOptions) -> String {
+    let mut rng = rand::thread_rng();
+    let mut words = random_words(&mut rng, opts.words.max(1));
+
+    if opts.capitalize {
+        for word in words.iter_mut() {
+            let mut chars = word.chars();
+            if let Some(first) = chars.next() {
+                *word = first.to_uppercase().chain(chars).collect();
+            }
+        }
+    }
+    if opts.digit {
+        let idx = rng.gen_range(0..words.len());
+        words[idx].push(char::from(DIGIT[rng.gen_range(0..DIGIT.len())]));
+    }
+
+    words.join(&opts.separator)
+}
+
+/// 生成随机字符口令, 每种选中的字符类别至少出现一次
+fn generate_random(opts: &GenOptions) -> String {
+    let mut classes = Vec::with_capacity(4);
+    if opts.lower { classes.push(charset(LOWER, opts.avoid_ambiguous)); }
+    if opts.upper { classes.push(charset(UPPER, opts.avoid_ambiguous)); }
+    if opts.digit { classes.push(charset(DIGIT, opts.avoid_ambiguous)); }
+    if opts.symbol { classes.push(charset(SYMBOL, opts.avoid_ambiguous)); }
+    if classes.is_empty() {
+        classes.push(charset(LOWER, opts.avoid_ambiguous));
+    }
+
+    let length = opts.length.max(classes.len());
+    let all: Vec<u8> = classes.iter().flat_map(|c| c.iter().copied()).collect();
+    let mut rng = rand::thread_rng();
+    let mut pass = Vec::with_capacity(length);
+
+    for class in classes.iter() {
+        pass.push(class[rng.gen_range(0..class.len())]);
+    }
+    while pass.len() < length {
+        pass.push(all[rng.gen_range(0..all.len())]);
+    }
+    pass.shuffle(&mut rng);
+
+    // 字符集均为ascii字符, 不会出现非法的utf8
+    String::from_utf8(pass).unwrap()
+}
+
+/// 生成可发音口令, 辅音元音交替组成, 选中的大写字母、数字及特殊符号各出现一次
+fn generate_pronounceable(opts: &GenOptions) -> String {
+    let consonant = charset(CONSONANT, opts.avoid_ambiguous);
+    let vowel = charset(VOWEL, opts.avoid_ambiguous);
+    let mut rng = rand::thread_rng();
+
+    let mut tail = Vec::with_capacity(2);
+    if opts.digit {
+        let digit = charset(DIGIT, opts.avoid_ambiguous);
+        tail.push(digit[rng.gen_range(0..digit.len())]);
+    }
+    if opts.symbol {
+        let symbol = charset(SYMBOL, opts.avoid_ambiguous);
+        tail.push(symbol[rng.gen_range(0..symbol.len())]);
+    }
+
+    let letters = opts.length.saturating_sub(tail.len()).max(1);
+    let mut pass = Vec::with_capacity(letters + tail.len());
+    let mut use_vowel = rng.gen_bool(0.5);
+    while pass.len() < letters {
+        let set = if use_vowel { &vowel } else { &consonant };
+        pass.push(set[rng.gen_range(0..set.len())]);
+        use_vowel = !use_vowel;
+    }
+    if opts.upper {
+        let idx = rng.gen_range(0..pass.len());
+        pass[idx] = pass[idx].to_ascii_uppercase();
+    }
+    pass.extend_from_slice(&tail);
+
+    // 字符集均为ascii字符, 不会出现非法的utf8
+    String::from_utf8(pass).unwrap()
+}
+
+/// 字符集, 需要时去除容易混淆的字符
+fn charset(set: &[u8], avoid_ambiguous: bool) -> Vec<u8> {
+    set.iter().copied().filter(|c| !avoid_ambiguous || !AMBIGUOUS.contains(c)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn username() {
+        let mut rng = rand::thread_rng();
+        let name = username_from_words(&mut rng, |_| vec![String::from("Tundra"), String::from("spoon")]);
+        assert!(name.starts_with("tundraspoon"));
+        assert_eq!(name.len(), "tundraspoon".len() + USERNAME_DIGITS);
+
+        // 非英文词表的单词不含ascii字母, 重试次数有限, 以随机字母补足
+        let mut tries = 0;
+        let name = username_from_words(&mut rng, |_| {
+            tries += 1;
+            vec![String::from("苹果"), String::from("香蕉")]
+        });
+        assert_eq!(tries, USERNAME_MAX_TRIES);
+        assert_eq!(name.len(), USERNAME_MIN_LETTERS + USERNAME_DIGITS);
+        let (letters, digits) = name.split_at(USERNAME_MIN_LETTERS);
+        assert!(letters.bytes().all(|c| c.is_ascii_lowercase()));
+        assert!(digits.bytes().all(|c| c.is_ascii_digit()));
+
+        let name = generate_username();
+        assert!(name.len() >= USERNAME_MIN_LETTERS + USERNAME_DIGITS);
+    }
+}
@@ -0,0 +1,286 @@
+//! 记录搜索索引, 数据库缓存内容变化后自动重建
+use std::{collections::{BTreeMap, HashSet}, ops::Bound, sync::{Arc, Weak}};
+
+use parking_lot::Mutex;
+
+use crate::aidb::{Database, Record};
+
+/// 可搜索的字段, 备注在内存中加密保存, 不建立索引
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    Title,
+    User,
+    Url,
+    Tag,
+    /// 未受保护的自定义字段内容
+    Custom,
+    /// 评论内容
+    Comment,
+}
+
+const FIELD_COUNT: usize = 6;
+static FIELDS: [Field; FIELD_COUNT] = [Field::Title, Field::User, Field::Url, Field::Tag, Field::Custom, Field::Comment];
+
+/// 全文搜索倒排索引, 每个字段一张表, key: 词, value: 包含该词的记录下标(升序)
+///
+/// 英文数字按单词切分, 中日韩文字按单字及相邻两字切分, 均不区分大小写
+pub struct SearchIndex {
+    fields: [BTreeMap<String, Vec<u32>>; FIELD_COUNT],
+}
+
+/// 前缀补全索引, 补全项为记录标题及网址的主机名
+pub struct SuggestIndex {
+    /// (小写的补全项, 原始补全项), 按小写内容排序并去重
+    terms: Vec<(String, String)>,
+}
+
+/// 索引及其对应的数据库内容, 数据库内容被替换后索引失效
+struct CachedIndex {
+    db: Weak<Database>,
+    suggest: Arc<SuggestIndex>,
+}
+
+static INDEX: Mutex<Option<CachedIndex>> = Mutex::new(None);
+
+/// 获取数据库内容对应的补全索引, 数据库内容变化时重建
+pub fn suggest_index(db: &Arc<Database>) -> Arc<SuggestIndex> {
+    let mut index = INDEX.lock();
+    if let Some(cached) = index.as_ref() {
+        if cached.db.as_ptr() == Arc::as_ptr(db) && cached.db.strong_count() > 0 {
+            return cached.suggest.clone();
+        }
+    }
+
+    let start = std::time::Instant::now();
+    let suggest = Arc::new(SuggestIndex::build(db));
+    log::debug!("build suggest index, term total: {}, elapsed: {:?}", suggest.terms.len(), start.elapsed());
+    *index = Some(CachedIndex { db: Arc::downgrade(db), suggest: suggest.clone() });
+
+    suggest
+}
+
+impl SuggestIndex {
+    pub fn build(db: &Database) -> Self {
+        let mut terms = Vec::with_capacity(db.records.len() * 2);
+        for rec in db.records.iter() {
+            if !rec.title.is_empty() {
+                terms.push((rec.title.to_lowercase(), rec.title.clone()));
+            }
+            if let Some(host) = url_host(&rec.url) {
+                terms.push((host.to_lowercase(), host.to_owned()));
+            }
+        }
+        terms.sort_unstable();
+        terms.dedup_by(|a, b| a.0 == b.0);
+
+        SuggestIndex { terms }
+    }
+
+    /// 查找以`prefix`开头(不区分大小写)的补全项, 最多返回`limit`项
+    pub fn suggest(&self, prefix: &str, limit: usize) -> Vec<&str> {
+        let key = prefix.to_lowercase();
+        let start = self.terms.partition_point(|(k, _)| k.as_str() < key.as_str());
+        self.terms[start..].iter()
+            .take_while(|(k, _)| k.starts_with(&key))
+            .take(limit)
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+}
+
+/// 获取网址的主机名部分, 忽略协议、端口及`www.`前缀
+fn url_host(url: &str) -> Option<&str> {
+    let url = url.trim();
+    let url = url.split_once("://").map(|(_, v)| v).unwrap_or(url);
+    let host = url.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = host.rsplit('@').next().unwrap_or_default();
+    let host = host.split(':').next().unwrap_or_default();
+    let host = host.strip_prefix("www.").unwrap_or(host);
+    if host.is_empty() { None } else { Some(host) }
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Field> {
+        match name.to_ascii_lowercase().as_str() {
+            "title" => Some(Field::Title),
+            "user" | "username" => Some(Field::User),
+            "url" => Some(Field::Url),
+            "tag" | "tags" => Some(Field::Tag),
+            "field" | "fields" => Some(Field::Custom),
+            "comment" | "comments" => Some(Field::Comment),
+            _ => None,
+        }
+    }
+}
+
+impl SearchIndex {
+    /// 建立索引, 记录较多时在多线程版本中分片并行建立后按分片顺序合并, 合并后记录下标仍为升序
+    pub fn build(db: &Database) -> Self {
+        let mut parts = crate::parallel::shards(&db.records, Self::build_shard).into_iter();
+        let mut fields = parts.next().unwrap_or_default();
+        for part in parts {
+            for (map, part) in fields.iter_mut().zip(part) {
+                for (token, ids) in part {
+                    map.entry(token).or_default().extend(ids);
+                }
+            }
+        }
+
+        SearchIndex { fields }
+    }
+
+    /// 为一个分片的记录建立索引
+    ///
+    /// * `offset`: 分片第一条记录在数据库中的下标
+    /// * `records`: 分片的记录
+    fn build_shard(offset: usize, records: &[Arc<Record>]) -> [BTreeMap<String, Vec<u32>>; FIELD_COUNT] {
+        let mut fields: [BTreeMap<String, Vec<u32>>; FIELD_COUNT] = Default::default();
+        let mut tokens = Vec::new();
+
+        for (idx, rec) in records.iter().enumerate() {
+            let idx = offset + idx;
+            for field in FIELDS {
+                tokens.clear();
+                match field {
+                    Field::Title => tokenize(&rec.title, false, &mut tokens),
+                    Field::User => tokenize(&rec.user, false, &mut tokens),
+                    Field::Url => tokenize(&rec.url, false, &mut tokens),
+                    Field::Tag => for tag in rec.tags.iter() {
+                        tokenize(tag, false, &mut tokens);
+                    },
+                    Field::Custom => for value in rec.fields.iter().filter_map(|f| f.plain()) {
+                        tokenize(value, false, &mut tokens);
+                    },
+                    Field::Comment => for c in rec.comments.iter() {
+                        tokenize(&c.text, false, &mut tokens);
+                    },
+                }
+                let map = &mut fields[field as usize];
+                for token in tokens.drain(..) {
+                    let ids = map.entry(token).or_default();
+                    // 同一记录按顺序处理, 只需检查最后一项即可去重
+                    if ids.last() != Some(&(idx as u32)) {
+                        ids.push(idx as u32);
+                    }
+                }
+            }
+        }
+
+        fields
+    }
+
+    /// 按查询语句搜索, 返回匹配的记录下标(升序)
+    ///
+    /// 查询语句由空格分隔的多个条件组成, 所有条件都必须匹配, 条件格式为`字段:内容`或者`内容`,
+    /// 字段支持title/user/url/tag/field/comment, 未指定字段时匹配任意字段; 词按前缀匹配, 例如`title:git user:kiven`
+    pub fn search(&self, query: &str) -> Vec<u32> {
+        let mut result: Option<Vec<u32>> = None;
+        let mut tokens = Vec::new();
+
+        for term in query.split_whitespace() {
+            let (fields, text) = match term.split_once(':') {
+                Some((name, text)) => match Field::parse(name) {
+                    Some(f) => (std::slice::from_ref(&FIELDS[f as usize]), text),
+                    None => (&FIELDS[..], term),
+                },
+                None => (&FIELDS[..], term),
+            };
+
+            tokens.clear();
+            tokenize(text, true, &mut tokens);
+            for token in tokens.iter() {
+                let mut ids: Vec<u32> = fields.iter()
+                    .flat_map(|f| self.prefix_ids(*f, token))
+                    .collect::<HashSet<u32>>()
+                    .into_iter()
+                    .collect();
+                ids.sort_unstable();
+                result = Some(match result {
+                    Some(prev) => intersect(&prev, &ids),
+                    None => ids,
+                });
+                if result.as_ref().is_some_and(|r| r.is_empty()) {
+                    return Vec::new();
+                }
+            }
+        }
+
+        result.unwrap_or_default()
+    }
+
+    /// 查找字段中以`prefix`开头的所有词对应的记录下标
+    fn prefix_ids<'a>(&'a self, field: Field, prefix: &'a str) -> impl Iterator<Item = u32> + 'a {
+        self.fields[field as usize]
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(move |(k, _)| k.starts_with(prefix))
+            .flat_map(|(_, ids)| ids.iter().copied())
+    }
+}
+
+/// 求两个升序数组的交集
+fn intersect(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (mut i, mut j) = (0, 0);
+    let mut out = Vec::with_capacity(a.len().min(b.len()));
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                out.push(a[i]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    out
+}
+
+/// 分词, 英文数字按单词切分并转为小写, 中日韩文字按相邻两字切分
+///
+/// 建立索引时同时输出单字, 以便单字查询; 查询时连续两个以上的中日韩文字只输出两字词,
+/// 只有一个字时输出单字
+fn tokenize(text: &str, query: bool, out: &mut Vec<String>) {
+    let mut word = String::new();
+    let mut cjk: Vec<char> = Vec::new();
+
+    let flush_cjk = |cjk: &mut Vec<char>, out: &mut Vec<String>| {
+        if cjk.len() == 1 || (!query && !cjk.is_empty()) {
+            out.extend(cjk.iter().map(|c| c.to_string()));
+        }
+        for pair in cjk.windows(2) {
+            out.push(pair.iter().collect());
+        }
+        cjk.clear();
+    };
+
+    for c in text.chars() {
+        if is_cjk(c) {
+            if !word.is_empty() {
+                out.push(std::mem::take(&mut word));
+            }
+            cjk.push(c);
+        } else {
+            flush_cjk(&mut cjk, out);
+            if c.is_alphanumeric() {
+                word.extend(c.to_lowercase());
+            } else if !word.is_empty() {
+                out.push(std::mem::take(&mut word));
+            }
+        }
+    }
+    flush_cjk(&mut cjk, out);
+    if !word.is_empty() {
+        out.push(word);
+    }
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF     // 日文假名
+        | 0x3400..=0x4DBF   // 中日韩统一表意文字扩展A
+        | 0x4E00..=0x9FFF   // 中日韩统一表意文字
+        | 0xAC00..=0xD7AF   // 韩文音节
+        | 0xF900..=0xFAFF   // 中日韩兼容表意文字
+        | 0x20000..=0x2A6DF // 中日韩统一表意文字扩展B
+    )
+}
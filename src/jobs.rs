@@ -0,0 +1,302 @@
+//! 后台任务, 导出等耗时操作在后台线程中执行, 客户端通过任务id查询进度及结果
+//!
+//! 任务属于创建它的数据库及会话, 只有同一会话可以查询、下载及取消, 文件结果下载一次后即删除
+use std::{
+    collections::HashMap,
+    sync::{atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering}, Arc},
+};
+
+use anyhow_ext::{bail, Result};
+use compact_str::CompactString;
+use parking_lot::Mutex;
+use serde_json::Value;
+use zeroize::Zeroizing;
+
+/// 同时保留的最大任务数量, 超过时不允许创建新任务
+const MAX_JOBS: usize = 64;
+/// 任务结束后保留结果的时间(单位: 秒)
+const KEEP_SECS: u64 = 3600;
+
+/// 任务id序号
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+/// 所有任务, key: 任务id
+static JOBS: Mutex<Option<HashMap<u64, Arc<Job>>>> = Mutex::new(None);
+
+/// 任务所属的会话
+#[derive(Clone, PartialEq, Eq)]
+pub struct JobOwner {
+    /// 会话登录的数据库名称
+    pub database: CompactString,
+    /// 会话id, basic认证的请求没有会话
+    pub session: Option<u128>,
+}
+
+/// 后台任务
+pub struct Job {
+    id: u64,
+    /// 创建任务的会话, 刷新令牌时转移到新会话
+    owner: Mutex<JobOwner>,
+    /// 任务类型, 例如export
+    kind: &'static str,
+    /// 创建时间(unix时间戳)
+    created: u64,
+    /// 完成进度(0-100)
+    progress: AtomicU8,
+    /// 是否已请求取消
+    cancelled: AtomicBool,
+    state: Mutex<JobState>,
+}
+
+/// 任务状态
+enum JobState {
+    Running,
+    Done { output: JobOutput, finished: u64 },
+    Failed { error: String, finished: u64 },
+    Cancelled { finished: u64 },
+    /// 结果已被下载, 任务随即删除
+    Downloaded { finished: u64 },
+}
+
+/// 任务结果
+pub enum JobOutput {
+    /// json数据
+    Json(Value),
+    /// 文件下载
+    File {
+        /// 下载的文件名
+        name: String,
+        /// 内容类型
+        mime: &'static str,
+        /// 文件内容, 任务删除或者下载发送完成时清零
+        data: Zeroizing<Vec<u8>>,
+    },
+}
+
+/// 任务状态, 用于接口返回, 与客户端共用
+pub use accinfo_api::JobStatus;
+
+impl Job {
+    /// 更新完成进度
+    pub fn progress(&self, pct: u8) {
+        self.progress.store(pct.min(100), Ordering::Relaxed);
+    }
+
+    /// 是否已请求取消
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// 已请求取消时返回错误, 任务在各个阶段之间调用以便及时结束
+    pub fn check_cancel(&self) -> Result<()> {
+        if self.is_cancelled() {
+            bail!("job {} cancelled", self.id);
+        }
+        Ok(())
+    }
+
+    /// 取出任务结果, 结果只能取出一次, 任务未完成或者结果已取出时返回None
+    fn take_output(&self) -> Option<JobOutput> {
+        let mut state = self.state.lock();
+        let finished = match &*state {
+            JobState::Done { finished, .. } => *finished,
+            _ => return None,
+        };
+        match std::mem::replace(&mut *state, JobState::Downloaded { finished }) {
+            JobState::Done { output, .. } => Some(output),
+            _ => None,
+        }
+    }
+
+    fn owned_by(&self, owner: &JobOwner) -> bool {
+        *self.owner.lock() == *owner
+    }
+
+    /// 当前状态
+    pub fn status(&self) -> JobStatus {
+        let state = self.state.lock();
+        let (status, finished, error, has_file) = match &*state {
+            JobState::Running => ("running", None, None, false),
+            JobState::Done { output, finished } =>
+                ("done", Some(*finished), None, matches!(output, JobOutput::File { .. })),
+            JobState::Failed { error, finished } => ("failed", Some(*finished), Some(error.clone()), false),
+            JobState::Cancelled { finished } => ("cancelled", Some(*finished), None, false),
+            JobState::Downloaded { finished } => ("downloaded", Some(*finished), None, false),
+        };
+        JobStatus {
+            id: self.id,
+            kind: self.kind.to_owned(),
+            status: status.to_owned(),
+            progress: self.progress.load(Ordering::Relaxed),
+            created: self.created,
+            finished,
+            error,
+            has_file,
+        }
+    }
+
+    fn finish(&self, res: Result<JobOutput>) {
+        let finished = localtime::unix_timestamp();
+        let state = match res {
+            Ok(output) => {
+                self.progress(100);
+                JobState::Done { output, finished }
+            }
+            Err(_) if self.is_cancelled() => JobState::Cancelled { finished },
+            Err(e) => {
+                log::error!("job {} ({}) failed: {e:?}", self.id, self.kind);
+                JobState::Failed { error: e.to_string(), finished }
+            }
+        };
+        *self.state.lock() = state;
+    }
+}
+
+/// 创建后台任务, 任务函数在阻塞线程池中执行
+///
+/// * `kind`: 任务类型
+/// * `owner`: 创建任务的会话
+/// * `f`: 任务函数, 通过参数更新进度及检查是否已取消
+///
+/// Returns:
+///
+/// 任务id, 任务数量达到上限时返回错误
+pub fn spawn<F>(kind: &'static str, owner: JobOwner, f: F) -> Result<u64>
+where
+    F: FnOnce(&Job) -> Result<JobOutput> + Send + 'static,
+{
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let job = Arc::new(Job {
+        id,
+        owner: Mutex::new(owner),
+        kind,
+        created: localtime::unix_timestamp(),
+        progress: AtomicU8::new(0),
+        cancelled: AtomicBool::new(false),
+        state: Mutex::new(JobState::Running),
+    });
+
+    let mut jobs = JOBS.lock();
+    let jobs = jobs.get_or_insert_with(HashMap::new);
+    if jobs.len() >= MAX_JOBS {
+        bail!("too many jobs");
+    }
+    jobs.insert(id, job.clone());
+    log::info!("job {id} ({kind}) started");
+
+    tokio::task::spawn_blocking(move || {
+        let res = f(&job);
+        job.finish(res);
+        log::info!("job {} ({}) finished", job.id, job.kind);
+    });
+
+    Ok(id)
+}
+
+/// 获取指定会话的任务, 其它会话的任务视为不存在
+pub fn get(id: u64, owner: &JobOwner) -> Option<Arc<Job>> {
+    JOBS.lock().as_ref().and_then(|jobs| jobs.get(&id)).filter(|j| j.owned_by(owner)).cloned()
+}
+
+/// 取出已完成任务的结果并删除任务
+///
+/// Returns:
+///
+/// 任务不存在时返回None, 任务未完成时返回`Some(None)`
+pub fn take_output(id: u64, owner: &JobOwner) -> Option<Option<JobOutput>> {
+    let job = get(id, owner)?;
+    let output = job.take_output();
+    if output.is_some() {
+        if let Some(jobs) = JOBS.lock().as_mut() {
+            jobs.remove(&id);
+        }
+    }
+    Some(output)
+}
+
+/// 指定会话的所有任务的状态, 按创建顺序排列
+pub fn list(owner: &JobOwner) -> Vec<JobStatus> {
+    let mut list: Vec<JobStatus> = match JOBS.lock().as_ref() {
+        Some(jobs) => jobs.values().filter(|j| j.owned_by(owner)).map(|j| j.status()).collect(),
+        None => Vec::new(),
+    };
+    list.sort_by_key(|s| s.id);
+    list
+}
+
+/// 取消任务, 运行中的任务在下一个检查点结束, 已结束的任务直接删除
+///
+/// Returns:
+///
+/// 任务是否存在
+pub fn cancel(id: u64, owner: &JobOwner) -> bool {
+    let mut jobs = JOBS.lock();
+    let jobs = match jobs.as_mut() {
+        Some(jobs) => jobs,
+        None => return false,
+    };
+    let job = match jobs.get(&id) {
+        Some(job) if job.owned_by(owner) => job,
+        _ => return false,
+    };
+    if matches!(*job.state.lock(), JobState::Running) {
+        job.cancelled.store(true, Ordering::Relaxed);
+    } else {
+        jobs.remove(&id);
+    }
+    true
+}
+
+/// 删除结束超过保留时间的任务, 由定时任务调用
+pub fn recycle() {
+    let now = localtime::unix_timestamp();
+    if let Some(jobs) = JOBS.lock().as_mut() {
+        jobs.retain(|_, job| match &*job.state.lock() {
+            JobState::Running => true,
+            JobState::Done { finished, .. }
+                | JobState::Failed { finished, .. }
+                | JobState::Cancelled { finished }
+                | JobState::Downloaded { finished } => finished + KEEP_SECS > now,
+        });
+    }
+}
+
+/// 刷新令牌后, 旧会话的任务转移到新会话
+pub fn move_session(old_id: u128, new_id: u128) {
+    if let Some(jobs) = JOBS.lock().as_ref() {
+        for job in jobs.values() {
+            let mut owner = job.owner.lock();
+            if owner.session == Some(old_id) {
+                owner.session = Some(new_id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn job_owner_and_download() {
+        let owner = JobOwner { database: "work".into(), session: Some(1) };
+        let other = JobOwner { database: "work".into(), session: Some(2) };
+        let id = spawn("test", owner.clone(), |_| Ok(JobOutput::File {
+            name: "a.csv".to_owned(), mime: "text/csv", data: Zeroizing::new(b"a,b".to_vec()),
+        })).unwrap();
+        while get(id, &owner).unwrap().status().status == "running" {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        // 其它会话无法查询、下载及取消
+        assert!(get(id, &other).is_none());
+        assert!(take_output(id, &other).is_none());
+        assert!(!cancel(id, &other));
+        assert!(list(&other).iter().all(|s| s.id != id));
+
+        // 刷新令牌后由新会话下载, 结果只能下载一次
+        move_session(1, 3);
+        let owner = JobOwner { session: Some(3), ..owner };
+        assert!(matches!(take_output(id, &owner), Some(Some(JobOutput::File { .. }))));
+        assert!(take_output(id, &owner).is_none());
+    }
+}
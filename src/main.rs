@@ -1,14 +1,32 @@
+mod access;
+mod acme;
 mod apis;
 mod aidb;
+mod applog;
+mod cli;
+mod dblock;
+mod events;
+mod generator;
+mod index;
+mod jobs;
+mod policy;
+mod profile;
+mod recipient;
+mod metrics;
+mod monitor;
+mod outbound;
+mod parallel;
+mod state;
+mod strength;
+mod timing;
+mod totp;
 
-use httpserver::HttpServer;
-use tokio::time;
+use std::sync::Arc;
 
-macro_rules! arg_err {
-    ($text:literal) => {
-        concat!("arg ", $text, " format error")
-    };
-}
+use cli::{CliError, ConfResult, Diagnostics, ExitCode};
+use httpserver::{ApiVersion, HttpServer};
+use state::AppState;
+use tokio::time;
 
 /// 应用程序内部名称
 const APP_NAME: &str = include_str!(concat!(env!("OUT_DIR"), "/.app_name"));
@@ -16,6 +34,9 @@ const APP_NAME: &str = include_str!(concat!(env!("OUT_DIR"), "/.app_name"));
 /// app版本号, 来自编译时由build.rs从cargo.toml中读取的版本号(读取内容写入.version文件)
 const APP_VER: &str = include_str!(concat!(env!("OUT_DIR"), "/.version"));
 
+/// 备份令牌的最小长度
+const MIN_BACKUP_TOKEN_LEN: usize = 16;
+
 const BANNER: &str = r#"
   kivensoft %      _       ____
   ____ ___________(_)___  / __/___
@@ -24,164 +45,1186 @@ const BANNER: &str = r#"
 \__,_/\___/\___/_/_/ /_/_/  \____/
 "#;
 
-appconfig::appglobal_define!(app_global, AppGlobal,
-    startup_time  : u64,
-    task_interval : u64, // 定时任务执行时间间隔（单位：秒）
-    cache_expire  : u64, // 数据缓存存活最大有效时间（单位：秒）
-    session_expire: u64, // session过期时间（单位：秒）
-);
-
 appconfig::appconfig_define!(app_conf, AppConf,
+    profile       : String => ["",  "profile",        "Profile",        "config profile (dev/prod), loads accinfo.<profile>.conf over the base config"],
     log_level     : String => ["L", "log-level",      "LogLevel",       "log level(trace/debug/info/warn/error/off)"],
     log_file      : String => ["F", "log-file",       "LogFile",        "log filename"],
     log_max       : String => ["M", "log-max",        "LogFileMaxSize", "log file max size (unit: k/m/g)"],
+    log_timezone  : String => ["",  "log-timezone",   "LogTimezone",    "log timestamp timezone (local/utc)"],
+    log_time_format: String => ["", "log-time-format", "LogTimeFormat", "log timestamp strftime format or iso8601"],
+    access_log    : String => ["",  "access-log",     "AccessLog",      "access log format (text/json)"],
     no_console    : bool   => ["",  "no-console",     "NoConsole",      "prohibit outputting logs to the console"],
+    no_color      : bool   => ["",  "no-color",       "NoColor",        "disable ansi color output (also NO_COLOR env)"],
+    no_banner     : bool   => ["",  "no-banner",      "NoBanner",       "do not print startup banner"],
+    json_errors   : bool   => ["",  "json-errors",    "JsonErrors",     "print command line errors as json (code, error, message, hint) to stderr"],
+    banner_file   : String => ["",  "banner-file",    "BannerFile",     "custom banner file, '%' is replaced by version"],
     threads       : String => ["t", "threads",        "Threads",        "set tokio runtime worker threads"],
     listen        : String => ["l", "listen",         "Listen",         "http service ip:port"],
+    tls_cert      : String => ["",  "tls-cert",       "TlsCert",        "https certificate chain file (pem), reloaded automatically when changed"],
+    tls_key       : String => ["",  "tls-key",        "TlsKey",         "https private key file (pem)"],
+    acme_domain   : String => ["",  "acme-domain",    "AcmeDomain",     "obtain and renew https certificate from Let's Encrypt for the domain"],
+    acme_email    : String => ["",  "acme-email",     "AcmeEmail",      "contact email of the acme account"],
+    acme_listen   : String => ["",  "acme-listen",    "AcmeListen",     "http-01 challenge service ip:port"],
+    acme_staging  : bool   => ["",  "acme-staging",   "AcmeStaging",    "use Let's Encrypt staging environment"],
+    http_redirect : String => ["",  "http-redirect",  "HttpRedirect",   "plain http ip:port that only redirects to https (empty: disabled)"],
+    hsts_max_age  : String => ["",  "hsts-max-age",   "HstsMaxAge",     "Strict-Transport-Security max-age on https (unit: second, 0: disabled)"],
+    hsts_preload  : bool   => ["",  "hsts-preload",   "HstsPreload",    "add includeSubDomains and preload to HSTS (max-age at least one year)"],
+    compress_min  : String => ["",  "compress-min",   "CompressMin",    "min response size to compress with br/gzip/deflate (unit: k/m, 0: disabled)"],
     no_root       : bool   => ["",  "no-root",        "NoRoot",         "disabled auto redirect / to /index.html"],
-    database      : String => ["d", "database",       "Database",       "set aidb database filename"],
+    fallback      : String => ["",  "fallback",       "Fallback",       "comma separated handler chain for unmatched requests (assets/proxy)"],
+    fallback_proxy: String => ["",  "fallback-proxy", "FallbackProxy",  "reverse proxy target of the fallback proxy step, e.g. http://127.0.0.1:5173"],
+    proxy_failures: String => ["",  "proxy-failures", "ProxyFailures",  "consecutive reverse proxy failures that open the circuit (0: disabled)"],
+    proxy_cooldown: String => ["",  "proxy-cooldown", "ProxyCooldown",  "reverse proxy circuit open time before a trial request (unit: second)"],
+    proxy_timeout : String => ["",  "proxy-timeout",  "ProxyTimeout",   "reverse proxy request timeout, including the response body (unit: second)"],
+    www_dir       : String => ["",  "www-dir",        "WwwDir",         "serve static files from this directory before the embedded assets"],
+    base_path     : String => ["",  "base-path",      "BasePath",       "url path prefix when served under a sub path by a reverse proxy, e.g. /vault"],
+    not_found     : String => ["",  "not-found",      "NotFound",       "not found response format (auto/json/html)"],
+    envelope      : String => ["",  "envelope",       "Envelope",       "api response field mapping, e.g. success=success,code=,message=errorMsg,data=result"],
+    allowed_hosts : String => ["",  "allowed-hosts",  "AllowedHosts",   "comma separated allowed Host header names, support *.domain (empty: allow all)"],
+    proxy_protocol: bool   => ["",  "proxy-protocol", "ProxyProtocol",  "accept HAProxy PROXY protocol (v1/v2) header on each connection"],
+    stable_order  : bool   => ["",  "stable-order",   "StableOrder",    "sort exported and returned records by group, title and id for diff-friendly output"],
+    http2         : bool   => ["",  "http2",          "Http2",          "enable http2 (h2c on plain connections, ALPN on https)"],
+    no_keep_alive : bool   => ["",  "no-keep-alive",  "NoKeepAlive",    "disable http1 keep-alive, close connection after each request"],
+    header_timeout: String => ["",  "header-timeout", "HeaderTimeout",  "http1 request header read timeout (unit: second, 0: hyper default)"],
+    h2_keep_alive : String => ["",  "h2-keep-alive",  "H2KeepAlive",    "http2 ping interval to detect dead connections (unit: second, 0: disabled)"],
+    h2_max_streams: String => ["",  "h2-max-streams", "H2MaxStreams",   "http2 max concurrent streams per connection (0: hyper default)"],
+    max_header_size: String => ["", "max-header-size", "MaxHeaderSize", "max request header size (unit: k/m, 0: hyper default)"],
+    allow_ips     : String => ["",  "allow-ips",      "AllowIps",       "comma separated allowed client CIDRs, other connections are dropped (empty: allow all)"],
+    deny_ips      : String => ["",  "deny-ips",       "DenyIps",        "comma separated denied client CIDRs, dropped at accept time without response"],
+    trusted_proxies: String => ["", "trusted-proxies", "TrustedProxies", "comma separated reverse proxy CIDRs whose X-Forwarded-For/X-Real-IP headers are trusted (empty: trust none)"],
+    rate_limit    : String => ["",  "rate-limit",     "RateLimit",      "max burst requests per client ip for authenticated apis (0: disabled)"],
+    rate_window   : String => ["",  "rate-window",    "RateWindow",     "time to refill the rate limit bucket (unit: second)"],
+    auth_whitelist: String => ["",  "auth-whitelist", "AuthWhitelist",  "comma separated extra api paths under /public/ (without /api prefix) accessible without login, * matches any characters, e.g. /public/*"],
+    honeypot      : String => ["",  "honeypot",       "Honeypot",       "comma separated decoy paths, trailing * matches prefix, e.g. /wp-login.php,/.env,/.git/*"],
+    honeypot_block: String => ["",  "honeypot-block", "HoneypotBlock",  "block client ip that hits a decoy path for this time (unit: second)"],
+    outbound_proxy: String => ["", "outbound-proxy", "OutboundProxy", "proxy for outbound requests (default: HTTP_PROXY/HTTPS_PROXY env, none: disabled)"],
+    outbound_timeout: String => ["", "outbound-timeout", "OutboundTimeout", "outbound request timeout (unit: second)"],
+    outbound_insecure: bool => ["", "outbound-insecure", "OutboundInsecure", "skip tls certificate verification for outbound requests"],
+    wordlist      : String => ["",  "wordlist",       "Wordlist",       "passphrase wordlist file, one word per line (default: embedded EFF large wordlist)"],
+    email_base    : String => ["",  "email-base",     "EmailBase",      "base email address for generating plus-addressed aliases"],
+    database      : String => ["d", "database",       "Database",       "set aidb database filenames (comma separated) or directory"],
+    duress        : String => ["",  "duress",         "Duress",         "decoy databases opened by duress password, format: [name=]decoy.aidb (comma separated)"],
     password      : String => ["p", "password",       "Password",       "encrypt database with password"],
-    encrypt       : String => ["",  "encrypt",        "Encrypt",        "encrypt KeePass xml file to aidb database format"],
+    encrypt       : String => ["",  "encrypt",        "Encrypt",        "encrypt KeePass xml/kdbx file to aidb database format"],
+    kdbx_password : String => ["",  "kdbx-password",  "KdbxPassword",   "KeePass kdbx file password (default: same as --password)"],
+    merge_strategy: String => ["",  "merge-strategy", "MergeStrategy",  "--encrypt into an existing database: overwrite, newer (merge by id, newer wins) or keep (merge by id, existing wins)"],
+    export        : String => ["",  "export",         "Export",         "export database to KeePass xml or csv file (by file extension)"],
+    export_recipients: String => ["", "export-recipients", "ExportRecipients", "encrypt exports to age recipients, ssh public keys or OpenPGP public key files (comma separated)"],
+    backup_token  : String => ["",  "backup-token",   "BackupToken",    "bearer token of /api/backup returning encrypted exports, at least 16 chars, requires --export-recipients (empty: disabled)"],
+    migrate       : bool   => ["",  "migrate",        "Migrate",        "upgrade database file to the newest format (backup to .bak)"],
+    auto_upgrade_db: bool  => ["",  "auto-upgrade-db", "AutoUpgradeDb", "upgrade v1 format databases at startup (backup to .bak), requires --password"],
+    change_password: String => ["", "change-password", "ChangePassword", "re-encrypt database with the new password, old password set by --password"],
     task_interval : String => ["",  "task-interval",  "TaskInterval",   "timed task time interval(unit: second)"],
     cache_expire  : String => ["",  "cache-expire",   "CacheExpire",    "maximum effective time for data cache survival"],
     session_expire: String => ["",  "session-expire", "SessionExpire",  "session expiration time"],
+    session_max_age: String => ["", "session-max-age", "SessionMaxAge", "session absolute max lifetime, refresh does not extend it (unit: second)"],
+    no_sliding_session: bool => ["", "no-sliding-session", "NoSlidingSession", "requests do not extend the session idle expiration, clients call /api/keepalive explicitly"],
+    login_max_failures: String => ["", "login-max-failures", "LoginMaxFailures", "lock client ip after consecutive login failures (0: disabled)"],
+    login_global_max: String => ["", "login-global-max", "LoginGlobalMax", "lock all logins after total login failures (0: disabled)"],
+    login_lockout : String => ["",  "login-lockout",  "LoginLockout",   "login lockout time, also failure counter lifetime (unit: second)"],
+    access_window : String => ["",  "access-window",  "AccessWindow",   "allowed access hours, e.g. mon-fri 07:00-23:00;work=mon-fri 08:00-19:00 (empty: unrestricted)"],
+    basic_auth    : bool   => ["",  "basic-auth",     "BasicAuth",      "allow http basic auth (user: database name, password: master password) for scripts"],
+    stepup_window : String => ["",  "stepup-window",  "StepupWindow",   "require master password re-entry within the last N minutes for export and sensitive records (0: disabled)"],
+    max_field_len : String => ["",  "max-field-len",  "MaxFieldLen",    "max length of record title, user and url (unit: byte, 0: unlimited)"],
+    max_notes_len : String => ["",  "max-notes-len",  "MaxNotesLen",    "max length of record notes (unit: k/m, 0: unlimited)"],
+    max_record_size: String => ["", "max-record-size", "MaxRecordSize", "max total size of a record (unit: k/m, 0: unlimited)"],
+    token_secret  : String => ["",  "token-secret",   "TokenSecret",    "hmac secret for stateless session tokens, at least 16 chars (empty: in-memory sessions)"],
+    token_header  : String => ["",  "token-header",   "TokenHeader",    "request header carrying the session token"],
+    token_scheme  : String => ["",  "token-scheme",   "TokenScheme",    "scheme before the session token in the header (empty: whole header value)"],
+    token_query   : String => ["",  "token-query",    "TokenQuery",     "url parameter carrying the session token for SSE/WebSocket (empty: disabled)"],
+    shadow        : String => ["",  "shadow",         "Shadow",         "mirror a percentage of api requests to candidate handlers and log differences, e.g. list=10 (empty: disabled)"],
+    timing_header : bool   => ["",  "timing-header",  "TimingHeader",   "return aidb operation timing breakdown in X-Timing response header"],
+    shutdown_timeout: String => ["", "shutdown-timeout", "ShutdownTimeout", "max time to wait for in-flight requests on shutdown (unit: second)"],
+    rss_watermark : String => ["",  "rss-watermark",  "RssWatermark",   "process memory warning watermark (unit: k/m/g, 0: disabled)"],
+    cache_watermark: String => ["", "cache-watermark", "CacheWatermark", "data cache memory warning watermark (unit: k/m/g, 0: disabled)"],
+    auto_drop_cache: bool  => ["",  "auto-drop-cache", "AutoDropCache", "drop the data cache when memory exceeds the watermark"],
 );
 
+/// 所有配置项的字段名、短参数名及长参数名, 敏感项以`secret`标记, 用于遍历配置项
+///
+/// `with_conf_fields!(callback!(args...))`展开为`callback!(args... field: "short", "long"; ...)`
+#[macro_export]
+macro_rules! with_conf_fields {
+    ($callback:ident!($($args:tt)*)) => {
+        $callback!($($args)*
+            profile: "", "profile";
+            log_level: "L", "log-level";
+            log_file: "F", "log-file";
+            log_max: "M", "log-max";
+            log_timezone: "", "log-timezone";
+            log_time_format: "", "log-time-format";
+            access_log: "", "access-log";
+            no_console: "", "no-console";
+            no_color: "", "no-color";
+            no_banner: "", "no-banner";
+            json_errors: "", "json-errors";
+            banner_file: "", "banner-file";
+            threads: "t", "threads";
+            listen: "l", "listen";
+            tls_cert: "", "tls-cert";
+            tls_key: "", "tls-key";
+            acme_domain: "", "acme-domain";
+            acme_email: "", "acme-email";
+            acme_listen: "", "acme-listen";
+            acme_staging: "", "acme-staging";
+            http_redirect: "", "http-redirect";
+            hsts_max_age: "", "hsts-max-age";
+            hsts_preload: "", "hsts-preload";
+            compress_min: "", "compress-min";
+            no_root: "", "no-root";
+            fallback: "", "fallback";
+            fallback_proxy: "", "fallback-proxy";
+            proxy_failures: "", "proxy-failures";
+            proxy_cooldown: "", "proxy-cooldown";
+            proxy_timeout: "", "proxy-timeout";
+            www_dir: "", "www-dir";
+            base_path: "", "base-path";
+            export_recipients: "", "export-recipients";
+            backup_token: "", "backup-token", secret;
+            not_found: "", "not-found";
+            envelope: "", "envelope";
+            allowed_hosts: "", "allowed-hosts";
+            proxy_protocol: "", "proxy-protocol";
+            stable_order: "", "stable-order";
+            http2: "", "http2";
+            no_keep_alive: "", "no-keep-alive";
+            header_timeout: "", "header-timeout";
+            h2_keep_alive: "", "h2-keep-alive";
+            h2_max_streams: "", "h2-max-streams";
+            max_header_size: "", "max-header-size";
+            allow_ips: "", "allow-ips";
+            deny_ips: "", "deny-ips";
+            trusted_proxies: "", "trusted-proxies";
+            rate_limit: "", "rate-limit";
+            rate_window: "", "rate-window";
+            auth_whitelist: "", "auth-whitelist";
+            honeypot: "", "honeypot";
+            honeypot_block: "", "honeypot-block";
+            outbound_proxy: "", "outbound-proxy", secret;
+            outbound_timeout: "", "outbound-timeout";
+            outbound_insecure: "", "outbound-insecure";
+            wordlist: "", "wordlist";
+            email_base: "", "email-base";
+            database: "d", "database";
+            duress: "", "duress", secret;
+            password: "p", "password", secret;
+            encrypt: "", "encrypt";
+            kdbx_password: "", "kdbx-password", secret;
+            merge_strategy: "", "merge-strategy";
+            export: "", "export";
+            migrate: "", "migrate";
+            auto_upgrade_db: "", "auto-upgrade-db";
+            change_password: "", "change-password", secret;
+            task_interval: "", "task-interval";
+            cache_expire: "", "cache-expire";
+            session_expire: "", "session-expire";
+            session_max_age: "", "session-max-age";
+            no_sliding_session: "", "no-sliding-session";
+            login_max_failures: "", "login-max-failures";
+            login_global_max: "", "login-global-max";
+            login_lockout: "", "login-lockout";
+            access_window: "", "access-window";
+            basic_auth: "", "basic-auth";
+            stepup_window: "", "stepup-window";
+            max_field_len: "", "max-field-len";
+            max_notes_len: "", "max-notes-len";
+            max_record_size: "", "max-record-size";
+            token_secret: "", "token-secret", secret;
+            token_header: "", "token-header";
+            token_scheme: "", "token-scheme";
+            token_query: "", "token-query";
+            shadow: "", "shadow";
+            timing_header: "", "timing-header";
+            shutdown_timeout: "", "shutdown-timeout";
+            rss_watermark: "", "rss-watermark";
+            cache_watermark: "", "cache-watermark";
+            auto_drop_cache: "", "auto-drop-cache";
+        )
+    };
+}
+
 impl Default for AppConf {
     fn default() -> AppConf {
         AppConf {
+            profile:        String::with_capacity(0),
             log_level:      String::from("info"),
             log_file:       String::with_capacity(0),
             log_max:        String::from("10m"),
+            log_timezone:   String::from("local"),
+            log_time_format: String::with_capacity(0),
+            access_log:     String::from("text"),
             no_console:     false,
+            no_color:       false,
+            no_banner:      false,
+            json_errors:    false,
+            banner_file:    String::with_capacity(0),
             threads:        String::from("1"),
             listen:         String::from("0.0.0.0:8888"),
+            tls_cert:       String::with_capacity(0),
+            tls_key:        String::with_capacity(0),
+            acme_domain:    String::with_capacity(0),
+            acme_email:     String::with_capacity(0),
+            acme_listen:    String::from("0.0.0.0:80"),
+            acme_staging:   false,
+            http_redirect:  String::with_capacity(0),
+            hsts_max_age:   String::from("0"),
+            hsts_preload:   false,
+            compress_min:   String::from("1k"),
             no_root:        false,
+            fallback:       String::from("assets"),
+            fallback_proxy: String::with_capacity(0),
+            proxy_failures: String::from("5"),
+            proxy_cooldown: String::from("30"),
+            proxy_timeout:  String::from("30"),
+            www_dir:        String::with_capacity(0),
+            base_path:      String::with_capacity(0),
+            export_recipients: String::with_capacity(0),
+            backup_token:   String::with_capacity(0),
+            not_found:      String::from("auto"),
+            envelope:       String::with_capacity(0),
+            allowed_hosts:  String::with_capacity(0),
+            proxy_protocol: false,
+            stable_order:   false,
+            http2:          false,
+            no_keep_alive:  false,
+            header_timeout: String::from("30"),
+            h2_keep_alive:  String::from("0"),
+            h2_max_streams: String::from("0"),
+            max_header_size: String::from("0"),
+            allow_ips:      String::with_capacity(0),
+            deny_ips:       String::with_capacity(0),
+            trusted_proxies: String::from("127.0.0.1,::1"),
+            rate_limit:     String::from("3"),
+            rate_window:    String::from("60"),
+            auth_whitelist: String::with_capacity(0),
+            honeypot:       String::with_capacity(0),
+            honeypot_block: String::from("3600"),
+            outbound_proxy: String::with_capacity(0),
+            outbound_timeout: String::from("10"),
+            outbound_insecure: false,
+            wordlist:       String::with_capacity(0),
+            email_base:     String::with_capacity(0),
             database:       String::with_capacity(0),
+            duress:         String::with_capacity(0),
             password:       String::with_capacity(0),
             encrypt:        String::with_capacity(0),
+            kdbx_password:  String::with_capacity(0),
+            merge_strategy: String::from("overwrite"),
+            export:         String::with_capacity(0),
+            migrate:        false,
+            auto_upgrade_db: false,
+            change_password: String::with_capacity(0),
             task_interval:  String::from("180"),
             cache_expire:   String::from("600"),
             session_expire: String::from("1800"),
+            session_max_age: String::from("43200"),
+            no_sliding_session: false,
+            login_max_failures: String::from("5"),
+            login_global_max: String::from("50"),
+            login_lockout:  String::from("900"),
+            access_window:  String::with_capacity(0),
+            basic_auth:     false,
+            stepup_window:  String::from("0"),
+            max_field_len:  String::from("1024"),
+            max_notes_len:  String::from("64k"),
+            max_record_size: String::from("128k"),
+            token_secret:   String::with_capacity(0),
+            token_header:   String::from("Authorization"),
+            token_scheme:   String::from("session"),
+            token_query:    String::with_capacity(0),
+            shadow:         String::with_capacity(0),
+            timing_header:  false,
+            shutdown_timeout: String::from("10"),
+            rss_watermark:  String::from("0"),
+            cache_watermark: String::from("0"),
+            auto_drop_cache: false,
         }
     }
 }
 
-fn init() -> bool {
+/// 解析配置并初始化, 命令行操作(转换、导出、升级)在此执行完毕
+///
+/// Returns:
+///
+/// Ok(Some(state)): 继续启动服务, Ok(None): 已执行完毕正常退出, Err(e): 执行失败
+fn init() -> Result<Option<Arc<AppState>>, CliError> {
     let version = format!("{APP_NAME} version {APP_VER} CopyLeft Kivensoft 2023.");
+    // 参数解析失败时配置尚不可用, 直接检查命令行
+    cli::set_json_errors(std::env::args().any(|arg| arg == "--json-errors"));
     let ac = AppConf::init();
-    if !appconfig::parse_args(ac, &version).expect("parse args fail") {
-        return false;
+    let parsed = appconfig::parse_args(ac, &version)
+        .map_err(|e| CliError::new(ExitCode::Usage, format!("parse args error: {e}"))
+            .hint("run with --help to see all options"))?;
+    if !parsed {
+        return Ok(None);
     }
+    let reparse = |ac: &mut AppConf| match appconfig::parse_args(ac, &version) {
+        Ok(_) => Ok(()),
+        Err(e) => anyhow_ext::bail!("parse args error: {e}"),
+    };
+    let profile_file = profile::apply(ac, APP_NAME, reparse)
+        .map_err(|e| CliError::from_error(ExitCode::Config, format!("--profile {}", ac.profile), e))?;
+    cli::set_json_errors(ac.json_errors);
 
     if ac.database.is_empty() {
-        eprintln!("must use --database set aidb database filename");
-        return false;
+        return Err(CliError::missing("database", "set aidb database filename"));
     }
+    let databases = database_list(&ac.database)
+        .map_err(|e| CliError::from_error(ExitCode::NoInput, format!("--database {}", ac.database), e))?;
+    let decoys = decoy_list(&ac.duress, &databases)
+        .map_err(|e| CliError::from_error(ExitCode::Config, format!("--duress {}", ac.duress), e))?;
+
+    aidb::set_stable_order(ac.stable_order);
 
-    AppGlobal::init(AppGlobal {
+    // 启动诊断: 先校验所有配置项, 有错误时一起输出后退出
+    let mut diag = Diagnostics::default();
+    let export_recipients = recipient::Recipients::parse(&ac.export_recipients).unwrap_or_else(|e| {
+        diag.add("export-recipients", &ac.export_recipients, e);
+        Default::default()
+    });
+    if !ac.backup_token.is_empty() && export_recipients.is_empty() {
+        diag.add("backup-token", "***", "requires --export-recipients");
+    }
+    if !ac.backup_token.is_empty() && ac.backup_token.len() < MIN_BACKUP_TOKEN_LEN {
+        diag.add("backup-token", "***", format!("must be at least {MIN_BACKUP_TOKEN_LEN} characters"));
+    }
+    let state = Arc::new(AppState {
         startup_time: localtime::unix_timestamp(),
-        task_interval: ac.task_interval.parse().expect(arg_err!("task_interval")),
-        cache_expire: ac.cache_expire.parse().expect(arg_err!("cache_expire")),
-        session_expire: ac.session_expire.parse().expect(arg_err!("session_expire")),
+        task_interval: ac.task_interval.parse().check(&mut diag, "task-interval", &ac.task_interval).unwrap_or_default(),
+        cache_expire: ac.cache_expire.parse().check(&mut diag, "cache-expire", &ac.cache_expire).unwrap_or_default(),
+        session_expire: ac.session_expire.parse().check(&mut diag, "session-expire", &ac.session_expire).unwrap_or_default(),
+        session_max_age: ac.session_max_age.parse().check(&mut diag, "session-max-age", &ac.session_max_age).unwrap_or_default(),
+        no_sliding_session: ac.no_sliding_session,
+        login_guard: apis::LoginGuard {
+            max_failures: ac.login_max_failures.parse()
+                .check(&mut diag, "login-max-failures", &ac.login_max_failures).unwrap_or_default(),
+            global_max_failures: ac.login_global_max.parse()
+                .check(&mut diag, "login-global-max", &ac.login_global_max).unwrap_or_default(),
+            lockout: ac.login_lockout.parse().check(&mut diag, "login-lockout", &ac.login_lockout).unwrap_or_default(),
+        },
+        access_windows: access::AccessWindows::parse(&ac.access_window)
+            .check(&mut diag, "access-window", &ac.access_window).unwrap_or_default(),
+        record_limits: apis::RecordLimits {
+            max_field_len: ac.max_field_len.parse().check(&mut diag, "max-field-len", &ac.max_field_len).unwrap_or_default(),
+            max_notes_len: parse_watermark(&ac.max_notes_len)
+                .check(&mut diag, "max-notes-len", &ac.max_notes_len).unwrap_or_default() as usize,
+            max_record_size: parse_watermark(&ac.max_record_size)
+                .check(&mut diag, "max-record-size", &ac.max_record_size).unwrap_or_default() as usize,
+        },
+        basic_auth: ac.basic_auth,
+        stepup_window: ac.stepup_window.parse::<u64>().ok().and_then(|m| m.checked_mul(60))
+            .check(&mut diag, "stepup-window", &ac.stepup_window).unwrap_or_default(),
+        rss_watermark: parse_watermark(&ac.rss_watermark).check(&mut diag, "rss-watermark", &ac.rss_watermark).unwrap_or_default(),
+        cache_watermark: parse_watermark(&ac.cache_watermark)
+            .check(&mut diag, "cache-watermark", &ac.cache_watermark).unwrap_or_default(),
+        auto_drop_cache: ac.auto_drop_cache,
+        database: databases[0].clone(),
+        databases,
+        decoys,
+        email_base: ac.email_base.clone(),
+        base_path: parse_base_path(&ac.base_path).check(&mut diag, "base-path", &ac.base_path).unwrap_or_default(),
+        export_recipients: Arc::new(export_recipients),
+        backup_token: ac.backup_token.clone(),
     });
 
     if !ac.listen.is_empty() && ac.listen.as_bytes()[0] == b':' {
         ac.listen.insert_str(0, "0.0.0.0");
     };
+    ac.listen.parse::<std::net::SocketAddr>().check(&mut diag, "listen", &ac.listen);
+
+    let log_utc = match ac.log_timezone.as_str() {
+        "local" | "" => false,
+        "utc" | "UTC" => true,
+        _ => {
+            diag.add("log-timezone", &ac.log_timezone, "must be local or utc");
+            false
+        }
+    };
+    if !httpserver::init_log_time(log_utc, &ac.log_time_format) {
+        diag.add("log-time-format", &ac.log_time_format, "invalid strftime format");
+    }
+    let log_level = asynclog::parse_level(&ac.log_level).check(&mut diag, "log-level", &ac.log_level)
+        .unwrap_or(log::Level::Info);
+    let log_max = asynclog::parse_size(&ac.log_max).check(&mut diag, "log-max", &ac.log_max).unwrap_or_default();
+
+    if let Some(timeout) = ac.outbound_timeout.parse().check(&mut diag, "outbound-timeout", &ac.outbound_timeout) {
+        let cfg = outbound::OutboundConfig { proxy: &ac.outbound_proxy, timeout, insecure: ac.outbound_insecure };
+        if let Err(e) = outbound::init(&cfg) {
+            diag.add("outbound-proxy", &ac.outbound_proxy, e);
+        }
+    }
+    if !ac.email_base.is_empty() {
+        if let Err(e) = generator::email_alias(&ac.email_base, "") {
+            diag.add("email-base", &ac.email_base, e);
+        }
+    }
+    aidb::MergeStrategy::parse(&ac.merge_strategy).check(&mut diag, "merge-strategy", &ac.merge_strategy);
+    if let Err(e) = apis::Authentication::set_token_transport(&ac.token_header, &ac.token_scheme, &ac.token_query) {
+        diag.add("token-header", &ac.token_header, e);
+    }
+    check_server_conf(&mut diag, ac);
+    diag.finish()?;
 
-    let log_level = asynclog::parse_level(&ac.log_level).expect(arg_err!("log-level"));
-    let log_max = asynclog::parse_size(&ac.log_max).expect(arg_err!("log-max"));
+    let use_color = httpserver::init_color(!ac.no_color);
 
     if log_level == log::Level::Trace {
         println!("config setting: {ac:#?}\n");
     }
 
-    asynclog::init_log(log_level, ac.log_file.clone(), log_max,
-        !ac.no_console, true).expect("init log error");
-    asynclog::set_level("mio".to_owned(), log::LevelFilter::Info);
-    asynclog::set_level("want".to_owned(), log::LevelFilter::Info);
+    applog::init_log(log_level, &ac.log_file, log_max as u64, !ac.no_console)
+        .map_err(|e| CliError::new(ExitCode::CantCreat, format!("init log {} error: {e}", ac.log_file)))?;
+    applog::set_level("mio", log::LevelFilter::Info);
+    applog::set_level("want", log::LevelFilter::Info);
+
+    if !ac.profile.is_empty() {
+        match &profile_file {
+            Some(file) => log::info!("config profile {} enabled, load {file}", ac.profile),
+            None => log::info!("config profile {} enabled", ac.profile),
+        }
+    }
+
+    if !ac.wordlist.is_empty() {
+        let count = generator::load_wordlist(&ac.wordlist)
+            .map_err(|e| CliError::from_error(ExitCode::DataErr, format!("--wordlist {}", ac.wordlist), e))?;
+        log::info!("load wordlist {}, word total: {count}", ac.wordlist);
+    }
+
+    if !ac.token_secret.is_empty() {
+        apis::set_shared_secret(&ac.token_secret)
+            .map_err(|e| CliError::new(ExitCode::Config, format!("--token-secret error: {e}")))?;
+        log::info!("stateless session token enabled");
+    }
+
+    if (!ac.encrypt.is_empty() || !ac.export.is_empty() || ac.migrate || !ac.change_password.is_empty())
+            && state.databases.len() > 1 {
+        return Err(CliError::new(ExitCode::Usage,
+            "--encrypt/--export/--migrate/--change-password must use --database set a single aidb database filename"));
+    }
+
+    // 除导出外都会写入数据库文件, 加锁防止多个进程同时写入同一个文件
+    if ac.export.is_empty() {
+        let listen = if ac.encrypt.is_empty() && !ac.migrate { ac.listen.as_str() } else { "" };
+        for database in state.databases() {
+            if let Err(e) = dblock::acquire(database, listen) {
+                return Err(CliError::new(ExitCode::TempFail, e)
+                    .hint("stop the process using the database, or remove the stale .lock file"));
+            }
+        }
+    }
 
     if !ac.encrypt.is_empty() {
         if ac.password.is_empty() {
-            eprintln!("must use --password set database password");
-            return false;
+            return Err(CliError::missing("password", "set database password"));
+        }
+        let strategy = aidb::MergeStrategy::parse(&ac.merge_strategy)
+            .conf("merge-strategy", &ac.merge_strategy)?;
+        let kdbx_password = if ac.kdbx_password.is_empty() { &ac.password } else { &ac.kdbx_password };
+        if strategy == aidb::MergeStrategy::Overwrite || !std::path::Path::new(&state.database).exists() {
+            aidb::encrypt_database(&ac.encrypt, kdbx_password, &ac.password, &state.database,
+                    &|r| state.record_limits.check(r))
+                .map_err(|e| CliError::from_error(ExitCode::DataErr, format!("convert {}", ac.encrypt), e))?;
+            println!("{} -> {} conversion completed.", ac.encrypt, state.database);
+            return Ok(None);
         }
-        aidb::encrypt_database(&ac.encrypt, &ac.password, &ac.database).unwrap();
-        println!("{} -> {} conversion completed.", ac.encrypt, ac.database);
-        return false;
+        check_password(&state.database, &ac.password)?;
+        let r = aidb::merge_database(&ac.encrypt, kdbx_password, &ac.password, &state.database, strategy,
+                &|r| state.record_limits.check(r))
+            .map_err(|e| CliError::from_error(ExitCode::DataErr,
+                format!("merge {} into {}", ac.encrypt, state.database), e))?;
+        println!("{} -> {} merge completed ({}), added: {}, updated: {}, unchanged: {}, conflicts: {}",
+            ac.encrypt, state.database, strategy.name(), r.added, r.updated, r.unchanged, r.conflicts.len());
+        for (id, title) in r.conflicts.iter() {
+            println!("  conflict: {title} ({id}), existing record kept");
+        }
+        return Ok(None);
     }
 
-    if let Some((s1, s2)) = BANNER.split_once('%') {
-        let s2 = &s2[APP_VER.len() - 1..];
-        let banner = format!("{s1}{APP_VER}{s2}");
-        appconfig::print_banner(&banner, true);
+    if !ac.export.is_empty() {
+        if ac.password.is_empty() {
+            return Err(CliError::missing("password", "set database password"));
+        }
+        check_password(&state.database, &ac.password)?;
+        let export = || -> anyhow_ext::Result<()> {
+            let recipients = &state.export_recipients;
+            if recipients.is_empty() {
+                let mut out = std::io::BufWriter::new(std::fs::File::create(&ac.export)?);
+                aidb::export_database(&state.database, &ac.password,
+                    aidb::ExportFormat::from_path(&ac.export), &mut out)?;
+                std::io::Write::flush(&mut out)?;
+                return Ok(());
+            }
+            // 加密导出时按去掉加密扩展名后的文件名确定导出格式, 例如backup.csv.age
+            let plain_name = ac.export.strip_suffix(&format!(".{}", recipients.ext())).unwrap_or(&ac.export);
+            let mut data = zeroize::Zeroizing::new(Vec::new());
+            aidb::export_database(&state.database, &ac.password, aidb::ExportFormat::from_path(plain_name), &mut *data)?;
+            let name = std::path::Path::new(plain_name).file_name().and_then(|s| s.to_str()).unwrap_or_default();
+            std::fs::write(&ac.export, recipients.encrypt(name, &data)?)?;
+            Ok(())
+        };
+        export().map_err(|e| CliError::from_error(ExitCode::CantCreat, format!("export {}", ac.export), e))?;
+        println!("{} -> {} export completed.", state.database, ac.export);
+        return Ok(None);
     }
 
-    true
+    if ac.migrate {
+        if ac.password.is_empty() {
+            return Err(CliError::missing("password", "set database password"));
+        }
+        check_password(&state.database, &ac.password)?;
+        let ver = aidb::migrate_database(&state.database, &ac.password)
+            .map_err(|e| CliError::from_error(ExitCode::DataErr, format!("migrate {}", state.database), e))?;
+        match ver {
+            Some(ver) => println!("{} migrated from {ver} to {}, backup: {}.bak",
+                state.database, aidb::FormatVersion::LATEST, state.database),
+            None => println!("{} is already the newest format {}.",
+                state.database, aidb::FormatVersion::LATEST),
+        }
+        return Ok(None);
+    }
+
+    if !ac.change_password.is_empty() {
+        if ac.password.is_empty() {
+            return Err(CliError::missing("password", "set the current database password"));
+        }
+        if ac.change_password == ac.password {
+            return Err(CliError::new(ExitCode::Usage, "--change-password is the same as --password"));
+        }
+        check_password(&state.database, &ac.password)?;
+        aidb::reencrypt(&state.database, &ac.password, &ac.change_password)
+            .map_err(|e| CliError::from_error(ExitCode::DataErr, format!("change password of {}", state.database), e))?;
+        println!("{} password changed.", state.database);
+        return Ok(None);
+    }
+
+    upgrade_legacy_databases(&state)?;
+
+    if !ac.no_banner {
+        let banner = if ac.banner_file.is_empty() {
+            render_banner(BANNER)
+        } else {
+            match std::fs::read_to_string(&ac.banner_file) {
+                Ok(text) => render_banner(&text),
+                Err(e) => {
+                    let code = if e.kind() == std::io::ErrorKind::NotFound { ExitCode::NoInput } else { ExitCode::IoErr };
+                    return Err(CliError::new(code, format!("read banner file {} error: {e}", ac.banner_file)));
+                }
+            }
+        };
+        appconfig::print_banner(&banner, use_color);
+    }
+
+    Ok(Some(state))
 }
 
-fn main() {
-    if !init() { return; }
+/// 根据配置创建http连接参数, 值为0的参数使用hyper的缺省值
+fn new_http_options() -> Result<httpserver::HttpOptions, CliError> {
+    use std::time::Duration;
+
+    let ac = AppConf::get();
+    let header_timeout: u64 = ac.header_timeout.parse().conf("header-timeout", &ac.header_timeout)?;
+    let keep_alive: u64 = ac.h2_keep_alive.parse().conf("h2-keep-alive", &ac.h2_keep_alive)?;
+    let max_streams: u32 = ac.h2_max_streams.parse().conf("h2-max-streams", &ac.h2_max_streams)?;
+    let max_header_size = parse_watermark(&ac.max_header_size).conf("max-header-size", &ac.max_header_size)?;
+
+    let mut options = httpserver::HttpOptions::new()
+        .http2(ac.http2)
+        .keep_alive(!ac.no_keep_alive);
+    if header_timeout > 0 {
+        options = options.header_read_timeout(Duration::from_secs(header_timeout));
+    }
+    if keep_alive > 0 {
+        // 等待应答的超时时间与发送间隔相同
+        options = options.keep_alive_ping(Duration::from_secs(keep_alive), Duration::from_secs(keep_alive));
+    }
+    if max_streams > 0 {
+        options = options.max_concurrent_streams(max_streams);
+    }
+    if max_header_size > 0 {
+        options = options.max_header_size(max_header_size as usize);
+    }
+    Ok(options)
+}
 
+/// 校验启动服务时才使用的配置项, 与其它配置项的错误一起输出
+fn check_server_conf(diag: &mut Diagnostics, ac: &AppConf) {
+    match ac.threads.parse::<usize>() {
+        Ok(threads) if threads > 256 => diag.add("threads", &ac.threads, "multi-threads range in 0-256"),
+        Ok(_) => {}
+        Err(_) => diag.add("threads", &ac.threads, "format error"),
+    }
+    ac.shutdown_timeout.parse::<u64>().check(diag, "shutdown-timeout", &ac.shutdown_timeout);
+    ac.header_timeout.parse::<u64>().check(diag, "header-timeout", &ac.header_timeout);
+    ac.h2_keep_alive.parse::<u64>().check(diag, "h2-keep-alive", &ac.h2_keep_alive);
+    ac.h2_max_streams.parse::<u32>().check(diag, "h2-max-streams", &ac.h2_max_streams);
+    parse_watermark(&ac.max_header_size).check(diag, "max-header-size", &ac.max_header_size);
+    ac.hsts_max_age.parse::<u64>().check(diag, "hsts-max-age", &ac.hsts_max_age);
+    parse_watermark(&ac.compress_min).check(diag, "compress-min", &ac.compress_min);
+    httpserver::LogFormat::parse(&ac.access_log).check(diag, "access-log", &ac.access_log);
+    if !ac.envelope.is_empty() {
+        if let Err(e) = ac.envelope.parse::<httpserver::Envelope>() {
+            diag.add("envelope", &ac.envelope, e);
+        }
+    }
+    if !ac.honeypot.is_empty() {
+        ac.honeypot_block.parse::<u64>().check(diag, "honeypot-block", &ac.honeypot_block);
+    }
+    ac.rate_limit.parse::<u32>().check(diag, "rate-limit", &ac.rate_limit);
+    let allow_ips: Vec<&str> = ac.allow_ips.split(',').collect();
+    if let Err(e) = httpserver::IpFilter::new(&allow_ips, &[]) {
+        diag.add("allow-ips", &ac.allow_ips, e);
+    }
+    let deny_ips: Vec<&str> = ac.deny_ips.split(',').collect();
+    if let Err(e) = httpserver::IpFilter::new(&[], &deny_ips) {
+        diag.add("deny-ips", &ac.deny_ips, e);
+    }
+    let trusted_proxies: Vec<&str> = ac.trusted_proxies.split(',').collect();
+    if let Err(e) = httpserver::TrustedProxies::new(&trusted_proxies) {
+        diag.add("trusted-proxies", &ac.trusted_proxies, e);
+    }
+    if let Err(e) = apis::Authentication::check_whitelist(&ac.auth_whitelist.split(',').collect::<Vec<_>>()) {
+        diag.add("auth-whitelist", &ac.auth_whitelist, e);
+    }
+    if let Err(e) = apis::new_shadow(&ac.shadow, &[""]) {
+        diag.add("shadow", &ac.shadow, e);
+    }
+    ac.rate_window.parse::<u64>().check(diag, "rate-window", &ac.rate_window);
+    if !ac.acme_domain.is_empty() {
+        ac.acme_listen.parse::<std::net::SocketAddr>().check(diag, "acme-listen", &ac.acme_listen);
+    }
+    if !ac.http_redirect.is_empty() {
+        let addr = match ac.http_redirect.starts_with(':') {
+            true => format!("0.0.0.0{}", ac.http_redirect),
+            false => ac.http_redirect.clone(),
+        };
+        addr.parse::<std::net::SocketAddr>().check(diag, "http-redirect", &ac.http_redirect);
+    }
+}
+
+/// 检查v1格式(md5校验+AES-CTR)的数据库, 缺省拒绝启动, 指定`--auto-upgrade-db`时升级为最新格式
+fn upgrade_legacy_databases(state: &AppState) -> Result<(), CliError> {
+    let ac = AppConf::get();
+    let (legacy, decoys) = legacy_databases(state);
+    // 诱饵数据库使用胁迫口令, 无法用主口令升级, v1格式仍可正常读取
+    for decoy in decoys {
+        log::warn!("decoy database {decoy} uses the legacy v1 format, \
+            upgrade it with --migrate --database {decoy} --password <duress password>");
+    }
+    if legacy.is_empty() {
+        return Ok(());
+    }
+
+    if !ac.auto_upgrade_db {
+        return Err(CliError::new(ExitCode::DataErr,
+            format!("database {} uses the legacy v1 format (md5 + aes-128-ctr)", legacy.join(", ")))
+            .hint("run with --migrate --password <password> to upgrade it, \
+                or start with --auto-upgrade-db --password <password>"));
+    }
+    if ac.password.is_empty() {
+        return Err(CliError::missing("password", "to upgrade v1 databases with --auto-upgrade-db"));
+    }
+
+    for database in legacy {
+        check_password(database, &ac.password)?;
+        let upgrade = || -> anyhow_ext::Result<()> {
+            log::info!("database {database} header before upgrade: {}", aidb::header_info(database)?);
+            aidb::migrate_database(database, &ac.password)?;
+            log::info!("database {database} header after upgrade: {}, backup: {database}.bak",
+                aidb::header_info(database)?);
+            Ok(())
+        };
+        upgrade().map_err(|e| CliError::from_error(ExitCode::DataErr, format!("upgrade {database}"), e))?;
+    }
+    Ok(())
+}
+
+/// 使用v1格式的数据库, 分别返回普通数据库及诱饵数据库
+///
+/// 文件不存在或者格式错误时由登录及就绪检查处理
+fn legacy_databases(state: &AppState) -> (Vec<&str>, Vec<&str>) {
+    state.databases()
+        .filter(|db| matches!(aidb::format_version(db), Ok(aidb::FormatVersion::V1)))
+        .partition(|db| !state.is_decoy(db))
+}
+
+/// 命令行操作前校验数据库口令, 区分口令错误与其它错误
+fn check_password(database: &str, password: &str) -> Result<(), CliError> {
+    match aidb::check_password(database, password) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(CliError::new(ExitCode::NoPerm, format!("password of {database} error"))
+            .hint("check the --password option")),
+        Err(e) => Err(CliError::from_error(ExitCode::DataErr, format!("read {database}"), e)),
+    }
+}
+
+/// 将banner中的第一个'%'替换为版本号, 并吞掉其后相应数量的空格以保持图形对齐
+fn render_banner(banner: &str) -> String {
+    match banner.split_once('%') {
+        Some((s1, s2)) => {
+            let skip = s2.bytes()
+                .take(APP_VER.len().saturating_sub(1))
+                .take_while(|c| *c == b' ')
+                .count();
+            format!("{s1}{APP_VER}{}", &s2[skip..])
+        }
+        None => banner.to_owned(),
+    }
+}
+
+/// 解析子路径部署的路径前缀, 返回以`/`开头且不以`/`结尾的前缀, 空或者`/`表示部署在根路径
+fn parse_base_path(val: &str) -> Option<String> {
+    let val = val.trim().trim_matches('/');
+    if val.is_empty() {
+        return Some(String::new());
+    }
+    let valid = val.split('/')
+        .all(|seg| !seg.is_empty() && seg != "." && seg != ".."
+            && seg.bytes().all(|c| c.is_ascii_alphanumeric() || matches!(c, b'-' | b'_' | b'.' | b'~')));
+    valid.then(|| format!("/{val}"))
+}
+
+/// 解析大小配置(单位: k/m/g), 例如内存水位线, 空或者0表示不检查
+fn parse_watermark(val: &str) -> Option<u64> {
+    let val = val.trim();
+    if val.is_empty() {
+        return Some(0);
+    }
+    let (num, unit) = match val.as_bytes()[val.len() - 1].to_ascii_lowercase() {
+        b'k' => (&val[..val.len() - 1], 1024),
+        b'm' => (&val[..val.len() - 1], 1024 * 1024),
+        b'g' => (&val[..val.len() - 1], 1024 * 1024 * 1024),
+        _ => (val, 1),
+    };
+    num.trim().parse::<u64>().ok().and_then(|n| n.checked_mul(unit))
+}
+
+/// 解析数据库参数, 多个数据库文件以逗号分隔, 目录时使用目录下所有的`.aidb`文件(按文件名排序)
+///
+/// 会话通过名称得到的id绑定数据库, 文件名(去掉扩展名)即登录时的数据库名称, 不允许重复
+fn database_list(arg: &str) -> anyhow_ext::Result<Vec<String>> {
+    let mut databases = Vec::new();
+    for item in arg.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let path = std::path::Path::new(item);
+        if !path.is_dir() {
+            databases.push(item.to_owned());
+            continue;
+        }
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(path)? {
+            let file = entry?.path();
+            if file.is_file() && file.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("aidb")) {
+                files.push(file.to_string_lossy().into_owned());
+            }
+        }
+        files.sort();
+        databases.extend(files);
+    }
+
+    if databases.is_empty() {
+        anyhow_ext::bail!("no aidb database found in {arg}");
+    }
+    let mut names = std::collections::HashSet::new();
+    for db in databases.iter() {
+        if !names.insert(AppState::database_name(db)) {
+            anyhow_ext::bail!("duplicate database name: {}", AppState::database_name(db));
+        }
+    }
+    Ok(databases)
+}
+
+/// 解析胁迫密码的诱饵数据库参数, 多个以逗号分隔, 格式: `[数据库名=]诱饵数据库文件名`, 省略数据库名表示缺省数据库
+///
+/// 诱饵数据库的主密码即对应数据库的胁迫密码, 返回(真实数据库文件名, 诱饵数据库文件名)
+fn decoy_list(arg: &str, databases: &[String]) -> anyhow_ext::Result<Vec<(String, String)>> {
+    let mut decoys: Vec<(String, String)> = Vec::new();
+    for item in arg.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let (name, file) = match item.split_once('=') {
+            Some((name, file)) => (name.trim(), file.trim()),
+            None => (AppState::database_name(&databases[0]), item),
+        };
+        let database = match databases.iter().find(|db| AppState::database_name(db) == name) {
+            Some(db) => db,
+            None => anyhow_ext::bail!("database not found: {name}"),
+        };
+        if !std::path::Path::new(file).is_file() {
+            anyhow_ext::bail!("decoy database not found: {file}");
+        }
+        let decoy_name = AppState::database_name(file);
+        if databases.iter().any(|db| AppState::database_name(db) == decoy_name)
+                || decoys.iter().any(|(_, db)| AppState::database_name(db) == decoy_name) {
+            anyhow_ext::bail!("duplicate database name: {decoy_name}");
+        }
+        if decoys.iter().any(|(db, _)| db == database) {
+            anyhow_ext::bail!("database {name} already has a decoy database");
+        }
+        decoys.push((database.clone(), file.to_owned()));
+    }
+
+    // 会话通过名称摘要得到的id绑定数据库, 名称不同但id冲突时无法区分
+    let mut ids = std::collections::HashSet::new();
+    for db in databases.iter().chain(decoys.iter().map(|(_, db)| db)) {
+        if !ids.insert(AppState::database_id(db)) {
+            anyhow_ext::bail!("database id conflict: {}", AppState::database_name(db));
+        }
+    }
+
+    Ok(decoys)
+}
+
+/// 根据配置创建蜜罐中间件及其使用的拒绝名单, 未配置诱饵路径时返回None
+fn new_honeypot() -> Result<Option<(httpserver::Honeypot, httpserver::Blocklist)>, CliError> {
+    let ac = AppConf::get();
+    if ac.honeypot.is_empty() {
+        return Ok(None);
+    }
+    let block: u64 = ac.honeypot_block.parse().conf("honeypot-block", &ac.honeypot_block)?;
+    let paths: Vec<&str> = ac.honeypot.split(',').collect();
+    let blocklist = httpserver::Blocklist::default();
+    let honeypot = httpserver::Honeypot::new(&paths, std::time::Duration::from_secs(block), blocklist.clone());
+    Ok(Some((honeypot, blocklist)))
+}
+
+/// 根据配置创建限流中间件, 只对需要登录的接口按客户端ip限流
+fn new_rate_limit(auth: &apis::Authentication) -> Result<Option<httpserver::RateLimit>, CliError> {
+    let ac = AppConf::get();
+    let burst: u32 = ac.rate_limit.parse().conf("rate-limit", &ac.rate_limit)?;
+    if burst == 0 {
+        return Ok(None);
+    }
+    let window: u64 = ac.rate_window.parse().conf("rate-window", &ac.rate_window)?;
+
+    // 备份接口使用自己的令牌认证, 不在登录校验范围内, 需要单独限流防止猜测令牌
+    let auth = auth.clone();
+    Ok(Some(httpserver::RateLimit::new(burst, std::time::Duration::from_secs(window))
+        .with_key(move |ctx| {
+            let path = ctx.req.uri().path();
+            (auth.require_authentication(path) || apis::Authentication::api_path(path) == Some("/backup"))
+                .then(|| ctx.remote_ip().to_string().into())
+        })))
+}
+
+/// 根据配置创建https配置, 配置了acme域名时自动申请证书并启动续期任务
+async fn tls_config() -> anyhow_ext::Result<Option<Arc<httpserver::TlsConfig>>> {
+    let ac = AppConf::get();
+    if !ac.acme_domain.is_empty() {
+        // 证书保存在第一个数据库所在的目录, 数据库参数为目录时直接使用该目录
+        let first = std::path::Path::new(ac.database.split(',').next().unwrap_or_default().trim());
+        let dir = if first.is_dir() { Some(first) } else { first.parent() };
+        let dir = dir.map(|p| p.join("acme"))
+            .unwrap_or_else(|| std::path::PathBuf::from("acme"));
+        let cfg = acme::AcmeConfig {
+            domain: ac.acme_domain.clone(),
+            email: ac.acme_email.clone(),
+            dir,
+            staging: ac.acme_staging,
+        };
+        // 与http跳转服务监听同一地址时, 由验证服务同时负责跳转
+        let acme_addr: std::net::SocketAddr = ac.acme_listen.parse()
+            .map_err(|_| anyhow_ext::anyhow!("--acme-listen {} format error", ac.acme_listen))?;
+        let redirect = match http_redirect_addr()? {
+            Some(addr) if addr == acme_addr => Some(https_redirect()),
+            Some(addr) => {
+                serve_https_redirect(addr);
+                None
+            }
+            None => None,
+        };
+        acme::serve_challenges(acme_addr, redirect);
+        if cfg.needs_renewal() {
+            acme::obtain_certificate(&cfg).await?;
+        }
+        let tls = Arc::new(httpserver::TlsConfig::from_files(&cfg.cert_file(), &cfg.key_file())?);
+        tokio::spawn(acme::renew_loop(cfg, tls.clone()));
+        return Ok(Some(tls));
+    }
+
+    if ac.tls_cert.is_empty() {
+        return Ok(None);
+    }
+    if ac.tls_key.is_empty() {
+        anyhow_ext::bail!("--tls-cert requires --tls-key");
+    }
+    if let Some(addr) = http_redirect_addr()? {
+        serve_https_redirect(addr);
+    }
+    Ok(Some(Arc::new(httpserver::TlsConfig::from_files(&ac.tls_cert, &ac.tls_key)?)))
+}
+
+/// http跳转服务的监听地址
+fn http_redirect_addr() -> anyhow_ext::Result<Option<std::net::SocketAddr>> {
+    let ac = AppConf::get();
+    if ac.http_redirect.is_empty() {
+        return Ok(None);
+    }
+    let mut addr = ac.http_redirect.clone();
+    if addr.starts_with(':') {
+        addr.insert_str(0, "0.0.0.0");
+    }
+    match addr.parse() {
+        Ok(addr) => Ok(Some(addr)),
+        Err(_) => anyhow_ext::bail!("--http-redirect {} format error", ac.http_redirect),
+    }
+}
+
+/// 跳转到本服务https端口的处理函数, 监听地址已在初始化时校验
+fn https_redirect() -> httpserver::HttpsRedirect {
+    let port = AppConf::get().listen.parse::<std::net::SocketAddr>().map_or(443, |addr| addr.port());
+    httpserver::HttpsRedirect::new(port)
+}
+
+/// 启动只负责跳转到https的http服务
+fn serve_https_redirect(addr: std::net::SocketAddr) {
     let mut srv = HttpServer::new();
+    srv.set_default_handler(https_redirect());
+    tokio::spawn(async move {
+        if let Err(e) = srv.run(addr).await {
+            log::error!("http redirect server {addr} error: {e:?}");
+        }
+    });
+}
+
+fn main() -> std::process::ExitCode {
+    match run() {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(e) => e.report(),
+    }
+}
+
+/// 初始化并运行服务, 失败时返回带退出码的错误
+fn run() -> Result<(), CliError> {
+    let state = match init()? {
+        Some(state) => state,
+        None => return Ok(()),
+    };
+
+    let mut srv = HttpServer::new();
+    srv.set_base_path(&state.base_path);
     srv.set_content_path("/api");
-    srv.set_default_handler(apis::default_handler);
-    srv.set_middleware(httpserver::AccessLog);
-    srv.set_middleware(apis::Authentication);
+    srv.add_api_version(ApiVersion::new("v1"));
+    srv.set_default_api_version("v1");
+    let ac = AppConf::get();
+    let chain = apis::default_chain(&ac.fallback, &ac.fallback_proxy, &ac.not_found, &ac.www_dir, &state.base_path)
+        .map_err(|e| CliError::from_error(ExitCode::Config, "--fallback", e))?;
+    srv.set_default_handler(chain);
+    srv.set_proxy_protocol(AppConf::get().proxy_protocol);
+    srv.set_http_options(new_http_options()?);
+    if !ac.envelope.is_empty() {
+        httpserver::Resp::set_envelope(ac.envelope.parse().conf("envelope", &ac.envelope)?);
+    }
+    let allow_ips: Vec<&str> = ac.allow_ips.split(',').collect();
+    let deny_ips: Vec<&str> = ac.deny_ips.split(',').collect();
+    let ip_filter = httpserver::IpFilter::new(&allow_ips, &deny_ips)
+        .map_err(|e| CliError::from_error(ExitCode::Config, "--allow-ips/--deny-ips", e))?;
+    srv.set_ip_filter(ip_filter);
+    let trusted_proxies: Vec<&str> = ac.trusted_proxies.split(',').collect();
+    let trusted_proxies = httpserver::TrustedProxies::new(&trusted_proxies)
+        .map_err(|e| CliError::from_error(ExitCode::Config, "--trusted-proxies", e))?;
+    srv.set_trusted_proxies(trusted_proxies);
+    // 压缩中间件放在最外层, 访问日志中输出的是未压缩的内容
+    let compress_min = parse_watermark(&ac.compress_min).conf("compress-min", &ac.compress_min)?;
+    if compress_min > 0 {
+        srv.set_middleware(httpserver::Compression::new(compress_min as usize));
+    }
+    let access_log = httpserver::LogFormat::parse(&ac.access_log).conf("access-log", &ac.access_log)?;
+    srv.set_middleware(httpserver::RequestId::default());
+    srv.set_middleware(httpserver::AccessLog::new(access_log));
+    srv.set_middleware(metrics::Metrics);
+    if !AppConf::get().allowed_hosts.is_empty() {
+        let hosts: Vec<&str> = AppConf::get().allowed_hosts.split(',').collect();
+        srv.set_middleware(httpserver::AllowedHosts::new(&hosts, &["/api/ping", "/api/health", "/api/ready"]));
+    }
+    // 蜜罐放在登录校验之前, 未登录的扫描请求同样会命中
+    let honeypot = new_honeypot()?;
+    let blocklist = honeypot.as_ref().map(|(_, b)| b.clone());
+    if let Some((honeypot, blocklist)) = honeypot {
+        srv.set_blocklist(blocklist);
+        srv.set_middleware(honeypot);
+    }
+    let auth = apis::Authentication::new(&AppConf::get().auth_whitelist.split(',').collect::<Vec<_>>());
+    let rate_limit = new_rate_limit(&auth)?;
+    if let Some(rl) = &rate_limit {
+        srv.set_middleware(rl.clone());
+    }
+    srv.set_middleware(auth);
+    if AppConf::get().timing_header {
+        srv.set_middleware(timing::TimingHeader);
+    }
+    // 影子路由放在登录校验之后, 候选实现收到与正式实现相同的会话信息
+    let shadow = apis::new_shadow(&ac.shadow, &["/api", "/api/v1"])
+        .map_err(|e| CliError::from_error(ExitCode::Config, "--shadow", e))?;
+    if let Some(shadow) = shadow {
+        srv.set_middleware(shadow);
+    }
+    srv.set_state(state.clone());
 
     httpserver::register_apis!(srv, "",
-        "ping": apis::ping,
-        "login": apis::login,
-        "logout": apis::logout,
-        "list": apis::list,
+        GET | POST "ping": apis::ping,
+        GET | POST "health": apis::health,
+        GET | POST "ready": apis::ready,
+        POST "login": apis::login,
+        POST "logout": apis::logout,
+        POST "refresh": apis::refresh,
+        POST "keepalive": apis::keepalive,
+        POST "stepup": apis::step_up,
+        POST "list": apis::list,
+        POST "search": apis::search,
+        GET | POST "suggest": apis::suggest,
+        GET | POST "stats": apis::stats,
+        GET | POST "icon/list": apis::icon_list,
+        POST "icon/get": apis::icon_get,
+        POST "icon/upload": apis::icon_upload,
+        POST "icon/assign": apis::icon_assign,
+        GET | POST "group/list": apis::group_list,
+        POST "group/defaults": apis::group_defaults,
+        POST "record/get": apis::record_get,
+        POST "record/reveal": apis::record_reveal,
+        POST "record/create": apis::record_create,
+        POST "record/update": apis::record_update,
+        POST "record/delete": apis::record_delete,
+        POST "record/notes": apis::record_notes,
+        POST "record/totp": apis::record_totp,
+        GET | POST "record/history/:id": apis::record_history,
+        POST "record/attachment": apis::attachment_download,
+        POST "record/attachment/upload": apis::attachment_upload,
+        POST "record/attachment/delete": apis::attachment_delete,
+        POST "record/comment": apis::record_comment,
+        POST "records/bulk": apis::records_bulk,
+        GET | POST "policy/get": apis::policy_get,
+        POST "policy/set": apis::policy_set,
+        POST "policy/strength": httpserver::typed(apis::policy_strength),
+        POST "identity": apis::identity,
+        POST "generate": apis::generate,
+        GET | POST "audit": apis::audit,
+        GET | POST "template/list": apis::template_list,
+        GET | POST "template/export": apis::template_export,
+        POST "template/import": apis::template_import,
+        POST "undo": apis::undo,
+        GET "ws": apis::ws,
+        GET "events": apis::events,
+        GET | POST "export": apis::export,
+        GET | POST "backup": apis::backup,
+        GET | POST "jobs": apis::job_list,
+        GET | POST "jobs/:id": apis::job_get,
+        GET | POST "jobs/:id/result": apis::job_result,
+        POST "jobs/:id/cancel": apis::job_cancel,
+        GET | POST "admin/config": apis::admin_config,
+        GET | POST "admin/access-stats/export": apis::admin_access_stats_export,
+        GET | POST "admin/proxy": apis::admin_proxy,
+        POST "admin/change-password": apis::change_password,
+        POST "share/create": apis::share_create,
+        POST "share/open": apis::share_open,
     );
 
     let async_fn = async move {
-        let (mut interval, cache_expire) = {
-            let ag = AppGlobal::get();
-            let interval = time::interval(std::time::Duration::from_secs(ag.task_interval));
-            (interval, ag.cache_expire)
-        };
+        let tls = tls_config().await.map_err(|e| CliError::from_error(ExitCode::Config, "init tls", e))?;
+        if let Some(tls) = &tls {
+            srv.set_tls(tls.clone());
+            let hsts_max_age: u64 = ac.hsts_max_age.parse().conf("hsts-max-age", &ac.hsts_max_age)?;
+            if hsts_max_age > 0 {
+                srv.set_middleware(httpserver::Hsts::new(hsts_max_age, ac.hsts_preload));
+            }
+        }
+
+        let mut interval = time::interval(std::time::Duration::from_secs(state.task_interval));
+        let task_state = state.clone();
         // 启动定时任务
         tokio::spawn(async move {
+            let state = task_state;
             interval.tick().await;
             loop {
                 interval.tick().await;
-                aidb::recycle_cache(std::time::Duration::from_secs(cache_expire));
+                aidb::recycle_cache(std::time::Duration::from_secs(state.cache_expire));
+                for database in aidb::reload_changed() {
+                    let database = AppState::database_name(&database).into();
+                    events::publish(events::Event::DatabaseReloaded { database });
+                }
                 apis::Authentication::recycle();
+                if let Some(tls) = &tls {
+                    if let Err(e) = tls.reload_if_changed() {
+                        log::warn!("reload tls certificate failed: {e:?}");
+                    }
+                }
+                if let Some(rl) = &rate_limit {
+                    rl.purge();
+                }
+                if let Some(blocklist) = &blocklist {
+                    blocklist.purge();
+                }
+                apis::recycle_undo(&state);
+                apis::recycle_shares();
+                jobs::recycle();
+                apis::flush_stats(&state);
+                monitor::report(&state, rate_limit.as_ref().map_or(0, |rl| rl.len()));
             }
         });
 
-        // 运行http server主服务
-        let addr: std::net::SocketAddr = AppConf::get().listen.parse().unwrap();
-        srv.run(addr).await.unwrap();
+        // 运行http server主服务, 收到退出信号后等待进行中的请求结束
+        let ac = AppConf::get();
+        let addr: std::net::SocketAddr = ac.listen.parse().conf("listen", &ac.listen)?;
+        let timeout = ac.shutdown_timeout.parse().conf("shutdown-timeout", &ac.shutdown_timeout)?;
+        srv.run_with_shutdown(addr, httpserver::shutdown_signal(), std::time::Duration::from_secs(timeout)).await
+            .map_err(|e| CliError::from_error(ExitCode::IoErr, format!("http server {addr}"), e))?;
+
+        // 保存未写入的访问统计
+        apis::flush_stats(&state);
+        log::info!("{APP_NAME} shutdown");
+        log::logger().flush();
+        Ok::<(), CliError>(())
     };
 
     let ac = AppConf::get();
-    let threads = ac.threads.parse::<usize>().expect(arg_err!("threads"));
+    let threads = ac.threads.parse::<usize>().conf("threads", &ac.threads)?;
 
     #[cfg(not(feature = "multi_thread"))]
     let mut builder = {
-        assert!(threads == 1, "{APP_NAME} current version unsupport multi-threads");
+        if threads != 1 {
+            return Err(CliError::new(ExitCode::Config, format!("{APP_NAME} current version unsupport multi-threads"))
+                .hint("rebuild with the multi_thread feature, or use --threads 1"));
+        }
         tokio::runtime::Builder::new_current_thread()
     };
 
     #[cfg(feature = "multi_thread")]
     let mut builder = {
-        assert!(threads <= 256, "multi-threads range in 0-256");
+        if threads > 256 {
+            return Err(CliError::new(ExitCode::Config, "multi-threads range in 0-256"));
+        }
 
         let mut builder = tokio::runtime::Builder::new_multi_thread();
         if threads > 0 {
             builder.worker_threads(threads);
         }
+        parallel::init(threads);
 
         builder
     };
 
     builder.enable_all()
         .build()
-        .unwrap()
+        .map_err(|e| CliError::new(ExitCode::Software, format!("build tokio runtime error: {e}")))?
         .block_on(async_fn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
+    #[test]
+    fn legacy_upgrade_skips_decoys() {
+        let (work, home, decoy) = (aidb::TempDatabase::new("legacy-work"), aidb::TempDatabase::new("legacy-home"),
+            aidb::TempDatabase::new("legacy-decoy"));
+        // v1格式的头部为magic + 数据长度, 格式识别只检查头部
+        let v1 = b"aidb\0\0\0\0".to_vec();
+        std::fs::write(&work.0, &v1).unwrap();
+        std::fs::write(&decoy.0, &v1).unwrap();
+        let state = AppState {
+            database: work.0.clone(),
+            databases: vec![work.0.clone(), home.0.clone()],
+            decoys: vec![(work.0.clone(), decoy.0.clone())],
+            ..Default::default()
+        };
+
+        let (legacy, decoys) = legacy_databases(&state);
+        assert_eq!(legacy, vec![work.0.as_str()]);
+        assert_eq!(decoys, vec![decoy.0.as_str()]);
+    }
 }
@@ -0,0 +1,108 @@
+//! 接口访问统计, 按天、按接口路径统计请求次数及耗时分布
+use std::{collections::BTreeMap, fmt::Write, time::{Duration, Instant}};
+
+use httpserver::{HttpContext, HttpResponse, Next};
+use parking_lot::Mutex;
+
+/// 耗时分布区间的上限(单位: 毫秒), 超过最后一个区间的计入溢出区间
+const LATENCY_BUCKETS: [u64; 12] = [1, 2, 5, 10, 20, 50, 100, 200, 500, 1000, 2000, 5000];
+/// 最多保留的天数
+const MAX_DAYS: usize = 31;
+/// 每天最多统计的接口路径数量, 超出的计入OTHER_ROUTE
+const MAX_ROUTES: usize = 256;
+/// 未找到的路径及超出数量限制的路径统一计入该项, 防止统计表无限增长
+const OTHER_ROUTE: &str = "(other)";
+
+/// 单个接口的访问统计
+#[derive(Clone, Default)]
+struct RouteStats {
+    count: u64,
+    errors: u64,
+    total_ms: u64,
+    buckets: [u64; LATENCY_BUCKETS.len() + 1],
+}
+
+/// 访问统计表, key: 日期(yyyy-mm-dd), value: (key: 接口路径, value: 统计)
+static METRICS: Mutex<BTreeMap<String, BTreeMap<String, RouteStats>>> = Mutex::new(BTreeMap::new());
+
+/// 访问统计中间件
+pub struct Metrics;
+
+impl RouteStats {
+    fn add(&mut self, ms: u64, is_err: bool) {
+        self.count += 1;
+        self.total_ms += ms;
+        if is_err {
+            self.errors += 1;
+        }
+        let idx = LATENCY_BUCKETS.iter().position(|b| ms <= *b).unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[idx] += 1;
+    }
+
+    /// 估算耗时百分位数, 返回所在区间的上限, 溢出区间返回"5000+"
+    fn percentile(&self, p: f64) -> String {
+        let target = ((self.count as f64 * p).ceil() as u64).max(1);
+        let mut acc = 0;
+        for (i, n) in self.buckets.iter().enumerate() {
+            acc += n;
+            if acc >= target {
+                return match LATENCY_BUCKETS.get(i) {
+                    Some(b) => b.to_string(),
+                    None => format!("{}+", LATENCY_BUCKETS[LATENCY_BUCKETS.len() - 1]),
+                };
+            }
+        }
+        String::from("0")
+    }
+}
+
+/// 累计一次接口访问
+pub fn record(route: &str, elapsed: Duration, is_err: bool) {
+    let day = chrono::Local::now().format("%Y-%m-%d").to_string();
+    let mut metrics = METRICS.lock();
+    let routes = metrics.entry(day).or_default();
+    let route = if routes.len() >= MAX_ROUTES && !routes.contains_key(route) { OTHER_ROUTE } else { route };
+    match routes.get_mut(route) {
+        Some(stats) => stats.add(elapsed.as_millis() as u64, is_err),
+        None => {
+            let mut stats = RouteStats::default();
+            stats.add(elapsed.as_millis() as u64, is_err);
+            routes.insert(route.to_owned(), stats);
+        }
+    }
+    while metrics.len() > MAX_DAYS {
+        metrics.pop_first();
+    }
+}
+
+/// 以csv格式导出访问统计, 列: date,route,count,errors,avg_ms,p50_ms,p95_ms,p99_ms
+pub fn export_csv() -> String {
+    let metrics = METRICS.lock();
+    let mut out = String::from("date,route,count,errors,avg_ms,p50_ms,p95_ms,p99_ms\n");
+    for (day, routes) in metrics.iter() {
+        for (route, s) in routes.iter() {
+            let _ = writeln!(out, "{day},\"{}\",{},{},{},{},{},{}", route.replace('"', "\"\""),
+                s.count, s.errors, s.total_ms / s.count.max(1),
+                s.percentile(0.5), s.percentile(0.95), s.percentile(0.99));
+        }
+    }
+    out
+}
+
+#[async_trait::async_trait]
+impl httpserver::HttpMiddleware for Metrics {
+    async fn handle<'a>(&'a self, ctx: HttpContext, next: Next<'a>) -> HttpResponse {
+        let start = Instant::now();
+        let path = ctx.req.uri().path().to_owned();
+        let res = next.run(ctx).await;
+
+        let (route, is_err) = match &res {
+            Ok(r) if r.status() == hyper::StatusCode::NOT_FOUND => (OTHER_ROUTE, true),
+            Ok(r) => (path.as_str(), r.status().as_u16() >= 400),
+            Err(_) => (path.as_str(), true),
+        };
+        record(route, start.elapsed(), is_err);
+
+        res
+    }
+}
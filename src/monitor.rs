@@ -0,0 +1,50 @@
+//! 内存使用监控
+use crate::{aidb, apis::Authentication, state::AppState};
+
+/// 报告进程内存使用情况, 超过水位线时输出警告, 并根据配置自动释放数据缓存
+///
+/// * `limitings`: 限流令牌桶的数量
+pub fn report(ag: &AppState, limitings: usize) {
+    let rss = process_rss();
+    let cache = aidb::cache_size();
+    let sessions = Authentication::session_count();
+
+    log::debug!("memory report: rss = {}, cache = {}, sessions = {sessions}, limitings = {limitings}",
+        rss.map(fmt_size).unwrap_or_else(|| String::from("unknown")), fmt_size(cache));
+
+    let mut exceed = false;
+    if let Some(rss) = rss {
+        if ag.rss_watermark > 0 && rss > ag.rss_watermark {
+            log::warn!("process memory {} exceeds the watermark {}", fmt_size(rss), fmt_size(ag.rss_watermark));
+            exceed = true;
+        }
+    }
+    if ag.cache_watermark > 0 && cache > ag.cache_watermark {
+        log::warn!("data cache memory {} exceeds the watermark {}", fmt_size(cache), fmt_size(ag.cache_watermark));
+        exceed = true;
+    }
+
+    if exceed && ag.auto_drop_cache && cache > 0 {
+        aidb::clear_cache();
+        log::warn!("the data cache has been dropped to free memory");
+    }
+}
+
+/// 读取进程的常驻内存大小(单位: 字节), 仅支持linux
+fn process_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kb: u64 = line["VmRSS:".len()..].trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kb * 1024)
+}
+
+fn fmt_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut val = size as f64;
+    let mut unit = 0;
+    while val >= 1024.0 && unit < UNITS.len() - 1 {
+        val /= 1024.0;
+        unit += 1;
+    }
+    format!("{val:.1}{}", UNITS[unit])
+}
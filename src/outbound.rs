@@ -0,0 +1,52 @@
+//! 外部http请求的共享客户端, 统一处理代理、超时及tls设置
+use std::{sync::OnceLock, time::Duration};
+
+use anyhow_ext::{anyhow, Result};
+
+/// 不使用代理的配置值
+const NO_PROXY: &str = "none";
+
+static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+/// 外部请求客户端配置
+pub struct OutboundConfig<'a> {
+    /// 代理地址, 为空时使用环境变量HTTP_PROXY/HTTPS_PROXY, "none"表示不使用代理
+    pub proxy: &'a str,
+    /// 请求超时时间(单位: 秒)
+    pub timeout: u64,
+    /// 是否忽略tls证书校验错误
+    pub insecure: bool,
+}
+
+/// 初始化共享客户端, 只能初始化一次
+pub fn init(cfg: &OutboundConfig) -> Result<()> {
+    let mut builder = reqwest::Client::builder()
+        .timeout(Duration::from_secs(cfg.timeout))
+        .connect_timeout(Duration::from_secs(cfg.timeout.min(10)))
+        .user_agent(concat!("accinfo/", env!("CARGO_PKG_VERSION")))
+        .danger_accept_invalid_certs(cfg.insecure);
+
+    match cfg.proxy {
+        // reqwest缺省读取HTTP_PROXY/HTTPS_PROXY/NO_PROXY环境变量
+        "" => {}
+        NO_PROXY => builder = builder.no_proxy(),
+        proxy => {
+            let proxy = reqwest::Proxy::all(proxy)
+                .map_err(|e| anyhow!("outbound proxy format error: {e}"))?;
+            builder = builder.proxy(proxy);
+        }
+    }
+
+    let client = builder.build()
+        .map_err(|e| anyhow!("create outbound http client failed: {e}"))?;
+    let _ = CLIENT.set(client);
+    log::trace!("outbound client: proxy = {:?}, timeout = {}s, insecure = {}",
+        cfg.proxy, cfg.timeout, cfg.insecure);
+    Ok(())
+}
+
+/// 获取共享客户端, 未初始化时使用缺省配置
+#[allow(dead_code)] // 供外部请求功能(图标抓取、泄露检测等)使用
+pub fn client() -> &'static reqwest::Client {
+    CLIENT.get_or_init(reqwest::Client::new)
+}
@@ -0,0 +1,60 @@
+//! 记录的并行扫描, 启用multi_thread特性时记录数超过阈值后按线程分片处理
+//!
+//! 记录数较少时线程调度的开销大于扫描本身, 低于阈值或者单线程版本时按顺序处理
+#[cfg(feature = "multi_thread")]
+use rayon::prelude::*;
+
+/// 并行处理的最小记录数
+#[cfg(feature = "multi_thread")]
+const PARALLEL_THRESHOLD: usize = 8192;
+
+/// 每个分片的最小记录数
+#[cfg(feature = "multi_thread")]
+const MIN_SHARD_LEN: usize = 2048;
+
+/// 是否对`len`条记录使用并行处理
+#[cfg(feature = "multi_thread")]
+fn enabled(len: usize) -> bool {
+    len >= PARALLEL_THRESHOLD
+}
+
+/// 设置并行处理的线程数, 0表示使用cpu核数
+#[cfg(feature = "multi_thread")]
+pub fn init(threads: usize) {
+    if threads > 0 {
+        if let Err(e) = rayon::ThreadPoolBuilder::new().num_threads(threads).build_global() {
+            log::warn!("init parallel search thread pool error: {e}");
+        }
+    }
+}
+
+/// 对每条记录执行`f`, 按记录原有顺序返回结果为Some的值
+pub fn filter_map<'a, T, R, F>(items: &'a [T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&'a T) -> Option<R> + Sync + Send,
+{
+    #[cfg(feature = "multi_thread")]
+    if enabled(items.len()) {
+        return items.par_iter().with_min_len(MIN_SHARD_LEN).filter_map(f).collect();
+    }
+    items.iter().filter_map(f).collect()
+}
+
+/// 将记录分片后对每个分片执行`f`, 按分片顺序返回结果
+///
+/// `f`的参数为分片在`items`中的起始下标及分片内容, 未启用并行时只有一个分片
+pub fn shards<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(usize, &[T]) -> R + Sync + Send,
+{
+    #[cfg(feature = "multi_thread")]
+    if enabled(items.len()) {
+        let size = items.len().div_ceil(rayon::current_num_threads()).max(MIN_SHARD_LEN);
+        return items.par_chunks(size).enumerate().map(|(i, chunk)| f(i * size, chunk)).collect();
+    }
+    vec![f(0, items)]
+}
@@ -0,0 +1,117 @@
+//! 口令策略
+use serde::{Serialize, Deserialize};
+
+use crate::{generator::{self, GenOptions}, strength};
+
+/// 生成符合策略的口令时的最大尝试次数
+const MAX_GEN_TRY: usize = 100;
+/// 口令最小长度的上限, 同时限制按策略生成的口令长度
+pub const MAX_MIN_LENGTH: usize = 256;
+
+/// 口令策略, 缺省值不做任何限制
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Policy {
+    /// 口令最小长度, 0表示不限制
+    pub min_length: usize,
+    /// 必须包含小写字母
+    pub require_lower: bool,
+    /// 必须包含大写字母
+    pub require_upper: bool,
+    /// 必须包含数字
+    pub require_digit: bool,
+    /// 必须包含特殊符号
+    pub require_symbol: bool,
+    /// 禁止包含的单词(不区分大小写)
+    pub banned_words: Vec<String>,
+    /// 口令最长使用天数, 0表示不限制
+    pub max_age_days: u32,
+    /// 口令最低强度得分(1~4), 0表示不限制
+    pub min_strength: u8,
+}
+
+impl Policy {
+    /// 校验口令是否符合策略
+    ///
+    /// Returns:
+    ///
+    /// 不符合策略的项的描述, 为空表示符合策略
+    pub fn check(&self, pass: &str) -> Vec<String> {
+        self.check_with_inputs(pass, &[])
+    }
+
+    /// 同`check`, `user_inputs`为与口令相关的用户信息(标题、用户名等), 用于评估口令强度
+    pub fn check_with_inputs(&self, pass: &str, user_inputs: &[&str]) -> Vec<String> {
+        let mut errs = Vec::new();
+
+        if pass.chars().count() < self.min_length {
+            errs.push(format!("口令长度不能小于{}", self.min_length));
+        }
+        if self.require_lower && !pass.chars().any(|c| c.is_lowercase()) {
+            errs.push(String::from("口令必须包含小写字母"));
+        }
+        if self.require_upper && !pass.chars().any(|c| c.is_uppercase()) {
+            errs.push(String::from("口令必须包含大写字母"));
+        }
+        if self.require_digit && !pass.chars().any(|c| c.is_ascii_digit()) {
+            errs.push(String::from("口令必须包含数字"));
+        }
+        if self.require_symbol && !pass.chars().any(is_symbol) {
+            errs.push(String::from("口令必须包含特殊符号"));
+        }
+
+        let lower_pass = pass.to_lowercase();
+        for word in self.banned_words.iter() {
+            if !word.is_empty() && lower_pass.contains(&word.to_lowercase()) {
+                errs.push(format!("口令不能包含禁用词: {word}"));
+            }
+        }
+
+        if self.min_strength > 0 {
+            let st = strength::estimate(pass, user_inputs);
+            if st.score < self.min_strength {
+                let mut msg = format!("口令强度不足(得分{}, 要求{})", st.score, self.min_strength);
+                if let Some(warning) = st.warning {
+                    msg.push_str(": ");
+                    msg.push_str(&warning);
+                }
+                errs.push(msg);
+            }
+        }
+
+        errs
+    }
+
+    /// 口令是否已超过最长使用天数
+    ///
+    /// * `modified`: 口令最后修改时间(unix时间戳)
+    /// * `now`: 当前时间(unix时间戳)
+    pub fn is_expired(&self, modified: u64, now: u64) -> bool {
+        self.max_age_days > 0 && now > modified + self.max_age_days as u64 * 86400
+    }
+
+    /// 在生成选项的基础上, 生成符合策略的口令
+    pub fn generate(&self, opts: &GenOptions) -> String {
+        let mut opts = opts.clone();
+        opts.length = opts.length.max(self.min_length.min(MAX_MIN_LENGTH));
+        opts.lower |= self.require_lower;
+        opts.upper |= self.require_upper;
+        opts.digit |= self.require_digit;
+        opts.symbol |= self.require_symbol;
+        // 口令短语只能通过首字母大写满足大写字母的要求
+        opts.capitalize |= self.require_upper;
+
+        for _ in 0..MAX_GEN_TRY {
+            let pass = generator::generate(&opts);
+            if self.check(&pass).is_empty() {
+                return pass;
+            }
+        }
+
+        generator::generate(&opts)
+    }
+}
+
+fn is_symbol(c: char) -> bool {
+    !c.is_alphanumeric() && !c.is_whitespace()
+}
@@ -0,0 +1,232 @@
+//! 配置档案, 同一套程序及配置文件同时用于开发及生产环境
+//!
+//! 指定`--profile dev`时, 在基础配置之上叠加与基础配置文件同一目录下的`accinfo.dev.conf`, 覆盖顺序:
+//! 缺省值 < 档案缺省值 < 基础配置文件 < 档案配置文件 < 命令行参数
+//!
+//! 档案配置文件每行一个配置项, 格式为`长参数名 = 值`, 以`#`开头的行为注释, 布尔值可以为true/false/yes/no/on/off/1/0
+use std::path::{Path, PathBuf};
+
+use anyhow_ext::{anyhow, bail, Result};
+
+use crate::AppConf;
+
+/// 内置的配置档案, 档案配置文件不存在时只使用档案缺省值
+const PROFILES: [&str; 2] = ["dev", "prod"];
+
+/// 开发环境的缺省值: 输出详细日志, 放宽限流, 不输出banner
+const DEV_DEFAULTS: [(&str, &str); 4] = [
+    ("log-level", "debug"),
+    ("rate-limit", "100"),
+    ("login-max-failures", "0"),
+    ("no-banner", "true"),
+];
+
+/// 指定基础配置文件的命令行参数(短参数名, 长参数名)
+const CONFIG_ARG: (&str, &str) = ("c", "config");
+
+/// 可以从文本设置的配置值
+trait SetConf {
+    fn set_conf(&mut self, value: &str) -> Result<()>;
+}
+
+impl SetConf for String {
+    fn set_conf(&mut self, value: &str) -> Result<()> {
+        value.clone_into(self);
+        Ok(())
+    }
+}
+
+impl SetConf for bool {
+    fn set_conf(&mut self, value: &str) -> Result<()> {
+        *self = match value.to_ascii_lowercase().as_str() {
+            "" | "true" | "yes" | "on" | "1" => true,
+            "false" | "no" | "off" | "0" => false,
+            _ => bail!("invalid boolean value: {value}"),
+        };
+        Ok(())
+    }
+}
+
+/// 生成按长参数名访问配置项的函数
+macro_rules! conf_access {
+    ($($field:ident: $short:literal, $long:literal $(, $secret:ident)?;)*) => {
+        /// 设置配置项
+        fn set_value(ac: &mut AppConf, key: &str, value: &str) -> Result<()> {
+            match key {
+                $($long => ac.$field.set_conf(value).map_err(|e| anyhow!("config item {key}: {e}")),)*
+                _ => bail!("unknown config item: {key}"),
+            }
+        }
+
+        /// 配置项是否在命令行参数中指定
+        fn in_args(args: &[String], key: &str) -> bool {
+            match key {
+                $($long => crate::cli::arg_present(args, $short, $long),)*
+                _ => false,
+            }
+        }
+    };
+}
+
+crate::with_conf_fields!(conf_access!());
+
+/// 应用`--profile`指定的配置档案
+///
+/// * `ac`: 已解析基础配置文件及命令行参数的配置
+/// * `app_name`: 应用程序名称, 档案配置文件名为`<app_name>.<profile>.conf`
+/// * `reparse`: 重新解析基础配置文件及命令行参数, 用于在档案缺省值之上叠加显式设置的配置项
+///
+/// Returns:
+///
+/// 加载的档案配置文件名, 未指定档案或者内置档案的配置文件不存在时返回None
+pub fn apply<F>(ac: &mut AppConf, app_name: &str, reparse: F) -> Result<Option<String>>
+where
+    F: FnOnce(&mut AppConf) -> Result<()>,
+{
+    if ac.profile.is_empty() {
+        return Ok(None);
+    }
+    let profile = ac.profile.clone();
+    if !profile.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_') {
+        bail!("invalid profile name: {profile}");
+    }
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    apply_defaults(ac, &profile, reparse)?;
+
+    let path = profile_path(crate::cli::arg_value(&args, CONFIG_ARG.0, CONFIG_ARG.1), app_name, &profile);
+    let file = path.display().to_string();
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && PROFILES.contains(&profile.as_str()) => {
+            return Ok(None);
+        }
+        Err(e) => bail!("read profile config {file} error: {e}"),
+    };
+    apply_text(ac, &args, &file, &text)?;
+
+    Ok(Some(file))
+}
+
+/// 档案缺省值, 没有缺省值的档案返回空
+fn profile_defaults(profile: &str) -> &'static [(&'static str, &'static str)] {
+    match profile {
+        "dev" => &DEV_DEFAULTS,
+        _ => &[],
+    }
+}
+
+/// 在缺省值之上设置档案缺省值, 然后重新解析基础配置文件及命令行参数,
+/// 显式设置的配置项(即使与缺省值相同)覆盖档案缺省值
+fn apply_defaults<F>(ac: &mut AppConf, profile: &str, reparse: F) -> Result<()>
+where
+    F: FnOnce(&mut AppConf) -> Result<()>,
+{
+    let defaults = profile_defaults(profile);
+    if defaults.is_empty() {
+        return Ok(());
+    }
+    let mut layered = AppConf::default();
+    for (key, value) in defaults {
+        set_value(&mut layered, key, value)?;
+    }
+    reparse(&mut layered)?;
+    *ac = layered;
+    Ok(())
+}
+
+/// 档案配置文件的路径, 与基础配置文件在同一目录下, 未指定基础配置文件时为当前目录
+///
+/// * `config`: 基础配置文件
+fn profile_path(config: Option<&str>, app_name: &str, profile: &str) -> PathBuf {
+    let name = format!("{}.{profile}.conf", app_name.trim());
+    match config.map(Path::new).and_then(Path::parent) {
+        Some(dir) => dir.join(name),
+        None => PathBuf::from(name),
+    }
+}
+
+/// 叠加档案配置文件的内容, 命令行参数中已指定的配置项保持不变
+///
+/// * `args`: 命令行参数(不含程序名)
+/// * `file`: 档案配置文件名, 用于错误信息
+/// * `text`: 档案配置文件的内容
+fn apply_text(ac: &mut AppConf, args: &[String], file: &str, text: &str) -> Result<()> {
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => bail!("{file} line {}: expected `key = value`", i + 1),
+        };
+        if key == "profile" {
+            bail!("{file} line {}: profile can not be set in profile config", i + 1);
+        }
+        if in_args(args, key) {
+            continue;
+        }
+        set_value(ac, key, value).map_err(|e| anyhow!("{file} line {}: {e}", i + 1))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn precedence() {
+        // 基础配置文件显式设置了与缺省值相同的log-level及rate-limit, 命令行参数指定了threads
+        let cli = args(&["--threads", "2"]);
+        let reparse = |ac: &mut AppConf| {
+            ac.log_level = String::from("info");
+            ac.rate_limit = String::from("10");
+            ac.threads = String::from("2");
+            Ok(())
+        };
+        let mut ac = AppConf { profile: String::from("dev"), ..AppConf::default() };
+        apply_defaults(&mut ac, "dev", reparse).unwrap();
+        // 显式配置覆盖档案缺省值, 未配置的项使用档案缺省值
+        assert_eq!(ac.log_level, "info");
+        assert_eq!(ac.rate_limit, "10");
+        assert!(ac.no_banner);
+        assert_eq!(ac.login_max_failures, "0");
+
+        // 档案配置文件覆盖基础配置文件, 命令行参数优先
+        apply_text(&mut ac, &cli, "accinfo.dev.conf", "# dev\nlog-level = trace\nthreads = 8\n").unwrap();
+        assert_eq!(ac.log_level, "trace");
+        assert_eq!(ac.threads, "2");
+    }
+
+    #[test]
+    fn defaults_of_other_profiles() {
+        let mut ac = AppConf::default();
+        apply_defaults(&mut ac, "prod", |ac| {
+            ac.log_level = String::from("warn");
+            Ok(())
+        }).unwrap();
+        // 没有档案缺省值时不重新解析
+        assert_eq!(ac.log_level, AppConf::default().log_level);
+        assert!(!ac.no_banner);
+    }
+
+    #[test]
+    fn profile_file() {
+        assert_eq!(profile_path(Some("/etc/accinfo/accinfo.conf"), "accinfo", "dev"),
+            PathBuf::from("/etc/accinfo/accinfo.dev.conf"));
+        assert_eq!(profile_path(None, "accinfo", "dev"), PathBuf::from("accinfo.dev.conf"));
+        assert_eq!(profile_path(Some("accinfo.conf"), "accinfo", "prod"), PathBuf::from("accinfo.prod.conf"));
+
+        let mut ac = AppConf::default();
+        assert!(apply_text(&mut ac, &[], "x.conf", "profile = dev").is_err());
+        assert!(apply_text(&mut ac, &[], "x.conf", "no-such-item = 1").is_err());
+        assert!(apply_text(&mut ac, &[], "x.conf", "no-banner = maybe").is_err());
+        assert!(apply_text(&mut ac, &[], "x.conf", "log-level").is_err());
+    }
+}
@@ -0,0 +1,146 @@
+//! 应用运行时状态, 通过请求扩展注入到接口函数中
+use std::sync::Arc;
+
+use anyhow_ext::{anyhow, Result};
+use httpserver::HttpContext;
+use sha2::{Digest, Sha256};
+
+use crate::{access::AccessWindows, apis::{LoginGuard, RecordLimits}, recipient::Recipients};
+
+/// 应用运行时状态
+#[derive(Debug, Default)]
+pub struct AppState {
+    /// 进程启动时间(unix时间戳)
+    pub startup_time: u64,
+    /// 定时任务执行时间间隔（单位：秒）
+    pub task_interval: u64,
+    /// 数据缓存存活最大有效时间（单位：秒）
+    pub cache_expire: u64,
+    /// session过期时间（单位：秒）
+    pub session_expire: u64,
+    /// session最长有效时间, 超过后必须重新登录（单位：秒）
+    pub session_max_age: u64,
+    /// 请求不延长session的空闲过期时间, 只有客户端调用保活接口时才延长
+    pub no_sliding_session: bool,
+    /// 登录防暴力破解的阈值配置
+    pub login_guard: LoginGuard,
+    /// 允许访问的时间段
+    pub access_windows: AccessWindows,
+    /// 记录的长度限制
+    pub record_limits: RecordLimits,
+    /// 允许使用http basic认证代替会话令牌
+    pub basic_auth: bool,
+    /// 高风险操作要求在该时间内重新验证过主密码（单位：秒，0表示不要求）
+    pub stepup_window: u64,
+    /// 进程内存警告水位线（单位：字节，0表示不检查）
+    pub rss_watermark: u64,
+    /// 数据缓存内存警告水位线（单位：字节，0表示不检查）
+    pub cache_watermark: u64,
+    /// 超过水位线时自动释放数据缓存
+    pub auto_drop_cache: bool,
+    /// 缺省数据库文件名, 即数据库列表中的第一个
+    pub database: String,
+    /// 所有数据库文件名(包含缺省数据库), 会话通过数据库id(`database_id`)绑定数据库
+    pub databases: Vec<String>,
+    /// 胁迫密码打开的诱饵数据库, (真实数据库文件名, 诱饵数据库文件名)
+    pub decoys: Vec<(String, String)>,
+    /// 生成邮箱别名的基础邮箱地址
+    pub email_base: String,
+    /// 子路径部署时的路径前缀, 例如`/vault`, 部署在根路径时为空
+    pub base_path: String,
+    /// 导出文件的加密接收者, 备份接口及请求加密的导出接口使用, 未配置时为`Recipients::None`
+    pub export_recipients: Arc<Recipients>,
+    /// 备份接口的访问令牌, 为空时不启用备份接口
+    pub backup_token: String,
+}
+
+impl AppState {
+    /// 从请求上下文中获取应用状态
+    pub fn from_ctx(ctx: &HttpContext) -> Result<&Arc<AppState>> {
+        ctx.state::<Arc<AppState>>().ok_or_else(|| anyhow!("app state not registered"))
+    }
+
+    /// 数据库名称, 即去掉目录及扩展名的文件名
+    pub fn database_name(database: &str) -> &str {
+        std::path::Path::new(database).file_stem().and_then(|s| s.to_str()).unwrap_or_default()
+    }
+
+    /// 数据库id, 取数据库名称的sha256摘要的前8个字节
+    ///
+    /// 会话、无状态令牌及令牌吊销通过id绑定数据库, 数据库列表增删或调整顺序后id不变,
+    /// 数据库改名或移除后原有令牌无法再找到对应的数据库, 视为无效
+    pub fn database_id(database: &str) -> u64 {
+        let hash = Sha256::digest(Self::database_name(database).as_bytes());
+        let mut id = [0u8; 8];
+        id.copy_from_slice(&hash[..8]);
+        u64::from_be_bytes(id)
+    }
+
+    /// 按名称查找数据库, 诱饵数据库不参与查找, 只能通过对应数据库的胁迫密码打开
+    ///
+    /// Returns:
+    ///
+    /// (数据库id, 数据库文件名), 不存在时返回None
+    pub fn find_database(&self, name: &str) -> Option<(u64, &str)> {
+        self.databases()
+            .filter(|db| !self.is_decoy(db))
+            .find(|db| Self::database_name(db) == name)
+            .map(|db| (Self::database_id(db), db))
+    }
+
+    /// 按数据库id查找数据库文件名, 数据库已不在列表中时返回None
+    pub fn database_by_id(&self, id: u64) -> Option<&str> {
+        self.databases().find(|db| Self::database_id(db) == id)
+    }
+
+    /// 当前请求的会话绑定的数据库文件名, 未经认证中间件校验的请求(uid为空)返回错误
+    pub fn session_database(&self, ctx: &HttpContext) -> Result<&str> {
+        if ctx.uid.is_empty() {
+            httpserver::http_bail!("未登录");
+        }
+        // uid由认证中间件根据会话写入, 胁迫密码登录的会话为诱饵数据库的名称
+        match self.databases().find(|db| Self::database_name(db) == ctx.uid) {
+            Some(db) => Ok(db),
+            None => httpserver::http_bail!("数据库不存在"),
+        }
+    }
+
+    /// 返回给客户端的数据库名称, 诱饵数据库返回对应的真实数据库名称, 避免被胁迫者察觉
+    ///
+    /// * `database`: 数据库文件名或者数据库名称(会话的uid)
+    pub fn display_name<'a>(&'a self, database: &'a str) -> &'a str {
+        match self.decoys.iter().find(|(_, decoy)| decoy == database || Self::database_name(decoy) == database) {
+            Some((db, _)) => Self::database_name(db),
+            None => Self::database_name(database),
+        }
+    }
+
+    /// 是否为诱饵数据库文件
+    pub fn is_decoy(&self, database: &str) -> bool {
+        self.decoys.iter().any(|(_, decoy)| decoy == database)
+    }
+
+    /// 指定数据库对应的诱饵数据库
+    ///
+    /// * `database`: 真实数据库文件名
+    ///
+    /// Returns:
+    ///
+    /// (诱饵数据库id, 诱饵数据库文件名), 未配置时返回None
+    pub fn decoy_of(&self, database: &str) -> Option<(u64, &str)> {
+        self.decoys.iter()
+            .find(|(db, _)| db == database)
+            .map(|(_, decoy)| (Self::database_id(decoy), decoy.as_str()))
+    }
+
+    /// 所有数据库文件名(包含诱饵数据库), 未配置数据库列表时只有缺省数据库
+    pub fn databases(&self) -> impl Iterator<Item = &str> {
+        let single = match self.databases.is_empty() {
+            true => Some(self.database.as_str()),
+            false => None,
+        };
+        single.into_iter()
+            .chain(self.databases.iter().map(String::as_str))
+            .chain(self.decoys.iter().map(|(_, db)| db.as_str()))
+    }
+}
@@ -0,0 +1,64 @@
+//! 口令强度评估, 使用zxcvbn算法, 评估算法通过`Estimator`接口抽象, 更换算法时只需修改`estimate`
+use serde::Serialize;
+
+/// 口令强度评估结果
+#[derive(Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Strength {
+    /// 强度得分, 0(极弱) ~ 4(极强)
+    pub score: u8,
+    /// 离线破解(每秒1万次慢哈希)所需时间的可读描述
+    pub crack_time: String,
+    /// 离线破解所需时间(单位: 秒)
+    pub crack_seconds: f64,
+    /// 警告信息
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+    /// 改进建议
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub suggestions: Vec<String>,
+}
+
+/// 口令强度评估接口
+pub trait Estimator: Send + Sync {
+    /// 评估口令强度
+    ///
+    /// * `password`: 口令
+    /// * `user_inputs`: 与口令相关的用户信息(标题、用户名等), 口令包含这些内容时降低得分
+    fn estimate(&self, password: &str, user_inputs: &[&str]) -> Strength;
+}
+
+/// 基于zxcvbn算法的口令强度评估
+pub struct Zxcvbn;
+
+impl Estimator for Zxcvbn {
+    fn estimate(&self, password: &str, user_inputs: &[&str]) -> Strength {
+        // 空口令返回错误, 按最低强度处理
+        let entropy = match zxcvbn::zxcvbn(password, user_inputs) {
+            Ok(entropy) => entropy,
+            Err(_) => return Strength { crack_time: String::from("0 seconds"), ..Default::default() },
+        };
+
+        let crack_time = entropy.crack_times().offline_slow_hashing_1e4_per_second();
+        let (warning, suggestions) = match entropy.feedback() {
+            Some(fb) => (
+                fb.warning().map(|w| w.to_string()),
+                fb.suggestions().iter().map(|s| s.to_string()).collect(),
+            ),
+            None => (None, Vec::new()),
+        };
+
+        Strength {
+            score: entropy.score(),
+            crack_time: crack_time.to_string(),
+            crack_seconds: f64::from(crack_time),
+            warning,
+            suggestions,
+        }
+    }
+}
+
+/// 评估口令强度
+pub fn estimate(password: &str, user_inputs: &[&str]) -> Strength {
+    Zxcvbn.estimate(password, user_inputs)
+}
@@ -0,0 +1,111 @@
+//! aidb数据库操作的耗时追踪, 各阶段耗时输出到debug日志, 并可通过`X-Timing`响应头返回
+use std::{cell::Cell, future::Future, time::{Duration, Instant}};
+
+use httpserver::{HttpContext, HttpResponse, Next};
+use hyper::header::HeaderValue;
+
+/// 耗时响应头名称
+const X_TIMING: &str = "X-Timing";
+
+tokio::task_local! {
+    /// 当前请求累计的各阶段耗时, 只在`TimingHeader`中间件的作用域内有效
+    static TIMING: Cell<Timing>;
+}
+
+/// 数据库操作阶段
+#[derive(Clone, Copy, Debug)]
+pub enum Phase {
+    /// 读取文件并解密
+    Decrypt,
+    /// 解析json内容
+    Parse,
+    /// 搜索记录
+    Search,
+    /// 序列化(包括写入数据库时的加密)
+    Serialize,
+}
+
+/// 请求中各阶段的累计耗时
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timing {
+    pub decrypt: Duration,
+    pub parse: Duration,
+    pub search: Duration,
+    pub serialize: Duration,
+}
+
+/// 耗时统计区间, 析构时累计耗时并输出debug日志
+pub struct Span {
+    phase: Phase,
+    start: Instant,
+}
+
+/// 耗时追踪中间件, 在响应头`X-Timing`中返回各阶段耗时(单位: 毫秒)
+pub struct TimingHeader;
+
+impl Phase {
+    fn name(&self) -> &'static str {
+        match self {
+            Phase::Decrypt => "decrypt",
+            Phase::Parse => "parse",
+            Phase::Search => "search",
+            Phase::Serialize => "serialize",
+        }
+    }
+}
+
+impl Timing {
+    fn slot(&mut self, phase: Phase) -> &mut Duration {
+        match phase {
+            Phase::Decrypt => &mut self.decrypt,
+            Phase::Parse => &mut self.parse,
+            Phase::Search => &mut self.search,
+            Phase::Serialize => &mut self.serialize,
+        }
+    }
+
+    /// 生成响应头内容, 格式: decrypt=1.234, parse=0.567, search=0.012, serialize=0.345
+    pub fn header_value(&self) -> String {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        format!("decrypt={:.3}, parse={:.3}, search={:.3}, serialize={:.3}",
+            ms(self.decrypt), ms(self.parse), ms(self.search), ms(self.serialize))
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        log::debug!("aidb {} took {:.3}ms", self.phase.name(), elapsed.as_secs_f64() * 1000.0);
+        // 不在追踪作用域内(例如定时任务、命令行模式)时只输出日志
+        let _ = TIMING.try_with(|t| {
+            let mut timing = t.get();
+            *timing.slot(self.phase) += elapsed;
+            t.set(timing);
+        });
+    }
+}
+
+/// 开始统计指定阶段的耗时, 返回值析构时结束统计
+pub fn span(phase: Phase) -> Span {
+    Span { phase, start: Instant::now() }
+}
+
+/// 执行`f`并返回其执行期间累计的各阶段耗时
+pub async fn trace<F: Future>(f: F) -> (F::Output, Timing) {
+    TIMING.scope(Cell::new(Timing::default()), async {
+        let ret = f.await;
+        (ret, TIMING.with(|t| t.get()))
+    }).await
+}
+
+#[async_trait::async_trait]
+impl httpserver::HttpMiddleware for TimingHeader {
+    async fn handle<'a>(&'a self, ctx: HttpContext, next: Next<'a>) -> HttpResponse {
+        let (res, timing) = trace(next.run(ctx)).await;
+        let mut res = res?;
+        if let Ok(val) = HeaderValue::from_str(&timing.header_value()) {
+            res.headers_mut().insert(X_TIMING, val);
+        }
+        Ok(res)
+    }
+}
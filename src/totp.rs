@@ -0,0 +1,305 @@
+//! 基于时间的一次性口令(TOTP, RFC 6238), 支持`otpauth://totp/...`格式的uri或者base32编码的密钥
+//!
+//! KeePass 2.47+将密钥及设置分别保存在条目的`TimeOtp-*`字段中, 导入时通过[`KeePassOtp`]转换为otpauth uri
+use anyhow_ext::{bail, Result};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+
+/// 缺省的验证码位数
+const DEFAULT_DIGITS: u32 = 6;
+/// 缺省的时间步长(单位: 秒)
+const DEFAULT_PERIOD: u64 = 30;
+
+/// hmac摘要算法
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl Algorithm {
+    /// otpauth uri中的算法名称
+    fn name(self) -> &'static str {
+        match self {
+            Algorithm::Sha1 => "SHA1",
+            Algorithm::Sha256 => "SHA256",
+            Algorithm::Sha512 => "SHA512",
+        }
+    }
+}
+
+/// 一次性口令生成器
+#[derive(Debug)]
+pub struct Totp {
+    secret: Vec<u8>,
+    algorithm: Algorithm,
+    /// 验证码位数
+    pub digits: u32,
+    /// 时间步长(单位: 秒)
+    pub period: u64,
+}
+
+impl Totp {
+    /// 解析otpauth uri(只支持totp类型)或者base32编码的密钥
+    pub fn parse(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let rest = match s.get(..10) {
+            Some(scheme) if scheme.eq_ignore_ascii_case("otpauth://") => &s[10..],
+            _ => return Self::from_base32(s),
+        };
+        match rest.get(..5) {
+            Some(kind) if kind.eq_ignore_ascii_case("totp/") => {}
+            _ => bail!("only totp type of otpauth uri is supported"),
+        }
+
+        let mut totp = None;
+        let (mut algorithm, mut digits, mut period) = (Algorithm::Sha1, DEFAULT_DIGITS, DEFAULT_PERIOD);
+        let query = rest.split_once('?').map(|(_, q)| q).unwrap_or_default();
+        for (key, value) in query.split('&').filter_map(|kv| kv.split_once('=')) {
+            match key.to_ascii_lowercase().as_str() {
+                "secret" => totp = Some(Self::from_base32(value)?),
+                "algorithm" => algorithm = match value.to_ascii_uppercase().as_str() {
+                    "SHA1" => Algorithm::Sha1,
+                    "SHA256" => Algorithm::Sha256,
+                    "SHA512" => Algorithm::Sha512,
+                    _ => bail!("unsupported totp algorithm: {value}"),
+                },
+                "digits" => digits = parse_digits(value)?,
+                "period" => period = parse_period(value)?,
+                _ => {}
+            }
+        }
+
+        match totp {
+            Some(totp) => Ok(Totp { algorithm, digits, period, ..totp }),
+            None => bail!("otpauth uri missing secret"),
+        }
+    }
+
+    /// 使用base32编码的密钥及缺省参数(SHA1, 6位, 30秒)创建
+    fn from_base32(s: &str) -> Result<Self> {
+        match base32_decode(s) {
+            Some(secret) if !secret.is_empty() => Ok(Totp {
+                secret,
+                algorithm: Algorithm::Sha1,
+                digits: DEFAULT_DIGITS,
+                period: DEFAULT_PERIOD,
+            }),
+            _ => bail!("totp secret is not valid base32"),
+        }
+    }
+
+    /// 生成指定时间的验证码
+    ///
+    /// * `now`: unix时间戳(单位: 秒)
+    pub fn code(&self, now: u64) -> String {
+        let counter = (now / self.period).to_be_bytes();
+        let digest = match self.algorithm {
+            Algorithm::Sha1 => mac::<Hmac<Sha1>>(&self.secret, &counter),
+            Algorithm::Sha256 => mac::<Hmac<Sha256>>(&self.secret, &counter),
+            Algorithm::Sha512 => mac::<Hmac<Sha512>>(&self.secret, &counter),
+        };
+
+        // 动态截断, 取摘要最后一个字节的低4位作为偏移量
+        let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+        let bin = u32::from_be_bytes([digest[offset], digest[offset + 1], digest[offset + 2], digest[offset + 3]])
+            & 0x7fff_ffff;
+        format!("{:0width$}", bin % 10u32.pow(self.digits), width = self.digits as usize)
+    }
+
+    /// 当前验证码剩余的有效时间(单位: 秒)
+    pub fn remaining(&self, now: u64) -> u64 {
+        self.period - now % self.period
+    }
+}
+
+/// KeePass 2.47+保存在条目字符串字段中的TOTP设置, 各项为对应字段的值, 未设置时为空
+#[derive(Debug, Default)]
+pub struct KeePassOtp {
+    /// `TimeOtp-Secret-Base32`
+    pub secret: String,
+    /// `TimeOtp-Length`
+    pub length: String,
+    /// `TimeOtp-Period`
+    pub period: String,
+    /// `TimeOtp-Algorithm`, 取值为`HMAC-SHA-1`、`HMAC-SHA-256`或`HMAC-SHA-512`
+    pub algorithm: String,
+}
+
+impl KeePassOtp {
+    /// 保存TOTP设置的字段名
+    pub const FIELDS: [&'static str; 4] = ["TimeOtp-Secret-Base32", "TimeOtp-Length", "TimeOtp-Period", "TimeOtp-Algorithm"];
+
+    /// 设置字段的值
+    ///
+    /// Returns:
+    ///
+    /// 不是TOTP设置字段时返回false
+    pub fn set(&mut self, key: &str, value: &str) -> bool {
+        let item = match key {
+            "TimeOtp-Secret-Base32" => &mut self.secret,
+            "TimeOtp-Length" => &mut self.length,
+            "TimeOtp-Period" => &mut self.period,
+            "TimeOtp-Algorithm" => &mut self.algorithm,
+            _ => return false,
+        };
+        value.trim().clone_into(item);
+        true
+    }
+
+    /// 转换为记录保存的otp值, 使用缺省设置时为base32密钥, 否则为包含设置的otpauth uri
+    ///
+    /// Returns:
+    ///
+    /// 没有密钥时返回空字符串
+    pub fn to_otp(&self) -> Result<String> {
+        if self.secret.is_empty() {
+            return Ok(String::new());
+        }
+        Totp::from_base32(&self.secret)?;
+        let algorithm = match self.algorithm.to_ascii_uppercase().as_str() {
+            "" | "HMAC-SHA-1" => Algorithm::Sha1,
+            "HMAC-SHA-256" => Algorithm::Sha256,
+            "HMAC-SHA-512" => Algorithm::Sha512,
+            _ => bail!("unsupported totp algorithm: {}", self.algorithm),
+        };
+        let digits = if self.length.is_empty() { DEFAULT_DIGITS } else { parse_digits(&self.length)? };
+        let period = if self.period.is_empty() { DEFAULT_PERIOD } else { parse_period(&self.period)? };
+
+        if algorithm == Algorithm::Sha1 && digits == DEFAULT_DIGITS && period == DEFAULT_PERIOD {
+            return Ok(self.secret.clone());
+        }
+        let secret: String = self.secret.chars().filter(|c| !c.is_ascii_whitespace() && *c != '-').collect();
+        Ok(format!("otpauth://totp/?secret={secret}&algorithm={}&digits={digits}&period={period}", algorithm.name()))
+    }
+}
+
+/// 解析验证码位数
+fn parse_digits(s: &str) -> Result<u32> {
+    match s.trim().parse() {
+        Ok(d @ 6..=8) => Ok(d),
+        _ => bail!("totp digits must be 6-8"),
+    }
+}
+
+/// 解析时间步长
+fn parse_period(s: &str) -> Result<u64> {
+    match s.trim().parse() {
+        Ok(p) if p > 0 => Ok(p),
+        _ => bail!("totp period format error"),
+    }
+}
+
+fn mac<M: Mac + hmac::digest::KeyInit>(key: &[u8], data: &[u8]) -> Vec<u8> {
+    // hmac支持任意长度的密钥, 不会出错
+    let mut mac = <M as Mac>::new_from_slice(key).unwrap();
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// base32解码(RFC 4648), 忽略大小写、空格及末尾的填充字符
+fn base32_decode(s: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(s.len() * 5 / 8);
+    let (mut buf, mut bits) = (0u32, 0u32);
+    for c in s.bytes().filter(|c| !c.is_ascii_whitespace() && *c != b'-') {
+        let v = match c.to_ascii_uppercase() {
+            c @ b'A'..=b'Z' => c - b'A',
+            c @ b'2'..=b'7' => c - b'2' + 26,
+            b'=' => break,
+            _ => return None,
+        };
+        buf = (buf << 5) | v as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buf >> bits) as u8);
+            buf &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RFC 6238附录B的测试向量: (时间, SHA1, SHA256, SHA512)
+    const RFC6238: [(u64, &str, &str, &str); 6] = [
+        (59, "94287082", "46119246", "90693936"),
+        (1111111109, "07081804", "68084774", "25091201"),
+        (1111111111, "14050471", "67062674", "99943326"),
+        (1234567890, "89005924", "91819424", "93441116"),
+        (2000000000, "69279037", "90698825", "38618901"),
+        (20000000000, "65353130", "77737706", "47863826"),
+    ];
+
+    fn rfc_totp(algorithm: Algorithm) -> Totp {
+        let seed = b"1234567890123456789012345678901234567890123456789012345678901234";
+        let len = match algorithm {
+            Algorithm::Sha1 => 20,
+            Algorithm::Sha256 => 32,
+            Algorithm::Sha512 => 64,
+        };
+        Totp { secret: seed[..len].to_vec(), algorithm, digits: 8, period: DEFAULT_PERIOD }
+    }
+
+    #[test]
+    fn rfc6238() {
+        let (sha1, sha256, sha512) = (rfc_totp(Algorithm::Sha1), rfc_totp(Algorithm::Sha256), rfc_totp(Algorithm::Sha512));
+        for (now, c1, c256, c512) in RFC6238 {
+            assert_eq!(sha1.code(now), c1, "sha1 at {now}");
+            assert_eq!(sha256.code(now), c256, "sha256 at {now}");
+            assert_eq!(sha512.code(now), c512, "sha512 at {now}");
+        }
+        assert_eq!(sha1.remaining(59), 1);
+        assert_eq!(sha1.remaining(60), 30);
+    }
+
+    #[test]
+    fn parse_uri() {
+        // base32("12345678901234567890")
+        let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+        let totp = Totp::parse(&format!("otpauth://totp/acme:alice?secret={secret}&digits=8&issuer=acme")).unwrap();
+        assert_eq!(totp.code(59), "94287082");
+
+        let totp = Totp::parse("gezd gnbv gy3t qojq gezd gnbv gy3t qojq").unwrap();
+        assert_eq!((totp.digits, totp.period, totp.algorithm), (DEFAULT_DIGITS, DEFAULT_PERIOD, Algorithm::Sha1));
+        assert_eq!(totp.code(59), "287082");
+
+        assert!(Totp::parse("otpauth://hotp/x?secret=GEZDGNBV").is_err());
+        assert!(Totp::parse("otpauth://totp/x?digits=6").is_err());
+        assert!(Totp::parse(&format!("otpauth://totp/x?secret={secret}&digits=9")).is_err());
+        assert!(Totp::parse(&format!("otpauth://totp/x?secret={secret}&period=0")).is_err());
+        assert!(Totp::parse(&format!("otpauth://totp/x?secret={secret}&algorithm=MD5")).is_err());
+        assert!(Totp::parse("not base32!").is_err());
+    }
+
+    #[test]
+    fn keepass_settings() {
+        let mut otp = KeePassOtp::default();
+        assert_eq!(otp.to_otp().unwrap(), "");
+        assert!(!otp.set("Title", "github"));
+
+        // 缺省设置保留原始密钥
+        assert!(otp.set("TimeOtp-Secret-Base32", "GEZDGNBVGY3TQOJQ GEZDGNBVGY3TQOJQ"));
+        assert!(otp.set("TimeOtp-Period", "30"));
+        assert_eq!(otp.to_otp().unwrap(), "GEZDGNBVGY3TQOJQ GEZDGNBVGY3TQOJQ");
+
+        assert!(otp.set("TimeOtp-Length", "8"));
+        assert!(otp.set("TimeOtp-Algorithm", "HMAC-SHA-256"));
+        assert!(otp.set("TimeOtp-Period", "60"));
+        let uri = otp.to_otp().unwrap();
+        assert_eq!(uri, "otpauth://totp/?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&algorithm=SHA256&digits=8&period=60");
+        let totp = Totp::parse(&uri).unwrap();
+        assert_eq!((totp.digits, totp.period, totp.algorithm), (8, 60, Algorithm::Sha256));
+        assert_eq!(totp.remaining(59), 1);
+
+        otp.set("TimeOtp-Algorithm", "HMAC-MD5");
+        assert!(otp.to_otp().is_err());
+        otp.set("TimeOtp-Algorithm", "");
+        otp.set("TimeOtp-Length", "12");
+        assert!(otp.to_otp().is_err());
+    }
+}
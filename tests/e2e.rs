@@ -0,0 +1,347 @@
+//! 端到端测试, 使用示例xml生成临时数据库, 在随机端口上启动完整的服务后通过http接口测试
+use std::{
+    net::TcpListener,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpStream};
+
+/// 测试数据库的主密码
+const PASSWORD: &str = "e2e-secret";
+/// 测试数据库名称, 也是登录的用户名
+const DATABASE: &str = "vault";
+/// 等待服务启动的最长时间
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// 测试服务, 析构时结束进程并删除临时目录
+struct Server {
+    child: Child,
+    dir: PathBuf,
+    base: String,
+    client: reqwest::Client,
+}
+
+impl Server {
+    /// 生成临时数据库并启动服务, `args`为附加的命令行参数
+    async fn start(name: &str, args: &[&str]) -> Server {
+        let dir = std::env::temp_dir().join(format!("accinfo-e2e-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let database = dir.join(format!("{DATABASE}.aidb"));
+        let fixture = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("simple.xml");
+
+        let status = Command::new(env!("CARGO_BIN_EXE_accinfo"))
+            .current_dir(&dir)
+            .arg("--encrypt").arg(&fixture)
+            .arg("-d").arg(&database)
+            .args(["-p", PASSWORD])
+            .stdout(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success(), "encrypt fixture failed: {status}");
+
+        // 获取一个空闲端口, 释放后由服务监听
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let listen = format!("127.0.0.1:{port}");
+        let child = Command::new(env!("CARGO_BIN_EXE_accinfo"))
+            .current_dir(&dir)
+            .arg("-d").arg(&database)
+            .args(["-l", &listen, "--no-banner", "--log-level", "warn"])
+            .args(args)
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let mut server = Server {
+            child,
+            dir,
+            base: format!("http://{listen}/api"),
+            client: reqwest::Client::new(),
+        };
+        server.wait_ready().await;
+        server
+    }
+
+    async fn wait_ready(&mut self) {
+        let start = Instant::now();
+        loop {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                panic!("server exited during startup: {status}");
+            }
+            if let Ok(res) = self.client.get(self.url("ping")).send().await {
+                if res.status().is_success() {
+                    return;
+                }
+            }
+            assert!(start.elapsed() < STARTUP_TIMEOUT, "server startup timeout");
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{path}", self.base)
+    }
+
+    /// 发送WebSocket升级请求, 返回连接及回复的状态行
+    async fn ws_connect(&self) -> (TcpStream, String) {
+        let addr = self.base.trim_start_matches("http://").trim_end_matches("/api");
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let req = format!("GET /api/ws HTTP/1.1\r\nHost: {addr}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+            Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n");
+        stream.write_all(req.as_bytes()).await.unwrap();
+
+        // 逐字节读取回复头, 不读取升级后的数据
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            let mut b = [0u8; 1];
+            assert_eq!(stream.read(&mut b).await.unwrap(), 1, "connection closed: {}", String::from_utf8_lossy(&head));
+            head.push(b[0]);
+        }
+        let head = String::from_utf8(head).unwrap();
+        let status = head.lines().next().unwrap_or_default().to_owned();
+        (stream, status)
+    }
+
+    /// 调用接口, 返回http状态及回复的json
+    async fn post(&self, path: &str, token: Option<&str>, body: Value) -> (StatusCode, Value) {
+        let mut req = self.client.post(self.url(path)).json(&body);
+        if let Some(token) = token {
+            req = req.header("Authorization", format!("session {token}"));
+        }
+        let res = req.send().await.unwrap();
+        let status = res.status();
+        let body = res.json::<Value>().await.unwrap_or(Value::Null);
+        (status, body)
+    }
+
+    /// 登录并返回会话令牌
+    async fn login(&self) -> String {
+        let (status, res) = self.post("login", None, json!({"user": DATABASE, "pass": PASSWORD})).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(res["code"], 200, "login failed: {res}");
+        res["data"]["token"].as_str().unwrap().to_owned()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[tokio::test]
+async fn login_failure() {
+    let srv = Server::start("login", &["--rate-limit", "0"]).await;
+
+    let (_, res) = srv.post("login", None, json!({"user": DATABASE, "pass": "wrong"})).await;
+    assert_ne!(res["code"], 200);
+    let (_, res) = srv.post("login", None, json!({"user": "missing", "pass": PASSWORD})).await;
+    assert_ne!(res["code"], 200);
+
+    // 未登录时不能访问需要认证的接口
+    let (status, _) = srv.post("list", None, json!({})).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = srv.post("list", Some("invalid-token"), json!({})).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn record_crud() {
+    let srv = Server::start("crud", &["--rate-limit", "0"]).await;
+    let token = srv.login().await;
+    let token = Some(token.as_str());
+
+    let (status, res) = srv.post("list", token, json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    let total = res["data"]["total"].as_u64().unwrap();
+    assert!(total > 0);
+
+    let (_, res) = srv.post("record/create", token, json!({
+        "title": "e2e-record",
+        "user": "tester",
+        "pass": "Str0ng-e2e-Passw0rd!",
+        "url": "https://e2e.example.com",
+    })).await;
+    assert_eq!(res["code"], 200, "create failed: {res}");
+    let id = res["data"]["id"].as_str().unwrap().to_owned();
+
+    let (_, res) = srv.post("list", token, json!({"q": "e2e-record"})).await;
+    assert_eq!(res["data"]["total"], 1);
+    assert_eq!(res["data"]["records"][0]["id"], id.as_str());
+
+    let (_, res) = srv.post("record/update", token, json!({"id": id, "title": "e2e-renamed"})).await;
+    assert_eq!(res["code"], 200, "update failed: {res}");
+    let (_, res) = srv.post("record/get", token, json!({"id": id})).await;
+    assert_eq!(res["data"]["title"], "e2e-renamed");
+    let (_, res) = srv.post("list", token, json!({"q": "e2e-record"})).await;
+    assert_eq!(res["data"]["total"], 0);
+
+    // 参数校验失败时回复400
+    let (status, res) = srv.post("record/create", token, json!({"title": ""})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(res["code"], 400);
+
+    let (_, res) = srv.post("record/delete", token, json!({"id": id})).await;
+    assert_eq!(res["code"], 200, "delete failed: {res}");
+    let (_, res) = srv.post("list", token, json!({})).await;
+    assert_eq!(res["data"]["total"].as_u64(), Some(total));
+
+    let (_, res) = srv.post("logout", token, json!({})).await;
+    assert_eq!(res["code"], 200);
+    let (status, _) = srv.post("list", token, json!({})).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn rate_limit() {
+    let srv = Server::start("ratelimit", &["--rate-limit", "3", "--rate-window", "3600"]).await;
+    let token = srv.login().await;
+    let token = Some(token.as_str());
+
+    for _ in 0..3 {
+        let (status, _) = srv.post("list", token, json!({})).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let (status, res) = srv.post("list", token, json!({})).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(res["code"], 429);
+
+    // 不需要认证的接口不限流
+    let (status, _) = srv.post("ping", None, json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn session_expiry() {
+    let srv = Server::start("expiry", &["--rate-limit", "0", "--session-expire", "2"]).await;
+    let token = srv.login().await;
+    let token = Some(token.as_str());
+
+    let (status, _) = srv.post("list", token, json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+
+    tokio::time::sleep(Duration::from_secs(4)).await;
+    let (status, _) = srv.post("list", token, json!({})).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn session_keepalive() {
+    let srv = Server::start("keepalive", &["--rate-limit", "0", "--session-expire", "4", "--no-sliding-session"]).await;
+    let idle = srv.login().await;
+    let alive = srv.login().await;
+    let (status, _) = srv.post("list", Some(&idle), json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    let (_, res) = srv.post("keepalive", Some(&alive), json!({})).await;
+    assert_eq!(res["code"], 200, "keepalive failed: {res}");
+    assert_eq!(res["data"]["token"], alive.as_str());
+
+    // 普通请求不延长会话, 调用过保活接口的会话仍然有效
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    let (status, _) = srv.post("list", Some(&idle), json!({})).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = srv.post("list", Some(&alive), json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn client_sdk() {
+    use accinfo_client::{ApiError, Client, NewRecord, RecordUpdate};
+
+    let srv = Server::start("client", &["--rate-limit", "0"]).await;
+    let client = Client::new(&srv.base).unwrap();
+    let info = client.login(DATABASE, PASSWORD).await.unwrap();
+    assert_eq!(info.database, DATABASE);
+    assert_eq!(client.token().as_deref(), Some(info.token.as_str()));
+
+    let total = client.list(None).await.unwrap().total;
+    let rec = client.record_create(&NewRecord {
+        title: "sdk-record".to_owned(),
+        user: "tester".to_owned(),
+        pass: "Str0ng-sdk-Passw0rd!".to_owned(),
+        ..Default::default()
+    }).await.unwrap();
+    assert_eq!(client.list(Some("sdk-record")).await.unwrap().records[0].id, rec.id);
+    assert_eq!(client.record_reveal(&rec.id).await.unwrap().pass, "Str0ng-sdk-Passw0rd!");
+
+    let update = RecordUpdate { id: rec.id.clone(), title: Some("sdk-renamed".to_owned()), ..Default::default() };
+    assert_eq!(client.record_update(&update).await.unwrap().title, "sdk-renamed");
+    assert_eq!(client.record_get(&rec.id).await.unwrap().user, "tester");
+    client.record_delete(&rec.id).await.unwrap();
+    assert_eq!(client.list(None).await.unwrap().total, total);
+
+    let job_id = client.export("csv").await.unwrap();
+    let start = Instant::now();
+    while !client.job(job_id).await.unwrap().is_finished() {
+        assert!(start.elapsed() < STARTUP_TIMEOUT, "export job timeout");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(!client.job_file(job_id).await.unwrap().is_empty());
+    assert!(client.jobs().await.unwrap().iter().any(|j| j.id == job_id));
+
+    client.logout().await.unwrap();
+    assert_eq!(client.token(), None);
+    client.set_token(&info.token);
+    let e = client.list(None).await.unwrap_err();
+    assert_eq!(e.downcast_ref::<ApiError>().unwrap().status, 401);
+}
+
+#[tokio::test]
+async fn backup_encrypted() {
+    const RECIPIENT: &str = "age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p";
+    let srv = Server::start("backup", &["--rate-limit", "0", "--backup-token", "e2e-backup-token-0123",
+        "--export-recipients", RECIPIENT]).await;
+    let backup = |token: &'static str| srv.client.get(srv.url("backup")).bearer_auth(token).send();
+
+    let res = backup("wrong").await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    // 服务启动后未登录过, 内存中没有主密码
+    let res = backup("e2e-backup-token-0123").await.unwrap();
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    srv.login().await;
+    let res = backup("e2e-backup-token-0123").await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = res.text().await.unwrap();
+    assert!(body.starts_with("-----BEGIN AGE ENCRYPTED FILE-----"), "unexpected backup: {body}");
+}
+
+#[tokio::test]
+async fn ws_pending_auth() {
+    /// 服务端等待令牌的超时时间
+    const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+    let srv = Server::start("ws", &["--rate-limit", "0"]).await;
+
+    // 同一地址未发送令牌的连接超过上限时拒绝升级
+    let start = Instant::now();
+    let mut conns = Vec::new();
+    for _ in 0..8 {
+        let (stream, status) = srv.ws_connect().await;
+        assert!(status.contains(" 101 "), "unexpected status: {status}");
+        conns.push(stream);
+    }
+    let (_, status) = srv.ws_connect().await;
+    assert!(status.contains(" 503 "), "unexpected status: {status}");
+
+    // 超时未发送令牌时服务端以1008关闭连接
+    let mut frame = [0u8; 4];
+    let read = tokio::time::timeout(AUTH_TIMEOUT * 2, conns[0].read_exact(&mut frame)).await;
+    assert!(read.is_ok_and(|r| r.is_ok()), "close frame not received");
+    assert_eq!(frame[0], 0x88);
+    assert_eq!(u16::from_be_bytes([frame[2], frame[3]]), 1008);
+    assert!(start.elapsed() >= AUTH_TIMEOUT - Duration::from_secs(1), "closed before timeout");
+
+    // 超时的连接释放后可以重新连接
+    drop(conns);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let (_, status) = srv.ws_connect().await;
+    assert!(status.contains(" 101 "), "unexpected status: {status}");
+}